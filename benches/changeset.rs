@@ -0,0 +1,43 @@
+#[macro_use]
+extern crate criterion;
+extern crate todiff;
+
+use criterion::{BatchSize, Criterion};
+use todiff::compute_changes::{compute_changeset_with, DiffOptions};
+use todiff::display_changes::{display_changeset_with, DisplayOptions};
+use todiff::synthetic;
+
+fn bench_changeset(c: &mut Criterion) {
+    let diff_options = DiffOptions::default().parallel(false);
+    let display_options = DisplayOptions::default();
+    let workloads = vec![
+        ("edited", synthetic::edited_subjects(1000, 10, 42)),
+        ("tag_churn", synthetic::tag_churn(500, 42)),
+        ("recurring", synthetic::recurring(500, 42)),
+    ];
+
+    let mut group = c.benchmark_group("compute_changeset");
+    group.sample_size(10);
+    for (name, (from, to)) in workloads.iter() {
+        group.bench_function(*name, |b| {
+            b.iter(|| compute_changeset_with(from.clone(), to.clone(), &diff_options))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("display_changeset");
+    for (name, (from, to)) in workloads {
+        let (new_tasks, changes) = compute_changeset_with(from, to, &diff_options);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || (new_tasks.clone(), changes.clone()),
+                |(new_tasks, changes)| display_changeset_with(new_tasks, changes, &display_options),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_changeset);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+#[macro_use]
+extern crate criterion;
+extern crate strsim;
+extern crate todiff;
+
+use criterion::Criterion;
+use todiff::edit_distance::bounded_levenshtein;
+use todiff::synthetic::Generator;
+
+// Subjects of a realistic length, most pairs of which are wildly dissimilar
+fn subjects() -> Vec<String> {
+    let mut generator = Generator::new(42);
+    (0..80).map(|_| generator.subject()).collect()
+}
+
+fn bench_admissibility(c: &mut Criterion) {
+    let subjects = subjects();
+    let pairs = subjects
+        .iter()
+        .flat_map(|a| subjects.iter().map(move |b| (a, b)))
+        .collect::<Vec<_>>();
+    // The default allowed divergence
+    let bound = |b: &str| 25 * b.chars().count() / 100;
+
+    let mut group = c.benchmark_group("admissibility");
+    group.bench_function("levenshtein", |bencher| {
+        bencher.iter(|| {
+            pairs
+                .iter()
+                .filter(|(a, b)| strsim::levenshtein(b, a) <= bound(b))
+                .count()
+        })
+    });
+    group.bench_function("bounded_levenshtein", |bencher| {
+        bencher.iter(|| {
+            pairs
+                .iter()
+                .filter(|(a, b)| bounded_levenshtein(b, a, bound(b)).is_some())
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_admissibility);
+criterion_main!(benches);
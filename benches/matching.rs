@@ -0,0 +1,65 @@
+#[macro_use]
+extern crate criterion;
+extern crate todiff;
+
+use criterion::Criterion;
+use todiff::compute_changes::{match_tasks, DiffOptions};
+use todiff::stable_marriage::{stable_marriage, Matcher};
+use todiff::synthetic::{self, Generator};
+
+// How each individual ranks the individuals of the other list, most preferred first
+struct IndexMatcher(Vec<Vec<usize>>);
+
+impl Matcher for IndexMatcher {
+    type Item = usize;
+    type Target = usize;
+
+    fn score(&self, x: &Self::Item, y: &Self::Target) -> Option<u32> {
+        let prefs = &self.0[*x];
+        let pos = prefs.iter().position(|j| j == y)?;
+        Some((prefs.len() - pos) as u32)
+    }
+}
+
+// `len` individuals, each finding `admissible` random individuals of the other list admissible
+fn preference_lists(generator: &mut Generator, len: usize, admissible: usize) -> IndexMatcher {
+    IndexMatcher(
+        (0..len)
+            .map(|_| (0..admissible).map(|_| generator.below(len)).collect())
+            .collect(),
+    )
+}
+
+// The matching algorithm alone, without the cost of comparing tasks
+fn bench_stable_marriage(c: &mut Criterion) {
+    let mut generator = Generator::new(42);
+    let men = preference_lists(&mut generator, 2000, 20);
+    let women = preference_lists(&mut generator, 2000, 20);
+    c.bench_function("stable_marriage/index", |b| {
+        b.iter(|| stable_marriage((0..2000).collect(), (0..2000).collect(), &men, &women))
+    });
+}
+
+fn bench_match_tasks(c: &mut Criterion) {
+    let opts = DiffOptions::default().parallel(false);
+    let workloads = vec![
+        ("edited", synthetic::edited_subjects(1000, 10, 42)),
+        ("tag_churn", synthetic::tag_churn(500, 42)),
+        ("recurring", synthetic::recurring(500, 42)),
+        // Only due dates changed, so that most subjects are found once in each list
+        ("postponed", synthetic::postponed(1000, 42)),
+        // Every pair of tasks is admissible, so that each distance is needed many times
+        ("similar", synthetic::all_pairs_admissible(200)),
+    ];
+    let mut group = c.benchmark_group("match_tasks");
+    group.sample_size(10);
+    for (name, (from, to)) in workloads {
+        group.bench_function(name, |b| {
+            b.iter(|| match_tasks(from.clone(), to.clone(), &opts))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_stable_marriage, bench_match_tasks);
+criterion_main!(benches);
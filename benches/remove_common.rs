@@ -0,0 +1,36 @@
+#[macro_use]
+extern crate criterion;
+extern crate todiff;
+extern crate todo_txt;
+
+use criterion::{BatchSize, Criterion};
+use todiff::compute_changes::{remove_common, remove_common_by_key};
+use todiff::synthetic;
+use todo_txt::task::Extended as Task;
+
+fn bench_remove_common(c: &mut Criterion) {
+    // As when diffing a large todo list with its previous version
+    let (from, to) = synthetic::edited_subjects(10_000, 5, 42);
+    let subjects = |tasks: &[Task]| tasks.iter().map(|t| t.subject.clone()).collect::<Vec<_>>();
+    let (from_subjects, to_subjects) = (subjects(&from), subjects(&to));
+
+    let mut group = c.benchmark_group("remove_common");
+    group.bench_function("tasks", |b| {
+        b.iter_batched(
+            || (from.clone(), to.clone()),
+            |(mut from, mut to)| remove_common_by_key(&mut from, &mut to, |t| t.subject.as_str()),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("subjects", |b| {
+        b.iter_batched(
+            || (from_subjects.clone(), to_subjects.clone()),
+            |(mut from, mut to)| remove_common(&mut from, &mut to),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_remove_common);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate todiff;
+
+fuzz_target!(|data: &[u8]| {
+    todiff::fuzz::run_pipeline(data);
+});
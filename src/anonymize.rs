@@ -0,0 +1,246 @@
+// Replacing the words of tasks with pseudo-words, so that a todo list can be shared to reproduce
+// a bug without disclosing it. Each distinct word (a run of letters and digits, compared
+// case-insensitively) is replaced by the same pseudo-word of the same length everywhere, with the
+// case of each letter kept, so that identical subjects stay identical and similar ones similar.
+// Priorities, dates, recurrences, the `+`, `@` and `#` markers and the tag keys are kept, and so
+// are the tag values that are dates.
+
+use std::collections::{HashMap, HashSet};
+use todo_txt::task::{Extended as Task, Note};
+use todo_txt::Date as TaskDate;
+
+const CONSONANTS: &[u8] = b"bcdfghjklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+
+// FNV-1a, whose result does not depend on the platform or the version of Rust
+fn hash(word: &str, attempt: u64) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64 ^ attempt;
+    for b in word.bytes() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+// A pronounceable word of the length of `word`, in lowercase, with digits instead of its digits
+fn pseudo_word(word: &str, attempt: u64) -> String {
+    let mut state = hash(word, attempt);
+    word.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            // A xorshift step per character, as the 64 bits of the hash do not last long
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            if c.is_numeric() {
+                char::from(b'0' + (state % 10) as u8)
+            } else if i % 2 == 0 {
+                char::from(CONSONANTS[(state % CONSONANTS.len() as u64) as usize])
+            } else {
+                char::from(VOWELS[(state % VOWELS.len() as u64) as usize])
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Anonymizer {
+    // The pseudo-word of each lowercase word seen so far
+    words: HashMap<String, String>,
+    // Their pseudo-words, so that no two words get the same one
+    used: HashSet<String>,
+}
+
+impl Anonymizer {
+    pub fn new() -> Anonymizer {
+        Anonymizer::default()
+    }
+
+    fn word(&mut self, word: &str) -> String {
+        let lowercase = word.to_lowercase();
+        if !self.words.contains_key(&lowercase) {
+            // Short words cannot always get a distinct pseudo-word, in which case they keep theirs
+            let pseudo = (0..64)
+                .map(|attempt| pseudo_word(&lowercase, attempt))
+                .find(|p| !self.used.contains(p) && !self.words.contains_key(p))
+                .unwrap_or_else(|| lowercase.clone());
+            self.used.insert(pseudo.clone());
+            self.words.insert(lowercase.clone(), pseudo);
+        }
+        let pseudo = &self.words[&lowercase];
+        if lowercase.chars().count() != word.chars().count() {
+            // Lowercasing changed the length, eg. for `İ`: the case cannot be kept
+            return pseudo.clone();
+        }
+        word.chars()
+            .zip(pseudo.chars())
+            .map(|(c, p)| {
+                if c.is_uppercase() {
+                    p.to_ascii_uppercase()
+                } else {
+                    p
+                }
+            })
+            .collect()
+    }
+
+    // `text` with each of its words replaced, anything else being kept
+    pub fn text(&mut self, text: &str) -> String {
+        let mut res = String::with_capacity(text.len());
+        let mut word = String::new();
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                word.push(c);
+            } else {
+                if !word.is_empty() {
+                    res += &self.word(&word);
+                    word.clear();
+                }
+                res.push(c);
+            }
+        }
+        if !word.is_empty() {
+            res += &self.word(&word);
+        }
+        res
+    }
+
+    pub fn task(&mut self, task: &Task) -> Task {
+        let mut res = task.clone();
+        res.subject = self.text(&task.subject);
+        // These are lowercase, and so are the pseudo-words
+        let inner = &mut res.inner;
+        for list in [
+            &mut inner.projects,
+            &mut inner.contexts,
+            &mut inner.hashtags,
+        ] {
+            for name in list.iter_mut() {
+                *name = self.text(name);
+            }
+        }
+        for value in res.tags.values_mut() {
+            if value.parse::<TaskDate>().is_err() {
+                *value = self.text(value);
+            }
+        }
+        res.note = match &task.note {
+            Note::None => Note::None,
+            Note::Short(filename) => Note::Short(self.text(filename)),
+            Note::Long { filename, content } => Note::Long {
+                filename: self.text(filename),
+                content: self.text(content),
+            },
+        };
+        res
+    }
+
+    pub fn tasks(&mut self, tasks: &[Task]) -> Vec<Task> {
+        tasks.iter().map(|t| self.task(t)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compute_changes::*;
+    use display_changes::count_changeset;
+    use std::mem::discriminant;
+    use std::str::FromStr;
+
+    fn tasks(lines: &[&str]) -> Vec<Task> {
+        lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_anonymize_task() {
+        let mut anonymizer = Anonymizer::new();
+        let task = Task::from_str(
+            "(A) 2018-06-01 Call Mom about the trip +Family @phone due:2018-06-03 \
+             t:2018-06-02 rec:+1w ticket:AB-12 since:2018-05-01",
+        )
+        .unwrap();
+        let anonymized = anonymizer.task(&task);
+        assert_eq!(anonymized.priority, task.priority);
+        assert_eq!(anonymized.create_date, task.create_date);
+        assert_eq!(anonymized.due_date, task.due_date);
+        assert_eq!(anonymized.threshold_date, task.threshold_date);
+        assert_eq!(anonymized.recurrence, task.recurrence);
+        assert_eq!(anonymized.tags["since"], "2018-05-01");
+        assert_ne!(anonymized.tags["ticket"], "AB-12");
+        assert_eq!(anonymized.tags["ticket"].len(), 5);
+        assert_eq!(&anonymized.tags["ticket"][2..3], "-");
+
+        let words = task.subject.split(' ').collect::<Vec<_>>();
+        let pseudo = anonymized.subject.split(' ').collect::<Vec<_>>();
+        assert_eq!(words.len(), pseudo.len());
+        for (w, p) in words.iter().zip(pseudo.iter()) {
+            assert_ne!(w, p);
+            assert_eq!(w.len(), p.len());
+            assert_eq!(
+                w.chars().next().map(char::is_uppercase),
+                p.chars().next().map(char::is_uppercase)
+            );
+        }
+        assert!(pseudo[5].starts_with('+') && pseudo[6].starts_with('@'));
+        // The projects and contexts still match the subject, as todo_txt would parse it
+        let reparsed = Task::from_str(&anonymized.to_string()).unwrap();
+        assert_eq!(reparsed, anonymized);
+
+        // The same words get the same pseudo-words, whatever their case
+        let other = anonymizer.task(&Task::from_str("call mom +family").unwrap());
+        assert_eq!(
+            other.subject,
+            format!(
+                "{} {} +{}",
+                pseudo[0].to_lowercase(),
+                pseudo[1].to_lowercase(),
+                anonymized.projects[0]
+            )
+        );
+    }
+
+    fn delta_shapes(
+        changes: &[ChangedTask<Vec<Changes>>],
+    ) -> Vec<Vec<Vec<std::mem::Discriminant<Changes>>>> {
+        changes
+            .iter()
+            .map(|x| {
+                x.delta
+                    .clone()
+                    .into_iter()
+                    .map(|changes| changes.iter().map(discriminant).collect())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_anonymized_changeset() {
+        let from = tasks(&[
+            "2018-06-01 call mom +family due:2018-06-02",
+            "(B) write the quarterly report @work",
+            "2018-06-01 water plants due:2018-06-02 rec:1w",
+            "buy milk and eggs",
+            "fix the bike",
+        ]);
+        let to = tasks(&[
+            "x 2018-06-03 2018-06-01 call mom +family due:2018-06-02",
+            "(A) write the quarterly report @work @office",
+            "x 2018-06-03 2018-06-01 water plants due:2018-06-02 rec:1w",
+            "2018-06-03 water plants due:2018-06-10 rec:1w",
+            "buy milk and bread",
+            "read a book",
+        ]);
+        let mut anonymizer = Anonymizer::new();
+        let (anon_from, anon_to) = (anonymizer.tasks(&from), anonymizer.tasks(&to));
+        let (new_tasks, changes) = compute_changeset(from, to, 25);
+        let (anon_new_tasks, anon_changes) = compute_changeset(anon_from, anon_to, 25);
+        assert_eq!(delta_shapes(&anon_changes), delta_shapes(&changes));
+        assert_eq!(anon_new_tasks.len(), new_tasks.len());
+        assert_eq!(
+            count_changeset(anon_new_tasks, anon_changes),
+            count_changeset(new_tasks, changes)
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use compute_changes::*;
+use std::fmt;
+use todo_txt::task::Extended as Task;
+
+// Why the changes of a task of a changeset could not be applied, the task being given as its line
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ApplyError {
+    // No task of the target list is identical or similar enough to the original task
+    Missing(String),
+    // None is identical, but several are similar enough (their number being given)
+    Ambiguous(String, usize),
+    // The changes are about the recurrence of the task, which has none
+    NotRecurring(String),
+    // A priority other than A to Z
+    InvalidPriority(char),
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApplyError::Missing(task) => {
+                write!(
+                    f,
+                    "no task matches ‘{}’, its changes were not applied",
+                    task
+                )
+            }
+            ApplyError::Ambiguous(task, count) => write!(
+                f,
+                "{} tasks match ‘{}’, its changes were not applied",
+                count, task
+            ),
+            ApplyError::NotRecurring(task) => write!(
+                f,
+                "‘{}’ is not recurring, its changes were not applied",
+                task
+            ),
+            ApplyError::InvalidPriority(p) => write!(f, "invalid priority ‘{}’", p),
+        }
+    }
+}
+
+impl std::error::Error for ApplyError {}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ApplyOutcome {
+    // The resulting tasks, each with the index in the target list of the task it stands for, if
+    // any: new tasks come last
+    pub tasks: Vec<(Option<usize>, Task)>,
+    pub errors: Vec<ApplyError>,
+}
+
+// Applies a changeset computed on another copy of `target`. The original task of each change is
+// looked for in `target` as-is, or else among the tasks `opts` would match it with, provided
+// there is only one. Identical tasks of the changeset are looked for too, so that their copy is
+// not mistaken for the one of another task.
+pub fn apply_changeset(target: &[Task], changeset: &Changeset, opts: &DiffOptions) -> ApplyOutcome {
+    let (new_tasks, changes) = changeset;
+    let mut applied = vec![None; target.len()];
+    let mut unmatched = Vec::new();
+    for c in changes.iter() {
+        match (0..target.len()).find(|&i| applied[i].is_none() && target[i] == c.orig) {
+            Some(i) => applied[i] = Some(c),
+            None if !c.delta.is_identical() => unmatched.push(c),
+            None => (),
+        }
+    }
+
+    let mut errors = Vec::new();
+    for c in unmatched {
+        let candidates = (0..target.len())
+            .filter(|&i| applied[i].is_none() && is_admissible_match(&c.orig, &target[i], opts))
+            .collect::<Vec<_>>();
+        match candidates[..] {
+            [] => errors.push(ApplyError::Missing(c.orig.to_string())),
+            [i] => applied[i] = Some(c),
+            _ => errors.push(ApplyError::Ambiguous(c.orig.to_string(), candidates.len())),
+        }
+    }
+
+    let mut tasks = Vec::new();
+    for (i, (task, change)) in target.iter().zip(applied).enumerate() {
+        let after = match change.map(|c| apply_delta(task, &c.delta)) {
+            Some(Ok(after)) => after,
+            Some(Err(e)) => {
+                errors.push(e);
+                vec![task.clone()]
+            }
+            None => vec![task.clone()],
+        };
+        tasks.extend(after.into_iter().map(|t| (Some(i), t)));
+    }
+    tasks.extend(new_tasks.iter().map(|t| (None, t.clone())));
+    ApplyOutcome { tasks, errors }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn tasks(lines: &[&str]) -> Vec<Task> {
+        lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+    }
+
+    fn apply(target: &[&str], changeset: &Changeset) -> (Vec<String>, Vec<ApplyError>) {
+        let outcome = apply_changeset(&tasks(target), changeset, &DiffOptions::default());
+        let tasks = outcome.tasks.iter().map(|(_, t)| t.to_string()).collect();
+        (tasks, outcome.errors)
+    }
+
+    #[test]
+    fn test_apply_changeset() {
+        let changeset = compute_changeset(
+            tasks(&["call mom", "buy milk", "pay the rent", "water the plants"]),
+            tasks(&[
+                "x call mom",
+                "pay the rent due:2018-06-01",
+                "water the plants",
+                "new",
+            ]),
+            25,
+        );
+        assert_eq!(
+            apply(
+                &[
+                    "(A) water the plants",
+                    "pay the rent",
+                    "call mum",
+                    "buy milk",
+                    "other"
+                ],
+                &changeset
+            ),
+            (
+                vec![
+                    "(A) water the plants".to_owned(),
+                    "pay the rent due:2018-06-01".to_owned(),
+                    "x call mum".to_owned(),
+                    "other".to_owned(),
+                    "new".to_owned(),
+                ],
+                vec![]
+            )
+        );
+    }
+
+    #[test]
+    fn test_apply_changeset_errors() {
+        let changeset = compute_changeset(
+            tasks(&["call mom", "buy milk", "buy milk and eggs"]),
+            tasks(&["x call mom", "buy milk"]),
+            25,
+        );
+        let (tasks, errors) = apply(&["call mum", "call mam", "buy bread"], &changeset);
+        assert_eq!(tasks, vec!["call mum", "call mam", "buy bread"]);
+        assert_eq!(
+            errors,
+            vec![
+                ApplyError::Ambiguous("call mom".to_owned(), 2),
+                ApplyError::Missing("buy milk and eggs".to_owned()),
+            ]
+        );
+    }
+}
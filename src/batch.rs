@@ -0,0 +1,301 @@
+// Diffing several pairs of todo.txt files at once, eg. consecutive snapshots of the same list. The
+// pairs come from a manifest where each line is `BEFORE AFTER [LABEL]` (so paths cannot contain
+// whitespace), or from the files matching a glob pattern, diffed in lexicographic order. Blank
+// lines and lines starting with `#` are ignored in manifests.
+
+use compute_changes::{compute_changeset_with, Changeset, DiffOptions};
+use io::{read_tasks_from_with, ReadError, ReadOptions};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BatchPair {
+    pub before: String,
+    pub after: String,
+    pub label: Option<String>,
+}
+
+impl BatchPair {
+    pub fn new(before: &str, after: &str) -> BatchPair {
+        BatchPair {
+            before: before.to_owned(),
+            after: after.to_owned(),
+            label: None,
+        }
+    }
+
+    // The label given in the manifest, or else `BEFORE → AFTER`
+    pub fn label(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => format!("{} → {}", self.before, self.after),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BatchError {
+    Open {
+        path: String,
+        error: io::Error,
+    },
+    Syntax {
+        path: String,
+        line_number: usize,
+        line: String,
+    },
+    // The glob pattern matches less than two files
+    TooFewFiles {
+        pattern: String,
+        found: usize,
+    },
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BatchError::Open { path, error } => {
+                write!(f, "Unable to read manifest ‘{}’: {}", path, error)
+            }
+            BatchError::Syntax {
+                path,
+                line_number,
+                line,
+            } => write!(
+                f,
+                "{}:{}: expected ‘BEFORE AFTER [LABEL]’, found ‘{}’",
+                path, line_number, line
+            ),
+            BatchError::TooFewFiles { pattern, found } => write!(
+                f,
+                "‘{}’ matches {} file{}, at least 2 are needed",
+                pattern,
+                found,
+                if *found == 1 { "" } else { "s" }
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+// The first word of `s`, and the rest of it
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    s.split_at(s.find(char::is_whitespace).unwrap_or(s.len()))
+}
+
+// `path` is only used in the errors
+pub fn parse_manifest(contents: &str, path: &str) -> Result<Vec<BatchPair>, BatchError> {
+    let mut pairs = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        // Columns may be aligned with any amount of whitespace
+        let (before, rest) = split_word(trimmed);
+        let (after, label) = split_word(rest);
+        if after.is_empty() {
+            return Err(BatchError::Syntax {
+                path: path.to_owned(),
+                line_number: i + 1,
+                line: line.to_owned(),
+            });
+        }
+        pairs.push(BatchPair {
+            before: before.to_owned(),
+            after: after.to_owned(),
+            label: Some(label.trim())
+                .filter(|l| !l.is_empty())
+                .map(str::to_owned),
+        });
+    }
+    Ok(pairs)
+}
+
+pub fn read_manifest(path: &str) -> Result<Vec<BatchPair>, BatchError> {
+    let contents = fs::read_to_string(path).map_err(|error| BatchError::Open {
+        path: path.to_owned(),
+        error,
+    })?;
+    parse_manifest(&contents, path)
+}
+
+// Whether `name` matches `pattern`, where `*` stands for any characters and `?` for any one
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|i| glob_matches(rest, &name[i..])),
+        Some((c, rest)) => match name.split_first() {
+            Some((n, name)) => (*c == '?' || c == n) && glob_matches(rest, name),
+            None => false,
+        },
+    }
+}
+
+// The files matching `pattern` in lexicographic order. Only the file name may contain wildcards,
+// and hidden files are only matched by a pattern starting with `.`.
+pub fn glob_files(pattern: &str) -> io::Result<Vec<String>> {
+    let path = Path::new(pattern);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_pattern = path
+        .file_name()
+        .map(|n| n.to_string_lossy().chars().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let hidden = name.starts_with('.') && file_pattern.first() != Some(&'.');
+        if !hidden
+            && entry.file_type()?.is_file()
+            && glob_matches(&file_pattern, &name.chars().collect::<Vec<_>>())
+        {
+            files.push(path.with_file_name(name).to_string_lossy().into_owned());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Each file of `files` paired with the next one
+pub fn consecutive_pairs(files: &[String]) -> Vec<BatchPair> {
+    files
+        .windows(2)
+        .map(|w| BatchPair::new(&w[0], &w[1]))
+        .collect()
+}
+
+// The consecutive pairs of the files matching `pattern`, see `glob_files`
+pub fn glob_pairs(pattern: &str) -> Result<Vec<BatchPair>, BatchError> {
+    let files = glob_files(pattern).map_err(|error| BatchError::Open {
+        path: pattern.to_owned(),
+        error,
+    })?;
+    if files.len() < 2 {
+        return Err(BatchError::TooFewFiles {
+            pattern: pattern.to_owned(),
+            found: files.len(),
+        });
+    }
+    Ok(consecutive_pairs(&files))
+}
+
+#[derive(Debug)]
+pub struct PairDiff {
+    pub pair: BatchPair,
+    // The changeset, or why one of the files could not be read
+    pub changeset: Result<Changeset, ReadError>,
+    // The lines of both files skipped in lenient mode
+    pub warnings: Vec<ReadError>,
+}
+
+fn diff_pair(pair: BatchPair, read_opts: &ReadOptions, diff_opts: &DiffOptions) -> PairDiff {
+    let mut warnings = Vec::new();
+    let mut read = |path: &str| {
+        read_tasks_from_with(path, read_opts).map(|outcome| {
+            warnings.extend(outcome.warnings);
+            outcome.tasks
+        })
+    };
+    let changeset = read(&pair.before).and_then(|from| {
+        let to = read(&pair.after)?;
+        Ok(compute_changeset_with(from, to, diff_opts))
+    });
+    PairDiff {
+        pair,
+        changeset,
+        warnings,
+    }
+}
+
+// Diffs each pair in turn. A pair whose files cannot be read gets an error, and the following
+// pairs are still diffed unless `fail_fast` is set, in which case it is the last result.
+pub fn diff_pairs(
+    pairs: Vec<BatchPair>,
+    read_opts: &ReadOptions,
+    diff_opts: &DiffOptions,
+    fail_fast: bool,
+) -> Vec<PairDiff> {
+    let mut diffs = Vec::with_capacity(pairs.len());
+    for pair in pairs {
+        let diff = diff_pair(pair, read_opts, diff_opts);
+        let failed = diff.changeset.is_err();
+        diffs.push(diff);
+        if failed && fail_fast {
+            break;
+        }
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let pairs = parse_manifest(
+            "# week 23\nmon.txt tue.txt\n\n  tue.txt\twed.txt  Tuesday to Wednesday \n",
+            "manifest",
+        )
+        .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                BatchPair::new("mon.txt", "tue.txt"),
+                BatchPair {
+                    label: Some("Tuesday to Wednesday".to_owned()),
+                    ..BatchPair::new("tue.txt", "wed.txt")
+                },
+            ]
+        );
+        assert_eq!(pairs[0].label(), "mon.txt → tue.txt");
+        // Aligned columns
+        assert_eq!(
+            parse_manifest(
+                "mon.txt    tue.txt  \t Monday\nwed.txt\t\tthu.txt\n",
+                "manifest"
+            )
+            .unwrap(),
+            vec![
+                BatchPair {
+                    label: Some("Monday".to_owned()),
+                    ..BatchPair::new("mon.txt", "tue.txt")
+                },
+                BatchPair::new("wed.txt", "thu.txt"),
+            ]
+        );
+        assert_eq!(
+            parse_manifest("mon.txt\n", "manifest")
+                .unwrap_err()
+                .to_string(),
+            "manifest:1: expected ‘BEFORE AFTER [LABEL]’, found ‘mon.txt’"
+        );
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        let matches = |pattern: &str, name: &str| {
+            glob_matches(
+                &pattern.chars().collect::<Vec<_>>(),
+                &name.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert!(matches("*.txt", "2018-06-01.txt"));
+        assert!(matches("todo-??.txt", "todo-01.txt"));
+        assert!(matches("*", ""));
+        assert!(!matches("*.txt", "todo.txt.bak"));
+        assert!(!matches("todo-??.txt", "todo-1.txt"));
+        assert_eq!(
+            consecutive_pairs(&["a".to_owned(), "b".to_owned(), "c".to_owned()]),
+            vec![BatchPair::new("a", "b"), BatchPair::new("b", "c")]
+        );
+    }
+}
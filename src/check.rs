@@ -0,0 +1,255 @@
+use io::{read_tasks_with, ReadError, ReadOptions};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
+use todo_txt::task::Extended as Task;
+use todo_txt::task::Recurrence;
+use todo_txt::Date as TaskDate;
+
+// What can be wrong with a line of a todo.txt file, usually making it not be read as intended
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Problem {
+    // The line is not valid UTF-8 or not a valid task
+    Unparseable,
+    // The task is completed, but has no completion date (a single date after the `x` being its
+    // creation date)
+    MissingFinishDate,
+    // The completion date (first) is before the creation date (second)
+    FinishedBeforeCreated(TaskDate, TaskDate),
+    // The value of a `rec:` tag is not a recurrence, so the tag is dropped when parsing
+    InvalidRecurrence(String),
+    // The value of a `due:` tag is not a YYYY-MM-DD date, so the tag is dropped when parsing
+    InvalidDueDate(String),
+    // The task is identical to the one on the given line
+    Duplicate(usize),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Problem::*;
+        match self {
+            Unparseable => write!(f, "unable to parse line"),
+            MissingFinishDate => write!(f, "completed task without a completion date"),
+            FinishedBeforeCreated(finish, create) => write!(
+                f,
+                "completed on {}, before being created on {}",
+                finish, create
+            ),
+            InvalidRecurrence(rec) => write!(f, "invalid recurrence ‘rec:{}’, ignored", rec),
+            InvalidDueDate(due) => write!(
+                f,
+                "invalid due date ‘due:{}’ (expected YYYY-MM-DD), ignored",
+                due
+            ),
+            Duplicate(line) => write!(f, "duplicate of the task on line {}", line),
+        }
+    }
+}
+
+// A problem of the line with the given 1-based number
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Finding {
+    pub line_number: usize,
+    pub problem: Problem,
+}
+
+// The values of the `key:value` tags of `line`, split like todo_txt does (a value starting with `/`
+// is part of the subject, eg. in a URL)
+fn tag_values<'a>(line: &'a str, key: &str) -> Vec<&'a str> {
+    line.split_whitespace()
+        .filter_map(|word| {
+            let (k, v) = word.split_once(':')?;
+            Some(v).filter(|v| k == key && !v.is_empty() && !v.starts_with('/'))
+        })
+        .collect()
+}
+
+// The problems of `task`, parsed from `line`, which is needed as the invalid tags are dropped when
+// parsing
+pub fn check_task(task: &Task, line: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if task.finished && task.finish_date.is_none() {
+        problems.push(Problem::MissingFinishDate);
+    }
+    if let (Some(finish), Some(create)) = (task.finish_date, task.create_date) {
+        if finish < create {
+            problems.push(Problem::FinishedBeforeCreated(finish, create));
+        }
+    }
+    for rec in tag_values(line, "rec") {
+        if Recurrence::from_str(rec).is_err() {
+            problems.push(Problem::InvalidRecurrence(rec.to_owned()));
+        }
+    }
+    for due in tag_values(line, "due") {
+        if TaskDate::parse_from_str(due, "%Y-%m-%d").is_err() {
+            problems.push(Problem::InvalidDueDate(due.to_owned()));
+        }
+    }
+    problems
+}
+
+// Checks the lines of a file read like `read_tasks_with` does, except that unparseable lines are
+// always reported as problems rather than errors. Findings are sorted by line.
+pub fn check_tasks<R: BufRead>(
+    reader: R,
+    path: &str,
+    opts: &ReadOptions,
+) -> Result<Vec<Finding>, ReadError> {
+    let lines = reader
+        .split(b'\n')
+        .enumerate()
+        .map(|(i, line)| {
+            line.map_err(|error| ReadError::Read {
+                path: path.to_owned(),
+                line_number: i + 1,
+                error,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let outcome = read_tasks_with(&lines.join(&b'\n')[..], path, &opts.lenient(true))?;
+
+    let mut findings = outcome
+        .warnings
+        .iter()
+        .filter_map(ReadError::line_number)
+        .map(|line_number| Finding {
+            line_number,
+            problem: Problem::Unparseable,
+        })
+        .collect::<Vec<_>>();
+    for (task, &line_number) in outcome.tasks.iter().zip(&outcome.line_numbers) {
+        let line = String::from_utf8_lossy(&lines[line_number - 1]);
+        findings.extend(check_task(task, &line).into_iter().map(|problem| Finding {
+            line_number,
+            problem,
+        }));
+    }
+    for copies in outcome.duplicates() {
+        findings.extend(copies[1..].iter().map(|&line_number| Finding {
+            line_number,
+            problem: Problem::Duplicate(copies[0]),
+        }));
+    }
+    findings.sort_by_key(|f| f.line_number);
+    Ok(findings)
+}
+
+pub fn check_tasks_from(path: &str, opts: &ReadOptions) -> Result<Vec<Finding>, ReadError> {
+    if path == "-" {
+        let stdin = io::stdin();
+        let reader = stdin.lock();
+        check_tasks(reader, "<stdin>", opts)
+    } else {
+        let file = File::open(path).map_err(|error| ReadError::Open {
+            path: path.to_owned(),
+            error,
+        })?;
+        check_tasks(BufReader::new(&file), path, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(line: &str) -> Vec<Problem> {
+        check_task(&Task::from_str(line).unwrap(), line)
+    }
+
+    fn date(s: &str) -> TaskDate {
+        TaskDate::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_valid_task() {
+        assert_eq!(
+            check("x 2018-06-20 2018-06-15 call mom due:2018-06-16 rec:+1w http://example.com"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_missing_finish_date() {
+        assert_eq!(check("x call mom"), vec![Problem::MissingFinishDate]);
+        // The only date is the creation date
+        assert_eq!(
+            check("x 2018-06-20 call mom"),
+            vec![Problem::MissingFinishDate]
+        );
+        assert_eq!(check("2018-06-20 call mom"), vec![]);
+    }
+
+    #[test]
+    fn test_finished_before_created() {
+        assert_eq!(
+            check("x 2018-06-15 2018-06-20 call mom"),
+            vec![Problem::FinishedBeforeCreated(
+                date("2018-06-15"),
+                date("2018-06-20")
+            )]
+        );
+        assert_eq!(check("x 2018-06-20 2018-06-20 call mom"), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_recurrence() {
+        assert_eq!(
+            check("call mom rec:weekly"),
+            vec![Problem::InvalidRecurrence("weekly".to_owned())]
+        );
+        assert_eq!(check("call mom rec:2w"), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_due_date() {
+        assert_eq!(
+            check("call mom due:2018-02-30"),
+            vec![Problem::InvalidDueDate("2018-02-30".to_owned())]
+        );
+        assert_eq!(
+            check("call mom due:tomorrow"),
+            vec![Problem::InvalidDueDate("tomorrow".to_owned())]
+        );
+        // Not tags
+        assert_eq!(check("call mom overdue:x due: due:/x"), vec![]);
+    }
+
+    #[test]
+    fn test_check_tasks() {
+        let input: &[u8] =
+            b"call mom\n# due:soon\nx pay rent\ndo a \xff thing\n\ncall mom\r\ncall mom rec:1\n";
+        let findings = check_tasks(input, "todo.txt", &ReadOptions::default()).unwrap();
+        assert_eq!(
+            findings,
+            vec![
+                Finding {
+                    line_number: 3,
+                    problem: Problem::MissingFinishDate,
+                },
+                Finding {
+                    line_number: 4,
+                    problem: Problem::Unparseable,
+                },
+                Finding {
+                    line_number: 6,
+                    problem: Problem::Duplicate(1),
+                },
+                Finding {
+                    line_number: 7,
+                    problem: Problem::InvalidRecurrence("1".to_owned()),
+                },
+                // Without its invalid tag, the task is the same as on line 1
+                Finding {
+                    line_number: 7,
+                    problem: Problem::Duplicate(1),
+                },
+            ]
+        );
+
+        // Comments are tasks too in strict mode
+        let findings = check_tasks(input, "todo.txt", &ReadOptions::default().strict(true));
+        assert_eq!(findings.unwrap().len(), 6);
+    }
+}
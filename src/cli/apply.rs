@@ -0,0 +1,102 @@
+use super::*;
+use apply_changes::*;
+use compute_changes::Changeset;
+use merge_changes::{merge_to_string_with_comments, MarkerOptions, MergeResult};
+use serialize_changes::deserialize_changeset;
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Read;
+
+// Exit codes, like patch's
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_REJECTED: i32 = 1;
+
+// The options of `app` taking a value, see `first_positional`
+pub const OPTIONS_WITH_VALUE: &[&str] = &[];
+
+pub fn app<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.about("Applies a changeset saved with `todiff --format json` to a todo.txt file")
+        .args_from_usage(
+            "
+            <CHANGESET>     'The changeset to apply (`-` for the standard input)'
+            <FILE>          'The file to apply it to (`-` for the standard input)'
+        ",
+        )
+        .arg(
+            clap::Arg::with_name("in-place")
+                .long("in-place")
+                .takes_value(false)
+                .help("Overwrites <FILE> with the result instead of printing it"),
+        )
+}
+
+fn read_changeset(path: &str) -> Result<Changeset, String> {
+    let mut json = String::new();
+    let read = if path == "-" {
+        std::io::stdin().read_to_string(&mut json).map(|_| ())
+    } else {
+        fs::File::open(path).and_then(|mut f| f.read_to_string(&mut json).map(|_| ()))
+    };
+    read.map_err(|e| format!("Unable to read changeset ‘{}’: {}", path, e))?;
+    deserialize_changeset(&json).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn run(matches: &clap::ArgMatches, config: &Config) -> i32 {
+    let in_place = matches.is_present("in-place");
+    let diff_options = diff_options(matches, config);
+
+    let changeset = matches.value_of("CHANGESET").expect("Internal error E001");
+    let file = matches.value_of("FILE").expect("Internal error E002");
+    check_stdin_used_once(&[changeset, file]);
+    if in_place && file == "-" {
+        exit_with(clap::Error::with_description(
+            "--in-place cannot be used when <FILE> is the standard input",
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
+    let changeset = match read_changeset(changeset) {
+        Ok(changeset) => changeset,
+        Err(e) => {
+            eprintln!("todiff: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    let [target] = match read_files("todiff", &[file], &read_options(matches, config)) {
+        Some(outcomes) => <[_; 1]>::try_from(outcomes).expect("Internal error E045"),
+        None => return EXIT_ERROR,
+    };
+    if in_place && !target.warnings.is_empty() {
+        eprintln!(
+            "todiff: refusing to overwrite ‘{}’, as its skipped lines would be lost",
+            file
+        );
+        return EXIT_ERROR;
+    }
+
+    let outcome = apply_changeset(&target.tasks, &changeset, &diff_options);
+    for e in outcome.errors.iter() {
+        eprintln!("todiff: {}", e);
+    }
+    let (anchors, tasks): (Vec<_>, Vec<_>) = outcome
+        .tasks
+        .into_iter()
+        .map(|(i, t)| (i.map(|i| target.line_numbers[i]), MergeResult::Merged(t)))
+        .unzip();
+    let output =
+        merge_to_string_with_comments(tasks, &MarkerOptions::default(), &anchors, &target.comments);
+
+    if in_place {
+        if let Err(e) = fs::write(file, output) {
+            eprintln!("todiff: Unable to write to file ‘{}’: {}", file, e);
+            return EXIT_ERROR;
+        }
+    } else {
+        println!("{}", output);
+    }
+    if outcome.errors.is_empty() {
+        EXIT_SUCCESS
+    } else {
+        EXIT_REJECTED
+    }
+}
@@ -0,0 +1,191 @@
+use super::pager::*;
+use super::*;
+use batch::*;
+use display_changes::*;
+use serialize_changes::serialize_batch;
+use std::io::{self, Write};
+
+// Exit codes, like diff's, an error for any pair taking precedence
+const EXIT_NO_CHANGES: i32 = 0;
+const EXIT_CHANGES: i32 = 1;
+
+// The options of `app` taking a value besides those of `output_args`, see `first_positional`
+pub const OPTIONS_WITH_VALUE: &[&str] = &["--glob"];
+
+pub fn app<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.about("Diffs several pairs of todo.txt files, eg. consecutive snapshots")
+        .arg(
+            clap::Arg::with_name("MANIFEST")
+                .required_unless("glob")
+                .conflicts_with("glob")
+                .help("The pairs to diff, one `BEFORE AFTER [LABEL]` per line"),
+        )
+        .arg(
+            clap::Arg::with_name("glob")
+                .long("glob")
+                .takes_value(true)
+                .value_name("PATTERN")
+                .help("Diff each file matching PATTERN (eg. ‘snapshots/*.txt’) with the next one, in lexicographic order"),
+        )
+        .arg(
+            clap::Arg::with_name("fail-fast")
+                .long("fail-fast")
+                .takes_value(false)
+                .help("Stop at the first pair whose files cannot be read"),
+        )
+        .args(&output_args())
+}
+
+// The options of `output_args` taking a value
+pub const OUTPUT_OPTIONS_WITH_VALUE: &[&str] = &["--format", "--word-diff", "--today"];
+
+// The options about how several changesets are displayed, shared with `log`
+pub fn output_args<'a, 'b>() -> Vec<clap::Arg<'a, 'b>> {
+    vec![
+        clap::Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .possible_values(&["text", "json"])
+            .default_value("text")
+            .help("Output format (json is an array of the changesets of `todiff --format json`)"),
+        clap::Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .takes_value(false)
+            .help("Only display the number of tasks in each category of each changeset"),
+        clap::Arg::with_name("word-diff")
+            .long("word-diff")
+            .takes_value(true)
+            .possible_values(&["char", "word", "none"])
+            .default_value("word")
+            .help("Granularity of the highlighted subject changes when colorizing"),
+        clap::Arg::with_name("today")
+            .long("today")
+            .takes_value(true)
+            .value_name("YYYY-MM-DD")
+            .validator(|s| {
+                s.parse::<chrono::NaiveDate>()
+                    .map(|_| ())
+                    .map_err(|e| format!("{}", e))
+            })
+            .help("The date new due dates are compared to [default: the current date]"),
+    ]
+}
+
+// How several changesets are computed and displayed, from the options of `output_args`
+pub fn batch_output_options<'a>(
+    matches: &'a clap::ArgMatches,
+    config: &Config,
+) -> OutputOptions<'a> {
+    let options = output_options(matches, config, &ColorEnvironment::from_env());
+    if options.format == "json" && matches.is_present("quiet") {
+        exit_with(clap::Error::with_description(
+            "--quiet cannot be used with --format json",
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
+    options
+}
+
+// Prints a section per changeset and their total, or else them all as JSON. `unit` is what each
+// of `diffs` is, eg. `pair`. Returns the exit code.
+pub fn print_diffs(
+    diffs: &[PairDiff],
+    unit: &str,
+    matches: &clap::ArgMatches,
+    options: &OutputOptions,
+) -> i32 {
+    let display_options = &options.display;
+    let mut failed = 0;
+    let mut total = ChangesetCounts::default();
+    // batch and log have no pager options, so their output is streamed to the standard output
+    let mut render = |mut output: &mut dyn Write| {
+        for diff in diffs.iter() {
+            for w in diff.warnings.iter() {
+                eprintln!("todiff: warning: {} (skipped)", w);
+            }
+            let label = diff.pair.label();
+            let (new_tasks, changes) = match &diff.changeset {
+                Ok(changeset) => changeset.clone(),
+                Err(e) => {
+                    eprintln!("todiff: {}: {}", label, e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            let counts = count_changeset_with(new_tasks.clone(), changes.clone(), display_options);
+            total += counts;
+            match options.format {
+                "json" => (),
+                _ if matches.is_present("quiet") => writeln!(output, "{}: {}", label, counts)?,
+                _ => {
+                    // Unlabelled pairs are already named after their files
+                    let opts = match diff.pair.label {
+                        Some(_) => display_options
+                            .clone()
+                            .labels(&diff.pair.before, &diff.pair.after),
+                        None => display_options.clone(),
+                    };
+                    writeln!(output, "=== {} ===\n", label)?;
+                    display_changeset_to_with(&mut output, new_tasks, changes, &opts)?;
+                    writeln!(output)?;
+                }
+            }
+        }
+        if options.format == "json" {
+            writeln!(output, "{}", serialize_batch(diffs))
+        } else {
+            writeln!(
+                output,
+                "Total over {} {}{}{}: {}",
+                diffs.len(),
+                unit,
+                if diffs.len() == 1 { "" } else { "s" },
+                if failed > 0 {
+                    format!(" ({} failed)", failed)
+                } else {
+                    String::new()
+                },
+                total.detailed()
+            )
+        }
+    };
+    let pager = ProcessPager {
+        option: PagerOption::Never,
+    };
+    if let Err(e) = write_output(&mut render, None, &pager, io::stdout().lock()) {
+        eprintln!("todiff: Unable to write the output: {}", e);
+        return EXIT_ERROR;
+    }
+
+    if failed > 0 {
+        EXIT_ERROR
+    } else if !total.is_empty() {
+        EXIT_CHANGES
+    } else {
+        EXIT_NO_CHANGES
+    }
+}
+
+pub fn run(matches: &clap::ArgMatches, config: &Config) -> i32 {
+    let options = batch_output_options(matches, config);
+    let pairs = match (matches.value_of("MANIFEST"), matches.value_of("glob")) {
+        (Some(manifest), _) => read_manifest(manifest),
+        (None, Some(pattern)) => glob_pairs(pattern),
+        (None, None) => panic!("Internal error E049"),
+    };
+    let pairs = match pairs {
+        Ok(pairs) => pairs,
+        Err(e) => {
+            eprintln!("todiff: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    let diffs = diff_pairs(
+        pairs,
+        &read_options(matches, config),
+        &options.diff,
+        matches.is_present("fail-fast"),
+    );
+    print_diffs(&diffs, "pair", matches, &options)
+}
@@ -0,0 +1,53 @@
+use super::*;
+use check::*;
+use serialize_changes::serialize_findings;
+
+// Exit codes, like diff's
+const EXIT_CLEAN: i32 = 0;
+const EXIT_FINDINGS: i32 = 1;
+
+// The options of `app` taking a value, see `first_positional`
+pub const OPTIONS_WITH_VALUE: &[&str] = &["--format"];
+
+pub fn app<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.about("Reports the lines of a todo.txt file that are likely not read as intended")
+        .args_from_usage(
+            "
+            <FILE>      'The file to check (`-` for the standard input)'
+        ",
+        )
+        .arg(
+            clap::Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Output format of the findings"),
+        )
+}
+
+pub fn run(matches: &clap::ArgMatches, config: &Config) -> i32 {
+    let path = matches.value_of("FILE").expect("Internal error E001");
+    let findings = match check_tasks_from(path, &read_options(matches, config)) {
+        Ok(findings) => findings,
+        Err(e) => {
+            eprintln!("todiff: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+
+    let path = if path == "-" { "<stdin>" } else { path };
+    if matches.value_of("format") == Some("json") {
+        println!("{}", serialize_findings(path, &findings));
+    } else {
+        for f in findings.iter() {
+            println!("{}:{}: {}", path, f.line_number, f.problem);
+        }
+    }
+
+    if findings.is_empty() {
+        EXIT_CLEAN
+    } else {
+        EXIT_FINDINGS
+    }
+}
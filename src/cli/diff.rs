@@ -0,0 +1,758 @@
+use super::pager::*;
+use super::*;
+use anonymize::Anonymizer;
+use color_policy::*;
+use compute_changes::*;
+use display_changes::*;
+use fail_on::*;
+use filter_changes::*;
+use io::{open_list, read_residual_tasks_with};
+use pin_matches::*;
+use progress::*;
+use serialize_changes::*;
+use stats::compute_stats;
+use template::{read_template, render_template};
+
+use std::fs;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+// Exit codes, following the conventions of diff(1)
+const EXIT_NO_CHANGES: i32 = 0;
+const EXIT_CHANGES: i32 = 1;
+// Changes of a kind given to --fail-on were found
+const EXIT_FAIL_ON: i32 = 3;
+
+// Above this many bytes in both files, diffs take long enough for progress to be shown on a
+// terminal without --progress
+const PROGRESS_THRESHOLD: usize = 256 * 1024;
+
+// The phase the diff is in, on a single line of the standard error that is overwritten as it
+// goes, and cleared before anything else is printed there
+#[derive(Default)]
+struct ProgressLine {
+    // The phase and percentage last shown, and the width of the line
+    last: Mutex<(Option<Phase>, usize, usize)>,
+}
+
+impl ProgressLine {
+    fn update(&self, phase: Phase, done: usize, total: usize) {
+        let percent = std::cmp::min(done, total) * 100 / std::cmp::max(total, 1);
+        let mut last = self.last.lock().expect("Internal error E064");
+        if last.0 == Some(phase) && last.1 == percent {
+            return;
+        }
+        let text = match phase {
+            // The size of the standard input is unknown
+            Phase::Parsing if total == 0 => "todiff: parsing".to_owned(),
+            Phase::Parsing => format!("todiff: parsing {}%", percent),
+            Phase::Preferences => format!("todiff: ranking candidates {}/{}", done, total),
+            Phase::Proposals => format!("todiff: matching {}/{}", done, total),
+        };
+        eprint!("\r{:width$}", text, width = last.2);
+        *last = (Some(phase), percent, text.chars().count());
+    }
+
+    fn clear(&self) {
+        let mut last = self.last.lock().expect("Internal error E065");
+        if last.0.is_some() {
+            eprint!("\r{:width$}\r", "", width = last.2);
+        }
+        *last = (None, 0, 0);
+    }
+}
+
+// The options of `app` taking a value, see `first_positional`
+pub const OPTIONS_WITH_VALUE: &[&str] = &[
+    "--word-diff",
+    "--group-by",
+    "--format",
+    "--template",
+    "--today",
+    "--date-format",
+    "--before-label",
+    "--after-label",
+    "--hyperlinks",
+    "--link-tag",
+    "--width",
+    "--only",
+    "--hide",
+    "--fail-on",
+    "--ignore",
+    "--filter",
+    "--filter-not",
+    "--done-after",
+    "--done-before",
+    "--pairs",
+];
+
+pub fn app<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.about("Diffs two todo.txt files")
+        .args_from_usage("
+            <BEFORE>        'The file to diff from (`-` for the standard input)'
+            <AFTER>         'The file to diff to (`-` for the standard input)'
+        ")
+        .arg(clap::Arg::with_name("word-diff")
+            .long("word-diff")
+            .takes_value(true)
+            .possible_values(&["char", "word", "none"])
+            .default_value("word")
+            .help("Granularity of the highlighted subject changes when colorizing"))
+        .arg(clap::Arg::with_name("group-by")
+            .long("group-by")
+            .takes_value(true)
+            .possible_values(&["project", "context"])
+            .help("Cluster the tasks of each section by project or context"))
+        .arg(clap::Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .possible_values(&["text", "json", "html"])
+            .default_value("text")
+            .help("Output format (json is meant to be parsed by scripts, html to be included in a page)"))
+        .arg(clap::Arg::with_name("template")
+            .long("template")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with_all(&["porcelain", "quiet"])
+            .help("Display the changes as laid out by the template in FILE (see the README for its syntax)"))
+        .arg(clap::Arg::with_name("porcelain")
+            .long("porcelain")
+            .takes_value(false)
+            .help("Display one line per task in a stable format, meant to be parsed by scripts"))
+        .arg(clap::Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .visible_alias("summary")
+            .takes_value(false)
+            .conflicts_with("porcelain")
+            .help("Only display the number of tasks in each category"))
+        .arg(clap::Arg::with_name("brief")
+            .long("brief")
+            .takes_value(false)
+            .conflicts_with_all(&["porcelain", "quiet", "template", "only", "hide"])
+            .help("Only report whether the files differ, like diff --brief"))
+        .arg(clap::Arg::with_name("summary-line")
+            .long("summary-line")
+            .takes_value(false)
+            .help("End the output with the number of tasks in each section"))
+        .arg(negation("summary-line", "no-summary-line"))
+        .arg(clap::Arg::with_name("today")
+            .long("today")
+            .takes_value(true)
+            .value_name("YYYY-MM-DD")
+            .validator(|s| s.parse::<chrono::NaiveDate>().map(|_| ()).map_err(|e| format!("{}", e)))
+            .help("The date new due dates are compared to [default: the current date]"))
+        .arg(clap::Arg::with_name("date-format")
+            .long("date-format")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .validator(|s| s.parse::<DateFormat>().map(|_| ()))
+            .help("How dates are displayed: a strftime format (eg. ‘%d %b %Y’) or ‘relative’ to --today [default: %Y-%m-%d]"))
+        .arg(clap::Arg::with_name("before-label")
+            .long("before-label")
+            .takes_value(true)
+            .value_name("LABEL")
+            .help("The name of <BEFORE> in the header [default: its path]"))
+        .arg(clap::Arg::with_name("after-label")
+            .long("after-label")
+            .takes_value(true)
+            .value_name("LABEL")
+            .help("The name of <AFTER> in the header [default: its path]"))
+        .arg(clap::Arg::with_name("no-header")
+            .long("no-header")
+            .takes_value(false)
+            .conflicts_with_all(&["before-label", "after-label"])
+            .help("Do not start the output with the names of the compared files"))
+        .arg(clap::Arg::with_name("show-line-numbers")
+            .long("show-line-numbers")
+            .takes_value(false)
+            .help("Prefix each task with its line numbers in <BEFORE> and <AFTER>"))
+        .arg(clap::Arg::with_name("show-moves")
+            .long("show-moves")
+            .takes_value(false)
+            .help("Also list the unchanged tasks that were reordered (this does not change the exit code)"))
+        .arg(clap::Arg::with_name("show-unchanged")
+            .long("show-unchanged")
+            .takes_value(false)
+            .help("Also list the unchanged tasks (this does not change the exit code)"))
+        .arg(clap::Arg::with_name("show-scores")
+            .long("show-scores")
+            .takes_value(false)
+            .help("Follow each changed task with how similar it is to what it became"))
+        .arg(clap::Arg::with_name("stats")
+            .long("stats")
+            .takes_value(false)
+            .help("End the output with aggregates such as the average time to complete a task and the days of postponement"))
+        .arg(clap::Arg::with_name("fancy")
+            .long("fancy")
+            .takes_value(false)
+            .help("Highlight the priority, dates, projects and contexts within colorized task lines"))
+        .arg(negation("fancy", "no-fancy"))
+        .arg(clap::Arg::with_name("hyperlinks")
+            .long("hyperlinks")
+            .takes_value(true)
+            .possible_values(&["auto", "always", "never"])
+            .default_value("auto")
+            .help("Make the URLs of colorized task lines clickable"))
+        .arg(clap::Arg::with_name("link-tag")
+            .long("link-tag")
+            .takes_value(true)
+            .value_name("TAG")
+            .default_value("url")
+            .help("Tag whose value is made clickable along with the URLs"))
+        .arg(clap::Arg::with_name("width")
+            .long("width")
+            .takes_value(true)
+            .value_name("N")
+            .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| format!("{}", e)))
+            .help("Wrap the task lines to N columns, 0 meaning no wrapping [default: the terminal width]"))
+        .arg(clap::Arg::with_name("only")
+            .long("only")
+            .takes_value(true)
+            .multiple(true)
+            .require_delimiter(true)
+            .value_name("SECTIONS")
+            .possible_values(Section::NAMES)
+            .conflicts_with("hide")
+            .help("Only display these comma-separated sections"))
+        .arg(clap::Arg::with_name("hide")
+            .long("hide")
+            .takes_value(true)
+            .multiple(true)
+            .require_delimiter(true)
+            .value_name("SECTIONS")
+            .possible_values(Section::NAMES)
+            .help("Do not display these comma-separated sections"))
+        .arg(clap::Arg::with_name("ignore-new")
+            .long("ignore-new")
+            .takes_value(false)
+            .help("Leave the new tasks out of the output and the exit code, like --hide new"))
+        .arg(clap::Arg::with_name("fail-on")
+            .long("fail-on")
+            .takes_value(true)
+            .multiple(true)
+            .require_delimiter(true)
+            .value_name("KINDS")
+            .possible_values(ChangeKind::NAMES)
+            .help("Exit with 3 if any change of these comma-separated kinds (sections or porcelain keywords) is displayed"))
+        .arg(clap::Arg::with_name("ignore")
+            .long("ignore")
+            .takes_value(true)
+            .multiple(true)
+            .require_delimiter(true)
+            .value_name("FIELDS")
+            .possible_values(Field::NAMES)
+            .help("Do not report changes to these comma-separated fields"))
+        .arg(clap::Arg::with_name("filter")
+            .long("filter")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("PATTERN")
+            .validator(|s| s.parse::<TaskPattern>().map(|_| ()))
+            .help("Only report tasks with this +project, @context or substring, before or after the change (can be repeated)"))
+        .arg(clap::Arg::with_name("filter-not")
+            .long("filter-not")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("PATTERN")
+            .validator(|s| s.parse::<TaskPattern>().map(|_| ()))
+            .help("Do not report tasks with this +project, @context or substring (can be repeated)"))
+        .arg(clap::Arg::with_name("done-after")
+            .long("done-after")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("The done.txt matching <AFTER>, where deleted tasks are looked for in case they were completed and archived"))
+        .arg(clap::Arg::with_name("done-before")
+            .long("done-before")
+            .takes_value(true)
+            .value_name("FILE")
+            .requires("done-after")
+            .help("The done.txt matching <BEFORE>, whose tasks are not looked for in --done-after as they were archived earlier"))
+        .arg(clap::Arg::with_name("loose-recurrence")
+            .long("loose-recurrence")
+            .takes_value(false)
+            .help("Consider similar new tasks as occurrences of recurring tasks even if their due date does not match the recurrence"))
+        .arg(negation("loose-recurrence", "no-loose-recurrence"))
+        .arg(clap::Arg::with_name("detect-splits")
+            .long("detect-splits")
+            .takes_value(false)
+            .conflicts_with("exact")
+            .help("Report tasks replaced by several tasks that each do part of them"))
+        .arg(negation("detect-splits", "no-detect-splits"))
+        .arg(clap::Arg::with_name("detect-copies")
+            .long("detect-copies")
+            .takes_value(false)
+            .conflicts_with("exact")
+            .help("Report tasks copied into several variants of them, rather than new tasks"))
+        .arg(negation("detect-copies", "no-detect-copies"))
+        .arg(clap::Arg::with_name("granular")
+            .long("granular")
+            .takes_value(false)
+            .help("Report each changed field on its own, eg. a completion date set with the completion rather than in it, as scripts reading --format json may prefer"))
+        .arg(negation("granular", "no-granular"))
+        .arg(clap::Arg::with_name("pairs")
+            .long("pairs")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Pin tasks together with `BEFORE ||| AFTER` or `LINE -> LINE` lines, or forbid them from being matched with `!SUBJECT` lines"))
+        .arg(clap::Arg::with_name("anonymize")
+            .long("anonymize")
+            .takes_value(false)
+            .conflicts_with_all(&["pairs", "filter", "filter-not"])
+            .help("Replace the words of the tasks with pseudo-words before diffing, to share a reproducer of a bug without disclosing them"))
+        .arg(clap::Arg::with_name("progress")
+            .long("progress")
+            .takes_value(false)
+            .help("Show how far along the diff is on the standard error [default: when it is a terminal and the files are large]"))
+        .arg(clap::Arg::with_name("paginate")
+            .long("paginate")
+            .short("p")
+            .takes_value(false)
+            .conflicts_with("no-pager")
+            .help("Show the output through $TODIFF_PAGER or $PAGER (default: less -RX), keeping it open even if the output fits in the terminal"))
+        .arg(clap::Arg::with_name("no-pager")
+            .long("no-pager")
+            .takes_value(false)
+            .help("Never show the output through a pager [default: $TODIFF_PAGER or $PAGER, or else less -FRX, which quits if the output fits in the terminal]"))
+        .arg(clap::Arg::with_name("explain")
+            .long("explain")
+            .takes_value(false)
+            .help("Tell on the standard error why each task was matched with another one or not"))
+}
+
+pub fn run(matches: &clap::ArgMatches, config: &Config) -> i32 {
+    let given = |name| given(matches, name);
+    let flag = |name, configured| flag(matches, name, configured);
+
+    let color_env = ColorEnvironment::from_env();
+    let OutputOptions {
+        format: format_option,
+        display: display_options,
+        diff: diff_options,
+    } = output_options(matches, config, &color_env);
+    let porcelain = matches.is_present("porcelain");
+    let quiet = matches.is_present("quiet");
+    let brief = matches.is_present("brief");
+    if format_option != "text" && (porcelain || quiet || brief || matches.is_present("template")) {
+        exit_with(clap::Error::with_description(
+            &format!(
+                "--porcelain, --quiet, --brief and --template cannot be used with --format {}",
+                format_option
+            ),
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
+    for option in [
+        "show-line-numbers",
+        "show-moves",
+        "show-unchanged",
+        "show-scores",
+        "fancy",
+    ] {
+        if (format_option != "text" || porcelain || quiet || brief) && matches.is_present(option) {
+            exit_with(clap::Error::with_description(
+                &format!("--{} can only be used with the default output", option),
+                clap::ErrorKind::ArgumentConflict,
+            ));
+        }
+    }
+    if (format_option == "html" || porcelain || quiet || brief) && matches.is_present("stats") {
+        exit_with(clap::Error::with_description(
+            "--stats can only be used with the default output or --format json",
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
+    if format_option == "json"
+        && (matches.is_present("only")
+            || matches.is_present("hide")
+            || matches.is_present("ignore-new"))
+    {
+        exit_with(clap::Error::with_description(
+            "--only, --hide and --ignore-new cannot be used with --format json",
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
+    let colorize = display_options.colorize;
+    let hyperlinks_option = match config.hyperlinks {
+        Some(hyperlinks) if !given("hyperlinks") => hyperlinks,
+        _ => matches
+            .value_of("hyperlinks")
+            .expect("Internal error E066")
+            .parse::<ColorOption>()
+            .expect("Internal error E077"),
+    };
+    let link_tag = match config.link_tag {
+        Some(ref link_tag) if !given("link-tag") => link_tag,
+        _ => matches.value_of("link-tag").expect("Internal error E067"),
+    };
+    let display_options = display_options
+        .summary_line(flag("summary-line", config.summary_line))
+        .show_moves(matches.is_present("show-moves"))
+        .show_unchanged(matches.is_present("show-unchanged"))
+        .show_scores(matches.is_present("show-scores"))
+        .show_stats(matches.is_present("stats"))
+        .fancy_tasks(flag("fancy", config.fancy))
+        .hyperlinks(should_hyperlink(hyperlinks_option, colorize, &color_env))
+        .link_tag(link_tag);
+    let sections = |name| -> Vec<Section> {
+        matches
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .map(|s| s.parse().expect("Internal error E024"))
+            .collect()
+    };
+    let hidden_sections = match matches.values_of("only") {
+        Some(_) => {
+            let shown = sections("only");
+            Section::NAMES
+                .iter()
+                .map(|s| s.parse().expect("Internal error E025"))
+                .filter(|s| !shown.contains(s))
+                .collect()
+        }
+        None => sections("hide"),
+    };
+    let ignored_sections = Some(Section::New).filter(|_| matches.is_present("ignore-new"));
+    let display_options = hidden_sections
+        .into_iter()
+        .chain(ignored_sections)
+        .fold(display_options, DisplayOptions::hide);
+
+    let patterns = |name| -> Vec<TaskPattern> {
+        matches
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .map(|p| p.parse().expect("Internal error E023"))
+            .collect()
+    };
+    let fail_on = matches
+        .values_of("fail-on")
+        .into_iter()
+        .flatten()
+        .map(|k| k.parse::<ChangeKind>().expect("Internal error E076"))
+        .collect::<Vec<_>>();
+
+    let filter = TaskFilter {
+        include: patterns("filter"),
+        exclude: patterns("filter-not"),
+    };
+    let diff_options = matches
+        .values_of("ignore")
+        .into_iter()
+        .flatten()
+        .map(|f| f.parse::<Field>().expect("Internal error E018"))
+        .fold(
+            diff_options
+                .loose_recurrence(flag("loose-recurrence", config.loose_recurrence))
+                .detect_splits(flag("detect-splits", config.detect_splits))
+                .detect_copies(flag("detect-copies", config.detect_copies))
+                .granular(flag("granular", config.granular))
+                .filter(filter),
+            DiffOptions::ignore,
+        );
+
+    let template = match matches.value_of("template").map(read_template) {
+        Some(Ok(template)) => Some(template),
+        Some(Err(e)) => {
+            eprintln!("todiff: {}", e);
+            return EXIT_ERROR;
+        }
+        None => None,
+    };
+    let diff_options = match matches.value_of("pairs").map(read_pins) {
+        Some(Ok(pins)) => diff_options.pins(pins),
+        Some(Err(e)) => {
+            eprintln!("todiff: {}", e);
+            return EXIT_ERROR;
+        }
+        None => diff_options,
+    };
+
+    // Read files
+    let before = matches.value_of("BEFORE").expect("Internal error E001");
+    let after = matches.value_of("AFTER").expect("Internal error E002");
+    let done_before = matches.value_of("done-before");
+    let done_after = matches.value_of("done-after");
+    check_stdin_used_once(
+        &[Some(before), Some(after), done_before, done_after]
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>(),
+    );
+    let label = |name, path| match matches.value_of(name) {
+        Some(label) => label,
+        None if path == "-" => "<stdin>",
+        None => path,
+    };
+    let display_options = if matches.is_present("no-header") {
+        display_options
+    } else {
+        display_options.labels(label("before-label", before), label("after-label", after))
+    };
+    let path_name = |path| if path == "-" { "<stdin>" } else { path };
+    let display_options = if matches.is_present("show-line-numbers") {
+        display_options.line_numbers(path_name(before), path_name(after))
+    } else {
+        display_options
+    };
+    let read_options = read_options(matches, config);
+    let sizes = [before, after].map(|path| fs::metadata(path).map_or(0, |m| m.len() as usize));
+    let progress_line = Some(Arc::new(ProgressLine::default())).filter(|_| {
+        matches.is_present("progress")
+            || (atty::is(atty::Stream::Stderr) && sizes[0] + sizes[1] > PROGRESS_THRESHOLD)
+    });
+    let progress = match progress_line {
+        Some(ref line) => {
+            let line = line.clone();
+            Progress::new(move |phase, done, total| line.update(phase, done, total))
+        }
+        None => Progress::default(),
+    };
+    let diff_options = diff_options.progress(progress.clone());
+    let total_size = if before == "-" || after == "-" {
+        0
+    } else {
+        sizes[0] + sizes[1]
+    };
+    // Both need all the tasks, and anonymizing them in another order would change the output
+    let set_aside = !matches.is_present("anonymize") && !matches.is_present("explain");
+    let residual = open_list(before).and_then(|(before, before_name)| {
+        let (after, after_name) = open_list(after)?;
+        read_residual_tasks_with(
+            ProgressReader::new(before, 0, total_size, progress.clone()),
+            ProgressReader::new(after, sizes[0], total_size, progress),
+            [before_name, after_name],
+            &read_options,
+            |line| set_aside && may_set_aside(line, &diff_options),
+        )
+    });
+    let clear_progress = || {
+        if let Some(ref line) = progress_line {
+            line.clear();
+        }
+    };
+    clear_progress();
+    let residual = match residual {
+        Ok(residual) => residual,
+        Err(e) => {
+            eprintln!("todiff: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    for w in residual
+        .before
+        .warnings
+        .iter()
+        .chain(&residual.after.warnings)
+    {
+        eprintln!("todiff: warning: {} (skipped)", w);
+    }
+
+    let [before_duplicates, after_duplicates] = residual.duplicates();
+    for (path, duplicates) in [(before, before_duplicates), (after, after_duplicates)] {
+        for lines in duplicates {
+            let copies = lines[1..].iter().map(usize::to_string).collect::<Vec<_>>();
+            eprintln!(
+                "todiff: warning: {}:{}: task duplicated on line{} {}",
+                path_name(path),
+                lines[0],
+                if copies.len() > 1 { "s" } else { "" },
+                copies.join(", ")
+            );
+        }
+    }
+    // Tasks that were already archived before are not candidates for completion in between
+    let mut archived = Vec::new();
+    let mut previously_archived = Vec::new();
+    for (path, list) in [
+        (done_after, &mut archived),
+        (done_before, &mut previously_archived),
+    ] {
+        if let Some(path) = path {
+            match read_files("todiff", &[path], &read_options) {
+                Some(mut outcomes) => *list = outcomes.remove(0).tasks,
+                None => return EXIT_ERROR,
+            }
+        }
+    }
+    remove_common_by_key(&mut previously_archived, &mut archived, |t| {
+        t.subject.as_str()
+    });
+    let (mut from, mut to) = (residual.before, residual.after);
+    if matches.is_present("anonymize") {
+        let mut anonymizer = Anonymizer::new();
+        for tasks in [&mut from.tasks, &mut to.tasks, &mut archived] {
+            *tasks = anonymizer.tasks(tasks);
+        }
+    }
+    let (from, to) = (from.indexed(), to.indexed());
+    if let Err(e) = diff_options.pins.check(&from, &to) {
+        eprintln!("todiff: {}", e);
+        return EXIT_ERROR;
+    }
+    if diff_options.reduces_matching(from.len(), to.len()) {
+        eprintln!(
+            "todiff: warning: more than {} tasks to match on each side, only comparing tasks \
+             with the same {} (see --max-fuzzy-candidates)",
+            diff_options.max_fuzzy_candidates.unwrap_or_default(),
+            match diff_options.fuzzy_partition {
+                FuzzyPartition::FirstWord => "first word",
+                FuzzyPartition::Project => "project",
+            }
+        );
+    }
+    let explain = matches.is_present("explain");
+    if brief && display_options.hidden_sections.is_empty() && fail_on.is_empty() && !explain {
+        // Tasks that were archived only change how the others are reported
+        let strip = |tasks: Vec<(usize, _)>| tasks.into_iter().map(|(_, t)| t).collect();
+        let has_changes = has_changes_with(strip(from), strip(to), &diff_options);
+        clear_progress();
+        if !has_changes {
+            return EXIT_NO_CHANGES;
+        }
+        println!(
+            "Files {} and {} differ",
+            path_name(before),
+            path_name(after)
+        );
+        return EXIT_CHANGES;
+    }
+    let ((new_tasks, changes), explanation) = compute_changeset_residual_with(
+        from,
+        to,
+        residual.identical,
+        archived,
+        &diff_options,
+        explain,
+    );
+    clear_progress();
+    if let Some(explanation) = explanation {
+        eprint!("{}", display_explanation(&explanation));
+    }
+    // Only the default output displays line numbers
+    let (new_lines, new_tasks): (Vec<_>, Vec<_>) = new_tasks.into_iter().unzip();
+    let has_changes = if display_options.hidden_sections.is_empty() {
+        changeset_has_changes(&new_tasks, &changes)
+    } else {
+        !count_changeset_with(new_tasks.clone(), changes.clone(), &display_options).is_empty()
+    };
+    // Nothing is displayed then, not even the header
+    let only_hidden_changes =
+        !has_changes && !display_options.show_stats && changeset_has_changes(&new_tasks, &changes);
+    let found_kinds = if fail_on.is_empty() {
+        Vec::new()
+    } else {
+        let report =
+            categorize_changeset_with(new_tasks.clone(), changes.clone(), &display_options);
+        find_kinds(&fail_on, &report)
+    };
+    let pager_option = if matches.is_present("no-pager") {
+        PagerOption::Never
+    } else if matches.is_present("paginate") {
+        PagerOption::Always
+    } else {
+        PagerOption::Auto
+    };
+    let command = Some(pager_option)
+        .filter(|&option| should_page(option, color_env.stdout_is_tty))
+        .and_then(pager_command_from_env);
+    // Rendered once, be it to the pager or to the standard output
+    let mut rendered = Some((new_lines, new_tasks, changes));
+    let mut render = |mut output: &mut dyn Write| {
+        let (new_lines, new_tasks, changes) = rendered.take().expect("Internal error E068");
+        match format_option {
+            "text" if brief => {
+                if has_changes {
+                    writeln!(
+                        output,
+                        "Files {} and {} differ",
+                        path_name(before),
+                        path_name(after)
+                    )
+                } else {
+                    Ok(())
+                }
+            }
+            "text" if quiet => write!(
+                output,
+                "{}",
+                display_changeset_summary_with(new_tasks, changes, &display_options)
+            ),
+            "text" if template.is_some() => {
+                let new_tasks = new_lines.into_iter().zip(new_tasks).collect();
+                let report =
+                    categorize_changeset_indexed_with(new_tasks, changes, &display_options);
+                let template = template.as_ref().expect("Internal error E075");
+                write!(
+                    output,
+                    "{}",
+                    render_template(template, &report, &display_options)
+                )
+            }
+            "text" if porcelain => write!(
+                output,
+                "{}",
+                display_changeset_porcelain_with(new_tasks, changes, &display_options)
+            ),
+            "text" if only_hidden_changes => Ok(()),
+            "text" => {
+                let new_tasks = new_lines.into_iter().zip(new_tasks).collect();
+                display_changeset_indexed_to_with(&mut output, new_tasks, changes, &display_options)
+                    .and_then(|()| writeln!(output))
+            }
+            "html" => write!(
+                output,
+                "{}",
+                display_changeset_html_with(new_tasks, changes, &display_options)
+            ),
+            "json" => {
+                let labels = display_options
+                    .labels
+                    .as_ref()
+                    .map(|(a, b)| (a.as_str(), b.as_str()));
+                let stats = if display_options.show_stats {
+                    let report = categorize_changeset_with(
+                        new_tasks.clone(),
+                        changes.clone(),
+                        &display_options,
+                    );
+                    Some(compute_stats(&report))
+                } else {
+                    None
+                };
+                writeln!(
+                    output,
+                    "{}",
+                    serialize_changeset_with_stats(new_tasks, changes, labels, stats.as_ref())
+                )
+            }
+            _ => panic!("Internal error E015"),
+        }
+    };
+    let stdout = io::stdout();
+    let pager = ProcessPager {
+        option: pager_option,
+    };
+    if let Err(e) = write_output(&mut render, command, &pager, stdout.lock()) {
+        eprintln!("todiff: Unable to write the output: {}", e);
+        return EXIT_ERROR;
+    }
+
+    if !found_kinds.is_empty() {
+        let names = found_kinds.iter().map(ChangeKind::name).collect::<Vec<_>>();
+        eprintln!(
+            "todiff: found changes of the kinds given to --fail-on: {}",
+            names.join(", ")
+        );
+        EXIT_FAIL_ON
+    } else if has_changes {
+        EXIT_CHANGES
+    } else {
+        EXIT_NO_CHANGES
+    }
+}
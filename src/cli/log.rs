@@ -0,0 +1,71 @@
+use super::batch::{batch_output_options, output_args, print_diffs};
+use super::*;
+use git_log::*;
+
+// The options of `app` taking a value besides those of `output_args`, see `first_positional`
+pub const OPTIONS_WITH_VALUE: &[&str] = &["--since", "--max"];
+
+pub fn app<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.about("Diffs a todo.txt file across the git commits that touched it")
+        .args_from_usage(
+            "
+            <FILE>      'The file, in a git repository'
+        ",
+        )
+        .arg(
+            clap::Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("YYYY-MM-DD")
+                .validator(|s| {
+                    s.parse::<chrono::NaiveDate>()
+                        .map(|_| ())
+                        .map_err(|e| format!("{}", e))
+                })
+                .help("Only the commits of this day or later"),
+        )
+        .arg(
+            clap::Arg::with_name("max")
+                .long("max")
+                .takes_value(true)
+                .value_name("N")
+                .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help("Only the N most recent commits"),
+        )
+        .args(&output_args())
+}
+
+pub fn run(matches: &clap::ArgMatches, config: &Config) -> i32 {
+    let options = batch_output_options(matches, config);
+    let path = matches.value_of("FILE").expect("Internal error E001");
+    let mut log_options = LogOptions::default();
+    if let Some(since) = matches.value_of("since") {
+        log_options = log_options.since(since.parse().expect("Internal error E050"));
+    }
+    if let Some(max) = matches.value_of("max") {
+        log_options = log_options.max(max.parse().expect("Internal error E051"));
+    }
+    let entries = match log_changesets(
+        path,
+        &log_options,
+        &read_options(matches, config),
+        &options.diff,
+    ) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("todiff: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+
+    let mut diffs = Vec::new();
+    for entry in entries {
+        match entry {
+            LogEntry::Diff(diff) => diffs.push(diff),
+            LogEntry::Skipped { commit, reason } => {
+                eprintln!("todiff: skipping {}: {}", commit.label(), reason)
+            }
+        }
+    }
+    print_diffs(&diffs, "commit", matches, &options)
+}
@@ -0,0 +1,154 @@
+use super::*;
+use merge_changes::*;
+
+use std::convert::TryFrom;
+use std::fs;
+
+// Exit codes, as expected from a git merge driver for the first two
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_CONFLICTS: i32 = 1;
+
+// The options of `app` taking a value, see `first_positional`
+pub const OPTIONS_WITH_VALUE: &[&str] = &[
+    "--strategy",
+    "--marker-size",
+    "--label-ours",
+    "--label-base",
+    "--label-theirs",
+];
+
+pub fn app<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+    app.about("Performs a 3-way merge of todo.txt files")
+        .args_from_usage("
+            <ANCESTOR>      'The original file (`-` for the standard input)'
+            <CURRENT>       'The first file to merge (`-` for the standard input)'
+            <OTHER>         'The second file to merge (`-` for the standard input)'
+        ")
+        .arg(clap::Arg::with_name("strategy")
+             .long("strategy")
+             .takes_value(true)
+             .possible_values(&["conflict", "ours", "theirs", "union"])
+             .default_value("conflict")
+             .help("How to handle conflicting changes: leave conflict markers, keep the <CURRENT> or <OTHER> tasks, or keep both"))
+        .arg(clap::Arg::with_name("marker-size")
+             .long("marker-size")
+             .takes_value(true)
+             .validator(|s| s.parse::<usize>()
+                             .map_err(|e| format!("{}", e))
+                             .and_then(|x| if x > 0 { Ok(()) }
+                                           else { Err("must be positive".to_owned()) }))
+             .default_value("7")
+             .help("Number of characters of the conflict markers (git passes it as %L)"))
+        .arg(clap::Arg::with_name("label-ours")
+             .long("label-ours")
+             .takes_value(true)
+             .value_name("LABEL")
+             .help("Label of the <CURRENT> side of conflicts (defaults to its file name)"))
+        .arg(clap::Arg::with_name("label-base")
+             .long("label-base")
+             .takes_value(true)
+             .value_name("LABEL")
+             .help("Label of the <ANCESTOR> side of conflicts with --diff3 (defaults to its file name)"))
+        .arg(clap::Arg::with_name("label-theirs")
+             .long("label-theirs")
+             .takes_value(true)
+             .value_name("LABEL")
+             .help("Label of the <OTHER> side of conflicts (defaults to its file name)"))
+        .arg(clap::Arg::with_name("diff3")
+             .long("diff3")
+             .takes_value(false)
+             .help("Also display the <ANCESTOR> version of conflicting tasks"))
+        .arg(negation("diff3", "no-diff3"))
+        .arg(clap::Arg::with_name("overwrite")
+             .long("overwrite")
+             .takes_value(false)
+             .help("Overwrites <CURRENT> with the result of the merge, as expected by git"))
+}
+
+// `prog` is the name the messages are prefixed with, as the `todiff-merge` shim also runs this
+pub fn run(matches: &clap::ArgMatches, config: &Config, prog: &str) -> i32 {
+    let given = |name| given(matches, name);
+    let flag = |name, configured| flag(matches, name, configured);
+
+    let overwrite = matches.is_present("overwrite");
+    let diff_options = diff_options(matches, config);
+
+    let ancestor = matches.value_of("ANCESTOR").expect("Internal error E001");
+    let current = matches.value_of("CURRENT").expect("Internal error E002");
+    let other = matches.value_of("OTHER").expect("Internal error E003");
+    check_stdin_used_once(&[ancestor, current, other]);
+    if overwrite && current == "-" {
+        exit_with(clap::Error::with_description(
+            "--overwrite cannot be used when <CURRENT> is the standard input",
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
+    let read_options = read_options(matches, config);
+    let [from, left, right] = match read_files(prog, &[ancestor, current, other], &read_options) {
+        Some(outcomes) => <[_; 3]>::try_from(outcomes).expect("Internal error E043"),
+        None => return EXIT_ERROR,
+    };
+    if overwrite && !left.warnings.is_empty() {
+        eprintln!(
+            "{}: refusing to overwrite ‘{}’, as its skipped lines would be lost",
+            prog, current
+        );
+        return EXIT_ERROR;
+    }
+    let (left, left_lines, comments) = (left.tasks, left.line_numbers, left.comments);
+    let (from, right) = (from.tasks, right.tasks);
+    let marker_size = match config.marker_size {
+        Some(marker_size) if !given("marker-size") => marker_size,
+        _ => matches
+            .value_of("marker-size")
+            .expect("Internal error E026")
+            .parse()
+            .expect("Internal error E027"),
+    };
+    let marker_options = MarkerOptions::default()
+        .marker_size(marker_size)
+        .label_ours(matches.value_of("label-ours").unwrap_or(current))
+        .label_base(matches.value_of("label-base").unwrap_or(ancestor))
+        .label_theirs(matches.value_of("label-theirs").unwrap_or(other))
+        .diff3(flag("diff3", config.diff3));
+    let strategy = match config.strategy {
+        Some(strategy) if !given("strategy") => strategy,
+        _ => matches
+            .value_of("strategy")
+            .expect("Internal error E028")
+            .parse::<MergeStrategy>()
+            .expect("Internal error E029"),
+    };
+
+    let changes = merge_3way_anchored_with(from, left, right, &diff_options);
+    let (changes, resolved) = resolve_anchored_conflicts(changes, strategy);
+    if resolved > 0 {
+        eprintln!(
+            "{}: {} conflict{} resolved with the {} strategy",
+            prog,
+            resolved,
+            if resolved == 1 { "" } else { "s" },
+            strategy
+        );
+    }
+    let (anchors, changes): (Vec<_>, Vec<_>) = changes
+        .into_iter()
+        .map(|(anchor, m)| (anchor.map(|i| left_lines[i]), m))
+        .unzip();
+    let success = merge_successful(&changes);
+    let output = merge_to_string_with_comments(changes, &marker_options, &anchors, &comments);
+
+    if overwrite {
+        if let Err(e) = fs::write(current, output) {
+            eprintln!("{}: Unable to write to file ‘{}’: {}", prog, current, e);
+            return EXIT_ERROR;
+        }
+    } else {
+        println!("{}", output);
+    }
+    if success {
+        EXIT_SUCCESS
+    } else {
+        EXIT_CONFLICTS
+    }
+}
@@ -0,0 +1,520 @@
+// The command-line interface, shared by `todiff` and the `todiff-merge` shim. Each subcommand has
+// its own module, defining its arguments and running it.
+
+mod apply;
+mod batch;
+mod check;
+mod diff;
+mod log;
+mod merge;
+mod pager;
+
+use clap;
+use color_policy::*;
+use compute_changes::{DiffOptions, FuzzyPartition, SimilarityMetric};
+use config::*;
+use display_changes::{DisplayOptions, WordDiff};
+use io::{read_tasks_from_with, ReadOptions, ReadOutcome};
+use std::ffi::OsString;
+
+// Exit code of all subcommands when an error occurred, that eg. git does not mistake for
+// conflicts
+const EXIT_ERROR: i32 = 2;
+
+// The subcommands, `diff` being the default one
+const SUBCOMMANDS: &[&str] = &["diff", "merge", "apply", "batch", "log", "check", "help"];
+
+// The shared options taking a value, which is thus not the subcommand
+const SHARED_OPTIONS_WITH_VALUE: &[&str] = &[
+    "--color",
+    "--id-tag",
+    "--similarity-metric",
+    "--similarity",
+    "--max-fuzzy-candidates",
+    "--fuzzy-partition",
+];
+
+// The options of todiff and its subcommands taking a value, checked against their definitions by
+// `test_options_with_value`
+const OPTIONS_WITH_VALUE: &[&[&str]] = &[
+    SHARED_OPTIONS_WITH_VALUE,
+    diff::OPTIONS_WITH_VALUE,
+    merge::OPTIONS_WITH_VALUE,
+    apply::OPTIONS_WITH_VALUE,
+    batch::OPTIONS_WITH_VALUE,
+    batch::OUTPUT_OPTIONS_WITH_VALUE,
+    log::OPTIONS_WITH_VALUE,
+    check::OPTIONS_WITH_VALUE,
+];
+
+// Like clap::Error::exit, but with our own exit code for usage errors
+fn exit_with(e: clap::Error) -> ! {
+    match e.kind {
+        clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => e.exit(),
+        _ => {
+            eprintln!("{}", e.message);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+fn check_stdin_used_once(paths: &[&str]) {
+    if paths.iter().filter(|p| **p == "-").count() > 1 {
+        exit_with(clap::Error::with_description(
+            "the standard input (‘-’) can be used for only one file",
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
+}
+
+// The options shared by all subcommands, about how the files are read and how their tasks are
+// matched
+fn shared_args<'a, 'b>() -> Vec<clap::Arg<'a, 'b>> {
+    vec![
+        clap::Arg::with_name("color")
+            .long("color")
+            .takes_value(true)
+            .possible_values(&["auto", "always", "never"])
+            .default_value("auto")
+            .help("Colorize the output"),
+        clap::Arg::with_name("strict-parse")
+            .long("strict-parse")
+            .takes_value(false)
+            .help("Parse lines starting with `#` as tasks rather than skipping them as comments (which merge otherwise keeps from <CURRENT>)"),
+        negation("strict-parse", "no-strict-parse"),
+        clap::Arg::with_name("lenient")
+            .long("lenient")
+            .takes_value(false)
+            .help("Skip unparseable lines with a warning instead of failing (refused by merge --overwrite if <CURRENT> has some, as they would be lost)"),
+        negation("lenient", "no-lenient"),
+        clap::Arg::with_name("exact")
+            .long("exact")
+            .takes_value(false)
+            .help("Only pair identical tasks, reporting any edited task as deleted and new"),
+        negation("exact", "no-exact"),
+        clap::Arg::with_name("id-tag")
+            .long("id-tag")
+            .takes_value(true)
+            .value_name("TAG")
+            .default_value("id")
+            .help("Tag identifying tasks, which are then matched by its value rather than by their subject"),
+        clap::Arg::with_name("strict-subject")
+            .long("strict-subject")
+            .takes_value(false)
+            .help("Take projects and contexts into account when comparing subjects"),
+        negation("strict-subject", "no-strict-subject"),
+        clap::Arg::with_name("similarity-metric")
+            .long("similarity-metric")
+            .takes_value(true)
+            .possible_values(&["levenshtein", "token"])
+            .default_value("levenshtein")
+            .help("How similarity is measured (token ignores the order of the words)"),
+        clap::Arg::with_name("similarity")
+            .long("similarity")
+            .takes_value(true)
+            .validator(|s| {
+                s.parse::<usize>()
+                    .map_err(|e| format!("{}", e))
+                    .and_then(|x| {
+                        if x <= 100 {
+                            Ok(())
+                        } else {
+                            Err("must be between 0 and 100".to_owned())
+                        }
+                    })
+            })
+            .default_value("75")
+            .help("Similarity index to consider two tasks identical (in percents, higher is more restrictive)"),
+        clap::Arg::with_name("max-fuzzy-candidates")
+            .long("max-fuzzy-candidates")
+            .takes_value(true)
+            .value_name("N")
+            .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| format!("{}", e)))
+            .default_value("5000")
+            .help("Above N tasks to match on each side, only compare tasks of the same --fuzzy-partition, 0 meaning no limit"),
+        clap::Arg::with_name("fuzzy-partition")
+            .long("fuzzy-partition")
+            .takes_value(true)
+            .possible_values(&["first-word", "project"])
+            .default_value("first-word")
+            .help("How tasks are grouped above --max-fuzzy-candidates (tasks with an id are grouped by id)"),
+    ]
+}
+
+// The first argument that is neither an option nor the value of one of `OPTIONS_WITH_VALUE`
+fn first_positional(args: &[OsString]) -> Option<String> {
+    let mut args = args.iter().map(|a| a.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return args.next().map(|a| a.into_owned());
+        } else if OPTIONS_WITH_VALUE
+            .iter()
+            .any(|options| options.contains(&&*arg))
+        {
+            args.next();
+        } else if arg == "-" || !arg.starts_with('-') {
+            return Some(arg.into_owned());
+        }
+    }
+    None
+}
+
+// Reads the configuration file, reporting unknown options on the standard error
+fn load_config(prog: &str) -> Result<Config, i32> {
+    match read_config_from_env() {
+        Ok(outcome) => {
+            for w in outcome.warnings.iter() {
+                eprintln!("{}: warning: {} (ignored)", prog, w);
+            }
+            Ok(outcome.config)
+        }
+        Err(e) => {
+            eprintln!("{}: {}", prog, e);
+            Err(EXIT_ERROR)
+        }
+    }
+}
+
+// The number of columns of the terminal the output is written to, if any
+#[cfg(unix)]
+fn terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let res = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    Some(size.ws_col as usize).filter(|w| res == 0 && *w > 0)
+}
+
+#[cfg(not(unix))]
+fn terminal_width() -> Option<usize> {
+    None
+}
+
+// Options given on the command line take precedence over the configuration file
+fn given(matches: &clap::ArgMatches, name: &str) -> bool {
+    matches.occurrences_of(name) > 0
+}
+
+fn flag(matches: &clap::ArgMatches, name: &str, configured: Option<bool>) -> bool {
+    matches.is_present(name)
+        || (configured == Some(true) && !matches.is_present(format!("no-{}", name)))
+}
+
+// `--no-<name>`, which turns the flag `name` off again when the configuration file sets it, the
+// last of both given winning. These are left out of the help, see the README.
+fn negation<'a, 'b>(name: &'a str, negated: &'a str) -> clap::Arg<'a, 'b> {
+    clap::Arg::with_name(negated)
+        .long(negated)
+        .takes_value(false)
+        .overrides_with(name)
+        .hidden(true)
+}
+
+// How tasks are matched according to the shared options
+fn diff_options(matches: &clap::ArgMatches, config: &Config) -> DiffOptions {
+    let similarity = match config.similarity {
+        Some(similarity) if !given(matches, "similarity") => similarity,
+        _ => matches
+            .value_of("similarity")
+            .expect("Internal error E011")
+            .parse::<usize>()
+            .expect("Internal error E012"),
+    };
+    let similarity_metric = match config.similarity_metric {
+        Some(metric) if !given(matches, "similarity-metric") => metric,
+        _ => matches
+            .value_of("similarity-metric")
+            .expect("Internal error E020")
+            .parse::<SimilarityMetric>()
+            .expect("Internal error E021"),
+    };
+    let id_tag = match config.id_tag {
+        Some(ref id_tag) if !given(matches, "id-tag") => id_tag,
+        _ => matches.value_of("id-tag").expect("Internal error E019"),
+    };
+    let max_fuzzy_candidates = match config.max_fuzzy_candidates {
+        Some(max) if !given(matches, "max-fuzzy-candidates") => max,
+        _ => matches
+            .value_of("max-fuzzy-candidates")
+            .expect("Internal error E060")
+            .parse::<usize>()
+            .expect("Internal error E061"),
+    };
+    let fuzzy_partition = match config.fuzzy_partition {
+        Some(partition) if !given(matches, "fuzzy-partition") => partition,
+        _ => matches
+            .value_of("fuzzy-partition")
+            .expect("Internal error E062")
+            .parse::<FuzzyPartition>()
+            .expect("Internal error E063"),
+    };
+    DiffOptions::default()
+        .allowed_divergence(100 - similarity)
+        .exact_only(flag(matches, "exact", config.exact))
+        .strict_subject(flag(matches, "strict-subject", config.strict_subject))
+        .similarity_metric(similarity_metric)
+        .id_tag(id_tag)
+        .max_fuzzy_candidates(Some(max_fuzzy_candidates).filter(|max| *max > 0))
+        .fuzzy_partition(fuzzy_partition)
+}
+
+// The options of the subcommands displaying changesets, ie. diff, batch and log
+struct OutputOptions<'a> {
+    format: &'a str,
+    display: DisplayOptions,
+    diff: DiffOptions,
+}
+
+// How changesets are computed and displayed according to the options of the subcommands displaying
+// them, the subjects being diffed as they are highlighted. The options a subcommand does not have
+// are taken from the configuration file.
+fn output_options<'a>(
+    matches: &'a clap::ArgMatches,
+    config: &Config,
+    color_env: &ColorEnvironment,
+) -> OutputOptions<'a> {
+    let given = |name| given(matches, name);
+    let format = matches.value_of("format").expect("Internal error E014");
+    let color_option = matches.value_of("color").expect("Internal error E009");
+    if format != "text" && color_option == "always" {
+        exit_with(clap::Error::with_description(
+            &format!("--color always cannot be used with --format {}", format),
+            clap::ErrorKind::ArgumentConflict,
+        ));
+    }
+    let color_option = match config.color {
+        Some(color) if !given("color") => color,
+        _ => color_option
+            .parse::<ColorOption>()
+            .expect("Internal error E010"),
+    };
+    let word_diff = match config.word_diff {
+        Some(word_diff) if !given("word-diff") => word_diff,
+        _ => matches
+            .value_of("word-diff")
+            .expect("Internal error E016")
+            .parse::<WordDiff>()
+            .expect("Internal error E017"),
+    };
+    let mut display = DisplayOptions::default()
+        .colorize(should_colorize(color_option, color_env))
+        .word_diff(word_diff)
+        .today(match matches.value_of("today") {
+            Some(today) => today.parse().expect("Internal error E034"),
+            None => chrono::Local::now().naive_local().date(),
+        });
+    let date_format = match matches.value_of("date-format") {
+        Some(date_format) => Some(date_format.parse().expect("Internal error E036")),
+        None => config.date_format.clone(),
+    };
+    if let Some(date_format) = date_format {
+        display = display.date_format(date_format);
+    }
+    let width = match matches.value_of("width") {
+        Some(width) => Some(width.parse().expect("Internal error E037")),
+        None => config.width.or_else(terminal_width),
+    };
+    if let Some(width) = width {
+        display = display.width(width);
+    }
+    let group_by = match matches.value_of("group-by") {
+        Some(group_by) => Some(group_by.parse().expect("Internal error E022")),
+        None => config.group_by,
+    };
+    if let Some(group_by) = group_by {
+        display = display.group_by(group_by);
+    }
+    OutputOptions {
+        format,
+        display,
+        diff: diff_options(matches, config).word_diff(word_diff),
+    }
+}
+
+fn read_options(matches: &clap::ArgMatches, config: &Config) -> ReadOptions {
+    ReadOptions::default()
+        .lenient(flag(matches, "lenient", config.lenient))
+        .strict(flag(matches, "strict-parse", config.strict_parse))
+}
+
+// Reads all of `paths`, reporting the first error or else the skipped lines on the standard error
+fn read_files(prog: &str, paths: &[&str], opts: &ReadOptions) -> Option<Vec<ReadOutcome>> {
+    let outcomes = match paths
+        .iter()
+        .map(|path| read_tasks_from_with(path, opts))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            eprintln!("{}: {}", prog, e);
+            return None;
+        }
+    };
+    for w in outcomes.iter().flat_map(|o| o.warnings.iter()) {
+        eprintln!("{}: warning: {} (skipped)", prog, w);
+    }
+    Some(outcomes)
+}
+
+fn todiff_app<'a, 'b>() -> clap::App<'a, 'b> {
+    let shared_args = shared_args()
+        .into_iter()
+        .map(|a| a.global(true))
+        .collect::<Vec<_>>();
+    clap::App::new("todiff")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Leo Gaspard <todiff@leo.gaspard.ninja>")
+        .about("Diffs, merges and checks todo.txt files")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .setting(clap::AppSettings::VersionlessSubcommands)
+        .args(&shared_args)
+        .subcommand(diff::app(clap::SubCommand::with_name("diff")))
+        .subcommand(merge::app(clap::SubCommand::with_name("merge")))
+        .subcommand(apply::app(clap::SubCommand::with_name("apply")))
+        .subcommand(batch::app(clap::SubCommand::with_name("batch")))
+        .subcommand(log::app(clap::SubCommand::with_name("log")))
+        .subcommand(check::app(clap::SubCommand::with_name("check")))
+}
+
+// `todiff BEFORE AFTER` is short for `todiff diff BEFORE AFTER`
+pub fn todiff_main<I, T>(args: I) -> i32
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let mut args = args.into_iter().map(Into::into).collect::<Vec<OsString>>();
+    if first_positional(args.get(1..).unwrap_or(&[]))
+        .is_some_and(|arg| !SUBCOMMANDS.contains(&arg.as_str()))
+    {
+        args.insert(1, OsString::from("diff"));
+    }
+    let matches = todiff_app()
+        .get_matches_from_safe(args)
+        .unwrap_or_else(|e| exit_with(e));
+
+    let config = match load_config("todiff") {
+        Ok(config) => config,
+        Err(code) => return code,
+    };
+    match matches.subcommand() {
+        ("diff", Some(matches)) => diff::run(matches, &config),
+        ("merge", Some(matches)) => merge::run(matches, &config, "todiff"),
+        ("apply", Some(matches)) => apply::run(matches, &config),
+        ("batch", Some(matches)) => batch::run(matches, &config),
+        ("log", Some(matches)) => log::run(matches, &config),
+        ("check", Some(matches)) => check::run(matches, &config),
+        _ => panic!("Internal error E041"),
+    }
+}
+
+// `todiff-merge ANCESTOR CURRENT OTHER`, kept for the git configurations using it
+pub fn todiff_merge_main<I, T>(args: I) -> i32
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let matches = merge::app(clap::App::new("todiff-merge"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Leo Gaspard <todiff@leo.gaspard.ninja>")
+        .args(&shared_args())
+        .get_matches_from_safe(args)
+        .unwrap_or_else(|e| exit_with(e));
+
+    let config = match load_config("todiff-merge") {
+        Ok(config) => config,
+        Err(code) => return code,
+    };
+    merge::run(&matches, &config, "todiff-merge")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_first_positional() {
+        let first =
+            |args: &[&str]| first_positional(&args.iter().map(OsString::from).collect::<Vec<_>>());
+        assert_eq!(first(&["a.txt", "b.txt"]), Some("a.txt".to_owned()));
+        assert_eq!(
+            first(&["--similarity", "50", "merge", "a", "b", "c"]),
+            Some("merge".to_owned())
+        );
+        assert_eq!(
+            first(&["--color=never", "--lenient", "-", "b.txt"]),
+            Some("-".to_owned())
+        );
+        assert_eq!(
+            first(&["--", "--weird-name.txt", "b.txt"]),
+            Some("--weird-name.txt".to_owned())
+        );
+        assert_eq!(first(&["--help"]), None);
+        // The values of the options of subcommands are not subcommands either
+        assert_eq!(
+            first(&["--template", "log", "a.txt", "b.txt"]),
+            Some("a.txt".to_owned())
+        );
+        assert_eq!(
+            first(&["--link-tag", "diff", "-p", "a.txt", "b.txt"]),
+            Some("a.txt".to_owned())
+        );
+    }
+
+    // The options taking a value according to the help of `app`, eg. `--color <color>`
+    fn help_options_with_value(app: clap::App) -> BTreeSet<String> {
+        let mut help = Vec::new();
+        app.set_term_width(0)
+            .write_help(&mut help)
+            .expect("writing the help");
+        let mut res = BTreeSet::new();
+        for line in String::from_utf8(help).unwrap().lines() {
+            let mut words = line.split_whitespace().peekable();
+            let mut options = Vec::new();
+            while let Some(option) = words.next_if(|w| w.starts_with('-')) {
+                options.push(option.trim_end_matches(','));
+            }
+            if words.peek().is_some_and(|w| w.starts_with('<')) {
+                res.extend(options.into_iter().map(str::to_owned));
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_options_with_value() {
+        let listed = |lists: &[&[&str]]| {
+            lists
+                .iter()
+                .flat_map(|l| l.iter().map(|o| o.to_string()))
+                .collect::<BTreeSet<_>>()
+        };
+        let app = || clap::App::new("todiff");
+        assert_eq!(
+            help_options_with_value(app().args(&shared_args())),
+            listed(&[SHARED_OPTIONS_WITH_VALUE])
+        );
+        assert_eq!(
+            help_options_with_value(diff::app(app())),
+            listed(&[diff::OPTIONS_WITH_VALUE])
+        );
+        assert_eq!(
+            help_options_with_value(merge::app(app())),
+            listed(&[merge::OPTIONS_WITH_VALUE])
+        );
+        assert_eq!(
+            help_options_with_value(apply::app(app())),
+            listed(&[apply::OPTIONS_WITH_VALUE])
+        );
+        assert_eq!(
+            help_options_with_value(batch::app(app())),
+            listed(&[batch::OPTIONS_WITH_VALUE, batch::OUTPUT_OPTIONS_WITH_VALUE])
+        );
+        assert_eq!(
+            help_options_with_value(log::app(app())),
+            listed(&[log::OPTIONS_WITH_VALUE, batch::OUTPUT_OPTIONS_WITH_VALUE])
+        );
+        assert_eq!(
+            help_options_with_value(check::app(app())),
+            listed(&[check::OPTIONS_WITH_VALUE])
+        );
+    }
+}
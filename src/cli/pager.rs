@@ -0,0 +1,253 @@
+// Showing the output through a pager, as git does. Whether to is decided before the output is
+// rendered, which is then streamed to the pager or to the standard output, so that large diffs are
+// never held in memory. The colors were already decided on the real standard output, which the
+// pager is also displayed on.
+
+use std::env;
+use std::io::{self, BufWriter, Write};
+use std::process::{Command, Stdio};
+
+// `-F` quits at once when the output fits in the terminal, so that short outputs are not paged
+const DEFAULT_PAGER: &str = "less -FRX";
+const DEFAULT_LESS: &str = "FRX";
+// For `--paginate`, which keeps the pager open even for short outputs
+const KEEP_OPEN_PAGER: &str = "less -RX";
+const KEEP_OPEN_LESS: &str = "RX";
+
+// Whether to page, from `--paginate` and `--no-pager`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PagerOption {
+    Auto,
+    Always,
+    Never,
+}
+
+// Paging only makes sense on a terminal, so that even `--paginate` does nothing when the output
+// is redirected
+pub fn should_page(option: PagerOption, stdout_is_tty: bool) -> bool {
+    stdout_is_tty && option != PagerOption::Never
+}
+
+// The pager command, split at whitespace, from `TODIFF_PAGER` then `PAGER`. As for git, an empty
+// command or `cat` means no pager.
+pub fn pager_command(
+    option: PagerOption,
+    todiff_pager: Option<String>,
+    pager: Option<String>,
+) -> Option<Vec<String>> {
+    let default = match option {
+        PagerOption::Always => KEEP_OPEN_PAGER,
+        _ => DEFAULT_PAGER,
+    };
+    let command = todiff_pager.or(pager).unwrap_or_else(|| default.to_owned());
+    let words = command
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    Some(words).filter(|w| !w.is_empty() && w[..] != ["cat"])
+}
+
+pub fn pager_command_from_env(option: PagerOption) -> Option<Vec<String>> {
+    pager_command(
+        option,
+        env::var("TODIFF_PAGER").ok(),
+        env::var("PAGER").ok(),
+    )
+}
+
+// Renders the output to the writer it is given
+pub type Render<'a> = dyn FnMut(&mut dyn Write) -> io::Result<()> + 'a;
+
+// Why the output could not be shown through a pager
+#[derive(Debug)]
+pub enum PageError {
+    // The pager could not be started, in which case `render` was not called
+    Start(io::Error),
+    // Rendering the output into the pager failed, otherwise than by the pager being quit
+    Write(io::Error),
+}
+
+// Running a pager, behind a trait so that tests need not start one
+pub trait Pager {
+    // Renders the output into `command` and waits for it to be closed
+    fn page(&self, command: &[String], render: &mut Render) -> Result<(), PageError>;
+}
+
+pub struct ProcessPager {
+    pub option: PagerOption,
+}
+
+impl Pager for ProcessPager {
+    fn page(&self, command: &[String], render: &mut Render) -> Result<(), PageError> {
+        let mut process = Command::new(&command[0]);
+        process.args(&command[1..]).stdin(Stdio::piped());
+        // Like git, so that a plain `PAGER=less` keeps the colors and short outputs
+        if env::var_os("LESS").is_none() {
+            process.env(
+                "LESS",
+                match self.option {
+                    PagerOption::Always => KEEP_OPEN_LESS,
+                    _ => DEFAULT_LESS,
+                },
+            );
+        }
+        let mut child = process.spawn().map_err(PageError::Start)?;
+        // The pipe is closed once rendered, so that the pager sees the end of the output
+        let rendered = match child.stdin.take() {
+            Some(stdin) => {
+                let mut stdin = BufWriter::new(stdin);
+                render(&mut stdin).and_then(|()| stdin.flush())
+            }
+            None => Ok(()),
+        };
+        let _ = child.wait();
+        match rendered {
+            // Quitting the pager before the end closes the pipe, which is not an error
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(PageError::Write(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Renders the output through `command` when given, or to `out` if there is none or it could not be
+// started
+pub fn write_output<P: Pager, W: Write>(
+    render: &mut Render,
+    command: Option<Vec<String>>,
+    pager: &P,
+    out: W,
+) -> io::Result<()> {
+    if let Some(command) = command {
+        match pager.page(&command, render) {
+            Ok(()) => return Ok(()),
+            Err(PageError::Write(e)) => return Err(e),
+            Err(PageError::Start(e)) => eprintln!(
+                "todiff: warning: unable to start the pager ‘{}’: {}",
+                command.join(" "),
+                e
+            ),
+        }
+    }
+    let mut out = BufWriter::new(out);
+    render(&mut out).and_then(|()| out.flush())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_should_page() {
+        use self::PagerOption::*;
+
+        assert!(should_page(Auto, true));
+        assert!(!should_page(Auto, false));
+        assert!(should_page(Always, true));
+        assert!(!should_page(Always, false));
+        assert!(!should_page(Never, true));
+    }
+
+    #[test]
+    fn test_pager_command() {
+        let command = |todiff_pager: Option<&str>, pager: Option<&str>| {
+            pager_command(
+                PagerOption::Auto,
+                todiff_pager.map(str::to_owned),
+                pager.map(str::to_owned),
+            )
+        };
+        let words = |words: &[&str]| Some(words.iter().map(|w| w.to_string()).collect());
+        assert_eq!(command(None, None), words(&["less", "-FRX"]));
+        assert_eq!(command(None, Some("more")), words(&["more"]));
+        assert_eq!(
+            command(Some("most -s"), Some("more")),
+            words(&["most", "-s"])
+        );
+        assert_eq!(command(Some(""), Some("more")), None);
+        assert_eq!(command(None, Some("cat")), None);
+        assert_eq!(command(Some("  "), None), None);
+        assert_eq!(
+            pager_command(PagerOption::Always, None, None),
+            words(&["less", "-RX"])
+        );
+    }
+
+    // Records what it was asked to show, or fails to start
+    struct FakePager {
+        starts: bool,
+        shown: RefCell<Vec<(Vec<String>, Vec<u8>)>>,
+    }
+
+    impl Pager for FakePager {
+        fn page(&self, command: &[String], render: &mut Render) -> Result<(), PageError> {
+            if !self.starts {
+                let error = io::Error::new(io::ErrorKind::NotFound, "not found");
+                return Err(PageError::Start(error));
+            }
+            let mut output = Vec::new();
+            render(&mut output).map_err(PageError::Write)?;
+            self.shown.borrow_mut().push((command.to_vec(), output));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_output() {
+        let command = || Some(vec!["less".to_owned()]);
+        let renders = RefCell::new(0);
+        let mut render = |out: &mut dyn Write| {
+            *renders.borrow_mut() += 1;
+            out.write_all(b"a\n")?;
+            out.write_all(b"b\n")
+        };
+        let pager = FakePager {
+            starts: true,
+            shown: RefCell::new(Vec::new()),
+        };
+        let mut out = Vec::new();
+        write_output(&mut render, command(), &pager, &mut out).unwrap();
+        assert!(out.is_empty());
+        assert_eq!(
+            *pager.shown.borrow(),
+            vec![(vec!["less".to_owned()], b"a\nb\n".to_vec())]
+        );
+
+        write_output(&mut render, None, &pager, &mut out).unwrap();
+        assert_eq!(out, b"a\nb\n");
+        assert_eq!(pager.shown.borrow().len(), 1);
+
+        // A pager that cannot be started is skipped, the output being rendered once
+        let broken = FakePager {
+            starts: false,
+            shown: RefCell::new(Vec::new()),
+        };
+        let mut out = Vec::new();
+        write_output(&mut render, command(), &broken, &mut out).unwrap();
+        assert_eq!(out, b"a\nb\n");
+        assert_eq!(*renders.borrow(), 3);
+
+        // Errors while rendering into the pager are reported
+        let mut failing = |_: &mut dyn Write| Err(io::Error::other("disk full"));
+        let error = write_output(&mut failing, command(), &pager, Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "disk full");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_process_pager() {
+        let pager = ProcessPager {
+            option: PagerOption::Never,
+        };
+        let command = ["true".to_owned()];
+        // The pager quitting before the end of the output is not an error
+        let mut broken_pipe =
+            |_: &mut dyn Write| Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"));
+        assert!(pager.page(&command, &mut broken_pipe).is_ok());
+        let mut failing = |_: &mut dyn Write| Err(io::Error::other("disk full"));
+        match pager.page(&command, &mut failing) {
+            Err(PageError::Write(e)) => assert_eq!(e.to_string(), "disk full"),
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+}
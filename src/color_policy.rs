@@ -0,0 +1,186 @@
+#[cfg(windows)]
+use ansi_term;
+use atty;
+use std::env;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorOption {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorOption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorOption, String> {
+        match s {
+            "auto" => Ok(ColorOption::Auto),
+            "always" => Ok(ColorOption::Always),
+            "never" => Ok(ColorOption::Never),
+            _ => Err(format!("invalid color option ‘{}’", s)),
+        }
+    }
+}
+
+// Everything the color decision depends on, so that it can be injected in tests
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ColorEnvironment {
+    pub stdout_is_tty: bool,
+    pub term: Option<String>,
+    pub no_color: Option<String>,
+    pub clicolor_force: Option<String>,
+    // Whether the terminal interprets escape sequences, which Windows consoles only do once asked
+    // to, see `enable_ansi_support`
+    pub ansi_support: bool,
+}
+
+impl ColorEnvironment {
+    // Also asks the Windows console to interpret escape sequences, so that `--color always` works
+    // there even if `ansi_support` is unset
+    pub fn from_env() -> ColorEnvironment {
+        ColorEnvironment {
+            stdout_is_tty: atty::is(atty::Stream::Stdout),
+            term: env::var("TERM").ok(),
+            no_color: env::var("NO_COLOR").ok(),
+            clicolor_force: env::var("CLICOLOR_FORCE").ok(),
+            ansi_support: enable_ansi_support(),
+        }
+    }
+}
+
+// Enables the virtual terminal processing of the console, which Windows 10 leaves disabled by
+// default. This fails on older versions of Windows.
+#[cfg(windows)]
+fn enable_ansi_support() -> bool {
+    ansi_term::enable_ansi_support().is_ok()
+}
+
+#[cfg(not(windows))]
+fn enable_ansi_support() -> bool {
+    true
+}
+
+// An explicit `always` or `never` always wins. Otherwise, a non-empty `NO_COLOR` disables colors
+// (see https://no-color.org), then a `CLICOLOR_FORCE` other than `0` enables them, and finally
+// colors are used only when writing to a terminal that is not dumb and interprets them.
+pub fn should_colorize(option: ColorOption, env: &ColorEnvironment) -> bool {
+    match option {
+        ColorOption::Always => true,
+        ColorOption::Never => false,
+        ColorOption::Auto => {
+            if env.no_color.as_ref().is_some_and(|v| !v.is_empty()) {
+                false
+            } else if env.clicolor_force.as_ref().is_some_and(|v| v != "0") {
+                true
+            } else {
+                env.stdout_is_tty
+                    && env.term.as_ref().is_none_or(|t| t != "dumb")
+                    && env.ansi_support
+            }
+        }
+    }
+}
+
+// Hyperlinks are escape sequences too, so they are only written along with colors. Terminals
+// that do not support them ignore them, but `auto` only writes them to terminals nonetheless, as
+// other programs reading the output may not expect them. The pager, which is only used on a
+// terminal, passes them through with `less -R`.
+pub fn should_hyperlink(option: ColorOption, colorize: bool, env: &ColorEnvironment) -> bool {
+    colorize
+        && match option {
+            ColorOption::Always => true,
+            ColorOption::Never => false,
+            ColorOption::Auto => env.stdout_is_tty,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_env(
+        stdout_is_tty: bool,
+        term: Option<&str>,
+        no_color: Option<&str>,
+        clicolor_force: Option<&str>,
+    ) -> ColorEnvironment {
+        ColorEnvironment {
+            stdout_is_tty,
+            term: term.map(str::to_owned),
+            no_color: no_color.map(str::to_owned),
+            clicolor_force: clicolor_force.map(str::to_owned),
+            ansi_support: true,
+        }
+    }
+
+    #[test]
+    fn test_should_colorize() {
+        use self::ColorOption::*;
+
+        let tty = test_env(true, Some("xterm"), None, None);
+        assert!(should_colorize(Auto, &tty));
+        assert!(should_colorize(Always, &tty));
+        assert!(!should_colorize(Never, &tty));
+
+        let pipe = test_env(false, Some("xterm"), None, None);
+        assert!(!should_colorize(Auto, &pipe));
+        assert!(should_colorize(Always, &pipe));
+
+        let dumb = test_env(true, Some("dumb"), None, None);
+        assert!(!should_colorize(Auto, &dumb));
+
+        let no_color = test_env(true, Some("xterm"), Some("1"), None);
+        assert!(!should_colorize(Auto, &no_color));
+        assert!(should_colorize(Always, &no_color));
+
+        let empty_no_color = test_env(true, Some("xterm"), Some(""), None);
+        assert!(should_colorize(Auto, &empty_no_color));
+
+        let forced = test_env(false, None, None, Some("1"));
+        assert!(should_colorize(Auto, &forced));
+        assert!(!should_colorize(Never, &forced));
+
+        let not_forced = test_env(false, None, None, Some("0"));
+        assert!(!should_colorize(Auto, &not_forced));
+
+        let both = test_env(false, None, Some("1"), Some("1"));
+        assert!(!should_colorize(Auto, &both));
+
+        // Eg. a Windows console whose escape sequences could not be enabled
+        let no_ansi = ColorEnvironment {
+            ansi_support: false,
+            ..tty.clone()
+        };
+        assert!(!should_colorize(Auto, &no_ansi));
+        assert!(should_colorize(Always, &no_ansi));
+        assert!(!should_colorize(Never, &no_ansi));
+        let forced_no_ansi = ColorEnvironment {
+            ansi_support: false,
+            ..forced.clone()
+        };
+        assert!(should_colorize(Auto, &forced_no_ansi));
+        let no_color_no_ansi = ColorEnvironment {
+            ansi_support: false,
+            ..no_color.clone()
+        };
+        assert!(!should_colorize(Auto, &no_color_no_ansi));
+    }
+
+    #[test]
+    fn test_should_hyperlink() {
+        use self::ColorOption::*;
+
+        let tty = test_env(true, Some("xterm"), None, None);
+        assert!(should_hyperlink(Auto, true, &tty));
+        assert!(!should_hyperlink(Never, true, &tty));
+        assert!(!should_hyperlink(Always, false, &tty));
+        assert!(!should_hyperlink(Auto, false, &tty));
+
+        // Eg. with `--color always` into a pager
+        let pipe = test_env(false, Some("xterm"), None, None);
+        assert!(!should_hyperlink(Auto, true, &pipe));
+        assert!(should_hyperlink(Always, true, &pipe));
+    }
+}
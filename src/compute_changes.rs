@@ -1,28 +1,97 @@
-use chrono::Duration;
+use apply_changes::ApplyError;
+use chrono::{Datelike, Duration};
+use edit_distance::bounded_levenshtein;
+use filter_changes::*;
+use io::{read_residual_tasks_with, ReadError, ReadOptions};
 use itertools::Either;
 use itertools::Itertools;
+use pin_matches::MatchPins;
+use progress::{Phase, Progress};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde_helpers;
 use stable_marriage;
+use stable_marriage::Matcher;
 use std;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::io::BufRead;
+use std::sync::{Mutex, MutexGuard};
 use strsim::levenshtein;
+use subject_diff::{SubjectDiff, WordDiff};
 use todo_txt::task::Extended as Task;
 use todo_txt::task::Recurrence;
 use todo_txt::Date as TaskDate;
+use todo_txt::Priority;
 
 // These structs will be used in two stages: first with T=Task when matching tasks together,
 // and then with T=Vec<Changes> when computing actual deltas to be displayed
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ChangedTask<T> {
     pub orig: Task,
     pub delta: TaskDelta<T>,
+    // The tasks `orig` became: none if it was deleted, one per occurrence if it recurred, one per
+    // part if it was split, one per copy if it was copied, and exactly one otherwise
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub after: Vec<Task>,
+    // The 1-based line numbers of `orig` and of each of `after`, when the tasks were matched by
+    // `match_tasks_indexed`. Tasks found in the archive have no line number.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub orig_line: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub after_lines: Vec<usize>,
+    // How similar `orig` is to the task it was matched with (the first occurrence if it recurred),
+    // in percents, see `match_similarity`: 100 if it is identical, and none if it was deleted or
+    // split
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub similarity: Option<u8>,
 }
 
-#[cfg_attr(feature = "integration_tests", derive(Deserialize))]
+impl ChangedTask<Task> {
+    pub fn new(orig: Task, delta: TaskDelta<Task>) -> ChangedTask<Task> {
+        let after = match delta {
+            TaskDelta::Identical => vec![orig.clone()],
+            _ => delta.iter().cloned().collect(),
+        };
+        ChangedTask {
+            orig,
+            delta,
+            after,
+            orig_line: None,
+            after_lines: Vec::new(),
+            similarity: None,
+        }
+    }
+
+    fn scored(self, matcher: &TaskMatcher) -> ChangedTask<Task> {
+        let similarity = match self.delta {
+            TaskDelta::Identical => Some(100),
+            TaskDelta::Changed(ref t) => Some(matcher.similarity(&self.orig, t).percent()),
+            TaskDelta::Recurred(ref r) | TaskDelta::Copied(ref r) => {
+                Some(matcher.similarity(&self.orig, &r[0]).percent())
+            }
+            TaskDelta::Deleted | TaskDelta::Split(_) => None,
+        };
+        ChangedTask { similarity, ..self }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TaskDelta<T> {
     Identical,
     Deleted,
     Changed(T),
     Recurred(Vec<T>),
+    // The task was split into several tasks, each one doing part of it, see `detect_splits`
+    Split(Vec<T>),
+    // The task was copied into several tasks, the first being the one it was matched with, see
+    // `detect_copies`
+    Copied(Vec<T>),
 }
 
 impl<T> IntoIterator for TaskDelta<T> {
@@ -36,8 +105,9 @@ impl<T> IntoIterator for TaskDelta<T> {
             Identical => Either::Left(None),
             Deleted => Either::Left(None),
             Changed(t) => Either::Left(Some(t)),
-            Recurred(vec) => Either::Right(vec),
-        }.into_iter()
+            Recurred(vec) | Split(vec) | Copied(vec) => Either::Right(vec),
+        }
+        .into_iter()
     }
 }
 
@@ -52,12 +122,17 @@ impl<'a, T> IntoIterator for &'a TaskDelta<T> {
             Identical => Either::Left(None),
             Deleted => Either::Left(None),
             Changed(t) => Either::Left(Some(t)),
-            Recurred(vec) => Either::Right(vec),
-        }.into_iter()
+            Recurred(vec) | Split(vec) | Copied(vec) => Either::Right(vec),
+        }
+        .into_iter()
     }
 }
 
 impl<T> TaskDelta<T> {
+    pub fn is_identical(&self) -> bool {
+        matches!(*self, TaskDelta::Identical)
+    }
+
     pub fn iter(&self) -> <&Self as IntoIterator>::IntoIter {
         self.into_iter()
     }
@@ -72,28 +147,394 @@ impl<T> TaskDelta<T> {
             Deleted => Deleted,
             Changed(t) => Changed(f(t)),
             Recurred(vec) => Recurred(vec.into_iter().map(f).collect::<Vec<_>>()),
+            Split(vec) => Split(vec.into_iter().map(f).collect::<Vec<_>>()),
+            Copied(vec) => Copied(vec.into_iter().map(f).collect::<Vec<_>>()),
         }
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Changes {
-    Created,
+    // A new task that was already completed, on its completion date if it has one. Not computed by
+    // `changes_between`, as such tasks have no original, see `ChangesetReport::completed_section`
+    Created(Option<TaskDate>),
     RecurredStrict,
     RecurredFrom(Option<TaskDate>),
+    // The number of occurrences of a strict recurrence that were never in the list
+    SkippedOccurrences(u32),
 
     FinishedAt(TaskDate),
-    PostponedStrictBy(Duration),
+    // Completed without a completion date, which was taken to be the creation date of the next
+    // occurrence, see `inferred_finish_date`
+    InferredFinishDate(TaskDate),
+    // (delta, due date before, due date after)
+    PostponedStrictBy(
+        #[cfg_attr(feature = "serde", serde(with = "serde_helpers::duration_days"))] Duration,
+        TaskDate,
+        TaskDate,
+    ),
+    // (delta, threshold date before, threshold date after), for tasks without due date
+    PostponedThresholdBy(
+        #[cfg_attr(feature = "serde", serde(with = "serde_helpers::duration_days"))] Duration,
+        TaskDate,
+        TaskDate,
+    ),
 
     // All the variants below are of the form (before, after)
     Finished(bool), // The exception: bool has only two values, so only store after
     Priority(Option<char>, Option<char>),
     FinishDate(Option<TaskDate>, Option<TaskDate>),
     CreateDate(Option<TaskDate>, Option<TaskDate>),
-    Subject(String, String),
+    Subject(SubjectDiff),
     DueDate(Option<TaskDate>, Option<TaskDate>),
     ThresholdDate(Option<TaskDate>, Option<TaskDate>),
     Tags(Vec<(String, String)>, Vec<(String, String)>),
+    Recurrence(Option<Recurrence>, Option<Recurrence>),
+    Projects(Vec<String>, Vec<String>), // (removed, added)
+    Contexts(Vec<String>, Vec<String>), // (removed, added)
+}
+
+// Fields whose changes can be ignored
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Field {
+    Priority,
+    CreateDate,
+    FinishDate,
+    DueDate,
+    ThresholdDate,
+    Tags,
+    Recurrence,
+    Projects,
+    Contexts,
+    Subject,
+    // Not a field, but the occurrences skipped between two recurred tasks
+    Skipped,
+    // Nor this one, the completion dates inferred for occurrences of recurring tasks
+    InferredFinishDate,
+}
+
+impl Field {
+    pub const NAMES: &'static [&'static str] = &[
+        "priority",
+        "create-date",
+        "finish-date",
+        "due-date",
+        "threshold-date",
+        "tags",
+        "recurrence",
+        "projects",
+        "contexts",
+        "subject",
+        "skipped",
+        "inferred-finish-date",
+    ];
+}
+
+impl std::str::FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Field, String> {
+        match s {
+            "priority" => Ok(Field::Priority),
+            "create-date" => Ok(Field::CreateDate),
+            "finish-date" => Ok(Field::FinishDate),
+            "due-date" => Ok(Field::DueDate),
+            "threshold-date" => Ok(Field::ThresholdDate),
+            "tags" => Ok(Field::Tags),
+            "recurrence" => Ok(Field::Recurrence),
+            "projects" => Ok(Field::Projects),
+            "contexts" => Ok(Field::Contexts),
+            "subject" => Ok(Field::Subject),
+            "skipped" => Ok(Field::Skipped),
+            "inferred-finish-date" => Ok(Field::InferredFinishDate),
+            _ => Err(format!("unknown field ‘{}’", s)),
+        }
+    }
+}
+
+// How the divergence between two subjects is measured
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SimilarityMetric {
+    // Edit distance on characters, relative to the length of the subject
+    Levenshtein,
+    // Proportion of distinct words not shared by both subjects, ignoring their order and case
+    Token,
+}
+
+impl std::str::FromStr for SimilarityMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SimilarityMetric, String> {
+        match s {
+            "levenshtein" => Ok(SimilarityMetric::Levenshtein),
+            "token" => Ok(SimilarityMetric::Token),
+            _ => Err(format!("unknown similarity metric ‘{}’", s)),
+        }
+    }
+}
+
+impl SimilarityMetric {
+    // Whether `from` diverges from `other` by at most `allowed_divergence` percents
+    fn is_admissible(
+        self,
+        from: &str,
+        other: &str,
+        allowed_divergence: usize,
+        distances: &DistanceCache,
+    ) -> bool {
+        match self {
+            SimilarityMetric::Levenshtein => {
+                is_levenshtein_admissible(from, other, allowed_divergence, distances)
+            }
+            SimilarityMetric::Token => {
+                let (differing, total) = token_divergence(from, other);
+                differing * 100 <= allowed_divergence * total
+            }
+        }
+    }
+
+    // How similar two subjects are, in percents (100 meaning identical)
+    fn similarity(self, from: &str, other: &str, distances: &DistanceCache) -> usize {
+        let (differing, total) = match self {
+            SimilarityMetric::Levenshtein => (
+                distances.levenshtein(other, from),
+                std::cmp::max(from.chars().count(), other.chars().count()),
+            ),
+            SimilarityMetric::Token => token_divergence(from, other),
+        };
+        // Two empty subjects are identical
+        (differing * 100)
+            .checked_div(total)
+            .map_or(100, |d| 100 - d)
+    }
+
+    // A distance to rank candidates by, lower being closer
+    fn distance(self, from: &str, other: &str, distances: &DistanceCache) -> usize {
+        match self {
+            SimilarityMetric::Levenshtein => distances.levenshtein(other, from),
+            SimilarityMetric::Token => match token_divergence(from, other) {
+                (_, 0) => 0,
+                (differing, total) => differing * 10000 / total,
+            },
+        }
+    }
+}
+
+// How tasks are grouped when there are too many of them to compare each pair, see
+// `partitioned_stable_marriage`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FuzzyPartition {
+    // The first word of the subject, ignoring case (and projects and contexts unless
+    // `strict_subject` is set)
+    FirstWord,
+    // The first project of the task in alphabetical order
+    Project,
+}
+
+impl std::str::FromStr for FuzzyPartition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FuzzyPartition, String> {
+        match s {
+            "first-word" => Ok(FuzzyPartition::FirstWord),
+            "project" => Ok(FuzzyPartition::Project),
+            _ => Err(format!("unknown partition ‘{}’", s)),
+        }
+    }
+}
+
+impl FuzzyPartition {
+    // Tasks with an id are only compared with tasks with the same id, so that they still find
+    // each other
+    fn key(self, task: &Task, opts: &DiffOptions) -> String {
+        if let Some(id) = task.tags.get(&opts.id_tag) {
+            return format!("{}:{}", opts.id_tag, id);
+        }
+        match self {
+            FuzzyPartition::FirstWord => matched_subject(task, opts)
+                .split_whitespace()
+                .next()
+                .map_or_else(String::new, |w| format!("word:{}", w.to_lowercase())),
+            FuzzyPartition::Project => task
+                .projects
+                .iter()
+                .min()
+                .map_or_else(String::new, |p| format!("project:{}", p)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DiffOptions {
+    // In percents of the length of the longest subject, see `is_levenshtein_admissible`
+    pub allowed_divergence: usize,
+    // Whether new tasks can be detected as occurrences of recurring tasks
+    pub detect_recurrences: bool,
+    // Fields whose changes are not reported
+    pub ignored_fields: BTreeSet<Field>,
+    // Only pair identical tasks, any other task being deleted or new
+    pub exact_only: bool,
+    // Tasks carrying this tag are matched by its value rather than by their subject
+    pub id_tag: String,
+    // Compare subjects as-is, rather than without their projects, contexts and tags
+    pub strict_subject: bool,
+    pub similarity_metric: SimilarityMetric,
+    // Only the tasks selected by this filter are reported, see `filter_matches`
+    pub filter: TaskFilter,
+    // Use all cores, which requires the `rayon` feature. Results are the same either way.
+    pub parallel: bool,
+    // Attach new tasks to recurring ones by subject only, without checking that their due date
+    // is consistent with the recurrence, see `is_plausible_occurrence`
+    pub loose_recurrence: bool,
+    // Report tasks that were split into several new tasks, see `detect_splits`
+    pub detect_splits: bool,
+    // Report tasks that were copied into several similar tasks, see `detect_copies`
+    pub detect_copies: bool,
+    // Report each field that changed on its own, rather than folding some of them into one change
+    // such as `FinishedAt` or `PostponedStrictBy`, see `changes_between_with`
+    pub granular: bool,
+    // The granularity of the diffs of subject changes
+    pub word_diff: WordDiff,
+    // Manual corrections of the matching, see `match_tasks`
+    pub pins: MatchPins,
+    // Above this many tasks to match on each side, tasks are only compared with those of the
+    // same partition, see `reduces_matching`. None means no limit.
+    pub max_fuzzy_candidates: Option<usize>,
+    pub fuzzy_partition: FuzzyPartition,
+    // Told how far along the matching is, see `Matcher::progress`
+    pub progress: Progress,
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        DiffOptions {
+            allowed_divergence: 25,
+            detect_recurrences: true,
+            ignored_fields: BTreeSet::new(),
+            exact_only: false,
+            id_tag: "id".to_owned(),
+            strict_subject: false,
+            similarity_metric: SimilarityMetric::Levenshtein,
+            filter: TaskFilter::default(),
+            parallel: true,
+            loose_recurrence: false,
+            detect_splits: false,
+            detect_copies: false,
+            granular: false,
+            word_diff: WordDiff::Word,
+            pins: MatchPins::default(),
+            max_fuzzy_candidates: Some(5000),
+            fuzzy_partition: FuzzyPartition::FirstWord,
+            progress: Progress::default(),
+        }
+    }
+}
+
+impl DiffOptions {
+    pub fn allowed_divergence(mut self, allowed_divergence: usize) -> DiffOptions {
+        self.allowed_divergence = allowed_divergence;
+        self
+    }
+
+    pub fn detect_recurrences(mut self, detect_recurrences: bool) -> DiffOptions {
+        self.detect_recurrences = detect_recurrences;
+        self
+    }
+
+    pub fn ignore(mut self, field: Field) -> DiffOptions {
+        self.ignored_fields.insert(field);
+        self
+    }
+
+    pub fn exact_only(mut self, exact_only: bool) -> DiffOptions {
+        self.exact_only = exact_only;
+        self
+    }
+
+    pub fn id_tag(mut self, id_tag: &str) -> DiffOptions {
+        self.id_tag = id_tag.to_owned();
+        self
+    }
+
+    pub fn strict_subject(mut self, strict_subject: bool) -> DiffOptions {
+        self.strict_subject = strict_subject;
+        self
+    }
+
+    pub fn similarity_metric(mut self, similarity_metric: SimilarityMetric) -> DiffOptions {
+        self.similarity_metric = similarity_metric;
+        self
+    }
+
+    pub fn filter(mut self, filter: TaskFilter) -> DiffOptions {
+        self.filter = filter;
+        self
+    }
+
+    pub fn parallel(mut self, parallel: bool) -> DiffOptions {
+        self.parallel = parallel;
+        self
+    }
+
+    pub fn loose_recurrence(mut self, loose_recurrence: bool) -> DiffOptions {
+        self.loose_recurrence = loose_recurrence;
+        self
+    }
+
+    pub fn detect_splits(mut self, detect_splits: bool) -> DiffOptions {
+        self.detect_splits = detect_splits;
+        self
+    }
+
+    pub fn detect_copies(mut self, detect_copies: bool) -> DiffOptions {
+        self.detect_copies = detect_copies;
+        self
+    }
+
+    pub fn granular(mut self, granular: bool) -> DiffOptions {
+        self.granular = granular;
+        self
+    }
+
+    pub fn word_diff(mut self, word_diff: WordDiff) -> DiffOptions {
+        self.word_diff = word_diff;
+        self
+    }
+
+    pub fn pins(mut self, pins: MatchPins) -> DiffOptions {
+        self.pins = pins;
+        self
+    }
+
+    pub fn max_fuzzy_candidates(mut self, max_fuzzy_candidates: Option<usize>) -> DiffOptions {
+        self.max_fuzzy_candidates = max_fuzzy_candidates;
+        self
+    }
+
+    pub fn fuzzy_partition(mut self, fuzzy_partition: FuzzyPartition) -> DiffOptions {
+        self.fuzzy_partition = fuzzy_partition;
+        self
+    }
+
+    pub fn progress(mut self, progress: Progress) -> DiffOptions {
+        self.progress = progress;
+        self
+    }
+
+    // Whether matching `from` tasks with `to` tasks (once pinned ones are set aside) only compares
+    // tasks of the same partition, as comparing each pair would take too long. Tasks of different
+    // partitions are then never matched, and reported as deleted and new instead.
+    pub fn reduces_matching(&self, from: usize, to: usize) -> bool {
+        !self.exact_only
+            && self
+                .max_fuzzy_candidates
+                .is_some_and(|max| from > max && to > max)
+    }
+
+    fn reports(&self, field: Field) -> bool {
+        !self.ignored_fields.contains(&field)
+    }
 }
 
 fn delta_task_dates(from: &Task, to: &Task) -> Option<Duration> {
@@ -118,6 +559,74 @@ fn delta_task_dates(from: &Task, to: &Task) -> Option<Duration> {
     None
 }
 
+// Removes from a subject the words starting with one of `prefixes` (eg. `+project` words)
+fn strip_subject_words(subject: &str, prefixes: &[char]) -> String {
+    subject
+        .split_whitespace()
+        .filter(|w| !(w.len() > 1 && w.starts_with(prefixes)))
+        .join(" ")
+}
+
+// The subject tasks are matched on: unless strict, without the `+project` and `@context` words,
+// which are not what makes a task recognizable (`key:value` tags are already not in the subject)
+fn matched_subject<'a>(task: &'a Task, opts: &DiffOptions) -> Cow<'a, str> {
+    if opts.strict_subject {
+        Cow::Borrowed(&task.subject)
+    } else {
+        Cow::Owned(strip_subject_words(&task.subject, &['+', '@']))
+    }
+}
+
+// Returns the (removed, added) words between two lists, each sorted and without duplicates
+fn diff_words(from: &[String], to: &[String]) -> (Vec<String>, Vec<String>) {
+    let from = from.iter().collect::<BTreeSet<_>>();
+    let to = to.iter().collect::<BTreeSet<_>>();
+    (
+        from.difference(&to).map(|w| w.to_string()).collect(),
+        to.difference(&from).map(|w| w.to_string()).collect(),
+    )
+}
+
+// `date` plus one period of `rec`, like `rec + date` but `None` rather than a panic when the result
+// cannot be represented, eg. with `rec:+99999999y`. Months are added like todo_txt does: the day is
+// kept, unless it is the last one of its month or does not exist in the new month, in which case
+// it becomes the last day of the new month.
+pub fn add_recurrence(rec: &Recurrence, date: TaskDate) -> Option<TaskDate> {
+    use todo_txt::task::Period;
+    // Far beyond the range of dates, but not of Duration
+    const MAX_DAYS: i64 = 1 << 32;
+    let months = match rec.period {
+        Period::Day | Period::Week => {
+            let days = match rec.period {
+                Period::Week => rec.num.checked_mul(7)?,
+                _ => rec.num,
+            };
+            if days.abs() > MAX_DAYS {
+                return None;
+            }
+            return date.checked_add_signed(Duration::days(days));
+        }
+        Period::Month => rec.num,
+        Period::Year => rec.num.checked_mul(12)?,
+    };
+    let month = (i64::from(date.year()) * 12 + i64::from(date.month0())).checked_add(months)?;
+    let year = i32::try_from(month.div_euclid(12)).ok()?;
+    let month = month.rem_euclid(12) as u32 + 1;
+    let was_last_day = date.succ_opt().is_none_or(|d| d.day() == 1);
+    let last_day = Period::days_in_month(month, year);
+    let day = if was_last_day {
+        last_day
+    } else {
+        date.day().min(last_day)
+    };
+    TaskDate::from_ymd_opt(year, month, day)
+}
+
+// The next occurrence of the recurring task `from`. Strict recurrences move the due and threshold
+// dates by one period, while non-strict ones are due one period after the completion, the
+// threshold date keeping its distance to the due date. Tasks with a threshold date but no due date
+// get their threshold date moved the same way, and still no due date: topydo would give them one,
+// on the day of their new threshold date, but todo.txt lists seldom do.
 fn recur_task(from: &Task, rec: Recurrence) -> (Task, Changes) {
     let mut new_task = from.clone();
     new_task.uncomplete();
@@ -126,253 +635,1183 @@ fn recur_task(from: &Task, rec: Recurrence) -> (Task, Changes) {
     let change;
     if rec.strict {
         change = Changes::RecurredStrict;
-        new_task.due_date = from.due_date.map(|d| rec.clone() + d);
-        new_task.threshold_date = from.threshold_date.map(|d| rec + d);
+        new_task.due_date = from.due_date.and_then(|d| add_recurrence(&rec, d));
+        new_task.threshold_date = from.threshold_date.and_then(|d| add_recurrence(&rec, d));
+    } else if from.due_date.is_none() && from.threshold_date.is_some() {
+        change = Changes::RecurredFrom(from_finish);
+        new_task.threshold_date = from_finish.and_then(|d| add_recurrence(&rec, d));
     } else {
         change = Changes::RecurredFrom(from_finish);
-        new_task.due_date = from_finish.map(|d| rec + d);
-        match (from.due_date, from.threshold_date) {
-            (Some(from_due), Some(from_thresh)) => {
-                let delta = from_due.signed_duration_since(from_thresh);
-                new_task.threshold_date = new_task.due_date.map(|d| d - delta);
-            }
-            _ => {}
+        new_task.due_date = from_finish.and_then(|d| add_recurrence(&rec, d));
+        if let (Some(from_due), Some(from_thresh)) = (from.due_date, from.threshold_date) {
+            let delta = from_due.signed_duration_since(from_thresh);
+            new_task.threshold_date = new_task.due_date.and_then(|d| d.checked_sub_signed(delta));
         }
     }
 
-    if let Some(_) = from_finish {
+    if from_finish.is_some() {
         new_task.create_date = from_finish;
     }
 
     (new_task, change)
 }
 
+// Whether the due date of `to` is consistent, within a day, with `to` being a later occurrence of
+// the recurring task `from`. Strict recurrences may have elapsed any number of times, while
+// non-strict ones are due one period after the completion, which is also when the next occurrence
+// is usually created. Tasks without due dates have nothing to check.
+fn is_plausible_occurrence(from: &Task, to: &Task) -> bool {
+    let rec = match from.recurrence {
+        Some(ref rec) => rec.clone(),
+        None => return false,
+    };
+    let to_due = match to.due_date {
+        Some(to_due) => to_due,
+        None => return from.due_date.is_none(),
+    };
+    let is_close = |d: TaskDate| (d - to_due).num_days().abs() <= 1;
+    if rec.strict {
+        let mut due = match from.due_date {
+            Some(from_due) => from_due,
+            None => return false,
+        };
+        while due <= to_due {
+            let next = match add_recurrence(&rec, due) {
+                Some(next) => next,
+                None => break,
+            };
+            if is_close(next) {
+                return true;
+            }
+            if next <= due {
+                break;
+            }
+            due = next;
+        }
+        false
+    } else {
+        let completions = from.finish_date.into_iter().chain(to.create_date);
+        let mut completions = completions.peekable();
+        completions.peek().is_none()
+            || completions.any(|d| add_recurrence(&rec, d).is_some_and(is_close))
+    }
+}
+
+// The lowest priority is how todo_txt represents the absence of a priority
+pub fn priority_char(priority: &Priority) -> Option<char> {
+    if priority.is_lowest() {
+        None
+    } else {
+        Some(char::from(priority.clone()))
+    }
+}
+
 pub fn changes_between(from: &Task, to: &Task) -> Vec<Changes> {
+    changes_between_with(from, to, &DiffOptions::default())
+}
+
+pub fn changes_between_with(from: &Task, to: &Task, opts: &DiffOptions) -> Vec<Changes> {
     use self::Changes::*;
 
     let mut res = Vec::new();
 
     // Completion
     let mut done_finished_at = false;
-    if (from.finished == false)
-        && to.finished == true
-        && from.finish_date.is_none()
-        && to.finish_date.is_some()
-    {
-        res.push(FinishedAt(to.finish_date.expect("Internal error E005")));
+    if let (true, false, true, None, Some(to_finish)) = (
+        !opts.granular && opts.reports(Field::FinishDate),
+        from.finished,
+        to.finished,
+        from.finish_date,
+        to.finish_date,
+    ) {
+        res.push(FinishedAt(to_finish));
         done_finished_at = true;
     }
     if !done_finished_at && from.finished != to.finished {
         res.push(Finished(to.finished));
     }
-    if !done_finished_at && from.finish_date != to.finish_date {
+    if !done_finished_at && opts.reports(Field::FinishDate) && from.finish_date != to.finish_date {
         res.push(FinishDate(from.finish_date, to.finish_date));
     }
 
     // Dates
     let mut done_postponed_strict = false;
-    if from.due_date != to.due_date {
-        if let Some(d) = delta_task_dates(from, to) {
-            res.push(PostponedStrictBy(d));
+    if !opts.granular && opts.reports(Field::DueDate) && from.due_date != to.due_date {
+        if let (Some(d), Some(from_due), Some(to_due)) =
+            (delta_task_dates(from, to), from.due_date, to.due_date)
+        {
+            res.push(PostponedStrictBy(d, from_due, to_due));
             done_postponed_strict = true;
         }
     }
-    if !done_postponed_strict && from.threshold_date != to.threshold_date {
-        res.push(ThresholdDate(from.threshold_date, to.threshold_date));
+    if !done_postponed_strict
+        && opts.reports(Field::ThresholdDate)
+        && from.threshold_date != to.threshold_date
+    {
+        // Without due date, the threshold date is when the task is planned
+        match (
+            from.due_date,
+            to.due_date,
+            from.threshold_date,
+            to.threshold_date,
+        ) {
+            (None, None, Some(from_thresh), Some(to_thresh)) if !opts.granular => {
+                res.push(PostponedThresholdBy(
+                    to_thresh.signed_duration_since(from_thresh),
+                    from_thresh,
+                    to_thresh,
+                ))
+            }
+            _ => res.push(ThresholdDate(from.threshold_date, to.threshold_date)),
+        }
     }
-    if !done_postponed_strict && from.due_date != to.due_date {
+    if !done_postponed_strict && opts.reports(Field::DueDate) && from.due_date != to.due_date {
         res.push(DueDate(from.due_date, to.due_date));
     }
-    if from.create_date != to.create_date {
+    if opts.reports(Field::CreateDate) && from.create_date != to.create_date {
         res.push(CreateDate(from.create_date, to.create_date));
     }
 
     // Other changes
-    if from.priority != to.priority {
-        let from_prio;
-        if from.priority < 26 {
-            from_prio = Some((b'A' + from.priority) as char);
-        } else {
-            from_prio = None;
-        }
-        let to_prio;
-        if to.priority < 26 {
-            to_prio = Some((b'A' + to.priority) as char);
-        } else {
-            to_prio = None;
-        }
-        if !(done_finished_at && to_prio.is_none()) {
+    // Completing a task usually removes its priority, which is then not reported, so a priority
+    // kept on completion is reported even though it did not change. Both are reported as they
+    // are when granular, completions then not being `FinishedAt`.
+    if opts.reports(Field::Priority) {
+        let from_prio = priority_char(&from.priority);
+        let to_prio = priority_char(&to.priority);
+        if from.priority != to.priority {
+            if !(done_finished_at && to_prio.is_none()) {
+                res.push(Priority(from_prio, to_prio));
+            }
+        } else if done_finished_at && to_prio.is_some() {
             res.push(Priority(from_prio, to_prio));
         }
     }
-    if from.tags != to.tags {
-        let mut from_t = from
-            .tags
-            .iter()
-            .map(|(a, b)| (a.clone(), b.clone()))
-            .collect::<Vec<(String, String)>>();
-        let mut to_t = to
-            .tags
-            .iter()
-            .map(|(a, b)| (a.clone(), b.clone()))
-            .collect::<Vec<(String, String)>>();
-        remove_common(&mut from_t, &mut to_t);
-        res.push(Tags(from_t, to_t));
+    if opts.reports(Field::Tags) && from.tags != to.tags {
+        // The tags of `a` that `b` does not have with the same value
+        let only_in = |a: &Task, b: &Task| {
+            a.tags
+                .iter()
+                .filter(|(k, v)| b.tags.get(*k) != Some(*v))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
+        };
+        res.push(Tags(only_in(from, to), only_in(to, from)));
+    }
+    if opts.reports(Field::Recurrence) && from.recurrence != to.recurrence {
+        res.push(Changes::Recurrence(
+            from.recurrence.clone(),
+            to.recurrence.clone(),
+        ));
+    }
+    // Projects and contexts are part of the subject, so changing them changes the subject too
+    let mut stripped_prefixes = Vec::new();
+    let mut explained_subject = Cow::Borrowed(from.subject.as_str());
+    if opts.reports(Field::Projects) && from.projects != to.projects {
+        let (removed, mut added) = diff_words(&from.projects, &to.projects);
+        // Reordering the projects leaves nothing to report here, only a subject change
+        if !removed.is_empty() || !added.is_empty() {
+            sort_as_in_subject(&mut added, &to.subject, '+');
+            explained_subject =
+                edit_subject_words(&explained_subject, '+', &removed, &added).into();
+            res.push(Projects(removed, added));
+            stripped_prefixes.push('+');
+        }
     }
-    if from.subject != to.subject {
-        res.push(Subject(from.subject.clone(), to.subject.clone()));
+    if opts.reports(Field::Contexts) && from.contexts != to.contexts {
+        let (removed, mut added) = diff_words(&from.contexts, &to.contexts);
+        if !removed.is_empty() || !added.is_empty() {
+            sort_as_in_subject(&mut added, &to.subject, '@');
+            explained_subject =
+                edit_subject_words(&explained_subject, '@', &removed, &added).into();
+            res.push(Contexts(removed, added));
+            stripped_prefixes.push('@');
+        }
+    }
+    // Only report a subject change if it is not entirely explained by the changes above, or if
+    // these moved projects or contexts around in a way `apply_changes` cannot reproduce
+    if opts.reports(Field::Subject)
+        && from.subject != to.subject
+        && (stripped_prefixes.is_empty()
+            || strip_subject_words(&from.subject, &stripped_prefixes)
+                != strip_subject_words(&to.subject, &stripped_prefixes)
+            || explained_subject != to.subject.as_str())
+    {
+        res.push(Subject(SubjectDiff::new(
+            from.subject.clone(),
+            to.subject.clone(),
+            opts.word_diff,
+        )));
     }
     res
 }
 
-fn changes_between_rec(mut from: Task, to: Task, orig: &Task) -> Vec<Changes> {
+// If the finish date of the completed occurrence `from` was not recorded, the creation date of the
+// next occurrence `to`, as the next occurrence is usually created on completion
+fn inferred_finish_date(from: &Task, to: &Task) -> Option<TaskDate> {
+    if from.finished && from.finish_date.is_none() {
+        to.create_date
+    } else {
+        None
+    }
+}
+
+// Marks the completion of an occurrence, whose changes are `chgs`, as having been inferred
+fn mark_inferred(chgs: &mut Vec<Changes>, date: TaskDate) {
+    match chgs.iter().position(|c| *c == Changes::Finished(true)) {
+        Some(i) => chgs[i] = Changes::InferredFinishDate(date),
+        // The occurrence was already completed in the original list
+        None => chgs.insert(0, Changes::InferredFinishDate(date)),
+    }
+}
+
+fn changes_between_rec(mut from: Task, to: Task, orig: &Task, opts: &DiffOptions) -> Vec<Changes> {
     let rec = orig.recurrence.clone().unwrap();
-    // If the finish date of `from` was not recorded, infer it from `to`
-    if from.finished && from.finish_date == None {
-        from.finish_date = to.create_date;
+    if let Some(date) = inferred_finish_date(&from, &to) {
+        from.finish_date = Some(date);
     }
-    let (mut virtual_task, recur_change) = recur_task(&from, rec);
+    let (mut virtual_task, recur_change) = recur_task(&from, rec.clone());
     // Work around priority being removed on completion
-    if orig.priority < 26 {
-        virtual_task.priority = orig.priority;
+    if !orig.priority.is_lowest() {
+        virtual_task.priority = orig.priority.clone();
     }
+    // Occurrences of strict recurrences can be skipped, in which case `to` is not postponed
+    let skipped = if rec.strict {
+        skip_occurrences(&mut virtual_task, to.due_date, &rec)
+    } else {
+        0
+    };
+    let skipped_change = if skipped > 0 && opts.reports(Field::Skipped) {
+        Some(Changes::SkippedOccurrences(skipped))
+    } else {
+        None
+    };
 
     std::iter::once(recur_change)
-        .chain(changes_between(&virtual_task, &to))
+        .chain(skipped_change)
+        .chain(changes_between_with(&virtual_task, &to, opts))
         .collect::<Vec<Changes>>()
 }
 
-pub fn remove_common<T: Clone + Eq>(a: &mut Vec<T>, b: &mut Vec<T>) -> Vec<T> {
-    a.clone()
+// Moves `task` to its latest occurrence that is due no later than `due`, and returns the number
+// of occurrences thus skipped
+fn skip_occurrences(task: &mut Task, due: Option<TaskDate>, rec: &Recurrence) -> u32 {
+    let (mut cur, due) = match (task.due_date, due) {
+        (Some(cur), Some(due)) => (cur, due),
+        _ => return 0,
+    };
+    let mut skipped = 0;
+    loop {
+        let next = match add_recurrence(rec, cur) {
+            Some(next) if next <= due && next > cur => next,
+            _ => break,
+        };
+        cur = next;
+        skipped += 1;
+    }
+    task.due_date = Some(cur);
+    task.threshold_date = task
+        .threshold_date
+        .and_then(|t| (0..skipped).try_fold(t, |t, _| add_recurrence(rec, t)));
+    skipped
+}
+
+// Replaces in a subject the `removed` words by the `added` ones, all prefixed by `prefix` and
+// compared in lowercase like todo_txt does. The added words take the place of the removed ones in
+// order, and those left over are appended.
+fn edit_subject_words(subject: &str, prefix: char, removed: &[String], added: &[String]) -> String {
+    let word = |w: &str| {
+        w.strip_prefix(prefix)
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+    };
+    let is_removed = |w: &str| word(w).is_some_and(|w| removed.contains(&w));
+    let words = subject.split_whitespace().collect::<Vec<_>>();
+    let mut new = added
+        .iter()
+        .filter(|a| {
+            !words
+                .iter()
+                .any(|w| !is_removed(w) && word(w).as_ref() == Some(*a))
+        })
+        .map(|a| format!("{}{}", prefix, a))
+        .collect::<Vec<_>>()
+        .into_iter();
+    let mut res = words
         .into_iter()
-        .enumerate()
-        .rev()
-        .filter_map(|(i, x)| {
-            if let Some(b_pos) = b.iter().position(|y| *y == x) {
-                b.swap_remove(b_pos);
-                Some(a.swap_remove(i))
+        .filter_map(|w| {
+            if is_removed(w) {
+                new.next()
             } else {
-                None
+                Some(w.to_owned())
             }
         })
-        .collect()
-}
-
-fn is_task_admissible(from: &Task, other: &Task, allowed_divergence: usize) -> bool {
-    // The levenshtein distance is at least the difference between the lenghts
-    if 100 * (other.subject.len() as i64 - from.subject.len() as i64).abs()
-        > allowed_divergence as i64 * other.subject.len() as i64
-    {
-        return false;
-    }
-    let distance = levenshtein(&other.subject, &from.subject);
-    distance * 100 <= allowed_divergence * other.subject.len()
+        .collect::<Vec<_>>();
+    res.extend(new);
+    res.join(" ")
 }
 
-// Compares two tasks to determine which is closest to a third task
-fn cmp_tasks_3way(from: &Task, left: &Task, right: &Task) -> std::cmp::Ordering {
-    use std::cmp::Ordering::*;
-    let left_lev = levenshtein(&left.subject, &from.subject);
-    let right_lev = levenshtein(&right.subject, &from.subject);
-    if left_lev != right_lev {
-        left_lev.cmp(&right_lev)
-    } else {
-        // TODO: compare on other fields
-        Equal
-    }
+// Sorts words by their first occurrence in `subject`, prefixed by `prefix`, so that
+// `edit_subject_words` puts them back in the same order
+fn sort_as_in_subject(words: &mut [String], subject: &str, prefix: char) {
+    let words_in_subject = subject
+        .split_whitespace()
+        .filter_map(|w| w.strip_prefix(prefix).map(str::to_lowercase))
+        .collect::<Vec<_>>();
+    words.sort_by_key(|w| words_in_subject.iter().position(|s| s == w));
 }
 
-struct TaskMatcher {
-    allowed_divergence: usize,
-}
+// Applies `changes`, as computed by `changes_between`, to `task`: applying the changes between
+// `from` and `to` to `from` gives `to` back. Only the values after each change are used, so that
+// the changes can also be applied to a task that is merely similar to `from`.
+// A completion without a priority change removes the priority, like most todo.txt clients do.
+// Fails if the changes are about a recurrence the task does not have, or set an invalid priority.
+pub fn apply_changes(task: &Task, changes: &[Changes]) -> Result<Task, ApplyError> {
+    use self::Changes::*;
 
-impl stable_marriage::Matcher for TaskMatcher {
-    type Item = Task;
-    type Target = Task;
+    let orig = task;
+    let recurrence = |task: &Task| {
+        task.recurrence
+            .clone()
+            .ok_or_else(|| ApplyError::NotRecurring(orig.to_string()))
+    };
+    let mut task = task.clone();
+    let mut subject_changed = false;
+    for change in changes {
+        match change {
+            Created(_) => (),
+            RecurredStrict | RecurredFrom(_) => {
+                let rec = recurrence(&task)?;
+                if let RecurredFrom(Some(finish)) = change {
+                    task.finish_date = Some(*finish);
+                }
+                task = recur_task(&task, rec).0;
+            }
+            SkippedOccurrences(skipped) => {
+                let rec = recurrence(&task)?;
+                for _ in 0..*skipped {
+                    task.due_date = task.due_date.and_then(|d| add_recurrence(&rec, d));
+                    task.threshold_date = task.threshold_date.and_then(|t| add_recurrence(&rec, t));
+                }
+            }
 
-    fn is_admissible(&self, x: &Self::Item, y: &Self::Target) -> bool {
-        is_task_admissible(x, y, self.allowed_divergence)
-    }
+            FinishedAt(date) => {
+                task.finished = true;
+                task.finish_date = Some(*date);
+                task.priority = todo_txt::Priority::lowest();
+            }
+            PostponedStrictBy(delta, _, after) => {
+                task.due_date = Some(*after);
+                task.threshold_date = task
+                    .threshold_date
+                    .and_then(|t| t.checked_add_signed(*delta));
+            }
+            PostponedThresholdBy(_, _, after) => task.threshold_date = Some(*after),
+            // The inferred date is only that of the recurrence that follows
+            InferredFinishDate(_) => task.finished = true,
 
-    fn is_perfect_match(&self, x: &Self::Item, y: &Self::Target) -> bool {
-        x == y
+            Finished(finished) => task.finished = *finished,
+            Priority(_, after) => {
+                task.priority = match after {
+                    Some(p) => todo_txt::Priority::try_from(*p)
+                        .map_err(|_| ApplyError::InvalidPriority(*p))?,
+                    None => todo_txt::Priority::lowest(),
+                }
+            }
+            FinishDate(_, after) => task.finish_date = *after,
+            CreateDate(_, after) => task.create_date = *after,
+            Subject(s) => {
+                task.subject = s.after.clone();
+                subject_changed = true;
+            }
+            DueDate(_, after) => task.due_date = *after,
+            ThresholdDate(_, after) => task.threshold_date = *after,
+            Tags(removed, added) => {
+                for (k, v) in removed {
+                    if task.tags.get(k) == Some(v) {
+                        task.tags.remove(k);
+                    }
+                }
+                for (k, v) in added {
+                    task.tags.insert(k.clone(), v.clone());
+                }
+            }
+            Recurrence(_, after) => task.recurrence = after.clone(),
+            Projects(removed, added) => {
+                task.subject = edit_subject_words(&task.subject, '+', removed, added);
+                subject_changed = true;
+            }
+            Contexts(removed, added) => {
+                task.subject = edit_subject_words(&task.subject, '@', removed, added);
+                subject_changed = true;
+            }
+        }
     }
-
-    fn cmp_3way(
-        &self,
-        from: &Self::Item,
-        left: &Self::Target,
-        right: &Self::Target,
-    ) -> std::cmp::Ordering {
-        cmp_tasks_3way(from, left, right)
+    // Projects, contexts and hashtags are parsed from the subject
+    if subject_changed {
+        let parsed = todo_txt::parser::task(&task.subject);
+        task.projects = parsed.projects;
+        task.contexts = parsed.contexts;
+        task.hashtags = parsed.hashtags;
     }
+    Ok(task)
 }
 
-pub fn match_tasks(
-    from: Vec<Task>,
-    to: Vec<Task>,
-    allowed_divergence: usize,
-) -> (Vec<Task>, Vec<ChangedTask<Task>>) {
+// The tasks `task` became according to `delta`, as computed by `compute_changeset`
+pub fn apply_delta(task: &Task, delta: &TaskDelta<Vec<Changes>>) -> Result<Vec<Task>, ApplyError> {
     use self::TaskDelta::*;
+    Ok(match delta {
+        Identical => vec![task.clone()],
+        Deleted => Vec::new(),
+        Changed(chgs) => vec![apply_changes(task, chgs)?],
+        Recurred(occurrences) => {
+            let mut tasks = vec![apply_changes(task, &occurrences[0])?];
+            for chgs in &occurrences[1..] {
+                // Each occurrence starts from the previous one, with the original priority as
+                // in `changes_between_rec`
+                let mut previous = tasks[tasks.len() - 1].clone();
+                if !task.priority.is_lowest() {
+                    previous.priority = task.priority.clone();
+                }
+                tasks.push(apply_changes(&previous, chgs)?);
+            }
+            tasks
+        }
+        Split(tasks) | Copied(tasks) => tasks
+            .iter()
+            .map(|c| apply_changes(task, c))
+            .collect::<Result<_, _>>()?,
+    })
+}
 
-    let matcher = TaskMatcher {
-        allowed_divergence: allowed_divergence,
-    };
+// The tags of a `Changes::Tags`, with the keys that are both removed and added (ie. whose value
+// changed) paired up as (key, value before, value after)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TagsDiff<'a> {
+    pub removed: Vec<&'a (String, String)>,
+    pub changed: Vec<(&'a str, &'a str, &'a str)>,
+    pub added: Vec<&'a (String, String)>,
+}
 
-    // Compute a stable matching between the two task lists
-    let (matches, new_tasks) = stable_marriage::stable_marriage(to, from, &matcher, &matcher);
+pub fn pair_tags<'a>(
+    removed: &'a [(String, String)],
+    added: &'a [(String, String)],
+) -> TagsDiff<'a> {
+    let count = |tags: &[(String, String)], key: &str| tags.iter().filter(|t| t.0 == key).count();
+    let is_changed = |key: &str| count(removed, key) == 1 && count(added, key) == 1;
+    TagsDiff {
+        removed: removed.iter().filter(|t| !is_changed(&t.0)).collect(),
+        changed: removed
+            .iter()
+            .filter(|t| is_changed(&t.0))
+            .map(|(k, v)| {
+                let after = &added
+                    .iter()
+                    .find(|t| t.0 == *k)
+                    .expect("Internal error E035")
+                    .1;
+                (k.as_str(), v.as_str(), after.as_str())
+            })
+            .collect(),
+        added: added.iter().filter(|t| !is_changed(&t.0)).collect(),
+    }
+}
 
-    // Extract changed and deleted tasks
-    let mut matches = matches
-        .into_iter()
-        .map(|(from, mtch)| {
-            let delta = match mtch {
-                Some(to) => {
-                    if from == to {
-                        Identical
-                    } else if from.recurrence.is_some() && !from.finished {
-                        Recurred(vec![to])
-                    } else {
-                        Changed(to)
+// Removes the elements `a` and `b` have in common, counting duplicates, and returns them. The
+// survivors of both vectors and the returned elements keep their relative order.
+pub fn remove_common<T: Eq + Hash>(a: &mut Vec<T>, b: &mut Vec<T>) -> Vec<T> {
+    remove_common_by_key(a, b, |x| x)
+}
+
+// Like `remove_common`, for elements that cannot be hashed themselves: equal elements must have
+// equal keys, elements with equal keys being then compared one by one
+pub fn remove_common_by_key<T, K, F>(a: &mut Vec<T>, b: &mut Vec<T>, key: F) -> Vec<T>
+where
+    T: Eq,
+    K: Hash + Eq + ?Sized,
+    F: Fn(&T) -> &K,
+{
+    let (in_a, in_b) = {
+        // The positions in `b` of the elements of each key, not in common yet
+        let mut unmatched = HashMap::<&K, VecDeque<usize>>::new();
+        for (i, y) in b.iter().enumerate() {
+            unmatched.entry(key(y)).or_default().push_back(i);
+        }
+        let mut in_b = vec![false; b.len()];
+        let in_a = a
+            .iter()
+            .map(|x| {
+                let positions = match unmatched.get_mut(key(x)) {
+                    Some(positions) => positions,
+                    None => return false,
+                };
+                match positions.iter().position(|&j| b[j] == *x) {
+                    Some(p) => {
+                        in_b[positions.remove(p).expect("Internal error E055")] = true;
+                        true
                     }
+                    None => false,
                 }
-                None => Deleted,
-            };
-            ChangedTask {
-                orig: from,
-                delta: delta,
-            }
-        })
-        .collect::<Vec<ChangedTask<Task>>>();
+            })
+            .collect::<Vec<_>>();
+        (in_a, in_b)
+    };
+    split_off_marked(b, &in_b);
+    split_off_marked(a, &in_a)
+}
 
-    // Extract new tasks
-    let new_tasks = new_tasks
+// Removes the elements of `v` that are marked, and returns them
+fn split_off_marked<T>(v: &mut Vec<T>, marked: &[bool]) -> Vec<T> {
+    let (res, kept): (Vec<_>, Vec<_>) = std::mem::take(v)
         .into_iter()
-        // Separate recurred tasks from actual new ones
-        .flat_map(|x| {
-            let mut best_match = matches
-                .iter_mut()
-                .filter_map(|x| match x.delta {
-                    Recurred(ref mut recurred) => Some((&x.orig, recurred)),
-                    _ => None,
-                })
-                .filter(|(t, _)| is_task_admissible(t, &x, allowed_divergence))
-                .min_by(|(left, _), (right, _)| cmp_tasks_3way(&x, left, right));
-            if let Some((_, ref mut recurred)) = best_match {
-                recurred.push(x);
-                None
-            } else {
-                Some(x)
+        .zip(marked)
+        .partition(|&(_, &m)| m);
+    *v = kept.into_iter().map(|(x, _)| x).collect();
+    res.into_iter().map(|(x, _)| x).collect()
+}
+
+// What is known of the levenshtein distance between two subjects: admissibility checks only
+// compute whether it is above some bound
+#[derive(Debug, Clone, Copy)]
+enum KnownDistance {
+    Exact(usize),
+    Above(usize),
+}
+
+// The number of pairs of subjects whose distance is kept by a `DistanceCache`, ie. a few dozen
+// megabytes at most
+const DISTANCE_CACHE_CAPACITY: usize = 1 << 19;
+// Each locked separately, so that parallel matching seldom waits for another thread
+const DISTANCE_CACHE_SHARDS: usize = 64;
+
+type DistanceShard = Mutex<HashMap<(u32, u32), KnownDistance>>;
+
+// Levenshtein distances already computed while matching, as each pair of subjects is compared
+// many times: for the admissibility in both directions, for its score, and for each comparison
+// while sorting the candidates.
+// Subjects are numbered upfront, so that pairs are keyed by their numbers rather than by copies of
+// the subjects, the lowest number first as the distance is symmetric. Pairs involving a subject
+// that was not numbered are not cached. A full shard is emptied before adding a pair to it, so that
+// memory stays bounded however many pairs are compared.
+#[derive(Debug)]
+struct DistanceCache {
+    numbers: HashMap<String, u32>,
+    shards: Vec<DistanceShard>,
+    shard_capacity: usize,
+}
+
+impl Default for DistanceCache {
+    fn default() -> DistanceCache {
+        DistanceCache::new(Vec::new(), DISTANCE_CACHE_CAPACITY)
+    }
+}
+
+impl DistanceCache {
+    fn new<'a, I: IntoIterator<Item = Cow<'a, str>>>(
+        subjects: I,
+        capacity: usize,
+    ) -> DistanceCache {
+        let mut numbers = HashMap::new();
+        for subject in subjects {
+            let number = numbers.len() as u32;
+            numbers.entry(subject.into_owned()).or_insert(number);
+        }
+        DistanceCache {
+            numbers,
+            shards: (0..DISTANCE_CACHE_SHARDS)
+                .map(|_| Mutex::default())
+                .collect(),
+            shard_capacity: std::cmp::max(capacity / DISTANCE_CACHE_SHARDS, 1),
+        }
+    }
+
+    fn key(&self, a: &str, b: &str) -> Option<(u32, u32)> {
+        let (a, b) = (*self.numbers.get(a)?, *self.numbers.get(b)?);
+        Some((std::cmp::min(a, b), std::cmp::max(a, b)))
+    }
+
+    fn shard(&self, (a, b): (u32, u32)) -> MutexGuard<'_, HashMap<(u32, u32), KnownDistance>> {
+        // Consecutive numbers are spread over the shards
+        let hash = (u64::from(a) << 32 | u64::from(b)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let shard = (hash >> 32) as usize % self.shards.len();
+        self.shards[shard].lock().expect("Internal error E032")
+    }
+
+    fn get(&self, a: &str, b: &str) -> Option<KnownDistance> {
+        let key = self.key(a, b)?;
+        self.shard(key).get(&key).cloned()
+    }
+
+    fn set(&self, a: &str, b: &str, d: KnownDistance) {
+        if let Some(key) = self.key(a, b) {
+            let mut shard = self.shard(key);
+            if shard.len() >= self.shard_capacity && !shard.contains_key(&key) {
+                shard.clear();
             }
+            shard.insert(key, d);
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().len()).sum()
+    }
+
+    fn levenshtein(&self, a: &str, b: &str) -> usize {
+        if let Some(KnownDistance::Exact(d)) = self.get(a, b) {
+            return d;
+        }
+        let d = levenshtein(a, b);
+        self.set(a, b, KnownDistance::Exact(d));
+        d
+    }
+
+    // Like `bounded_levenshtein`
+    fn levenshtein_within(&self, a: &str, b: &str, bound: usize) -> Option<usize> {
+        match self.get(a, b) {
+            Some(KnownDistance::Exact(d)) => return Some(d).filter(|d| *d <= bound),
+            Some(KnownDistance::Above(n)) if n >= bound => return None,
+            _ => (),
+        }
+        let res = bounded_levenshtein(a, b, bound);
+        self.set(
+            a,
+            b,
+            res.map_or(KnownDistance::Above(bound), KnownDistance::Exact),
+        );
+        res
+    }
+}
+
+fn is_task_admissible(
+    from: &Task,
+    other: &Task,
+    opts: &DiffOptions,
+    distances: &DistanceCache,
+) -> bool {
+    opts.similarity_metric.is_admissible(
+        &matched_subject(from, opts),
+        &matched_subject(other, opts),
+        opts.allowed_divergence,
+        distances,
+    )
+}
+
+fn is_levenshtein_admissible(
+    from: &str,
+    other: &str,
+    allowed_divergence: usize,
+    distances: &DistanceCache,
+) -> bool {
+    // The distance is normalized by the length of the longest subject, so that admissibility is
+    // symmetric. Lengths are counted in characters, like the levenshtein distance.
+    let max_len = std::cmp::max(from.chars().count(), other.chars().count());
+    // Only whether the distance is small enough matters, not its exact value. In particular, the
+    // distance is at least the difference between the lengths, which is checked first.
+    let bound = allowed_divergence * max_len / 100;
+    distances.levenshtein_within(other, from, bound).is_some()
+}
+
+// Returns the number of distinct words in only one of the subjects, and the total number of
+// distinct words
+fn token_divergence(from: &str, other: &str) -> (usize, usize) {
+    let words = |s: &str| {
+        s.split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<BTreeSet<_>>()
+    };
+    let (from, other) = (words(from), words(other));
+    let total = from.union(&other).count();
+    (total - from.intersection(&other).count(), total)
+}
+
+// Orders left before right if only left is equal to from
+fn cmp_equality<T: PartialEq>(from: &T, left: &T, right: &T) -> std::cmp::Ordering {
+    (right == from).cmp(&(left == from))
+}
+
+fn shared_tags(x: &Task, y: &Task) -> usize {
+    x.tags
+        .iter()
+        .filter(|(k, v)| y.tags.get(*k) == Some(v))
+        .count()
+}
+
+// Compares two tasks to determine which is closest to a third task. Only identical tasks are
+// equally close, so that which one is preferred does not depend on their order in the lists.
+fn cmp_tasks_3way(
+    from: &Task,
+    left: &Task,
+    right: &Task,
+    opts: &DiffOptions,
+    distances: &DistanceCache,
+) -> std::cmp::Ordering {
+    let metric = opts.similarity_metric;
+    let from_subject = matched_subject(from, opts);
+    let left_dist = metric.distance(&from_subject, &matched_subject(left, opts), distances);
+    let right_dist = metric.distance(&from_subject, &matched_subject(right, opts), distances);
+    // On equal subject distances, fall back to the other fields
+    left_dist
+        .cmp(&right_dist)
+        .then_with(|| cmp_equality(&from.due_date, &left.due_date, &right.due_date))
+        .then_with(|| cmp_equality(&from.create_date, &left.create_date, &right.create_date))
+        .then_with(|| cmp_equality(&from.priority, &left.priority, &right.priority))
+        .then_with(|| shared_tags(from, right).cmp(&shared_tags(from, left)))
+        // Arbitrary, but depending on the tasks only
+        .then_with(|| cmp_task_fields(left, right))
+}
+
+// Orders tasks by all their fields, so that only identical tasks are equal, without formatting
+// them as this is called on every comparison of the preference lists
+fn cmp_task_fields(left: &Task, right: &Task) -> std::cmp::Ordering {
+    left.subject
+        .cmp(&right.subject)
+        .then_with(|| left.cmp(right))
+        .then_with(|| left.finished.cmp(&right.finished))
+        .then_with(|| left.finish_date.cmp(&right.finish_date))
+        .then_with(|| left.create_date.cmp(&right.create_date))
+        .then_with(|| left.threshold_date.cmp(&right.threshold_date))
+        .then_with(|| left.tags.cmp(&right.tags))
+        .then_with(|| (left.flagged, left.hidden).cmp(&(right.flagged, right.hidden)))
+        .then_with(|| {
+            let rec = |t: &Task| t.recurrence.as_ref().map(ToString::to_string);
+            rec(left).cmp(&rec(right))
+        })
+        .then_with(|| {
+            use todo_txt::task::Note;
+            let note = |t: &Task| match t.note {
+                Note::None => None,
+                Note::Short(ref content) => Some((None, content.clone())),
+                Note::Long {
+                    ref filename,
+                    ref content,
+                } => Some((Some(filename.clone()), content.clone())),
+            };
+            note(left).cmp(&note(right))
+        })
+}
+
+// How similar two tasks are for matching, see `explain_matches`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Similarity {
+    Identical,
+    // Both tasks carry the id tag, with the same value or not, so that their subjects do not matter
+    SameId,
+    DifferentId,
+    // The similarity of the subjects in percents, and whether it is enough for the tasks to be
+    // matched
+    Subject(usize, bool),
+}
+
+impl Similarity {
+    // Tasks matched by their id are as similar as identical ones
+    pub fn percent(self) -> u8 {
+        match self {
+            Similarity::Identical | Similarity::SameId => 100,
+            Similarity::DifferentId => 0,
+            Similarity::Subject(percent, _) => percent as u8,
+        }
+    }
+
+    pub fn is_admissible(self) -> bool {
+        match self {
+            Similarity::Identical | Similarity::SameId => true,
+            Similarity::DifferentId => false,
+            Similarity::Subject(_, admissible) => admissible,
+        }
+    }
+}
+
+impl std::fmt::Display for Similarity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Similarity::Identical => write!(f, "identical"),
+            Similarity::SameId => write!(f, "same id"),
+            Similarity::DifferentId => write!(f, "different id"),
+            Similarity::Subject(percent, true) => write!(f, "{}% similar", percent),
+            Similarity::Subject(percent, false) => {
+                write!(f, "{}% similar, not enough to match", percent)
+            }
+        }
+    }
+}
+
+// Why a task of `from` was matched with a task of `to`, or with none
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MatchExplanation {
+    pub orig: Task,
+    pub partner: Option<(Task, Similarity)>,
+    // Whether the partner is a task of `archived` rather than of `to`, see `match_archived_tasks`
+    pub archived: bool,
+    // The closest task of `to` other than the partner, matched with another task or not
+    // admissible
+    pub best_rejected: Option<(Task, Similarity)>,
+}
+
+// Why a new task was attached as an occurrence of a recurring task, or not
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Reattachment {
+    Attached(Task),
+    // No task of `from` recurred, so that there is nothing to attach to
+    NoRecurringTask,
+    // The closest recurring task is not similar enough
+    NotSimilar(Task, Similarity),
+    // The closest recurring task that is similar enough has a due date inconsistent with the new
+    // task being an occurrence of it, see `is_plausible_occurrence`
+    NotPlausible(Task),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Explanation {
+    pub matches: Vec<MatchExplanation>,
+    // The tasks of `to` not matched with any task of `from`
+    pub new_tasks: Vec<(Task, Reattachment)>,
+}
+
+struct TaskMatcher<'a> {
+    opts: &'a DiffOptions,
+    distances: DistanceCache,
+}
+
+impl<'a> TaskMatcher<'a> {
+    // Only caches the distances between the subjects of `tasks`, see `DistanceCache`
+    fn new<'t, I: IntoIterator<Item = &'t Task>>(
+        opts: &'a DiffOptions,
+        tasks: I,
+    ) -> TaskMatcher<'a> {
+        let subjects = tasks.into_iter().map(|t| matched_subject(t, opts));
+        TaskMatcher {
+            opts,
+            distances: DistanceCache::new(subjects, DISTANCE_CACHE_CAPACITY),
+        }
+    }
+
+    // Returns None if either task has no id, otherwise whether both ids are the same
+    fn same_id(&self, x: &Task, y: &Task) -> Option<bool> {
+        match (x.tags.get(&self.opts.id_tag), y.tags.get(&self.opts.id_tag)) {
+            (Some(a), Some(b)) => Some(a == b),
+            _ => None,
+        }
+    }
+
+    // Whether either task may only be matched with an identical task, see `MatchPins`
+    fn is_unmatchable(&self, x: &Task, y: &Task) -> bool {
+        self.opts.pins.is_unmatchable(x) || self.opts.pins.is_unmatchable(y)
+    }
+
+    fn similarity(&self, x: &Task, y: &Task) -> Similarity {
+        if x == y {
+            return Similarity::Identical;
+        }
+        let by_id = !self.opts.exact_only && !self.is_unmatchable(x, y);
+        match self.same_id(x, y) {
+            Some(true) if by_id => Similarity::SameId,
+            Some(false) if by_id => Similarity::DifferentId,
+            _ => Similarity::Subject(
+                self.opts.similarity_metric.similarity(
+                    &matched_subject(x, self.opts),
+                    &matched_subject(y, self.opts),
+                    &self.distances,
+                ),
+                self.is_admissible(x, y),
+            ),
+        }
+    }
+
+    // Explains the match of `from` with `partner`, one of the tasks of `to`
+    fn explain_match(&self, from: &Task, partner: Option<&Task>, to: &[Task]) -> MatchExplanation {
+        let mut partner_skipped = false;
+        let best_rejected = to
+            .iter()
+            .filter(|t| {
+                // Only skip one copy of the partner, in case it is duplicated
+                let is_partner = !partner_skipped && Some(*t) == partner;
+                partner_skipped |= is_partner;
+                !is_partner
+            })
+            .min_by(|left, right| self.cmp_3way(from, left, right));
+        MatchExplanation {
+            orig: from.clone(),
+            partner: partner.map(|t| (t.clone(), self.similarity(from, t))),
+            archived: false,
+            best_rejected: best_rejected.map(|t| (t.clone(), self.similarity(from, t))),
+        }
+    }
+}
+
+impl<'a> stable_marriage::Matcher for TaskMatcher<'a> {
+    type Item = Task;
+    type Target = Task;
+
+    fn is_admissible(&self, x: &Self::Item, y: &Self::Target) -> bool {
+        if self.opts.exact_only || self.is_unmatchable(x, y) {
+            return x == y;
+        }
+        match self.same_id(x, y) {
+            Some(same) => same,
+            None => is_task_admissible(x, y, self.opts, &self.distances),
+        }
+    }
+
+    // The similarity of admissible tasks, which `cmp_3way` refines with the other criteria of
+    // `cmp_tasks_3way`
+    fn score(&self, x: &Self::Item, y: &Self::Target) -> Option<u32> {
+        if self.is_admissible(x, y) {
+            Some(u32::from(self.similarity(x, y).percent()))
+        } else {
+            None
+        }
+    }
+
+    fn is_perfect_match(&self, x: &Self::Item, y: &Self::Target) -> bool {
+        x == y
+            || (!self.opts.exact_only
+                && !self.is_unmatchable(x, y)
+                && self.same_id(x, y) == Some(true))
+    }
+
+    fn precompute_preference_lists(&self) -> bool {
+        self.opts.parallel
+    }
+
+    fn supports_ranking(&self) -> bool {
+        true
+    }
+
+    fn progress(&self, phase: Phase, done: usize, total: usize) {
+        self.opts.progress.report(phase, done, total);
+    }
+
+    fn cmp_3way(
+        &self,
+        from: &Self::Item,
+        left: &Self::Target,
+        right: &Self::Target,
+    ) -> std::cmp::Ordering {
+        // A task with the same id is always closer
+        let left_same = self.same_id(from, left) == Some(true);
+        let right_same = self.same_id(from, right) == Some(true);
+        right_same
+            .cmp(&left_same)
+            .then_with(|| cmp_tasks_3way(from, left, right, self.opts, &self.distances))
+    }
+}
+
+// Pairs each task of `left` with the most similar task of `right`, the same way `match_tasks` does.
+// Returns the tasks of `left` with their match, and the tasks of `right` left unmatched.
+pub fn match_similar_tasks(
+    left: Vec<Task>,
+    right: Vec<Task>,
+    opts: &DiffOptions,
+) -> (Vec<(Task, Option<Task>)>, Vec<Task>) {
+    let matcher = TaskMatcher::new(opts, left.iter().chain(&right));
+    stable_marriage::stable_marriage(right, left, &matcher, &matcher)
+}
+
+// How similar `from` and `to` are for `match_tasks`, in percents: the normalized distance between
+// their subjects according to `opts.similarity_metric`, or 100 if they share an id
+pub fn match_similarity(from: &Task, to: &Task, opts: &DiffOptions) -> u8 {
+    TaskMatcher::new(opts, None).similarity(from, to).percent()
+}
+
+// Whether `match_tasks` could pair `from` with `to`, regardless of the other tasks
+pub fn is_admissible_match(from: &Task, to: &Task, opts: &DiffOptions) -> bool {
+    TaskMatcher::new(opts, None).is_admissible(from, to)
+}
+
+// The line numbers of `match_tasks`, where tasks are not read from files
+fn numbered(tasks: Vec<Task>) -> Vec<(usize, Task)> {
+    tasks
+        .into_iter()
+        .enumerate()
+        .map(|(i, t)| (i + 1, t))
+        .collect()
+}
+
+// Tasks pinned together by `opts.pins` are matched regardless of their similarity, the line
+// numbers of the pins being the positions of the tasks in the lists
+pub fn match_tasks(
+    from: Vec<Task>,
+    to: Vec<Task>,
+    opts: &DiffOptions,
+) -> (Vec<Task>, Vec<ChangedTask<Task>>) {
+    let (new_tasks, matches, _) =
+        match_tasks_with_explanation(numbered(from), numbered(to), opts, false);
+    (new_tasks, matches)
+}
+
+// Tells how similar each task was to its match in `match_tasks_indexed` and to the closest other
+// candidate, and why new tasks were attached to recurring tasks or not
+pub fn explain_matches(
+    from: Vec<(usize, Task)>,
+    to: Vec<(usize, Task)>,
+    opts: &DiffOptions,
+) -> Explanation {
+    let (_, _, explanation) = match_tasks_with_explanation(from, to, opts, true);
+    explanation.expect("Internal error E046")
+}
+
+// Two tasks pinned together, after the position of the task of `from`
+type PinnedPair = (usize, Task, Task);
+
+// Sets the tasks pinned together by `opts.pins` aside from the others
+fn take_pinned(
+    from: Vec<(usize, Task)>,
+    to: Vec<(usize, Task)>,
+    opts: &DiffOptions,
+) -> (Vec<Task>, Vec<Task>, Vec<PinnedPair>) {
+    let pairs = opts.pins.resolve(&from, &to);
+    let mut from = from.into_iter().map(|(_, t)| Some(t)).collect::<Vec<_>>();
+    let mut to = to.into_iter().map(|(_, t)| Some(t)).collect::<Vec<_>>();
+    let mut pinned = pairs
+        .into_iter()
+        .map(|(i, j)| {
+            let take = |t: &mut Option<Task>| t.take().expect("Internal error E047");
+            (i, take(&mut from[i]), take(&mut to[j]))
         })
         .collect::<Vec<_>>();
+    pinned.sort_by_key(|(i, _, _)| *i);
+    let rest = |tasks: Vec<Option<Task>>| tasks.into_iter().flatten().collect();
+    (rest(from), rest(to), pinned)
+}
+
+// The explanation is only computed if `explain` is set, as it compares each task with all others
+fn match_tasks_with_explanation(
+    from: Vec<(usize, Task)>,
+    to: Vec<(usize, Task)>,
+    opts: &DiffOptions,
+    explain: bool,
+) -> (Vec<Task>, Vec<ChangedTask<Task>>, Option<Explanation>) {
+    use self::TaskDelta::*;
+
+    let matcher = TaskMatcher::new(opts, from.iter().chain(&to).map(|(_, t)| t));
+    // Even identical subjects would let new occurrences be attached to recurring tasks
+    let detect_recurrences = opts.detect_recurrences && !opts.exact_only;
+    let all_to = if explain {
+        to.iter().map(|(_, t)| t.clone()).collect()
+    } else {
+        Vec::new()
+    };
+
+    // Compute a stable matching between the two task lists, once pinned tasks are set aside
+    let (from, to, pinned) = take_pinned(from, to, opts);
+    let reduced = opts.reduces_matching(from.len(), to.len());
+    let (matches, new_tasks) = match_equal_subjects(from, to, opts, |from, to| {
+        if reduced {
+            partitioned_stable_marriage(from, to, &matcher)
+        } else {
+            stable_marriage::stable_marriage(to, from, &matcher, &matcher)
+        }
+    });
+    let mut matches = matches.into_iter();
+    let mut pinned = pinned.into_iter().peekable();
+    let matches = (0..)
+        .map_while(|i| match pinned.next_if(|(j, _, _)| *j == i) {
+            Some((_, from, to)) => Some((from, Some(to))),
+            None => matches.next(),
+        })
+        .collect::<Vec<_>>();
+    let mut explanation = Some(Explanation::default()).filter(|_| explain);
+    if let Some(ref mut explanation) = explanation {
+        explanation.matches = matches
+            .iter()
+            .map(|(from, mtch)| matcher.explain_match(from, mtch.as_ref(), &all_to))
+            .collect();
+    }
+
+    // Extract changed and deleted tasks
+    let mut matches = matches
+        .into_iter()
+        .map(|(from, mtch)| {
+            let delta = match mtch {
+                Some(to) => {
+                    let recurring = detect_recurrences
+                        && from.recurrence.is_some()
+                        && from.recurrence == to.recurrence;
+                    // A task already completed can still have its next occurrence in `to`
+                    if recurring && from.finished && to.finished {
+                        Recurred(vec![to])
+                    } else if from == to {
+                        Identical
+                    } else if recurring && !from.finished {
+                        Recurred(vec![to])
+                    } else {
+                        Changed(to)
+                    }
+                }
+                None => Deleted,
+            };
+            (from, delta)
+        })
+        .collect::<Vec<(Task, TaskDelta<Task>)>>();
+
+    // Separate recurred tasks from actual new ones. Attaching an occurrence does not change which
+    // tasks recurred, so the best match of each new task can be looked for independently.
+    // New tasks must also have a due date consistent with the recurrence, lest recurring tasks
+    // steal new tasks that merely have a similar subject. Tasks already completed in `from` are
+    // always checked, as they would otherwise adopt any such task.
+    let recurring = matches
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (orig, delta))| match delta {
+            Recurred(r) => Some((i, orig, &r[0])),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let best_matches = map_tasks(new_tasks, opts, |x| {
+        // The closest recurring task among those accepted by `keep`
+        let closest = |keep: &dyn Fn(&Task, &Task) -> bool| {
+            recurring
+                .iter()
+                .filter(|(_, t, first)| keep(t, first))
+                .min_by(|(_, left, _), (_, right, _)| matcher.cmp_3way(&x, left, right))
+        };
+        let admissible = |t: &Task| matcher.is_admissible(t, &x);
+        let best_match = closest(&|t, first| {
+            admissible(t)
+                && ((opts.loose_recurrence && !t.finished) || is_plausible_occurrence(first, &x))
+        });
+        let reattachment = if !explain {
+            None
+        } else if let Some((_, t, _)) = best_match {
+            Some(Reattachment::Attached((*t).clone()))
+        } else if let Some((_, t, _)) = closest(&|t, _| admissible(t)) {
+            Some(Reattachment::NotPlausible((*t).clone()))
+        } else if let Some((_, t, _)) = closest(&|_, _| true) {
+            Some(Reattachment::NotSimilar(
+                (*t).clone(),
+                matcher.similarity(t, &x),
+            ))
+        } else {
+            Some(Reattachment::NoRecurringTask)
+        };
+        (best_match.map(|(i, _, _)| *i), reattachment, x)
+    });
+    let mut new_tasks = Vec::new();
+    for (best_match, reattachment, x) in best_matches {
+        if let (Some(explanation), Some(reattachment)) = (explanation.as_mut(), reattachment) {
+            explanation.new_tasks.push((x.clone(), reattachment));
+        }
+        match best_match.map(|i| &mut matches[i].1) {
+            Some(Recurred(recurred)) => recurred.push(x),
+            _ => new_tasks.push(x),
+        }
+    }
+    if opts.detect_splits && !opts.exact_only {
+        new_tasks = detect_splits(&mut matches, new_tasks, opts);
+    }
+    if opts.detect_copies && !opts.exact_only {
+        new_tasks = detect_copies(&mut matches, new_tasks, &matcher);
+    }
 
     let matches = matches
         .into_iter()
-        .map(|ChangedTask { orig, delta }| {
+        .map(|(orig, delta)| {
             let new_delta = match delta {
                 Recurred(mut recurred) => {
-                    if recurred.len() == 1 {
+                    if recurred == std::slice::from_ref(&orig) {
+                        Identical
+                    } else if recurred.len() == 1 {
                         Changed(recurred.remove(0))
                     } else {
                         recurred.sort_by_key(|t| t.due_date);
@@ -381,14 +1820,321 @@ pub fn match_tasks(
                 }
                 _ => delta,
             };
-            ChangedTask {
-                orig: orig,
-                delta: new_delta,
-            }
+            ChangedTask::new(orig, new_delta).scored(&matcher)
         })
         .collect::<Vec<ChangedTask<Task>>>();
 
-    (new_tasks, matches)
+    (new_tasks, matches, explanation)
+}
+
+// The subjects of tasks with the same key are at distance zero from each other, see
+// `SimilarityMetric::distance`, and further from any other subject
+fn subject_key(task: &Task, opts: &DiffOptions) -> String {
+    match opts.similarity_metric {
+        SimilarityMetric::Levenshtein => matched_subject(task, opts).into_owned(),
+        SimilarityMetric::Token => subject_words(task, opts).into_iter().join(" "),
+    }
+}
+
+// Pairs the tasks of `from` and `to` whose subject key (see `subject_key`) is found once in each
+// list, as such tasks prefer each other to any other task and are thus matched together by
+// `stable_marriage` anyway, and leaves the other tasks to `rest`. This spares ranking candidates
+// for the tasks whose dates or priority merely changed. Tasks carrying the id tag are matched by
+// it, and unmatchable tasks only with identical ones, so that they are always left to `rest`, but
+// their subjects still count.
+fn match_equal_subjects<F>(
+    from: Vec<Task>,
+    to: Vec<Task>,
+    opts: &DiffOptions,
+    rest: F,
+) -> (Vec<(Task, Option<Task>)>, Vec<Task>)
+where
+    F: FnOnce(Vec<Task>, Vec<Task>) -> (Vec<(Task, Option<Task>)>, Vec<Task>),
+{
+    if opts.exact_only {
+        return rest(from, to);
+    }
+    let is_eligible = |t: &Task| !t.tags.contains_key(&opts.id_tag) && !opts.pins.is_unmatchable(t);
+    let from_keys = from
+        .iter()
+        .map(|t| subject_key(t, opts))
+        .collect::<Vec<_>>();
+    let to_keys = to.iter().map(|t| subject_key(t, opts)).collect::<Vec<_>>();
+    // The number of tasks with each key in `from` and in `to`, and the position of the last one
+    // in `to`
+    let mut counts = HashMap::<&str, (usize, usize, usize)>::new();
+    for k in from_keys.iter() {
+        counts.entry(k).or_default().0 += 1;
+    }
+    for (j, k) in to_keys.iter().enumerate() {
+        let count = counts.entry(k).or_default();
+        count.1 += 1;
+        count.2 = j;
+    }
+    let pairs = from
+        .iter()
+        .zip(&from_keys)
+        .map(|(t, k)| match counts[k.as_str()] {
+            (1, 1, j) if is_eligible(t) && is_eligible(&to[j]) => Some(j),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if pairs.iter().all(Option::is_none) {
+        return rest(from, to);
+    }
+
+    let mut to = to.into_iter().map(Some).collect::<Vec<_>>();
+    let mut matches = Vec::with_capacity(from.len());
+    let mut rest_positions = Vec::new();
+    let mut rest_from = Vec::new();
+    for (i, (t, pair)) in from.into_iter().zip(pairs).enumerate() {
+        match pair {
+            Some(j) => matches.push((i, (t, to[j].take()))),
+            None => {
+                rest_positions.push(i);
+                rest_from.push(t);
+            }
+        }
+    }
+    let (rest_matches, new_tasks) = rest(rest_from, to.into_iter().flatten().collect());
+    matches.extend(rest_positions.into_iter().zip(rest_matches));
+    // Back in the order of `from`
+    matches.sort_by_key(|(i, _)| *i);
+    (matches.into_iter().map(|(_, x)| x).collect(), new_tasks)
+}
+
+// Like `stable_marriage::stable_marriage(to, from, ..)`, but only between the tasks of each
+// partition (see `FuzzyPartition`), so that the number of comparisons is that of the largest one.
+// The new tasks are grouped by partition.
+fn partitioned_stable_marriage(
+    from: Vec<Task>,
+    to: Vec<Task>,
+    matcher: &TaskMatcher,
+) -> (Vec<(Task, Option<Task>)>, Vec<Task>) {
+    let opts = matcher.opts;
+    let mut partitions = BTreeMap::<String, (Vec<usize>, Vec<Task>, Vec<Task>)>::new();
+    for (i, t) in from.into_iter().enumerate() {
+        let partition = partitions.entry(opts.fuzzy_partition.key(&t, opts));
+        let (positions, from, _) = partition.or_default();
+        positions.push(i);
+        from.push(t);
+    }
+    for t in to {
+        let partition = partitions.entry(opts.fuzzy_partition.key(&t, opts));
+        partition.or_default().2.push(t);
+    }
+
+    let mut matches = Vec::new();
+    let mut new_tasks = Vec::new();
+    for (positions, from, to) in partitions.into_values() {
+        let (partition_matches, partition_new) =
+            stable_marriage::stable_marriage(to, from, matcher, matcher);
+        matches.extend(positions.into_iter().zip(partition_matches));
+        new_tasks.extend(partition_new);
+    }
+    // Back in the order of `from`
+    matches.sort_by_key(|(i, _)| *i);
+    (matches.into_iter().map(|(_, x)| x).collect(), new_tasks)
+}
+
+// The line numbers of the tasks of a list, looked up by their content: matching moves tasks
+// around, but identical tasks are interchangeable anyway
+struct LineIndex(HashMap<String, Vec<(usize, Task)>>);
+
+impl LineIndex {
+    fn new(tasks: &[(usize, Task)]) -> LineIndex {
+        let mut index = HashMap::new();
+        for (line, t) in tasks.iter().rev() {
+            index
+                .entry(t.to_string())
+                .or_insert_with(Vec::new)
+                .push((*line, t.clone()));
+        }
+        LineIndex(index)
+    }
+
+    // Each line is only handed out once, in file order for identical tasks
+    fn take(&mut self, task: &Task) -> usize {
+        let lines = self
+            .0
+            .get_mut(&task.to_string())
+            .expect("Internal error E039");
+        let pos = lines
+            .iter()
+            .rposition(|(_, t)| t == task)
+            .expect("Internal error E040");
+        lines.remove(pos).0
+    }
+}
+
+// Like `match_tasks`, but for tasks paired with their line numbers (see `read_tasks_indexed`),
+// which are carried over to the result. The matching itself only uses them for the pins.
+pub fn match_tasks_indexed(
+    from: Vec<(usize, Task)>,
+    to: Vec<(usize, Task)>,
+    opts: &DiffOptions,
+) -> IndexedMatches {
+    let (matches, _) = match_tasks_indexed_with_explanation(from, to, opts, false);
+    matches
+}
+
+// The new tasks with their line numbers, and the matches of the other tasks
+type IndexedMatches = (Vec<(usize, Task)>, Vec<ChangedTask<Task>>);
+
+// Like `match_tasks_indexed`, see `match_tasks_with_explanation`
+fn match_tasks_indexed_with_explanation(
+    from: Vec<(usize, Task)>,
+    to: Vec<(usize, Task)>,
+    opts: &DiffOptions,
+    explain: bool,
+) -> (IndexedMatches, Option<Explanation>) {
+    let mut from_lines = LineIndex::new(&from);
+    let mut to_lines = LineIndex::new(&to);
+    let (new_tasks, matches, explanation) = match_tasks_with_explanation(from, to, opts, explain);
+    let matches = matches
+        .into_iter()
+        .map(|x| ChangedTask {
+            orig_line: Some(from_lines.take(&x.orig)),
+            after_lines: x.after.iter().map(|t| to_lines.take(t)).collect(),
+            ..x
+        })
+        .collect();
+    let new_tasks = new_tasks
+        .into_iter()
+        .map(|t| (to_lines.take(&t), t))
+        .collect();
+    ((new_tasks, matches), explanation)
+}
+
+// The number of words of `task` if it could be part of `orig` once split, that is if its subject
+// is made of at least two words of the subject of `orig`, and of at least a third of them. This
+// is meant to be conservative, as eg. any single-word task would otherwise be part of many.
+fn fragment_size(orig_words: &BTreeSet<String>, task: &Task, opts: &DiffOptions) -> Option<usize> {
+    let words = subject_words(task, opts);
+    let is_fragment = words.len() >= 2
+        && words.len() < orig_words.len()
+        && 3 * words.len() >= orig_words.len()
+        && words.is_subset(orig_words);
+    Some(words.len()).filter(|_| is_fragment)
+}
+
+fn subject_words(task: &Task, opts: &DiffOptions) -> BTreeSet<String> {
+    matched_subject(task, opts)
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// Turns the changed and deleted tasks of `matches` into splits when enough new tasks are
+// fragments of them (see `fragment_size`): a changed task needs the task it became to be a
+// fragment too, and a deleted one two new fragments. Returns the remaining new tasks.
+fn detect_splits(
+    matches: &mut [(Task, TaskDelta<Task>)],
+    new_tasks: Vec<Task>,
+    opts: &DiffOptions,
+) -> Vec<Task> {
+    use self::TaskDelta::*;
+    let candidates = matches
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (orig, delta))| {
+            let words = subject_words(orig, opts);
+            let is_candidate = match delta {
+                Deleted => true,
+                Changed(t) => fragment_size(&words, t, opts).is_some(),
+                _ => false,
+            };
+            Some((i, words)).filter(|_| is_candidate)
+        })
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return new_tasks;
+    }
+
+    // Each new task is a fragment of the task it shares the most words with
+    let mut fragments = HashMap::new();
+    for (j, t) in new_tasks.iter().enumerate() {
+        let best = candidates
+            .iter()
+            .filter_map(|(i, words)| fragment_size(words, t, opts).map(|n| (n, *i)))
+            .max_by_key(|(n, i)| (*n, std::cmp::Reverse(*i)));
+        if let Some((_, i)) = best {
+            fragments.entry(i).or_insert_with(Vec::new).push(j);
+        }
+    }
+
+    let mut split_tasks = BTreeSet::new();
+    for (i, js) in fragments {
+        let delta = &mut matches[i].1;
+        let mut parts = match delta {
+            Changed(t) => vec![t.clone()],
+            _ => Vec::new(),
+        };
+        if parts.len() + js.len() < 2 {
+            continue;
+        }
+        parts.extend(js.iter().map(|j| new_tasks[*j].clone()));
+        *delta = Split(parts);
+        split_tasks.extend(js);
+    }
+    new_tasks
+        .into_iter()
+        .enumerate()
+        .filter(|(j, _)| !split_tasks.contains(j))
+        .map(|(_, t)| t)
+        .collect()
+}
+
+// Turns the changed and unchanged tasks of `matches` that do not recur into copies when new tasks
+// could have been matched with them, each new task going to the closest one. Returns the
+// remaining new tasks.
+fn detect_copies(
+    matches: &mut [(Task, TaskDelta<Task>)],
+    new_tasks: Vec<Task>,
+    matcher: &TaskMatcher,
+) -> Vec<Task> {
+    use self::TaskDelta::*;
+    let candidates = matches
+        .iter()
+        .enumerate()
+        .filter(|(_, (orig, delta))| {
+            orig.recurrence.is_none() && matches!(delta, Identical | Changed(_))
+        })
+        .map(|(i, (orig, _))| (i, orig))
+        .collect::<Vec<_>>();
+    let copies = new_tasks
+        .into_iter()
+        .map(|x| {
+            let closest = candidates
+                .iter()
+                .filter(|(_, orig)| matcher.is_admissible(orig, &x))
+                .min_by(|(_, left), (_, right)| matcher.cmp_3way(&x, left, right))
+                .map(|(i, _)| *i);
+            (closest, x)
+        })
+        .collect::<Vec<_>>();
+
+    let mut new_tasks = Vec::new();
+    for (closest, x) in copies {
+        let i = match closest {
+            Some(i) => i,
+            None => {
+                new_tasks.push(x);
+                continue;
+            }
+        };
+        let (orig, delta) = &mut matches[i];
+        *delta = match std::mem::replace(delta, Deleted) {
+            Identical => Copied(vec![orig.clone(), x]),
+            Changed(t) => Copied(vec![t, x]),
+            Copied(mut copies) => {
+                copies.push(x);
+                Copied(copies)
+            }
+            _ => panic!("Internal error E053"),
+        };
+    }
+    new_tasks
 }
 
 pub fn compute_changeset(
@@ -396,42 +2142,381 @@ pub fn compute_changeset(
     to: Vec<Task>,
     allowed_divergence: usize,
 ) -> (Vec<Task>, Vec<ChangedTask<Vec<Changes>>>) {
+    compute_changeset_with(
+        from,
+        to,
+        &DiffOptions::default().allowed_divergence(allowed_divergence),
+    )
+}
+
+// Looks the deleted tasks up among the completed tasks of `archived` (usually the tasks recently
+// moved to done.txt), so that tasks archived right after being completed are not reported as
+// deleted. Tasks still present in the todo list are thus always preferred.
+pub fn match_archived_tasks(
+    matches: Vec<ChangedTask<Task>>,
+    archived: Vec<Task>,
+    opts: &DiffOptions,
+) -> Vec<ChangedTask<Task>> {
     use self::TaskDelta::*;
-    let (new_tasks, matches) = match_tasks(from, to, allowed_divergence);
+    let (deleted, mut matches): (Vec<_>, Vec<_>) = matches
+        .into_iter()
+        .enumerate()
+        .partition(|(_, x)| x.delta == Deleted);
+    let matcher = TaskMatcher::new(opts, None);
+    let candidates = archived.into_iter().filter(|t| t.finished).collect();
+    let (positions, deleted): (Vec<_>, Vec<_>) = deleted
+        .into_iter()
+        .map(|(i, x)| ((i, x.orig_line), x.orig))
+        .unzip();
+    let (pairs, _) = match_similar_tasks(deleted, candidates, opts);
+    matches.extend(
+        positions
+            .into_iter()
+            .zip(pairs)
+            .map(|((i, orig_line), (orig, t))| {
+                let delta = match t {
+                    Some(t) => Changed(t),
+                    None => Deleted,
+                };
+                (
+                    i,
+                    ChangedTask {
+                        orig_line,
+                        ..ChangedTask::new(orig, delta).scored(&matcher)
+                    },
+                )
+            }),
+    );
+    matches.sort_by_key(|(i, _)| *i);
+    matches.into_iter().map(|(_, x)| x).collect()
+}
 
-    let changes = matches
+// Records the partners found by `match_archived_tasks` in `explanation`, the matches being in the
+// order of the explained ones
+fn explain_archived_matches(
+    explanation: &mut Explanation,
+    matches: &[ChangedTask<Task>],
+    opts: &DiffOptions,
+) {
+    let matcher = TaskMatcher::new(opts, None);
+    for (m, x) in explanation.matches.iter_mut().zip(matches) {
+        if let (None, TaskDelta::Changed(t)) = (&m.partner, &x.delta) {
+            m.partner = Some((t.clone(), matcher.similarity(&m.orig, t)));
+            m.archived = true;
+        }
+    }
+}
+
+pub fn compute_changeset_with(
+    from: Vec<Task>,
+    to: Vec<Task>,
+    opts: &DiffOptions,
+) -> (Vec<Task>, Vec<ChangedTask<Vec<Changes>>>) {
+    compute_changeset_archived_with(from, to, Vec::new(), opts)
+}
+
+// Like `compute_changeset_with`, but with the tasks archived in the meantime, see
+// `match_archived_tasks`
+pub fn compute_changeset_archived_with(
+    from: Vec<Task>,
+    to: Vec<Task>,
+    archived: Vec<Task>,
+    opts: &DiffOptions,
+) -> (Vec<Task>, Vec<ChangedTask<Vec<Changes>>>) {
+    let (new_tasks, matches) = match_tasks(from, to, opts);
+    let matches = if archived.is_empty() {
+        matches
+    } else {
+        match_archived_tasks(matches, archived, opts)
+    };
+    let (new_tasks, matches) = filter_matches(new_tasks, matches, &opts.filter);
+    (new_tasks, compute_deltas(matches, opts))
+}
+
+// The new tasks, and the changes of the other tasks
+pub type Changeset = (Vec<Task>, Vec<ChangedTask<Vec<Changes>>>);
+
+// The new tasks with their line numbers, and the changes of the other tasks
+pub type IndexedChangeset = (Vec<(usize, Task)>, Vec<ChangedTask<Vec<Changes>>>);
+
+// Like `compute_changeset_archived_with`, but with line numbers, see `match_tasks_indexed`
+pub fn compute_changeset_indexed_with(
+    from: Vec<(usize, Task)>,
+    to: Vec<(usize, Task)>,
+    archived: Vec<Task>,
+    opts: &DiffOptions,
+) -> IndexedChangeset {
+    let (new_tasks, matches) = match_tasks_indexed(from, to, opts);
+    finish_changeset_indexed(new_tasks, matches, archived, opts)
+}
+
+// Looks the deleted tasks up in `archived`, and computes the changes of the selected tasks
+fn finish_changeset_indexed(
+    new_tasks: Vec<(usize, Task)>,
+    matches: Vec<ChangedTask<Task>>,
+    archived: Vec<Task>,
+    opts: &DiffOptions,
+) -> IndexedChangeset {
+    let matches = if archived.is_empty() {
+        matches
+    } else {
+        match_archived_tasks(matches, archived, opts)
+    };
+    let new_tasks = new_tasks
         .into_iter()
-        .map(|ChangedTask { orig, delta }| {
-            let new_delta = match delta {
-                Identical => Identical,
-                Deleted => Deleted,
-                Changed(t) => Changed(changes_between(&orig, &t)),
-                Recurred(tasks) => {
-                    let init_change = changes_between(&orig, &tasks[0]);
-                    let rec_changes = tasks
-                        .into_iter()
-                        .tuple_windows()
-                        .map(|(t1, t2)| changes_between_rec(t1, t2, &orig));
-                    let all_changes = std::iter::once(init_change)
-                        .chain(rec_changes)
-                        .collect::<Vec<_>>();
-                    Recurred(all_changes)
+        .filter(|(_, t)| opts.filter.selects_task(t))
+        .collect();
+    let (_, matches) = filter_matches(Vec::new(), matches, &opts.filter);
+    (new_tasks, compute_deltas(matches, opts))
+}
+
+// Whether a line found identically in both lists is sure to be reported as an identical task,
+// and can thus be set aside before matching, see `read_residual_tasks_with`. This is the case
+// as both copies prefer each other to any other task, unless the line is a completed recurring
+// task, which may get new occurrences, or carries an id, or identical tasks may be copies, or the
+// matching is corrected by pins.
+pub fn may_set_aside(line: &str, opts: &DiffOptions) -> bool {
+    if (opts.detect_copies && !opts.exact_only) || !opts.pins.is_empty() {
+        return false;
+    }
+    let is_completed_recurring = line.trim_start().starts_with("x ") && line.contains("rec:");
+    !is_completed_recurring && !line.contains(&format!("{}:", opts.id_tag))
+}
+
+// Like `compute_changeset_indexed_with`, for the tasks left by `read_residual_tasks_with` and the
+// `identical` ones it set aside. The explanation of the matching (see `explain_matches`), tasks
+// matched with archived ones included, is only computed if `explain` is set.
+pub fn compute_changeset_residual_with(
+    from: Vec<(usize, Task)>,
+    to: Vec<(usize, Task)>,
+    identical: Vec<(usize, usize, Task)>,
+    archived: Vec<Task>,
+    opts: &DiffOptions,
+    explain: bool,
+) -> (IndexedChangeset, Option<Explanation>) {
+    let ((new_tasks, matches), mut explanation) =
+        match_tasks_indexed_with_explanation(from, to, opts, explain);
+    // Identical tasks are never deleted, and can thus be merged back afterwards
+    let matches = if archived.is_empty() {
+        matches
+    } else {
+        match_archived_tasks(matches, archived, opts)
+    };
+    if let Some(ref mut explanation) = explanation {
+        explain_archived_matches(explanation, &matches, opts);
+    }
+    let identical = identical
+        .into_iter()
+        .map(|(orig_line, after_line, t)| ChangedTask {
+            orig_line: Some(orig_line),
+            after_lines: vec![after_line],
+            similarity: Some(100),
+            ..ChangedTask::new(t, TaskDelta::Identical)
+        })
+        .collect::<Vec<_>>();
+    // Both are in the order of the lines of `from`
+    let matches = matches
+        .into_iter()
+        .merge_by(identical, |x, y| x.orig_line < y.orig_line)
+        .collect();
+    let changeset = finish_changeset_indexed(new_tasks, matches, Vec::new(), opts);
+    (changeset, explanation)
+}
+
+// Reads and diffs two todo lists, without parsing nor matching the lines found identically in
+// both when possible, see `may_set_aside`. The changeset is the same as that of
+// `compute_changeset_indexed_with` on the whole lists, up to which line numbers go to which copy
+// of a task found several times.
+pub fn compute_changeset_from_readers<R: BufRead, S: BufRead>(
+    before: R,
+    after: S,
+    opts: &DiffOptions,
+) -> Result<IndexedChangeset, ReadError> {
+    let residual = read_residual_tasks_with(
+        before,
+        after,
+        ["before", "after"],
+        &ReadOptions::default(),
+        |line| may_set_aside(line, opts),
+    )?;
+    let (changeset, _) = compute_changeset_residual_with(
+        residual.before.indexed(),
+        residual.after.indexed(),
+        residual.identical,
+        Vec::new(),
+        opts,
+        false,
+    );
+    Ok(changeset)
+}
+
+fn compute_deltas(
+    matches: Vec<ChangedTask<Task>>,
+    opts: &DiffOptions,
+) -> Vec<ChangedTask<Vec<Changes>>> {
+    use self::TaskDelta::*;
+    map_tasks(matches, opts, |x| {
+        let ChangedTask {
+            orig,
+            delta,
+            after,
+            orig_line,
+            after_lines,
+            similarity,
+        } = x;
+        let new_delta = match delta {
+            Identical => Identical,
+            Deleted => Deleted,
+            Changed(t) => {
+                let chgs = changes_between_with(&orig, &t, opts);
+                // Only ignored fields changed
+                if chgs.is_empty() {
+                    Identical
+                } else {
+                    Changed(chgs)
                 }
-            };
-            ChangedTask {
-                orig: orig,
-                delta: new_delta,
             }
-        })
-        .collect::<Vec<ChangedTask<Vec<Changes>>>>();
+            Recurred(tasks) => {
+                let mut all_changes = vec![changes_between_with(&orig, &tasks[0], opts)];
+                for (t1, t2) in tasks.into_iter().tuple_windows() {
+                    if let Some(date) = inferred_finish_date(&t1, &t2) {
+                        if !opts.granular && opts.reports(Field::InferredFinishDate) {
+                            let previous = all_changes.last_mut().expect("Internal error E054");
+                            mark_inferred(previous, date);
+                        }
+                    }
+                    all_changes.push(changes_between_rec(t1, t2, &orig, opts));
+                }
+                Recurred(all_changes)
+            }
+            Split(tasks) => Split(
+                tasks
+                    .iter()
+                    .map(|t| changes_between_with(&orig, t, opts))
+                    .collect(),
+            ),
+            Copied(tasks) => Copied(
+                tasks
+                    .iter()
+                    .map(|t| changes_between_with(&orig, t, opts))
+                    .collect(),
+            ),
+        };
+        ChangedTask {
+            orig,
+            delta: new_delta,
+            after,
+            orig_line,
+            after_lines,
+            similarity,
+        }
+    })
+}
+
+// Maps `f` over `items` while keeping their order, on all cores if allowed by `opts`
+#[cfg(feature = "rayon")]
+fn map_tasks<T, U, F>(items: Vec<T>, opts: &DiffOptions, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Sync + Send,
+{
+    if opts.parallel {
+        items.into_par_iter().map(f).collect()
+    } else {
+        items.into_iter().map(f).collect()
+    }
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_tasks<T, U, F: Fn(T) -> U>(items: Vec<T>, _opts: &DiffOptions, f: F) -> Vec<U> {
+    items.into_iter().map(f).collect()
+}
+
+// Returns whether a changeset, as computed by `compute_changeset`, contains any change
+pub fn changeset_has_changes<T>(new_tasks: &[Task], changes: &[ChangedTask<T>]) -> bool {
+    !new_tasks.is_empty() || changes.iter().any(|x| !x.delta.is_identical())
+}
+
+pub fn has_changes(from: Vec<Task>, to: Vec<Task>) -> bool {
+    has_changes_with(from, to, &DiffOptions::default())
+}
+
+// Like `changeset_has_changes` on the changeset of `from` and `to`, which is not computed when the
+// lists hold the same tasks, possibly in another order: they have no changes then, unless pins
+// pair some of these tasks with others
+pub fn has_changes_with(from: Vec<Task>, to: Vec<Task>, opts: &DiffOptions) -> bool {
+    if opts.pins.is_empty() {
+        let (mut from_left, mut to_left) = (from.iter().collect(), to.iter().collect());
+        remove_common_by_key(&mut from_left, &mut to_left, |t| t.subject.as_str());
+        if from_left.is_empty() && to_left.is_empty() {
+            return false;
+        }
+    }
+    let (new_tasks, changes) = compute_changeset_with(from, to, opts);
+    changeset_has_changes(&new_tasks, &changes)
+}
+
+// The indices of a longest strictly increasing subsequence of `xs`
+fn longest_increasing_subsequence(xs: &[usize]) -> Vec<usize> {
+    // `tails[k]` ends the increasing subsequence of length k + 1 with the smallest last element
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev = vec![None; xs.len()];
+    for (i, x) in xs.iter().enumerate() {
+        let pos = tails.partition_point(|&j| xs[j] < *x);
+        prev[i] = pos.checked_sub(1).map(|p| tails[p]);
+        if pos == tails.len() {
+            tails.push(i);
+        } else {
+            tails[pos] = i;
+        }
+    }
+    let mut res = Vec::new();
+    let mut cur = tails.last().cloned();
+    while let Some(i) = cur {
+        res.push(i);
+        cur = prev[i];
+    }
+    res.reverse();
+    res
+}
 
-    (new_tasks, changes)
+// The identical tasks whose position relative to the other identical tasks changed, in their
+// order in `from`. The tasks deemed in place are a longest run of identical tasks in the same
+// order in both lists, so that moving one task does not make all the others moved. Only the
+// tasks matched by `match_tasks_indexed` have the line numbers this needs.
+pub fn moved_tasks<T>(changes: &[ChangedTask<T>]) -> Vec<&ChangedTask<T>> {
+    let mut identical = changes
+        .iter()
+        .filter(|x| {
+            x.delta.is_identical()
+                && x.after == std::slice::from_ref(&x.orig)
+                && x.orig_line.is_some()
+                && x.after_lines.len() == 1
+        })
+        .collect::<Vec<_>>();
+    identical.sort_by_key(|x| x.orig_line);
+    let after_lines = identical
+        .iter()
+        .map(|x| x.after_lines[0])
+        .collect::<Vec<_>>();
+    let in_place = longest_increasing_subsequence(&after_lines);
+    identical
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| in_place.binary_search(i).is_err())
+        .map(|(_, x)| x)
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pin_matches::parse_pins;
+    use proptest::prelude::*;
     use std::str::FromStr;
+    use synthetic::{self, Generator};
+    use test_utils::{arb_task, arb_tasks, arb_workload};
     use todo_txt::task::Extended as Task;
 
     fn cmp3(from: &str, left: &str, right: &str) -> std::cmp::Ordering {
@@ -439,9 +2524,62 @@ mod tests {
             &Task::from_str(from).unwrap(),
             &Task::from_str(left).unwrap(),
             &Task::from_str(right).unwrap(),
+            &DiffOptions::default(),
+            &DistanceCache::default(),
         )
     }
 
+    #[test]
+    fn test_pair_tags() {
+        let tags = |t: &[(&str, &str)]| -> Vec<(String, String)> {
+            t.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let removed = tags(&[("a", "1"), ("b", "2"), ("c", "3"), ("c", "4")]);
+        let added = tags(&[("a", "2"), ("c", "5"), ("d", "6")]);
+        let diff = pair_tags(&removed, &added);
+        assert_eq!(diff.removed, vec![&removed[1], &removed[2], &removed[3]]);
+        assert_eq!(diff.changed, vec![("a", "1", "2")]);
+        assert_eq!(diff.added, vec![&added[1], &added[2]]);
+    }
+
+    #[test]
+    fn test_remove_common() {
+        let mut a = vec![3, 1, 2, 1, 4, 1, 5];
+        let mut b = vec![1, 6, 2, 1, 2, 7, 3];
+        assert_eq!(remove_common(&mut a, &mut b), vec![3, 1, 2, 1]);
+        assert_eq!(a, vec![4, 1, 5]);
+        assert_eq!(b, vec![6, 2, 7]);
+
+        let (mut a, mut b) = (vec![1, 1], Vec::new());
+        assert!(remove_common(&mut a, &mut b).is_empty());
+        assert_eq!(a, vec![1, 1]);
+        assert!(remove_common(&mut b, &mut a).is_empty());
+    }
+
+    #[test]
+    fn test_remove_common_by_key() {
+        let tasks = |lines: &[&str]| -> Vec<Task> {
+            lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+        };
+        // Tasks with the same subject are not necessarily equal
+        let mut a = tasks(&["(A) call mom", "buy milk", "call mom", "call mom"]);
+        let mut b = tasks(&["call mom", "fix the bike", "(B) call mom", "buy milk"]);
+        let common = remove_common_by_key(&mut a, &mut b, |t| t.subject.as_str());
+        assert_eq!(common, tasks(&["buy milk", "call mom"]));
+        assert_eq!(a, tasks(&["(A) call mom", "call mom"]));
+        assert_eq!(b, tasks(&["fix the bike", "(B) call mom"]));
+    }
+
+    #[test]
+    fn test_priority_char() {
+        let prio = |s: &str| priority_char(&Task::from_str(s).unwrap().priority);
+        assert_eq!(prio("(A) do a thing"), Some('A'));
+        assert_eq!(prio("(Z) do a thing"), Some('Z'));
+        assert_eq!(prio("do a thing"), None);
+    }
+
     #[test]
     fn test_cmp_3way() {
         use std::cmp::Ordering::*;
@@ -450,12 +2588,870 @@ mod tests {
         assert_eq!(cmp3("do a thing", "x do a thing", "do any thing"), Less);
     }
 
+    fn tasks(lines: &[&str]) -> Vec<Task> {
+        lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+    }
+
+    // The changeset in an order that does not depend on the order of the tasks
+    fn normalized_changeset(from: Vec<Task>, to: Vec<Task>, opts: &DiffOptions) -> Vec<String> {
+        let (new_tasks, changes) = compute_changeset_with(from, to, opts);
+        let mut res = new_tasks
+            .iter()
+            .map(|t| format!("new {}", t))
+            .chain(changes.iter().map(|x| {
+                let after = x.after.iter().map(Task::to_string).collect::<Vec<_>>();
+                format!("{} {:?} {:?} {:?}", x.orig, x.delta, after, x.similarity)
+            }))
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn test_changeset_independent_of_order() {
+        let from = tasks(&[
+            "buy milk x",
+            "buy milk y",
+            "(A) call the bank",
+            "(B) call the bank",
+            "2018-06-01 water plants due:2018-06-02 rec:1w",
+            "2018-06-01 water flowers due:2018-06-02 rec:1w",
+            "plan holidays",
+            "plan holiday",
+        ]);
+        let to = tasks(&[
+            "buy milk z",
+            "call the bank",
+            "x 2018-06-03 2018-06-01 water plants due:2018-06-02 rec:1w",
+            "2018-06-03 water plents due:2018-06-10 rec:1w",
+            "plan holidayz",
+            "plan holidaya",
+        ]);
+        let opts = DiffOptions::default().parallel(false);
+        let expected = normalized_changeset(from.clone(), to.clone(), &opts);
+        let mut generator = Generator::new(7);
+        for _ in 0..20 {
+            let mut shuffle = |tasks: &[Task]| {
+                let mut tasks = tasks.to_vec();
+                generator.shuffle(&mut tasks);
+                tasks
+            };
+            let (from, to) = (shuffle(&from), shuffle(&to));
+            assert_eq!(normalized_changeset(from, to, &opts), expected);
+        }
+    }
+
+    #[test]
+    fn test_cmp_3way_tie_breaks() {
+        use std::cmp::Ordering::*;
+        // Subject distance first
+        assert_eq!(
+            cmp3(
+                "do a thing due:2018-01-01",
+                "do a thing",
+                "do an thing due:2018-01-01"
+            ),
+            Less
+        );
+        // Then due date
+        assert_eq!(
+            cmp3(
+                "2018-01-01 do a thing due:2018-02-01",
+                "2018-01-02 do a thing due:2018-02-01",
+                "2018-01-01 do a thing due:2018-02-02"
+            ),
+            Less
+        );
+        // Then creation date
+        assert_eq!(
+            cmp3(
+                "(A) 2018-01-01 do a thing",
+                "(B) 2018-01-01 do a thing",
+                "(A) 2018-01-02 do a thing"
+            ),
+            Less
+        );
+        // Then priority
+        assert_eq!(
+            cmp3("(A) do a thing a:1", "do a thing a:1", "(A) do a thing"),
+            Greater
+        );
+        // Then shared tags
+        assert_eq!(
+            cmp3(
+                "do a thing a:1 b:2",
+                "do a thing a:1 b:3",
+                "do a thing a:1 b:2"
+            ),
+            Greater
+        );
+        // Then the tasks themselves, so that ties do not depend on the order of the tasks
+        assert_eq!(
+            cmp3("do a thing a:1", "do a thing a:2", "do a thing"),
+            Greater
+        );
+        assert_eq!(
+            cmp3("do a thing a:1", "do a thing a:2", "do a thing a:2"),
+            Equal
+        );
+        // Whatever field they differ by
+        assert_eq!(
+            cmp3("do a thing", "x do a thing", "do a thing t:2018-01-01"),
+            Greater
+        );
+        assert_eq!(
+            cmp3("do a thing", "do a thing rec:1w", "do a thing rec:2w"),
+            Less
+        );
+        // Notes included
+        let noted = |note: &str| {
+            let mut task = Task::from_str("do a thing").unwrap();
+            task.note = todo_txt::task::Note::Short(note.to_owned());
+            task
+        };
+        let (from, first, second) = (noted(""), noted("first"), noted("second"));
+        let cmp = |left, right| {
+            cmp_tasks_3way(
+                &from,
+                left,
+                right,
+                &DiffOptions::default(),
+                &DistanceCache::default(),
+            )
+        };
+        assert_eq!(cmp(&first, &second), Less);
+        assert_eq!(cmp(&second, &first), Greater);
+    }
+
+    fn admissible(from: &str, other: &str, allowed_divergence: usize) -> bool {
+        is_task_admissible(
+            &Task::from_str(from).unwrap(),
+            &Task::from_str(other).unwrap(),
+            &DiffOptions::default().allowed_divergence(allowed_divergence),
+            &DistanceCache::default(),
+        )
+    }
+
+    #[test]
+    fn test_admissible_unicode() {
+        // One edit out of ten characters, whatever their encoding
+        for &(from, other) in &[
+            ("abcdefghij", "abcdefghiX"),
+            ("éèàùâêîôûç", "éèàùâêîôûX"),
+            ("買い物に行って野菜を", "買い物に行って野菜X"),
+        ] {
+            assert!(admissible(from, other, 10), "{} / {}", from, other);
+            assert!(!admissible(from, other, 9), "{} / {}", from, other);
+        }
+        // Two deleted characters out of ten
+        for &(from, other) in &[
+            ("abcdefghij", "abcdefgh"),
+            ("éèàùâêîôûç", "éèàùâêîô"),
+            ("買い物に行って野菜を", "買い物に行って野"),
+        ] {
+            assert!(admissible(from, other, 20), "{} / {}", from, other);
+            assert!(!admissible(from, other, 19), "{} / {}", from, other);
+        }
+    }
+
+    #[test]
+    fn test_admissible_symmetric() {
+        // Five characters added to a fifteen characters subject, ie. a quarter of the longest one
+        let (short, long) = ("water the plant", "water the plants now");
+        assert!(admissible(short, long, 25));
+        assert!(admissible(long, short, 25));
+        assert!(!admissible(short, long, 24));
+        assert!(!admissible(long, short, 24));
+    }
+
+    #[test]
+    fn test_token_metric() {
+        use self::SimilarityMetric::*;
+        let d = DistanceCache::default();
+        assert!(!Levenshtein.is_admissible("buy milk and eggs", "buy eggs and milk", 25, &d));
+        assert!(Token.is_admissible("buy milk and eggs", "buy eggs and milk", 0, &d));
+        assert!(Token.is_admissible("Buy milk", "buy  milk", 0, &d));
+        // 2 words out of 5 differ
+        assert!(Token.is_admissible("buy milk and eggs", "buy milk and bread", 40, &d));
+        assert!(!Token.is_admissible("buy milk and eggs", "buy milk and bread", 39, &d));
+        assert!(Token.is_admissible("", "", 0, &d));
+        assert!(!Token.is_admissible("", "buy milk", 99, &d));
+        assert!(
+            Token.distance("buy milk and eggs", "buy eggs and milk", &d)
+                < Token.distance("buy milk and eggs", "buy milk and bread", &d)
+        );
+    }
+
+    #[test]
+    fn test_distance_cache_bound() {
+        let (from, to) = synthetic::edited_subjects(300, 30, 3);
+        let opts = DiffOptions::default().parallel(false);
+        let subjects = from.iter().chain(&to).map(|t| matched_subject(t, &opts));
+        let matcher = TaskMatcher {
+            opts: &opts,
+            distances: DistanceCache::new(subjects, 500),
+        };
+        let matches =
+            stable_marriage::stable_marriage(to.clone(), from.clone(), &matcher, &matcher);
+        assert!(matcher.distances.len() <= 500);
+        assert!(matcher.distances.len() > 0);
+        // Emptying the cache does not change the result
+        assert_eq!(matches, match_similar_tasks(from, to, &opts));
+        // Subjects that were not numbered are not cached
+        let d = DistanceCache::new(vec![Cow::Borrowed("call mom")], 500);
+        assert_eq!(d.levenshtein("call mom", "call dad"), 3);
+        assert_eq!(d.len(), 0);
+    }
+
+    #[test]
+    fn test_match_similarity() {
+        let similarity = |from: &str, to: &str, opts: &DiffOptions| {
+            match_similarity(
+                &Task::from_str(from).unwrap(),
+                &Task::from_str(to).unwrap(),
+                opts,
+            )
+        };
+        let opts = DiffOptions::default();
+        // 1 edit out of 4 characters
+        assert_eq!(similarity("foo", "fool", &opts), 75);
+        // 7 edits out of 15 characters, rounded towards lower similarities
+        assert_eq!(similarity("call mom", "call mom please", &opts), 54);
+        assert_eq!(
+            similarity("call mom", "x 2018-06-01 call mom +family", &opts),
+            100
+        );
+        assert_eq!(similarity("call mom id:1", "phone dad id:1", &opts), 100);
+        assert_eq!(similarity("call mom id:1", "call mom id:2", &opts), 0);
+        let opts = DiffOptions::default().strict_subject(true);
+        // 8 edits out of 16 characters
+        assert_eq!(similarity("call mom", "call mom +family", &opts), 50);
+        let opts = DiffOptions::default().similarity_metric(SimilarityMetric::Token);
+        // 2 words out of 5 differ
+        assert_eq!(
+            similarity("buy milk and eggs", "buy milk and bread", &opts),
+            60
+        );
+        assert_eq!(
+            similarity("buy milk and eggs", "buy eggs and milk", &opts),
+            100
+        );
+    }
+
+    #[test]
+    fn test_admissible_empty_subject() {
+        assert!(admissible("x 2018-01-01 ", "x 2018-01-02 ", 0));
+        assert!(admissible("", "x 2018-01-02 ", 0));
+        assert!(!admissible("some task", "", 99));
+        assert!(admissible("some task", "", 100));
+        assert!(!admissible("", "some task", 99));
+        assert!(admissible("", "some task", 100));
+    }
+
+    #[test]
+    fn test_matched_subject() {
+        let t = Task::from_str("call bob +work about  the @office move due:2019-01-01").unwrap();
+        assert_eq!(
+            matched_subject(&t, &DiffOptions::default()),
+            "call bob about the move"
+        );
+        assert_eq!(
+            matched_subject(&t, &DiffOptions::default().strict_subject(true)),
+            t.subject
+        );
+    }
+
+    #[test]
+    fn test_cmp_3way_empty_subject() {
+        use std::cmp::Ordering::*;
+        assert_eq!(cmp3("", "", ""), Equal);
+        assert_eq!(cmp3("", "", "some task"), Less);
+        assert_eq!(cmp3("some task", "", "some tasks"), Greater);
+    }
+
+    #[test]
+    fn test_match_many_similar_tasks() {
+        // Every pair is admissible, so that distances are needed many times each
+        let from = (0..300)
+            .map(|i| Task::from_str(&format!("water the plant number {} +home", i)).unwrap())
+            .collect::<Vec<_>>();
+        let to = (0..300)
+            .rev()
+            .map(|i| Task::from_str(&format!("water the plants number {} +home", i)).unwrap())
+            .collect::<Vec<_>>();
+        let (new_tasks, matches) = match_tasks(from, to, &DiffOptions::default());
+        assert_eq!(new_tasks, vec![]);
+        for m in matches {
+            let mut expected = m.orig.clone();
+            expected.subject = expected.subject.replace("plant", "plants");
+            assert_eq!(m.delta, TaskDelta::Changed(expected));
+        }
+    }
+
+    #[test]
+    fn test_partitioned_matching() {
+        let from = tasks(&[
+            "call mom about the trip",
+            "buy milk and eggs +home",
+            "write the report +work",
+            "fix the bike +home",
+            "2018-06-01 review slides id:12",
+        ]);
+        let to = tasks(&[
+            "fix the bikes +home",
+            "Call mom about the trips",
+            "purchase milk and eggs +home",
+            "write the reports +work",
+            "2018-06-01 go over slides id:12",
+        ]);
+        let opts = DiffOptions::default()
+            .allowed_divergence(50)
+            .max_fuzzy_candidates(Some(4));
+        assert!(opts.reduces_matching(5, 5));
+        assert!(!opts.reduces_matching(5, 4));
+        assert!(!opts.clone().exact_only(true).reduces_matching(5, 5));
+        let (new_tasks, matches) = match_tasks(from.clone(), to.clone(), &opts);
+        // Only the task whose first word changed is no longer matched
+        assert_eq!(new_tasks, tasks(&["purchase milk and eggs +home"]));
+        let after = |matches: &[ChangedTask<Task>]| {
+            matches
+                .iter()
+                .map(|x| x.after.iter().map(Task::to_string).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            after(&matches),
+            vec![
+                vec!["Call mom about the trips"],
+                vec![],
+                vec!["write the reports +work"],
+                vec!["fix the bikes +home"],
+                vec!["2018-06-01 go over slides id:12"],
+            ]
+        );
+
+        // Partitioning by project, tasks only need to share their project
+        let (new_tasks, matches) =
+            match_tasks(from, to, &opts.fuzzy_partition(FuzzyPartition::Project));
+        assert_eq!(new_tasks, vec![]);
+        assert_eq!(after(&matches)[1], vec!["purchase milk and eggs +home"]);
+    }
+
+    #[test]
+    fn test_match_equal_subjects() {
+        let from = tasks(&[
+            "call mom due:2018-06-01",
+            "(A) buy milk",
+            "water plants due:2018-06-01",
+            "water plants due:2018-06-08",
+            "review slides id:1",
+        ]);
+        let to = tasks(&[
+            "water plants due:2018-06-09",
+            "call mom due:2018-06-03",
+            "milk buy",
+            "review slides id:2",
+            "water plants due:2018-06-01",
+        ]);
+        let opts = DiffOptions::default().similarity_metric(SimilarityMetric::Token);
+        let matcher = TaskMatcher::new(&opts, None);
+        let marriage = |from: Vec<Task>, to: Vec<Task>| {
+            // Only the tasks whose subject is found several times, or with an id, are left
+            assert_eq!(from.len(), 3);
+            assert_eq!(to.len(), 3);
+            stable_marriage::stable_marriage(to, from, &matcher, &matcher)
+        };
+        let (matches, new_tasks) = match_equal_subjects(from, to, &opts, marriage);
+        assert_eq!(
+            matches
+                .iter()
+                .map(|(_, t)| t.as_ref().map(Task::to_string))
+                .collect::<Vec<_>>(),
+            vec![
+                Some("call mom due:2018-06-03".to_owned()),
+                Some("milk buy".to_owned()),
+                Some("water plants due:2018-06-01".to_owned()),
+                Some("water plants due:2018-06-09".to_owned()),
+                None,
+            ]
+        );
+        assert_eq!(new_tasks, tasks(&["review slides id:2"]));
+    }
+
+    #[test]
+    fn test_match_progress() {
+        use std::sync::{Arc, Mutex};
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let progress = {
+            let reports = reports.clone();
+            Progress::new(move |phase, done, total| {
+                reports.lock().unwrap().push((phase, done, total))
+            })
+        };
+        let from = tasks(&["call mom", "buy milk", "fix the bike"]);
+        let to = tasks(&["call moms", "buy milks", "fix the bikes", "read a book"]);
+        let opts = DiffOptions::default().progress(progress);
+        let (new_tasks, _) = match_tasks(from, to, &opts);
+        assert_eq!(new_tasks, tasks(&["read a book"]));
+        let reports = reports.lock().unwrap();
+        // The tasks after propose to the tasks before
+        assert!(reports.contains(&(Phase::Proposals, 0, 4)));
+        assert_eq!(reports.last(), Some(&(Phase::Proposals, 4, 4)));
+        assert!(reports
+            .iter()
+            .all(|(phase, done, total)| *phase != Phase::Parsing && done <= total));
+    }
+
+    #[test]
+    fn test_has_changes() {
+        use std::sync::{Arc, Mutex};
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let progress = {
+            let reports = reports.clone();
+            Progress::new(move |phase, _, _| reports.lock().unwrap().push(phase))
+        };
+        let opts = DiffOptions::default().progress(progress);
+        // Duplicate subjects are not paired upfront, see `match_equal_subjects`
+        let from = tasks(&["call mom", "buy milk", "call mom", "x fix the bike"]);
+        let to = tasks(&["buy milk", "call mom", "x fix the bike", "call mom"]);
+        assert!(!has_changes_with(from.clone(), to.clone(), &opts));
+        // The lists were not matched
+        assert!(reports.lock().unwrap().is_empty());
+        let (new_tasks, changes) = compute_changeset_with(from.clone(), to.clone(), &opts);
+        assert!(!changeset_has_changes(&new_tasks, &changes));
+        assert!(reports.lock().unwrap().contains(&Phase::Proposals));
+
+        let mut edited = to.clone();
+        edited[0] = Task::from_str("buy milks").unwrap();
+        assert!(has_changes(from.clone(), edited));
+        assert!(has_changes(from.clone(), to[1..].to_vec()));
+        assert!(has_changes(Vec::new(), to.clone()));
+        assert!(!has_changes(Vec::new(), Vec::new()));
+        // Pins may pair identical tasks with different ones
+        let pins = parse_pins("buy milk ||| call mom\ncall mom ||| buy milk\n", "pins").unwrap();
+        assert!(has_changes_with(
+            from,
+            to,
+            &DiffOptions::default().pins(pins)
+        ));
+    }
+
+    #[test]
+    fn test_match_tasks_indexed() {
+        let indexed = |tasks: &[(usize, &str)]| -> Vec<(usize, Task)> {
+            tasks
+                .iter()
+                .map(|(i, t)| (*i, Task::from_str(t).unwrap()))
+                .collect()
+        };
+        let from = indexed(&[
+            (1, "water the plants rec:1w due:2018-06-01"),
+            (3, "call mom"),
+            (4, "call mom"),
+            (7, "buy milk"),
+        ]);
+        let to = indexed(&[
+            (2, "x buy milk"),
+            (5, "water the plants rec:1w due:2018-06-15"),
+            (6, "call mom"),
+            (8, "water the plants rec:1w due:2018-06-08"),
+            (9, "call dad"),
+        ]);
+        let (new_tasks, matches) = match_tasks_indexed(from, to, &DiffOptions::default());
+        assert_eq!(
+            new_tasks
+                .iter()
+                .map(|(i, t)| (*i, t.to_string()))
+                .collect::<Vec<_>>(),
+            vec![(9, "call dad".to_owned())]
+        );
+        assert_eq!(
+            matches
+                .iter()
+                .map(|m| (m.orig_line, m.after_lines.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some(1), vec![8, 5]),
+                (Some(3), vec![6]),
+                (Some(4), vec![]),
+                (Some(7), vec![2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_matches() {
+        let task = |s: &str| Task::from_str(s).unwrap();
+        let tasks = |tasks: &[&str]| tasks.iter().map(|t| task(t)).collect::<Vec<_>>();
+        let from = tasks(&[
+            "water the plants rec:+1w due:2018-06-01",
+            "call mom",
+            "pay rent",
+        ]);
+        let to = tasks(&[
+            "call mom please",
+            "call mom",
+            "water the plants rec:+1w due:2018-06-08",
+            "water the plants rec:+1w due:2018-06-11",
+            "water the flowers",
+        ]);
+        let explanation = explain_matches(numbered(from), numbered(to), &DiffOptions::default());
+        assert_eq!(
+            explanation.matches,
+            vec![
+                MatchExplanation {
+                    orig: task("water the plants rec:+1w due:2018-06-01"),
+                    partner: Some((
+                        task("water the plants rec:+1w due:2018-06-08"),
+                        Similarity::Subject(100, true)
+                    )),
+                    archived: false,
+                    best_rejected: Some((
+                        task("water the plants rec:+1w due:2018-06-11"),
+                        Similarity::Subject(100, true)
+                    )),
+                },
+                MatchExplanation {
+                    orig: task("call mom"),
+                    partner: Some((task("call mom"), Similarity::Identical)),
+                    archived: false,
+                    best_rejected: Some((task("call mom please"), Similarity::Subject(54, false))),
+                },
+                MatchExplanation {
+                    orig: task("pay rent"),
+                    partner: None,
+                    archived: false,
+                    // Even if it is matched with another task
+                    best_rejected: Some((task("call mom"), Similarity::Subject(13, false))),
+                },
+            ]
+        );
+        let recurring = task("water the plants rec:+1w due:2018-06-01");
+        assert_eq!(
+            explanation.new_tasks,
+            vec![
+                (
+                    task("call mom please"),
+                    Reattachment::NotSimilar(recurring.clone(), Similarity::Subject(32, false))
+                ),
+                (
+                    task("water the plants rec:+1w due:2018-06-11"),
+                    Reattachment::NotPlausible(recurring.clone())
+                ),
+                (
+                    task("water the flowers"),
+                    Reattachment::NotSimilar(recurring, Similarity::Subject(71, false))
+                ),
+            ]
+        );
+
+        // Tasks found among the archived ones are explained along with the others
+        let ((_, changes), explanation) = compute_changeset_residual_with(
+            numbered(tasks(&["call mom", "pay rent"])),
+            numbered(tasks(&["call mom"])),
+            Vec::new(),
+            tasks(&["x 2018-06-02 pay rent"]),
+            &DiffOptions::default(),
+            true,
+        );
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            explanation.unwrap().matches[1],
+            MatchExplanation {
+                orig: task("pay rent"),
+                partner: Some((
+                    task("x 2018-06-02 pay rent"),
+                    Similarity::Subject(100, true)
+                )),
+                archived: true,
+                best_rejected: Some((task("call mom"), Similarity::Subject(13, false))),
+            }
+        );
+    }
+
+    #[test]
+    fn test_pinned_matches() {
+        let tasks = |tasks: &[&str]| -> Vec<Task> {
+            tasks.iter().map(|t| Task::from_str(t).unwrap()).collect()
+        };
+        let matched = |from: &[&str], to: &[&str], pins: &str| {
+            let opts = DiffOptions::default().pins(parse_pins(pins, "pairs.txt").unwrap());
+            let (new_tasks, matches) = match_tasks(tasks(from), tasks(to), &opts);
+            let matches = matches
+                .iter()
+                .map(|m| {
+                    (
+                        m.orig.to_string(),
+                        m.after.iter().map(Task::to_string).collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            (
+                new_tasks.iter().map(Task::to_string).collect::<Vec<_>>(),
+                matches,
+            )
+        };
+        let pair =
+            |from: &str, to: &[&str]| (from.to_owned(), to.iter().map(|t| t.to_string()).collect());
+
+        let from = ["call the plumber", "water the plants", "call the plumbers"];
+        let to = ["call the plumbers!", "call the plumber!"];
+        assert_eq!(
+            matched(&from, &to, ""),
+            (
+                vec![],
+                vec![
+                    pair("call the plumber", &["call the plumber!"]),
+                    pair("water the plants", &[]),
+                    pair("call the plumbers", &["call the plumbers!"]),
+                ]
+            )
+        );
+        // Pinned pairs are kept in the order of `from` along with the other tasks
+        let swapped = (
+            vec![],
+            vec![
+                pair("call the plumber", &["call the plumbers!"]),
+                pair("water the plants", &[]),
+                pair("call the plumbers", &["call the plumber!"]),
+            ],
+        );
+        assert_eq!(
+            matched(
+                &from,
+                &to,
+                "call the plumbers ||| call the plumber!\n\
+                 call the plumber ||| call the plumbers!\n"
+            ),
+            swapped
+        );
+        // Pinning one of them leaves the other one to the matcher
+        assert_eq!(matched(&from, &to, "3 -> 2\n"), swapped);
+
+        assert_eq!(
+            matched(
+                &["buy milk", "buy eggs"],
+                &["buy milk!", "buy eggs"],
+                "!buy milk\n"
+            ),
+            (
+                vec!["buy milk!".to_owned()],
+                vec![pair("buy milk", &[]), pair("buy eggs", &["buy eggs"])]
+            )
+        );
+    }
+
+    #[test]
+    fn test_moved_tasks() {
+        assert_eq!(longest_increasing_subsequence(&[]), Vec::<usize>::new());
+        assert_eq!(
+            longest_increasing_subsequence(&[3, 1, 2, 5, 4]),
+            vec![1, 2, 4]
+        );
+
+        let indexed = |tasks: &[&str]| -> Vec<(usize, Task)> {
+            tasks
+                .iter()
+                .enumerate()
+                .map(|(i, t)| (i + 1, Task::from_str(t).unwrap()))
+                .collect()
+        };
+        let from = indexed(&["a thing", "b thing", "c thing", "d thing", "e thing"]);
+        let to = indexed(&["d thing", "a thing", "b things", "c thing", "e thing"]);
+        let opts = DiffOptions::default().allowed_divergence(25);
+        let (_, matches) = match_tasks_indexed(from, to, &opts);
+        assert_eq!(
+            moved_tasks(&matches)
+                .iter()
+                .map(|m| (m.orig.to_string(), m.orig_line, m.after_lines.clone()))
+                .collect::<Vec<_>>(),
+            vec![("d thing".to_owned(), Some(4), vec![1])]
+        );
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let pairs = [
+            ("call mom", "x 2018-06-02 call mom"),
+            ("2018-06-01 call mom", "x 2018-06-02 2018-06-01 call mom"),
+            (
+                "(A) 2018-06-01 call mom",
+                "x 2018-06-02 2018-06-01 call mom",
+            ),
+            ("x 2018-06-02 2018-06-01 call mom", "2018-06-01 call mom"),
+            (
+                "x 2018-06-02 2018-06-01 call mom",
+                "x 2018-06-03 2018-06-01 call mom",
+            ),
+            ("(A) call mom", "(B) call mom"),
+            ("(A) call mom", "call mom"),
+            ("call mom", "2018-06-01 call mom"),
+            (
+                "call mom due:2018-06-01 t:2018-05-25",
+                "call mom due:2018-06-08 t:2018-06-01",
+            ),
+            (
+                "call mom due:2018-06-01 t:2018-05-25",
+                "call mom due:2018-06-08",
+            ),
+            ("call mom due:2018-06-01", "call mom t:2018-05-25"),
+            ("call mom", "call dad"),
+            ("call mom +family @phone", "call mom @phone +friends @home"),
+            ("call mom +family", "call dad +friends"),
+            ("+family call mom @home", "+work call mom @phone"),
+            ("call +family mom", "+work call mom +friends"),
+            ("call mom id:1 who:me", "call mom id:1 who:you when:now"),
+            ("call mom rec:1w", "call mom rec:+2w"),
+            ("call mom rec:1w", "call mom"),
+        ];
+        for &(from, to) in pairs.iter() {
+            let from = Task::from_str(from).unwrap();
+            let to = Task::from_str(to).unwrap();
+            assert_eq!(
+                apply_changes(&from, &changes_between(&from, &to)),
+                Ok(to.clone()),
+                "{} → {}",
+                from,
+                to
+            );
+        }
+
+        // Changes can be applied to a similar task
+        let from = Task::from_str("call mom due:2018-06-01").unwrap();
+        let to = Task::from_str("x 2018-06-02 2018-06-01 call mom +family due:2018-06-01").unwrap();
+        assert_eq!(
+            apply_changes(
+                &Task::from_str("(B) 2018-06-01 call mom @phone due:2018-06-01").unwrap(),
+                &changes_between(&from, &to)
+            ),
+            Ok(
+                Task::from_str("x 2018-06-02 2018-06-01 call mom @phone +family due:2018-06-01")
+                    .unwrap()
+            )
+        );
+
+        // Tasks built by hand may list the same projects or contexts in another order
+        let tagged = Task::from_str("call mom +family +phone @home @work").unwrap();
+        let mut reordered = tagged.clone();
+        reordered.projects.reverse();
+        reordered.contexts.reverse();
+        assert_eq!(changes_between(&tagged, &reordered), Vec::new());
+
+        let recurred = [Changes::RecurredStrict];
+        assert_eq!(
+            apply_changes(&from, &recurred),
+            Err(ApplyError::NotRecurring(
+                "call mom due:2018-06-01".to_owned()
+            ))
+        );
+        let priority = [Changes::Priority(None, Some('1'))];
+        assert_eq!(
+            apply_changes(&from, &priority),
+            Err(ApplyError::InvalidPriority('1'))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_apply_changes_between((from, to) in (arb_task(), arb_task())) {
+            prop_assert_eq!(apply_changes(&from, &changes_between(&from, &to)), Ok(to));
+        }
+
+        #[test]
+        fn prop_self_diff_is_empty(tasks in arb_tasks(), allowed_divergence in 0..101usize) {
+            let (new_tasks, changes) =
+                compute_changeset(tasks.clone(), tasks.clone(), allowed_divergence);
+            prop_assert_eq!(new_tasks, Vec::<Task>::new());
+            prop_assert_eq!(changes.len(), tasks.len());
+            for (x, task) in changes.into_iter().zip(tasks) {
+                prop_assert_eq!(x.delta, TaskDelta::Identical);
+                prop_assert_eq!(x.orig, task);
+            }
+        }
+
+        #[test]
+        fn prop_apply_delta((from, to) in arb_workload()) {
+            let opts = DiffOptions::default().parallel(false);
+            let (new_tasks, changes) = compute_changeset_with(from, to.clone(), &opts);
+            let mut after = new_tasks;
+            for x in changes {
+                prop_assert_eq!(apply_delta(&x.orig, &x.delta), Ok(x.after.clone()));
+                after.extend(x.after);
+            }
+            // Each task of `to` is either new or what a task became
+            prop_assert_eq!(after.len(), to.len());
+        }
+
+        #[test]
+        fn prop_match_equal_subjects(
+            (from, to) in prop_oneof![(arb_tasks(), arb_tasks()), arb_workload()],
+            token in any::<bool>(),
+        ) {
+            let metric = if token {
+                SimilarityMetric::Token
+            } else {
+                SimilarityMetric::Levenshtein
+            };
+            let opts = DiffOptions::default().similarity_metric(metric).parallel(false);
+            let matcher = TaskMatcher::new(&opts, None);
+            let marriage = |from, to| stable_marriage::stable_marriage(to, from, &matcher, &matcher);
+            let (matches, mut new_tasks) =
+                match_equal_subjects(from.clone(), to.clone(), &opts, marriage);
+            let (expected_matches, mut expected_new_tasks) = marriage(from, to);
+            prop_assert_eq!(matches, expected_matches);
+            // Only the order of the new tasks may change
+            new_tasks.sort_by_key(Task::to_string);
+            expected_new_tasks.sort_by_key(Task::to_string);
+            prop_assert_eq!(new_tasks, expected_new_tasks);
+        }
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let tasks = |lines: &[&str]| -> Vec<Task> {
+            lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+        };
+        let from = tasks(&[
+            "(A) 2018-06-01 pay the rent due:2018-06-01 rec:+1m",
+            "2018-06-01 water the plants due:2018-06-02 rec:1w",
+            "x 2018-05-31 2018-05-01 do the dishes due:2018-05-31 t:2018-05-30 rec:+1d",
+            "call mom +family",
+            "plan and book holidays",
+            "buy milk",
+        ]);
+        let to = tasks(&[
+            "x 2018-06-02 2018-06-01 pay the rent due:2018-06-01 rec:+1m",
+            "(A) 2018-06-02 pay the rent due:2018-07-01 rec:+1m",
+            "x 2018-06-03 2018-06-01 water the plants due:2018-06-02 rec:1w",
+            "2018-06-03 water the plants due:2018-06-10 rec:1w",
+            "2018-05-31 do the dishes due:2018-06-04 t:2018-06-03 rec:+1d",
+            "call mom @phone",
+            "plan holidays",
+            "book holidays",
+        ]);
+        let (_, changes) = compute_changeset_with(
+            from,
+            to,
+            &DiffOptions::default()
+                .allowed_divergence(60)
+                .detect_splits(true),
+        );
+        assert!(changes
+            .iter()
+            .any(|c| matches!(c.delta, TaskDelta::Recurred(_))));
+        for c in changes {
+            assert_eq!(apply_delta(&c.orig, &c.delta), Ok(c.after), "{}", c.orig);
+        }
+    }
+
     #[test]
     fn test_add_recspec() {
         fn test(from: &str, rec: &str, to: &str) {
             let from = TaskDate::from_str(from).unwrap();
             let to = TaskDate::from_str(to).unwrap();
-            assert_eq!(Recurrence::from_str(rec).unwrap() + from, to);
+            let rec = Recurrence::from_str(rec).unwrap();
+            assert_eq!(add_recurrence(&rec, from), Some(to));
+            assert_eq!(rec + from, to);
         }
 
         test("2010-01-01", "2d", "2010-01-03");
@@ -466,8 +3462,96 @@ mod tests {
         test("2010-01-30", "1m", "2010-02-28");
         test("2010-02-28", "1m", "2010-03-31");
         test("2010-01-30", "2m", "2010-03-30");
-        // test("2010-01-01", "20m", "2011-09-01");
+        test("2010-01-01", "20m", "2011-09-01");
+        test("2010-11-15", "14m", "2012-01-15");
+        test("2010-12-31", "12m", "2011-12-31");
+        test("2010-12-31", "26m", "2013-02-28");
+        test("2011-01-31", "13m", "2012-02-29");
+        test("2010-01-31", "1m", "2010-02-28");
+        test("2012-01-31", "1m", "2012-02-29");
         test("2003-02-28", "1y", "2004-02-29");
         test("2004-02-29", "1y", "2005-02-28");
+
+        // Where `rec + date` would panic
+        let overflows = |date: &str, rec: &str| {
+            add_recurrence(
+                &Recurrence::from_str(rec).unwrap(),
+                TaskDate::from_str(date).unwrap(),
+            )
+        };
+        assert_eq!(overflows("2010-01-01", "+9223372036854775807d"), None);
+        assert_eq!(overflows("2010-01-01", "4294967295w"), None);
+        assert_eq!(overflows("2010-01-01", "4294967295y"), None);
+        assert_eq!(overflows("9999-12-31", "+5000000000d"), None);
+        assert_eq!(
+            overflows("2010-03-31", "-1m"),
+            Some(TaskDate::from_str("2010-02-28").unwrap())
+        );
+    }
+
+    // Generates a todo list along with an edited version of it, deterministically from `seed`.
+    // `fuzzy_percent` of the tasks had their subject edited or are in only one of the lists, so
+    // that they need the fuzzy matching, which is what takes time.
+    #[cfg(feature = "rayon")]
+    fn generate_task_lists(seed: u64, len: usize, fuzzy_percent: usize) -> (Vec<Task>, Vec<Task>) {
+        let mut generator = Generator::new(seed);
+        let mut from = Vec::new();
+        let mut to = Vec::new();
+        for i in 0..len {
+            let mut task = generator.task();
+            task.subject += &format!(" {}", i % 97);
+            if generator.chance(10) {
+                task.recurrence = Some(Recurrence::from_str("1w").unwrap());
+            }
+            let mut edited = task.clone();
+            if generator.chance(fuzzy_percent) {
+                match generator.below(4) {
+                    0 => edited = generator.edit_subject(&task),
+                    1 => edited.subject = edited.subject.replacen(' ', "", 1),
+                    2 => {
+                        from.push(task);
+                        continue;
+                    }
+                    _ => {
+                        to.push(edited);
+                        continue;
+                    }
+                }
+            } else {
+                match generator.below(6) {
+                    0 => edited.finished = true,
+                    1 => edited.priority = todo_txt::Priority::from(generator.below(26) as u8),
+                    2 => edited.due_date = Some(generator.date()),
+                    3 => edited = generator.edit_tags(&task),
+                    _ => (),
+                }
+            }
+            from.push(task);
+            to.push(edited);
+        }
+        (from, to)
+    }
+
+    #[cfg(feature = "rayon")]
+    fn check_parallel_changeset(len: usize, fuzzy_percent: usize) {
+        let (from, to) = generate_task_lists(42, len, fuzzy_percent);
+        let opts = DiffOptions::default();
+        assert_eq!(
+            compute_changeset_with(from.clone(), to.clone(), &opts.clone().parallel(false)),
+            compute_changeset_with(from, to, &opts)
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_changeset() {
+        check_parallel_changeset(300, 30);
+    }
+
+    // Few fuzzy matches keep that many tasks fast enough to check without optimizations
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_changeset_large() {
+        check_parallel_changeset(5000, 3);
     }
 }
@@ -0,0 +1,302 @@
+use color_policy::ColorOption;
+use compute_changes::{FuzzyPartition, SimilarityMetric};
+use display_changes::{DateFormat, GroupBy, WordDiff};
+use merge_changes::MergeStrategy;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml;
+
+// Default values for the command-line options, keyed by the name of the option in the
+// configuration file (eg. `word-diff = "char"`). Options given on the command line take
+// precedence, and options that only make sense for one of `todiff` and `todiff-merge` are ignored
+// by the other one.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Config {
+    pub color: Option<ColorOption>,
+    pub word_diff: Option<WordDiff>,
+    pub date_format: Option<DateFormat>,
+    pub width: Option<usize>,
+    pub group_by: Option<GroupBy>,
+    pub summary_line: Option<bool>,
+    pub fancy: Option<bool>,
+    pub hyperlinks: Option<ColorOption>,
+    pub link_tag: Option<String>,
+    pub similarity: Option<usize>,
+    pub similarity_metric: Option<SimilarityMetric>,
+    pub id_tag: Option<String>,
+    pub max_fuzzy_candidates: Option<usize>,
+    pub fuzzy_partition: Option<FuzzyPartition>,
+    pub exact: Option<bool>,
+    pub strict_subject: Option<bool>,
+    pub loose_recurrence: Option<bool>,
+    pub detect_splits: Option<bool>,
+    pub detect_copies: Option<bool>,
+    pub granular: Option<bool>,
+    pub lenient: Option<bool>,
+    pub strict_parse: Option<bool>,
+    pub strategy: Option<MergeStrategy>,
+    pub marker_size: Option<usize>,
+    pub diff3: Option<bool>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // The file could not be read at all
+    Open {
+        path: String,
+        error: io::Error,
+    },
+    // The file is not valid TOML
+    Syntax {
+        path: String,
+        error: String,
+    },
+    // An option has a value it cannot take
+    Invalid {
+        path: String,
+        option: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Open { path, error } => {
+                write!(f, "Unable to open configuration file ‘{}’: {}", path, error)
+            }
+            ConfigError::Syntax { path, error } => write!(f, "{}: {}", path, error),
+            ConfigError::Invalid {
+                path,
+                option,
+                error,
+            } => write!(f, "{}: invalid value for ‘{}’: {}", path, option, error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ConfigOutcome {
+    pub config: Config,
+    // Unknown options, which are ignored
+    pub warnings: Vec<String>,
+}
+
+// Strings are parsed like the value of the option on the command line, so are integers
+fn parse_value<T>(value: &toml::Value) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let s = match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Integer(i) => i.to_string(),
+        v => return Err(format!("expected a string, found {}", v.type_str())),
+    };
+    s.parse().map_err(|e: T::Err| e.to_string())
+}
+
+fn parse_bool(value: &toml::Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("expected a boolean, found {}", value.type_str()))
+}
+
+fn parse_similarity(value: &toml::Value) -> Result<usize, String> {
+    parse_value(value).and_then(|x| {
+        if x <= 100 {
+            Ok(x)
+        } else {
+            Err("must be between 0 and 100".to_owned())
+        }
+    })
+}
+
+fn parse_marker_size(value: &toml::Value) -> Result<usize, String> {
+    parse_value(value).and_then(|x| {
+        if x > 0 {
+            Ok(x)
+        } else {
+            Err("must be positive".to_owned())
+        }
+    })
+}
+
+// `path` is only used in the errors and warnings
+pub fn parse_config(contents: &str, path: &str) -> Result<ConfigOutcome, ConfigError> {
+    let table =
+        toml::from_str::<toml::value::Table>(contents).map_err(|e| ConfigError::Syntax {
+            path: path.to_owned(),
+            error: e.to_string(),
+        })?;
+    let mut outcome = ConfigOutcome::default();
+    let config = &mut outcome.config;
+    for (option, value) in table {
+        let result = match option.as_str() {
+            "color" => parse_value(&value).map(|v| config.color = Some(v)),
+            "word-diff" => parse_value(&value).map(|v| config.word_diff = Some(v)),
+            "date-format" => parse_value(&value).map(|v| config.date_format = Some(v)),
+            "width" => parse_value(&value).map(|v| config.width = Some(v)),
+            "group-by" => parse_value(&value).map(|v| config.group_by = Some(v)),
+            "summary-line" => parse_bool(&value).map(|v| config.summary_line = Some(v)),
+            "fancy" => parse_bool(&value).map(|v| config.fancy = Some(v)),
+            "hyperlinks" => parse_value(&value).map(|v| config.hyperlinks = Some(v)),
+            "link-tag" => parse_value(&value).map(|v| config.link_tag = Some(v)),
+            "similarity" => parse_similarity(&value).map(|v| config.similarity = Some(v)),
+            "similarity-metric" => parse_value(&value).map(|v| config.similarity_metric = Some(v)),
+            "id-tag" => parse_value(&value).map(|v| config.id_tag = Some(v)),
+            "max-fuzzy-candidates" => {
+                parse_value(&value).map(|v| config.max_fuzzy_candidates = Some(v))
+            }
+            "fuzzy-partition" => parse_value(&value).map(|v| config.fuzzy_partition = Some(v)),
+            "exact" => parse_bool(&value).map(|v| config.exact = Some(v)),
+            "strict-subject" => parse_bool(&value).map(|v| config.strict_subject = Some(v)),
+            "loose-recurrence" => parse_bool(&value).map(|v| config.loose_recurrence = Some(v)),
+            "detect-splits" => parse_bool(&value).map(|v| config.detect_splits = Some(v)),
+            "detect-copies" => parse_bool(&value).map(|v| config.detect_copies = Some(v)),
+            "granular" => parse_bool(&value).map(|v| config.granular = Some(v)),
+            "lenient" => parse_bool(&value).map(|v| config.lenient = Some(v)),
+            "strict-parse" => parse_bool(&value).map(|v| config.strict_parse = Some(v)),
+            "strategy" => parse_value(&value).map(|v| config.strategy = Some(v)),
+            "marker-size" => parse_marker_size(&value).map(|v| config.marker_size = Some(v)),
+            "diff3" => parse_bool(&value).map(|v| config.diff3 = Some(v)),
+            _ => {
+                outcome
+                    .warnings
+                    .push(format!("{}: unknown option ‘{}’", path, option));
+                Ok(())
+            }
+        };
+        result.map_err(|error| ConfigError::Invalid {
+            path: path.to_owned(),
+            option,
+            error,
+        })?;
+    }
+    Ok(outcome)
+}
+
+pub fn read_config(path: &Path) -> Result<ConfigOutcome, ConfigError> {
+    let path_str = path.to_string_lossy().into_owned();
+    match fs::read_to_string(path) {
+        Ok(contents) => parse_config(&contents, &path_str),
+        Err(error) => Err(ConfigError::Open {
+            path: path_str,
+            error,
+        }),
+    }
+}
+
+// Where the configuration file is: `$TODIFF_CONFIG` if set, else `todiff/config.toml` in
+// `$XDG_CONFIG_HOME`, which defaults to `~/.config`. The boolean tells whether it was explicitly
+// given, in which case it must exist. `var` looks up environment variables.
+pub fn config_path<F>(var: F) -> Option<(PathBuf, bool)>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if let Some(path) = var("TODIFF_CONFIG").filter(|p| !p.is_empty()) {
+        return Some((PathBuf::from(path), true));
+    }
+    // Relative paths are invalid according to the XDG base directory specification
+    let config_home = var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|d| d.is_absolute())
+        .or_else(|| {
+            var("HOME")
+                .filter(|h| !h.is_empty())
+                .map(|h| Path::new(&h).join(".config"))
+        })?;
+    Some((config_home.join("todiff").join("config.toml"), false))
+}
+
+// Reads the configuration file found from the environment, if any
+pub fn read_config_from_env() -> Result<ConfigOutcome, ConfigError> {
+    match config_path(|name| std::env::var(name).ok()) {
+        Some((ref path, false)) if !path.exists() => Ok(ConfigOutcome::default()),
+        Some((path, _)) => read_config(&path),
+        None => Ok(ConfigOutcome::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_config() {
+        let outcome = parse_config(
+            "similarity = 85\ncolor = \"always\"\nword-diff = \"char\"\nexact = true\n\
+             strategy = \"union\"\nfuzzy-partition = \"project\"\nfoo = 1\n",
+            "config.toml",
+        )
+        .unwrap();
+        assert_eq!(
+            outcome.config,
+            Config {
+                similarity: Some(85),
+                color: Some(ColorOption::Always),
+                word_diff: Some(WordDiff::Char),
+                exact: Some(true),
+                strategy: Some(MergeStrategy::Union),
+                fuzzy_partition: Some(FuzzyPartition::Project),
+                ..Config::default()
+            }
+        );
+        assert_eq!(
+            outcome.warnings,
+            vec!["config.toml: unknown option ‘foo’".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        let error = |contents| {
+            parse_config(contents, "config.toml")
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("similarity = 101"),
+            "config.toml: invalid value for ‘similarity’: must be between 0 and 100"
+        );
+        assert_eq!(
+            error("color = \"sometimes\""),
+            "config.toml: invalid value for ‘color’: invalid color option ‘sometimes’"
+        );
+        assert_eq!(
+            error("exact = \"yes\""),
+            "config.toml: invalid value for ‘exact’: expected a boolean, found string"
+        );
+        assert!(error("similarity =").starts_with("config.toml: "));
+    }
+
+    #[test]
+    fn test_config_path() {
+        let path = |vars: &[(&str, &str)]| {
+            let vars = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>();
+            config_path(|name| vars.get(name).cloned())
+        };
+        assert_eq!(
+            path(&[("TODIFF_CONFIG", "my.toml"), ("HOME", "/home/me")]),
+            Some((PathBuf::from("my.toml"), true))
+        );
+        assert_eq!(
+            path(&[("XDG_CONFIG_HOME", "/xdg"), ("HOME", "/home/me")]),
+            Some((PathBuf::from("/xdg/todiff/config.toml"), false))
+        );
+        assert_eq!(
+            path(&[("XDG_CONFIG_HOME", "xdg"), ("HOME", "/home/me")]),
+            Some((PathBuf::from("/home/me/.config/todiff/config.toml"), false))
+        );
+        assert_eq!(path(&[]), None);
+    }
+}
@@ -1,283 +1,2494 @@
 use ansi_term::Color::{Blue, Green, Red, Yellow};
 use ansi_term::{ANSIString, ANSIStrings};
 use ansi_term::{Color, Style};
+use chrono::Datelike;
 use compute_changes::*;
-use diff;
 use itertools::Itertools;
+use stats::compute_stats;
 use std;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use subject_diff::DiffOp;
 use todo_txt::task::Extended as Task;
+use todo_txt::task::Recurrence;
+use todo_txt::Date as TaskDate;
+use unicode_width::UnicodeWidthStr;
+
+pub use subject_diff::WordDiff;
+
+// How dates are displayed, both in the tasks and in the descriptions of their changes
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DateFormat {
+    // A chrono format string, `%Y-%m-%d` being the todo.txt format
+    Strftime(String),
+    // Relative to `DisplayOptions::today`, eg. “yesterday” or “in 2 weeks”
+    Relative,
+}
+
+impl Default for DateFormat {
+    fn default() -> DateFormat {
+        DateFormat::Strftime("%Y-%m-%d".to_owned())
+    }
+}
+
+impl std::str::FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DateFormat, String> {
+        use chrono::format::{Item, StrftimeItems};
+        if s == "relative" {
+            Ok(DateFormat::Relative)
+        } else if StrftimeItems::new(s).any(|i| i == Item::Error) {
+            Err(format!("invalid date format ‘{}’", s))
+        } else {
+            Ok(DateFormat::Strftime(s.to_owned()))
+        }
+    }
+}
+
+impl DateFormat {
+    // Relative dates fall back to the todo.txt format when `today` is unknown
+    pub fn format(&self, d: TaskDate, today: Option<TaskDate>) -> String {
+        match (self, today) {
+            (DateFormat::Strftime(f), _) => d.format(f).to_string(),
+            (DateFormat::Relative, Some(today)) => relative_date_str(d, today),
+            (DateFormat::Relative, None) => d.to_string(),
+        }
+    }
+}
+
+// What tasks are clustered by in each section
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GroupBy {
+    Project,
+    Context,
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GroupBy, String> {
+        match s {
+            "project" => Ok(GroupBy::Project),
+            "context" => Ok(GroupBy::Context),
+            _ => Err(format!("invalid grouping ‘{}’", s)),
+        }
+    }
+}
+
+// The sections tasks are sorted into by `display_changeset`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Section {
+    New,
+    Deleted,
+    Completed,
+    Changed,
+}
+
+impl Section {
+    pub const NAMES: &'static [&'static str] = &["new", "deleted", "completed", "changed"];
+}
+
+impl std::str::FromStr for Section {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Section, String> {
+        match s {
+            "new" => Ok(Section::New),
+            "deleted" => Ok(Section::Deleted),
+            "completed" => Ok(Section::Completed),
+            "changed" => Ok(Section::Changed),
+            _ => Err(format!("unknown section ‘{}’", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DisplayOptions {
+    pub colorize: bool,
+    pub word_diff: WordDiff,
+    pub group_by: Option<GroupBy>,
+    // Sections left out of the output, as if they had no tasks
+    pub hidden_sections: BTreeSet<Section>,
+    // End the output with a recap of the number of tasks in each section
+    pub summary_line: bool,
+    // The date new due dates are compared to, see `due_annotation`
+    pub today: Option<TaskDate>,
+    pub date_format: DateFormat,
+    // The names of the compared lists, eg. their paths, announced in a header when set
+    pub labels: Option<(String, String)>,
+    // The number of columns task lines are wrapped to, if any, see `wrap_line`
+    pub width: Option<usize>,
+    // The names of the compared files, when tasks are prefixed with their line numbers in them
+    pub line_numbers: Option<(String, String)>,
+    // Also list the identical tasks that were reordered, see `moved_tasks`
+    pub show_moves: bool,
+    // Also list the identical tasks, in a section that does not count as a change
+    pub show_unchanged: bool,
+    // Follow changed tasks with how similar they are to what they became, see
+    // `ChangedTask::similarity`
+    pub show_scores: bool,
+    // End the sections with aggregates such as how long the completed tasks took, see
+    // `compute_stats`
+    pub show_stats: bool,
+    // Highlight the priority, dates, projects and contexts within colorized task lines, see
+    // `paint_task`
+    pub fancy_tasks: bool,
+    // Make the URLs of colorized task lines clickable, see `link_word`
+    pub hyperlinks: bool,
+    // The tag whose value is made clickable along with the URLs, eg. `url` for `url:…`
+    pub link_tag: String,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> DisplayOptions {
+        DisplayOptions {
+            colorize: false,
+            word_diff: WordDiff::Word,
+            group_by: None,
+            hidden_sections: BTreeSet::new(),
+            summary_line: false,
+            today: None,
+            date_format: DateFormat::default(),
+            labels: None,
+            width: None,
+            line_numbers: None,
+            show_moves: false,
+            show_unchanged: false,
+            show_scores: false,
+            show_stats: false,
+            fancy_tasks: false,
+            hyperlinks: false,
+            link_tag: "url".to_owned(),
+        }
+    }
+}
+
+impl DisplayOptions {
+    pub fn colorize(mut self, colorize: bool) -> DisplayOptions {
+        self.colorize = colorize;
+        self
+    }
+
+    pub fn word_diff(mut self, word_diff: WordDiff) -> DisplayOptions {
+        self.word_diff = word_diff;
+        self
+    }
+
+    pub fn group_by(mut self, group_by: GroupBy) -> DisplayOptions {
+        self.group_by = Some(group_by);
+        self
+    }
+
+    pub fn hide(mut self, section: Section) -> DisplayOptions {
+        self.hidden_sections.insert(section);
+        self
+    }
+
+    pub fn summary_line(mut self, summary_line: bool) -> DisplayOptions {
+        self.summary_line = summary_line;
+        self
+    }
+
+    pub fn today(mut self, today: TaskDate) -> DisplayOptions {
+        self.today = Some(today);
+        self
+    }
+
+    pub fn date_format(mut self, date_format: DateFormat) -> DisplayOptions {
+        self.date_format = date_format;
+        self
+    }
+
+    pub fn labels(mut self, before: &str, after: &str) -> DisplayOptions {
+        self.labels = Some((before.to_owned(), after.to_owned()));
+        self
+    }
+
+    pub fn width(mut self, width: usize) -> DisplayOptions {
+        self.width = Some(width).filter(|w| *w > 0);
+        self
+    }
+
+    pub fn line_numbers(mut self, before: &str, after: &str) -> DisplayOptions {
+        self.line_numbers = Some((before.to_owned(), after.to_owned()));
+        self
+    }
+
+    pub fn show_moves(mut self, show_moves: bool) -> DisplayOptions {
+        self.show_moves = show_moves;
+        self
+    }
+
+    pub fn show_unchanged(mut self, show_unchanged: bool) -> DisplayOptions {
+        self.show_unchanged = show_unchanged;
+        self
+    }
+
+    pub fn show_scores(mut self, show_scores: bool) -> DisplayOptions {
+        self.show_scores = show_scores;
+        self
+    }
+
+    pub fn show_stats(mut self, show_stats: bool) -> DisplayOptions {
+        self.show_stats = show_stats;
+        self
+    }
+
+    pub fn fancy_tasks(mut self, fancy_tasks: bool) -> DisplayOptions {
+        self.fancy_tasks = fancy_tasks;
+        self
+    }
+
+    pub fn hyperlinks(mut self, hyperlinks: bool) -> DisplayOptions {
+        self.hyperlinks = hyperlinks;
+        self
+    }
+
+    pub fn link_tag(mut self, link_tag: &str) -> DisplayOptions {
+        self.link_tag = link_tag.to_owned();
+        self
+    }
+
+    pub fn format_date(&self, d: TaskDate) -> String {
+        self.date_format.format(d, self.today)
+    }
+
+    fn shows(&self, section: Section) -> bool {
+        !self.hidden_sections.contains(&section)
+    }
+}
 
 fn is_recurred(c: &Changes) -> bool {
     use self::Changes::*;
-    match *c {
-        RecurredStrict => true,
-        RecurredFrom(_) => true,
-        _ => false,
-    }
+    matches!(*c, RecurredStrict | RecurredFrom(_))
 }
 fn is_completion(c: &Changes) -> bool {
     use self::Changes::*;
-    match *c {
-        FinishedAt(_) => true,
-        Finished(true) => true,
-        _ => false,
-    }
+    matches!(
+        *c,
+        FinishedAt(_) | InferredFinishDate(_) | Finished(true) | Created(_)
+    )
 }
+// Tasks brought forward are not postponed
 fn is_postponed(c: &Changes) -> bool {
     use self::Changes::*;
     match *c {
-        PostponedStrictBy(_) => true,
-        DueDate(Some(_), Some(_)) => true,
+        PostponedStrictBy(d, _, _) | PostponedThresholdBy(d, _, _) => d.num_days() > 0,
+        DueDate(Some(a), Some(b)) => b > a,
         _ => false,
     }
 }
 
 fn has_been_recurred(x: &ChangedTask<Vec<Changes>>) -> bool {
-    x.delta.iter().flat_map(|c| c).any(is_recurred)
+    x.delta.iter().flatten().any(is_recurred)
 }
 fn has_been_completed(x: &ChangedTask<Vec<Changes>>) -> bool {
-    x.delta.iter().flat_map(|c| c).any(is_completion)
+    x.delta.iter().flatten().any(is_completion)
 }
 fn has_been_postponed(x: &ChangedTask<Vec<Changes>>) -> bool {
-    x.delta.iter().flat_map(|c| c).any(is_postponed)
+    x.delta.iter().flatten().any(is_postponed)
 }
 
-fn color<T>(colorize: bool, color: Color, e: &T) -> ANSIString
-where
-    T: std::fmt::Display,
-{
-    let e_str = format!("{}", e);
-    if colorize {
-        color.paint(e_str)
-    } else {
-        ANSIString::from(e_str)
+// Splits `s` into its text and its ANSI escape sequences, tagging the latter with `true`. These
+// are either SGR sequences, ending with `m`, or OSC ones such as hyperlinks, ending with `ESC \`.
+fn ansi_parts(s: &str) -> Vec<(bool, &str)> {
+    let mut res = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('\x1b') {
+        let end = if rest[start..].starts_with("\x1b]") {
+            rest[start + 2..]
+                .find("\x1b\\")
+                .map_or(rest.len(), |i| start + 2 + i + 2)
+        } else {
+            rest[start..]
+                .find('m')
+                .map_or(rest.len(), |i| start + i + 1)
+        };
+        res.push((false, &rest[..start]));
+        res.push((true, &rest[start..end]));
+        rest = &rest[end..];
     }
+    res.push((false, rest));
+    res
 }
 
-fn change_str(colorize: bool, c: &Changes) -> Vec<ANSIString> {
-    use self::Changes::*;
-    match *c {
-        Created => vec!["created".into()],
-        RecurredStrict => vec!["recurred (strict)".into()],
-        RecurredFrom(Some(d)) => vec![format!("recurred (from {})", d).into()],
-        RecurredFrom(None) => vec!["recurred".into()],
-
-        FinishedAt(d) => vec![format!("completed on {}", d).into()],
-        PostponedStrictBy(d) => vec![format!("postponed (strict) by {} days", d.num_days()).into()],
-
-        Finished(true) => vec!["completed".into()],
-        Finished(false) => vec!["uncompleted".into()],
-        Priority(_, None) => vec!["removed priority".into()],
-        Priority(None, Some(c)) => vec![format!("added priority ({})", c).into()],
-        Priority(Some(_), Some(b)) => vec![format!("set priority to ({})", b).into()],
-        FinishDate(_, None) => vec!["removed completion date".into()],
-        FinishDate(None, Some(d)) => vec![format!("added completion date {}", d).into()],
-        FinishDate(Some(_), Some(d)) => vec![format!("set completion date to {}", d).into()],
-        CreateDate(_, None) => vec!["removed creation date".into()],
-        CreateDate(None, Some(d)) => vec![format!("added creation date {}", d).into()],
-        CreateDate(Some(_), Some(d)) => vec![format!("set creation date to {}", d).into()],
-        Subject(ref s, ref t) if colorize => {
-            let mut res = vec![ANSIString::from("changed subject ‘")];
-            for d in diff::chars(s, t) {
-                use diff::Result::*;
-                match d {
-                    Both(c, _) => res.push(c.to_string().into()),
-                    Left(c) => res.push(Style::new().on(Red).paint(c.to_string())),
-                    Right(c) => res.push(Style::new().on(Green).paint(c.to_string())),
-                }
+// The number of columns `s` takes once displayed
+fn display_width(s: &str) -> usize {
+    ansi_parts(s)
+        .into_iter()
+        .filter(|(is_escape, _)| !is_escape)
+        .map(|(_, t)| t.width())
+        .sum()
+}
+
+// Wraps `text` at word boundaries so that its lines fit in `width` columns (words too long being
+// left as-is), continuation lines being indented to line up under the text that follows `prefix`.
+// Colors are reset before each line break and restored after the indentation.
+fn wrap_line(width: Option<usize>, prefix: &str, text: &str) -> String {
+    let width = match width {
+        Some(width) => width,
+        None => return format!("{}{}", prefix, text),
+    };
+    let indent = " ".repeat(display_width(prefix));
+    let mut res = prefix.to_owned();
+    let mut column = indent.len();
+    let mut style = String::new();
+    for (i, word) in text.split(' ').enumerate() {
+        let word_width = display_width(word);
+        if i > 0 && column + 1 + word_width > width && column > indent.len() {
+            if !style.is_empty() {
+                res += "\x1b[0m";
             }
-            res.push("’".into());
-            res
+            res += "\n";
+            res += &indent;
+            res += &style;
+            column = indent.len();
+        } else if i > 0 {
+            res.push(' ');
+            column += 1;
         }
-        Subject(_, ref s) => vec![format!("set subject to ‘{}’", s).into()],
-        DueDate(_, None) => vec!["removed due date".into()],
-        DueDate(None, Some(d)) => vec![format!("added due date {}", d).into()],
-        DueDate(Some(_), Some(d)) => vec![format!("postponed to {}", d).into()],
-        ThresholdDate(_, None) => vec!["removed threshold date".into()],
-        ThresholdDate(None, Some(d)) => vec![format!("added threshold date {}", d).into()],
-        ThresholdDate(Some(_), Some(d)) => vec![format!("set threshold date to {}", d).into()],
-        Tags(ref a, ref b) => {
-            use itertools::Position::*;
-            let mut res = String::new();
-            if a.len() == 1 {
-                res += "removed tag ";
-            } else if a.len() > 1 {
-                res += "removed tags ";
-            }
-            for t in a.iter().with_position() {
-                match t {
-                    First(t) | Only(t) => res += &format!("{}:{}", t.0, t.1),
-                    Middle(t) => res += &format!(", {}:{}", t.0, t.1),
-                    Last(t) => res += &format!(" and {}:{}", t.0, t.1),
-                };
-            }
-            if !a.is_empty() && !b.is_empty() {
-                res += " and ";
-            }
-            if b.len() == 1 {
-                res += "added tag ";
-            } else if b.len() > 1 {
-                res += "added tags ";
-            }
-            for t in b.iter().with_position() {
-                match t {
-                    First(t) | Only(t) => res += &format!("{}:{}", t.0, t.1),
-                    Middle(t) => res += &format!(", {}:{}", t.0, t.1),
-                    Last(t) => res += &format!(" and {}:{}", t.0, t.1),
-                };
+        res += word;
+        column += word_width;
+        for (_, escape) in ansi_parts(word).into_iter().filter(|(e, _)| *e) {
+            if escape == "\x1b[0m" {
+                style.clear();
+            } else if !escape.starts_with("\x1b]") {
+                style += escape;
             }
-            vec![res.into()]
         }
     }
+    res
 }
 
-fn display_changes(colorize: bool, chgs_for_me: &Vec<Changes>) -> String {
+// Joins words as in “a, b and c”
+fn join_words<I: Iterator<Item = String>>(words: I) -> String {
     use itertools::Position::*;
-    chgs_for_me
-        .into_iter()
+    words
         .with_position()
-        .map(|c| match c {
-            First(c) | Only(c) => {
-                let chg = change_str(colorize, &c);
-                let mut chars = chg[0].chars();
-                let first_char = chars.next().expect("Internal error E004").to_uppercase();
-                format!("{}{}{}", first_char, chars.as_str(), ANSIStrings(&chg[1..]))
-            }
-            Middle(c) => format!(", {}", ANSIStrings(&change_str(colorize, &c))),
-            Last(c) => format!(" and {}", ANSIStrings(&change_str(colorize, &c))),
+        .map(|w| match w {
+            First(w) | Only(w) => w,
+            Middle(w) => format!(", {}", w),
+            Last(w) => format!(" and {}", w),
         })
         .join("")
 }
 
-pub fn display_changeset(
-    new_tasks: Vec<Task>,
-    changes: Vec<ChangedTask<Vec<Changes>>>,
-    colorize: bool,
-) -> String {
-    use self::TaskDelta::*;
-
-    // Sort changes by category
-    let (completed_new_tasks, mut category_new) =
-        new_tasks.into_iter().partition::<Vec<_>, _>(|x| x.finished);
+// Like `join_words`, but for words made of several differently colored strings
+fn join_segments<I>(words: I) -> Vec<ANSIString<'static>>
+where
+    I: Iterator<Item = Vec<ANSIString<'static>>>,
+{
+    use itertools::Position::*;
+    let mut res = Vec::new();
+    for w in words.with_position() {
+        match w {
+            First(w) | Only(w) => res.extend(w),
+            Middle(w) => res.extend(std::iter::once(", ".into()).chain(w)),
+            Last(w) => res.extend(std::iter::once(" and ".into()).chain(w)),
+        }
+    }
+    res
+}
 
-    let category_deleted = changes
-        .iter()
-        .filter(|x| x.delta == Deleted)
-        .map(|x| x.orig.clone())
-        .collect::<Vec<Task>>();
+fn plural(n: usize, singular: &str, plural: &str) -> String {
+    if n == 1 {
+        singular.to_owned()
+    } else {
+        plural.to_owned()
+    }
+}
 
-    let mut category_completed = changes
-        .iter()
-        .filter(|x| has_been_recurred(x) || has_been_completed(x))
-        .cloned()
-        .chain(completed_new_tasks.into_iter().map(|x| {
-            let mut chgs = vec![Changes::Created];
-            let mut u = x.clone();
-            u.uncomplete();
-            chgs.extend(changes_between(&u, &x));
-            ChangedTask {
-                orig: u,
-                delta: Changed(chgs),
-            }
-        }))
-        .collect::<Vec<ChangedTask<_>>>();
+// Splits eg. `45m` into (45, "m"), for tag values that are numbers with an optional unit
+fn numeric_tag_value(v: &str) -> Option<(i64, &str)> {
+    let digits_end = v
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+        .map_or(v.len(), |(i, _)| i);
+    let (number, unit) = v.split_at(digits_end);
+    if unit.chars().all(|c| c.is_ascii_alphabetic()) {
+        number.parse().ok().map(|n| (n, unit))
+    } else {
+        None
+    }
+}
 
-    let mut category_changed = changes
-        .iter()
-        .filter(|x| {
-            x.delta != Identical
-                && x.delta != Deleted
-                && !has_been_recurred(x)
-                && !has_been_completed(x)
-        })
-        .cloned()
-        .collect::<Vec<ChangedTask<_>>>();
+// Describes the change of a numeric tag value, eg. “increased est by 2 (3 → 5)”
+fn numeric_tag_str(
+    paint: &dyn Fn(Color, &str) -> ANSIString<'static>,
+    key: &str,
+    before: &str,
+    after: &str,
+) -> Option<Vec<ANSIString<'static>>> {
+    let (a, unit_a) = numeric_tag_value(before)?;
+    let (b, unit_b) = numeric_tag_value(after)?;
+    if unit_a != unit_b || a == b {
+        return None;
+    }
+    // Values too far apart for their difference to fit are described as merely changed
+    let delta = b.checked_sub(a).and_then(i64::checked_abs)?;
+    let verb = if b > a { "increased" } else { "decreased" };
+    Some(vec![
+        format!("{} {} by {}{} (", verb, key, delta, unit_a).into(),
+        paint(Red, before),
+        " → ".into(),
+        paint(Green, after),
+        ")".into(),
+    ])
+}
 
-    category_new.sort_by_key(|x| x.create_date);
-    category_completed.sort_by_key(|x| {
-        if has_been_recurred(x) {
-            100
-        } else if has_been_completed(x) {
-            200
+fn tags_str(
+    colorize: bool,
+    removed: &[(String, String)],
+    added: &[(String, String)],
+) -> Vec<ANSIString<'static>> {
+    let paint = |c: Color, s: &str| -> ANSIString<'static> {
+        if colorize {
+            c.paint(s.to_owned())
         } else {
-            500
+            ANSIString::from(s.to_owned())
         }
-    });
-    category_changed.sort_by_key(|x| if has_been_postponed(x) { 100 } else { 500 });
+    };
+    let tags = pair_tags(removed, added);
 
-    let mut res = String::new();
-    let mut is_first_change = true;
-    if !category_new.is_empty() {
-        is_first_change = false;
-        res += "New tasks\n";
-        res += "---------\n";
-        res += "\n";
-        for t in category_new {
-            res += &format!(" → {}\n", color(colorize, Green, &t));
+    // Each group is the verb, followed by the list of the tags it applies to
+    let group = |verb: &str, tags: Vec<Vec<ANSIString<'static>>>| {
+        if tags.is_empty() {
+            None
+        } else {
+            let head = ANSIString::from(verb.to_owned());
+            Some(
+                std::iter::once(head)
+                    .chain(join_segments(tags.into_iter()))
+                    .collect(),
+            )
+        }
+    };
+    let tags_group = |verb: &str, tags: &[&(String, String)], c: Color| {
+        let verb = format!("{} {} ", verb, plural(tags.len(), "tag", "tags"));
+        let tags = tags
+            .iter()
+            .map(|(k, v)| vec![paint(c, &format!("{}:{}", k, v))]);
+        group(&verb, tags.collect())
+    };
+    // Numeric changes carry their own verb, thus are groups of their own
+    let mut changed = Vec::new();
+    let mut numeric = Vec::new();
+    for &(k, a, b) in &tags.changed {
+        match numeric_tag_str(&paint, k, a, b) {
+            Some(n) => numeric.push(Some(n)),
+            None => changed.push(vec![
+                format!("{} from ", k).into(),
+                paint(Red, a),
+                " to ".into(),
+                paint(Green, b),
+            ]),
         }
     }
+    let groups = std::iter::once(tags_group("removed", &tags.removed, Red))
+        .chain(std::iter::once(group("changed ", changed)))
+        .chain(numeric)
+        .chain(std::iter::once(tags_group("added", &tags.added, Green)));
+    join_segments(groups.flatten())
+}
 
-    if !category_deleted.is_empty() {
-        if !is_first_change {
-            res += "\n";
-        }
-        is_first_change = false;
-        res += "Deleted tasks\n";
-        res += "-------------\n";
-        res += "\n";
-        for t in category_deleted {
-            res += &format!(" → {}\n", color(colorize, Red, &t));
-        }
+// Added projects and contexts are in the order of the subject, but displayed in alphabetical order
+// like the removed ones
+fn sorted(words: &[String]) -> Vec<String> {
+    let mut words = words.to_vec();
+    words.sort();
+    words
+}
+
+fn projects_str(removed: &[String], added: &[String]) -> String {
+    let fmt = |p: &[String]| join_words(p.iter().map(|p| format!("+{}", p)));
+    match (removed.len(), added.len()) {
+        (1, 1) => format!("moved from project +{} to +{}", removed[0], added[0]),
+        (0, n) => format!(
+            "added to {} {}",
+            plural(n, "project", "projects"),
+            fmt(added)
+        ),
+        (n, 0) => format!(
+            "removed from {} {}",
+            plural(n, "project", "projects"),
+            fmt(removed)
+        ),
+        (n, m) => format!(
+            "removed from {} {} and added to {} {}",
+            plural(n, "project", "projects"),
+            fmt(removed),
+            plural(m, "project", "projects"),
+            fmt(added)
+        ),
     }
+}
 
-    if !category_completed.is_empty() {
-        if !is_first_change {
-            res += "\n";
+fn contexts_str(removed: &[String], added: &[String]) -> String {
+    let fmt = |c: &[String]| join_words(c.iter().map(|c| format!("@{}", c)));
+    let removed_str = format!(
+        "removed {} {}",
+        plural(removed.len(), "context", "contexts"),
+        fmt(removed)
+    );
+    let added_str = format!(
+        "added {} {}",
+        plural(added.len(), "context", "contexts"),
+        fmt(added)
+    );
+    match (removed.is_empty(), added.is_empty()) {
+        (false, true) => removed_str,
+        (true, false) => added_str,
+        _ => format!("{} and {}", removed_str, added_str),
+    }
+}
+
+// Number of whole calendar months between two dates (the last day of a month being a whole month
+// after the last day of the previous one), if any
+fn whole_months_between(from: TaskDate, to: TaskDate) -> Option<i32> {
+    let is_last_day = |d: TaskDate| d.succ_opt().is_none_or(|n| n.month() != d.month());
+    if from.day() != to.day() && !(is_last_day(from) && is_last_day(to)) {
+        return None;
+    }
+    Some((to.year() - from.year()) * 12 + to.month() as i32 - from.month() as i32)
+}
+
+// Formats the duration between two dates, `to` being after `from`, in the largest sensible unit
+fn duration_str(from: TaskDate, to: TaskDate) -> String {
+    let days = to.signed_duration_since(from).num_days();
+    if let Some(months) = whole_months_between(from, to).filter(|m| *m > 0) {
+        if months % 12 == 0 {
+            let years = (months / 12) as usize;
+            format!("{} {}", years, plural(years, "year", "years"))
+        } else {
+            format!("{} {}", months, plural(months as usize, "month", "months"))
         }
-        is_first_change = false;
-        res += "Completed tasks\n";
-        res += "---------------\n";
-        for x in category_completed {
-            res += "\n";
+    } else if days % 7 == 0 {
+        let weeks = (days / 7) as usize;
+        format!("{} {}", weeks, plural(weeks, "week", "weeks"))
+    } else if days >= 360 {
+        let years = ((days + 182) / 365) as usize;
+        format!("about {} {}", years, plural(years, "year", "years"))
+    } else {
+        format!("{} {}", days, plural(days as usize, "day", "days"))
+    }
+}
 
-            if has_been_recurred(&x) {
-                res += &format!(" → {}\n", color(colorize, Green, &x.orig));
-            } else {
-                res += &format!(" → {}\n", color(colorize, Blue, &x.orig));
+fn relative_date_str(d: TaskDate, today: TaskDate) -> String {
+    match d.signed_duration_since(today).num_days() {
+        0 => "today".to_owned(),
+        1 => "tomorrow".to_owned(),
+        -1 => "yesterday".to_owned(),
+        n if n > 0 => format!("in {}", duration_str(today, d)),
+        _ => format!("{} ago", duration_str(d, today)),
+    }
+}
+
+// What a word of a task line is, for `paint_task` to highlight it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum TaskWord {
+    Priority,
+    Date,
+    // A project or a context
+    Label,
+    Text,
+}
+
+fn is_priority(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() == 3 && bytes[0] == b'(' && bytes[1].is_ascii_uppercase() && bytes[2] == b')'
+}
+
+// The words of `task_str`, each with what it is. Only the completion, creation, due and threshold
+// dates are dates, those written in the subject being left as they are.
+fn task_words(opts: &DisplayOptions, t: &Task) -> Vec<(TaskWord, String)> {
+    let reformat = opts.date_format != DateFormat::default();
+    // The completion and creation dates come right after the `x` and the priority
+    let first_date = t.finished as usize + !t.priority.is_lowest() as usize;
+    let dates = first_date..first_date + t.finish_date.iter().chain(&t.create_date).count();
+    t.to_string()
+        .split(' ')
+        .enumerate()
+        .map(|(i, word)| {
+            let (key, value) = match word.find(':') {
+                Some(i) if ["due", "t"].contains(&&word[..i]) => word.split_at(i + 1),
+                _ if dates.contains(&i) => ("", word),
+                _ => ("", ""),
+            };
+            match value.parse::<TaskDate>() {
+                Ok(d) if reformat => (TaskWord::Date, format!("{}{}", key, opts.format_date(d))),
+                Ok(_) => (TaskWord::Date, word.to_owned()),
+                Err(_) if i == 0 && is_priority(word) => (TaskWord::Priority, word.to_owned()),
+                Err(_) if word.len() > 1 && (word.starts_with('+') || word.starts_with('@')) => {
+                    (TaskWord::Label, word.to_owned())
+                }
+                Err(_) => (TaskWord::Text, word.to_owned()),
             }
+        })
+        .collect()
+}
 
-            for chgs in x.delta.iter() {
-                res += &format!("    → {}\n", display_changes(colorize, chgs));
+// Displays the task with the dates it contains in `opts.date_format`, which is thus not
+// necessarily a valid todo.txt line
+pub fn task_str(opts: &DisplayOptions, t: &Task) -> String {
+    task_words(opts, t)
+        .into_iter()
+        .map(|(_, word)| word)
+        .join(" ")
+}
+
+fn is_url(word: &str) -> bool {
+    ["http://", "https://"]
+        .iter()
+        .any(|scheme| word.len() > scheme.len() && word.starts_with(scheme))
+}
+
+// `text` as an OSC 8 hyperlink to `url`, which terminals display as `text`
+fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+// `word` made clickable if it is a URL, or only its value if it is an `opts.link_tag` tag. Links
+// with control characters are left alone, as they could end the escape sequence early.
+fn link_word(opts: &DisplayOptions, word: String) -> String {
+    let start = match word.find(':') {
+        Some(i) if word[..i] == *opts.link_tag && i + 1 < word.len() => i + 1,
+        _ if is_url(&word) => 0,
+        _ => return word,
+    };
+    if word.chars().any(char::is_control) {
+        return word;
+    }
+    let (key, url) = word.split_at(start);
+    format!("{}{}", key, hyperlink(url, url))
+}
+
+// The line of `t` in `style`, that of its section. With `opts.fancy_tasks`, the priority letter
+// is also colored by urgency, the dates dimmed and the projects and contexts in bold, on top of
+// `style` so that eg. a deleted task stays red. With `opts.hyperlinks`, its URLs are clickable.
+fn paint_task(opts: &DisplayOptions, t: &Task, style: Style) -> String {
+    if !opts.colorize {
+        return task_str(opts, t);
+    }
+    if !opts.fancy_tasks && !opts.hyperlinks {
+        return style.paint(task_str(opts, t)).to_string();
+    }
+    let mut parts = Vec::new();
+    for (i, (kind, word)) in task_words(opts, t).into_iter().enumerate() {
+        if i > 0 {
+            parts.push(style.paint(" "));
+        }
+        let word = if opts.hyperlinks {
+            link_word(opts, word)
+        } else {
+            word
+        };
+        match kind {
+            _ if !opts.fancy_tasks => parts.push(style.paint(word)),
+            TaskWord::Priority => {
+                let letter = word[1..2].to_owned();
+                let letter_style = match letter.as_str() {
+                    "A" => style.fg(Red),
+                    "B" => style.fg(Yellow),
+                    "C" => style.fg(Green),
+                    _ => style,
+                };
+                parts.push(style.paint("("));
+                parts.push(letter_style.paint(letter));
+                parts.push(style.paint(")"));
             }
+            TaskWord::Date => parts.push(style.dimmed().paint(word)),
+            TaskWord::Label => parts.push(style.bold().paint(word)),
+            TaskWord::Text => parts.push(style.paint(word)),
         }
     }
+    ANSIStrings(&parts).to_string()
+}
 
-    if !category_changed.is_empty() {
-        if !is_first_change {
-            res += "\n";
-        }
-        is_first_change = false;
-        res += "Changed tasks\n";
-        res += "-------------\n";
-        for x in category_changed {
-            res += "\n";
+fn recurrence_str(r: &Recurrence) -> String {
+    if r.strict {
+        format!("{}{} (strict)", r.num, r.period)
+    } else {
+        r.to_string()
+    }
+}
 
-            if has_been_postponed(&x) {
-                res += &format!(" → {}\n", color(colorize, Yellow, &x.orig));
-            } else {
-                res += &format!(" → {}\n", x.orig);
+impl Changes {
+    // The uncolored description of the change, as displayed by `display_changeset`
+    pub fn describe(&self) -> String {
+        self.describe_with(&|d| d.to_string())
+    }
+
+    // Like `describe`, but with the dates formatted by `date`
+    pub fn describe_with(&self, date: &dyn Fn(TaskDate) -> String) -> String {
+        use self::Changes::*;
+        match *self {
+            Created(Some(d)) => format!("created already completed on {}", date(d)),
+            Created(None) => "created already completed".to_owned(),
+            RecurredStrict => "recurred (strict)".to_owned(),
+            RecurredFrom(Some(d)) => format!("recurred (from {})", date(d)),
+            RecurredFrom(None) => "recurred".to_owned(),
+            SkippedOccurrences(n) => format!(
+                "skipped {} {}",
+                n,
+                plural(n as usize, "occurrence", "occurrences")
+            ),
+
+            FinishedAt(d) => format!("completed on {}", date(d)),
+            InferredFinishDate(d) => format!("completed on {} (inferred)", date(d)),
+            PostponedStrictBy(d, a, b) if d.num_days() < 0 => {
+                format!("brought forward (strict) by {}", duration_str(b, a))
+            }
+            PostponedStrictBy(_, a, b) => format!("postponed (strict) by {}", duration_str(a, b)),
+            PostponedThresholdBy(d, a, b) if d.num_days() < 0 => {
+                format!("brought forward (threshold) by {}", duration_str(b, a))
+            }
+            PostponedThresholdBy(_, a, b) => {
+                format!("postponed (threshold) by {}", duration_str(a, b))
             }
 
-            for chgs in x.delta.iter() {
-                res += &format!("    → {}\n", display_changes(colorize, chgs));
+            Finished(true) => "completed".to_owned(),
+            Finished(false) => "uncompleted".to_owned(),
+            Priority(_, None) => "removed priority".to_owned(),
+            Priority(None, Some(c)) => format!("added priority ({})", c),
+            Priority(Some(a), Some(b)) if a == b => format!("kept priority ({})", a),
+            Priority(Some(a), Some(b)) if b < a => {
+                format!("raised priority from ({}) to ({})", a, b)
             }
+            Priority(Some(a), Some(b)) => format!("lowered priority from ({}) to ({})", a, b),
+            FinishDate(_, None) => "removed completion date".to_owned(),
+            FinishDate(None, Some(d)) => format!("added completion date {}", date(d)),
+            FinishDate(Some(_), Some(d)) => format!("set completion date to {}", date(d)),
+            CreateDate(_, None) => "removed creation date".to_owned(),
+            CreateDate(None, Some(d)) => format!("added creation date {}", date(d)),
+            CreateDate(Some(_), Some(d)) => format!("set creation date to {}", date(d)),
+            Subject(ref s) => format!("set subject to ‘{}’", s.after),
+            DueDate(_, None) => "removed due date".to_owned(),
+            DueDate(None, Some(d)) => format!("added due date {}", date(d)),
+            DueDate(Some(a), Some(b)) if b < a => format!("moved due date earlier to {}", date(b)),
+            DueDate(Some(_), Some(d)) => format!("postponed to {}", date(d)),
+            ThresholdDate(_, None) => "removed threshold date".to_owned(),
+            ThresholdDate(None, Some(d)) => format!("added threshold date {}", date(d)),
+            ThresholdDate(Some(_), Some(d)) => format!("set threshold date to {}", date(d)),
+            Tags(ref a, ref b) => ANSIStrings(&tags_str(false, a, b)).to_string(),
+            Recurrence(_, None) => "removed recurrence".to_owned(),
+            Recurrence(None, Some(ref r)) => format!("made recurring every {}", recurrence_str(r)),
+            Recurrence(Some(ref a), Some(ref b)) => format!(
+                "changed recurrence from {} to {}",
+                recurrence_str(a),
+                recurrence_str(b)
+            ),
+            Projects(ref a, ref b) => projects_str(a, &sorted(b)),
+            Contexts(ref a, ref b) => contexts_str(a, &sorted(b)),
         }
     }
+}
 
-    // Nice display
-    if is_first_change {
-        res += "No changes.\n";
+impl std::fmt::Display for Changes {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.describe())
     }
+}
+
+// Tells how far away a new due date is from `opts.today`, if set, and whether it is overdue
+fn due_note(opts: &DisplayOptions, c: &Changes) -> Option<(bool, String)> {
+    let today = opts.today?;
+    let due = match *c {
+        Changes::DueDate(_, Some(d)) | Changes::PostponedStrictBy(_, _, d) => d,
+        _ => return None,
+    };
+    Some(match due.signed_duration_since(today).num_days() {
+        n if n < 0 => (true, " (now overdue)".to_owned()),
+        0 => (false, " (due today)".to_owned()),
+        n => (
+            false,
+            format!(" (due in {} {})", n, plural(n as usize, "day", "days")),
+        ),
+    })
+}
+
+fn due_annotation(opts: &DisplayOptions, c: &Changes) -> Option<ANSIString<'static>> {
+    let (overdue, text) = due_note(opts, c)?;
+    let color = if overdue { Red } else { Green };
+    Some(if opts.colorize {
+        color.paint(text)
+    } else {
+        text.into()
+    })
+}
 
+fn change_str(opts: &DisplayOptions, c: &Changes) -> Vec<ANSIString<'static>> {
+    let mut res = change_description(opts, c);
+    res.extend(due_annotation(opts, c));
     res
 }
+
+// Like `Changes::describe`, but highlighting the changes to the subject when colorizing
+fn change_description(opts: &DisplayOptions, c: &Changes) -> Vec<ANSIString<'static>> {
+    match *c {
+        Changes::Subject(ref s) if opts.colorize && opts.word_diff != WordDiff::None => {
+            let mut res = vec![ANSIString::from("changed subject ‘")];
+            for op in s.ops.iter().cloned() {
+                match op {
+                    DiffOp::Equal(w) => res.push(w.into()),
+                    DiffOp::Delete(w) => res.push(Style::new().on(Red).paint(w)),
+                    DiffOp::Insert(w) => res.push(Style::new().on(Green).paint(w)),
+                }
+            }
+            res.push("’".into());
+            res
+        }
+        Changes::Tags(ref a, ref b) => tags_str(opts.colorize, a, b),
+        _ => vec![c.describe_with(&|d| opts.format_date(d)).into()],
+    }
+}
+
+fn display_changes(opts: &DisplayOptions, chgs_for_me: &[Changes]) -> String {
+    changes_sentence(opts, chgs_for_me, true)
+}
+
+fn changes_sentence(opts: &DisplayOptions, chgs_for_me: &[Changes], capitalize: bool) -> String {
+    use itertools::Position::*;
+    chgs_for_me
+        .iter()
+        .with_position()
+        .map(|c| match c {
+            First(c) | Only(c) if !capitalize => ANSIStrings(&change_str(opts, c)).to_string(),
+            First(c) | Only(c) => {
+                let chg = change_str(opts, c);
+                let mut chars = chg[0].chars();
+                let first_char = chars.next().expect("Internal error E004").to_uppercase();
+                format!("{}{}{}", first_char, chars.as_str(), ANSIStrings(&chg[1..]))
+            }
+            Middle(c) => format!(", {}", ANSIStrings(&change_str(opts, c))),
+            Last(c) => format!(" and {}", ANSIStrings(&change_str(opts, c))),
+        })
+        .join("")
+}
+
+// The tasks a task was split into, eg. “Split into ‘plan holiday’ and ‘book holiday’”
+fn split_description(opts: &DisplayOptions, fragments: &[Task]) -> String {
+    let fragments = fragments
+        .iter()
+        .map(|t| vec![format!("‘{}’", task_str(opts, t)).into()]);
+    format!("Split into {}", ANSIStrings(&join_segments(fragments)))
+}
+
+fn is_split<T>(x: &ChangedTask<T>) -> bool {
+    matches!(x.delta, TaskDelta::Split(_))
+}
+
+// A copy of a task other than the one it was matched with, eg. “Copied and then changed priority
+// from (A) to (B)”
+fn copy_description(opts: &DisplayOptions, chgs: &[Changes]) -> String {
+    if chgs.is_empty() {
+        "Copied as is".to_owned()
+    } else {
+        format!("Copied and then {}", changes_sentence(opts, chgs, false))
+    }
+}
+
+// The lines describing what happened to a changed task, without their `→`: one per occurrence
+// of a recurring task, per copy of a copied one, or else a single one
+fn delta_descriptions(opts: &DisplayOptions, x: &ChangedTask<Vec<Changes>>) -> Vec<String> {
+    match x.delta {
+        TaskDelta::Split(_) => vec![split_description(opts, &x.after)],
+        TaskDelta::Copied(ref copies) => copies[..1]
+            .iter()
+            .filter(|c| !c.is_empty())
+            .map(|c| display_changes(opts, c))
+            .chain(copies[1..].iter().map(|c| copy_description(opts, c)))
+            .collect(),
+        // The occurrence already completed in BEFORE may not have changed
+        _ => x
+            .delta
+            .iter()
+            .filter(|c| !c.is_empty())
+            .map(|c| display_changes(opts, c))
+            .collect(),
+    }
+}
+
+// The tasks of each section displayed by `display_changeset`, in display order. New tasks that were
+// already completed are in `new`, but displayed and counted in the completed section, see
+// `new_section` and `completed_section`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChangesetReport {
+    pub new: Vec<Task>,
+    pub deleted: Vec<Task>,
+    // The line numbers of each of `new` and `deleted`, when known, see `match_tasks_indexed`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub new_lines: Vec<Option<usize>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub deleted_lines: Vec<Option<usize>>,
+    // Only filled with `DisplayOptions::show_moves`, and not counted as changes
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub moved: Vec<ChangedTask<Vec<Changes>>>,
+    // Only set with `DisplayOptions::show_unchanged`, and not counted as changes either
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unchanged: Option<Vec<Task>>,
+    pub completed: Vec<ChangedTask<Vec<Changes>>>,
+    pub changed: Vec<ChangedTask<Vec<Changes>>>,
+}
+
+impl ChangesetReport {
+    // The new tasks that are not completed, with their line numbers when known
+    pub fn new_section(&self) -> Vec<(&Task, Option<usize>)> {
+        self.new
+            .iter()
+            .zip(
+                self.new_lines
+                    .iter()
+                    .cloned()
+                    .chain(std::iter::repeat(None)),
+            )
+            .filter(|(t, _)| !t.finished)
+            .collect()
+    }
+
+    // The completed tasks, followed by the new tasks that were already completed, see
+    // `created_completed`
+    pub fn completed_section(&self) -> Vec<ChangedTask<Vec<Changes>>> {
+        let mut res = self.completed.clone();
+        res.extend(self.created_completed());
+        res
+    }
+
+    // The new tasks that were already completed, each with a single `Created` change
+    pub fn created_completed(&self) -> Vec<ChangedTask<Vec<Changes>>> {
+        self.new
+            .iter()
+            .zip(
+                self.new_lines
+                    .iter()
+                    .cloned()
+                    .chain(std::iter::repeat(None)),
+            )
+            .filter(|(t, _)| t.finished)
+            .map(|(t, line)| ChangedTask {
+                orig: t.clone(),
+                delta: TaskDelta::Changed(vec![Changes::Created(t.finish_date)]),
+                after: vec![t.clone()],
+                orig_line: None,
+                after_lines: line.into_iter().collect(),
+                similarity: None,
+            })
+            .collect()
+    }
+
+    pub fn counts(&self) -> ChangesetCounts {
+        let created_completed = self.new.iter().filter(|t| t.finished).count();
+        ChangesetCounts {
+            new: self.new.len() - created_completed,
+            deleted: self.deleted.len(),
+            completed: self.completed.len() + created_completed,
+            recurred: self
+                .completed
+                .iter()
+                .filter(|x| has_been_recurred(x))
+                .count(),
+            changed: self.changed.len(),
+            postponed: self
+                .changed
+                .iter()
+                .filter(|x| has_been_postponed(x))
+                .count(),
+            unchanged: self.unchanged.as_ref().map(Vec::len),
+        }
+    }
+}
+
+// Number of tasks in each of the sections displayed by `display_changeset`
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ChangesetCounts {
+    pub new: usize,
+    pub deleted: usize,
+    pub completed: usize,
+    // Among the completed tasks, those that recurred
+    pub recurred: usize,
+    pub changed: usize,
+    // Among the changed tasks, those that were postponed
+    pub postponed: usize,
+    // Only counted when the unchanged tasks are displayed
+    pub unchanged: Option<usize>,
+}
+
+impl ChangesetCounts {
+    pub fn is_empty(&self) -> bool {
+        self.new == 0 && self.deleted == 0 && self.completed == 0 && self.changed == 0
+    }
+
+    // Like the `Display` implementation, but also detailing the recurred and postponed tasks
+    pub fn detailed(&self) -> String {
+        let detail = |n: usize, what: &str| {
+            if n == 0 {
+                String::new()
+            } else {
+                format!(" ({} {})", n, what)
+            }
+        };
+        let unchanged = match self.unchanged {
+            Some(n) => format!(", {} unchanged", n),
+            None => String::new(),
+        };
+        format!(
+            "{} new, {} deleted, {} completed{}, {} changed{}{}",
+            self.new,
+            self.deleted,
+            self.completed,
+            detail(self.recurred, "recurred"),
+            self.changed,
+            detail(self.postponed, "postponed"),
+            unchanged
+        )
+    }
+}
+
+// Adding up the counts of several changesets, eg. in batch mode
+impl std::ops::AddAssign for ChangesetCounts {
+    fn add_assign(&mut self, other: ChangesetCounts) {
+        self.new += other.new;
+        self.deleted += other.deleted;
+        self.completed += other.completed;
+        self.recurred += other.recurred;
+        self.changed += other.changed;
+        self.postponed += other.postponed;
+        self.unchanged = match (self.unchanged, other.unchanged) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+impl std::fmt::Display for ChangesetCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_empty() {
+            write!(f, "No changes.")
+        } else {
+            write!(
+                f,
+                "{} new, {} deleted, {} completed, {} changed",
+                self.new, self.deleted, self.completed, self.changed
+            )
+        }
+    }
+}
+
+// Hidden sections are left empty
+pub fn categorize_changeset_with(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> ChangesetReport {
+    categorize(
+        new_tasks.into_iter().map(|t| (None, t)).collect(),
+        changes,
+        opts,
+    )
+}
+
+// Like `categorize_changeset_with`, with the line numbers of the new tasks, see
+// `compute_changeset_indexed_with`
+pub fn categorize_changeset_indexed_with(
+    new_tasks: Vec<(usize, Task)>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> ChangesetReport {
+    categorize(
+        new_tasks.into_iter().map(|(i, t)| (Some(i), t)).collect(),
+        changes,
+        opts,
+    )
+}
+
+fn categorize(
+    new_tasks: Vec<(Option<usize>, Task)>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> ChangesetReport {
+    use self::TaskDelta::*;
+
+    // Sort changes by category
+    let mut category_new = new_tasks;
+
+    let category_moved = if opts.show_moves {
+        moved_tasks(&changes).into_iter().cloned().collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut category_unchanged = Vec::new();
+    let mut category_deleted = Vec::new();
+    let mut category_completed = Vec::new();
+    let mut category_changed = Vec::new();
+    for x in changes {
+        match x.delta {
+            Identical => category_unchanged.push(x.orig),
+            Deleted => category_deleted.push((x.orig_line, x.orig)),
+            _ if has_been_recurred(&x) || has_been_completed(&x) => category_completed.push(x),
+            _ => category_changed.push(x),
+        }
+    }
+    let category_unchanged = Some(category_unchanged).filter(|_| opts.show_unchanged);
+
+    category_new.sort_by_key(|(_, x)| x.create_date);
+    category_completed.sort_by_key(|x| {
+        if has_been_recurred(x) {
+            100
+        } else if has_been_completed(x) {
+            200
+        } else {
+            500
+        }
+    });
+    category_changed.sort_by_key(|x| if has_been_postponed(x) { 100 } else { 500 });
+
+    // Hidden sections are emptied, so that they are neither displayed nor counted. The new tasks
+    // already completed belong to the completed section.
+    category_new.retain(|(_, x)| {
+        opts.shows(if x.finished {
+            Section::Completed
+        } else {
+            Section::New
+        })
+    });
+    if !opts.shows(Section::Deleted) {
+        category_deleted.clear();
+    }
+    if !opts.shows(Section::Completed) {
+        category_completed.clear();
+    }
+    if !opts.shows(Section::Changed) {
+        category_changed.clear();
+    }
+
+    let (new_lines, category_new) = category_new.into_iter().unzip();
+    let (deleted_lines, category_deleted) = category_deleted.into_iter().unzip();
+    ChangesetReport {
+        new: category_new,
+        deleted: category_deleted,
+        new_lines,
+        deleted_lines,
+        moved: category_moved,
+        unchanged: category_unchanged,
+        completed: category_completed,
+        changed: category_changed,
+    }
+}
+
+pub fn categorize_changeset(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+) -> ChangesetReport {
+    categorize_changeset_with(new_tasks, changes, &DisplayOptions::default())
+}
+
+pub fn count_changeset(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+) -> ChangesetCounts {
+    count_changeset_with(new_tasks, changes, &DisplayOptions::default())
+}
+
+// Hidden sections are counted as empty
+pub fn count_changeset_with(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> ChangesetCounts {
+    categorize_changeset_with(new_tasks, changes, opts).counts()
+}
+
+pub fn display_changeset_summary(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+) -> String {
+    display_changeset_summary_with(new_tasks, changes, &DisplayOptions::default())
+}
+
+pub fn display_changeset_summary_with(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> String {
+    format!("{}\n", count_changeset_with(new_tasks, changes, opts))
+}
+
+pub fn display_changeset(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    colorize: bool,
+) -> String {
+    display_changeset_with(
+        new_tasks,
+        changes,
+        &DisplayOptions::default().colorize(colorize),
+    )
+}
+
+// Splits the items of a section into the groups they are displayed under, along with the heading
+// of each group. Items with several projects (or contexts) appear in each of their groups, and
+// keep their relative order in each group.
+fn group_items<'a, T, F>(
+    opts: &DisplayOptions,
+    items: &'a [T],
+    task: F,
+) -> Vec<(Option<String>, Vec<&'a T>)>
+where
+    F: Fn(&T) -> &Task,
+{
+    let group_by = match opts.group_by {
+        Some(g) => g,
+        None => return vec![(None, items.iter().collect())],
+    };
+    let (prefix, no_group) = match group_by {
+        GroupBy::Project => ('+', "(no project)"),
+        GroupBy::Context => ('@', "(no context)"),
+    };
+    let mut groups = BTreeMap::new();
+    let mut ungrouped = Vec::new();
+    for x in items {
+        let keys = match group_by {
+            GroupBy::Project => &task(x).projects,
+            GroupBy::Context => &task(x).contexts,
+        };
+        if keys.is_empty() {
+            ungrouped.push(x);
+        }
+        for k in keys {
+            groups
+                .entry(format!("{}{}", prefix, k))
+                .or_insert_with(Vec::new)
+                .push(x);
+        }
+    }
+    let mut res = groups
+        .into_iter()
+        .map(|(k, v)| (Some(k), v))
+        .collect::<Vec<_>>();
+    if !ungrouped.is_empty() {
+        res.push((Some(no_group.to_owned()), ungrouped));
+    }
+    res
+}
+
+pub fn display_changeset_to<W: io::Write>(
+    w: &mut W,
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    colorize: bool,
+) -> io::Result<()> {
+    display_changeset_to_with(
+        w,
+        new_tasks,
+        changes,
+        &DisplayOptions::default().colorize(colorize),
+    )
+}
+
+pub fn display_changeset_with(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> String {
+    let mut res = Vec::new();
+    display_changeset_to_with(&mut res, new_tasks, changes, opts).expect("Internal error E030");
+    String::from_utf8(res).expect("Internal error E031")
+}
+
+// How far a task returned by `moved_tasks` moved, eg. “Moved up 3 lines”. Its line number may
+// also be unchanged, when the tasks around it moved instead.
+fn move_description<T>(x: &ChangedTask<T>) -> String {
+    let (before, after) = match (x.orig_line, x.after_lines.first()) {
+        (Some(before), Some(after)) => (before, *after),
+        _ => return "Moved".to_owned(),
+    };
+    let (direction, n) = match after.cmp(&before) {
+        std::cmp::Ordering::Less => ("up", before - after),
+        std::cmp::Ordering::Greater => ("down", after - before),
+        std::cmp::Ordering::Equal => return "Moved".to_owned(),
+    };
+    format!("Moved {} {} {}", direction, n, plural(n, "line", "lines"))
+}
+
+// Where a task was in the compared files, eg. `[todo.txt:3 → todo.txt:5]`, when line numbers are
+// to be displayed and known
+fn line_prefix(opts: &DisplayOptions, before: Option<usize>, after: &[usize]) -> String {
+    let (before_name, after_name) = match &opts.line_numbers {
+        Some(names) => names,
+        None => return String::new(),
+    };
+    let mut locations = Vec::new();
+    if let Some(line) = before {
+        locations.push(format!("{}:{}", before_name, line));
+    }
+    if !after.is_empty() {
+        locations.push(format!("{}:{}", after_name, after.iter().join(",")));
+    }
+    if locations.is_empty() {
+        String::new()
+    } else {
+        format!("[{}] ", locations.join(" → "))
+    }
+}
+
+pub fn display_changeset_to_with<W: io::Write>(
+    w: &mut W,
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> io::Result<()> {
+    let has_changes = changeset_has_changes(&new_tasks, &changes);
+    let report = categorize_changeset_with(new_tasks, changes, opts);
+    display_report_to(w, report, has_changes, opts)
+}
+
+// Like `display_changeset_to_with`, with the line numbers of the new tasks, see
+// `compute_changeset_indexed_with`
+pub fn display_changeset_indexed_to_with<W: io::Write>(
+    w: &mut W,
+    new_tasks: Vec<(usize, Task)>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> io::Result<()> {
+    let has_changes = !new_tasks.is_empty() || changeset_has_changes(&[], &changes);
+    let report = categorize_changeset_indexed_with(new_tasks, changes, opts);
+    display_report_to(w, report, has_changes, opts)
+}
+
+fn score_suffix<T>(opts: &DisplayOptions, x: &ChangedTask<T>) -> String {
+    match x.similarity {
+        Some(similarity) if opts.show_scores => {
+            format!(" (match confidence {}%)", similarity)
+        }
+        _ => String::new(),
+    }
+}
+
+// `has_changes` is whether the changeset has changes, be they in hidden sections or not
+fn display_report_to<W: io::Write>(
+    w: &mut W,
+    report: ChangesetReport,
+    has_changes: bool,
+    opts: &DisplayOptions,
+) -> io::Result<()> {
+    let counts = report.counts();
+    // Changes that are all hidden are not displayed, not even as “No changes.”, unless their
+    // statistics are
+    if has_changes && counts.is_empty() && !opts.hidden_sections.is_empty() && !opts.show_stats {
+        return Ok(());
+    }
+    let stats = if opts.show_stats {
+        Some(compute_stats(&report))
+    } else {
+        None
+    };
+    let created_completed = report.created_completed();
+    let ChangesetReport {
+        new,
+        new_lines,
+        deleted,
+        deleted_lines,
+        moved: category_moved,
+        unchanged: category_unchanged,
+        completed,
+        changed: category_changed,
+    } = report;
+    let category_new = new
+        .into_iter()
+        .zip(new_lines.into_iter().chain(std::iter::repeat(None)))
+        .filter(|(t, _)| !t.finished)
+        .collect::<Vec<_>>();
+    let category_completed = completed
+        .into_iter()
+        .chain(created_completed)
+        .collect::<Vec<_>>();
+    // The line numbers are unknown when the tasks were not matched by `match_tasks_indexed`
+    let category_deleted = deleted
+        .into_iter()
+        .zip(deleted_lines.into_iter().chain(std::iter::repeat(None)))
+        .collect::<Vec<_>>();
+
+    if let Some((before, after)) = &opts.labels {
+        writeln!(w, "Comparing ‘{}’ → ‘{}’", before, after)?;
+        writeln!(w)?;
+    }
+
+    let mut is_first_change = true;
+    if !category_new.is_empty() {
+        is_first_change = false;
+        writeln!(w, "New tasks")?;
+        writeln!(w, "---------")?;
+        for (heading, tasks) in group_items(opts, &category_new, |(t, _)| t) {
+            writeln!(w)?;
+            if let Some(heading) = heading {
+                writeln!(w, "{}", heading)?;
+            }
+            for (t, line) in tasks {
+                let task = paint_task(opts, t, Green.normal());
+                let task = format!("{}{}", line_prefix(opts, None, line.as_slice()), task);
+                writeln!(w, "{}", wrap_line(opts.width, " → ", &task))?;
+            }
+        }
+    }
+
+    if !category_deleted.is_empty() {
+        if !is_first_change {
+            writeln!(w)?;
+        }
+        is_first_change = false;
+        writeln!(w, "Deleted tasks")?;
+        writeln!(w, "-------------")?;
+        for (heading, tasks) in group_items(opts, &category_deleted, |(t, _)| t) {
+            writeln!(w)?;
+            if let Some(heading) = heading {
+                writeln!(w, "{}", heading)?;
+            }
+            for (t, line) in tasks {
+                let task = paint_task(opts, t, Red.normal());
+                let task = format!("{}{}", line_prefix(opts, *line, &[]), task);
+                writeln!(w, "{}", wrap_line(opts.width, " → ", &task))?;
+            }
+        }
+    }
+
+    if !category_completed.is_empty() {
+        if !is_first_change {
+            writeln!(w)?;
+        }
+        is_first_change = false;
+        writeln!(w, "Completed tasks")?;
+        writeln!(w, "---------------")?;
+        for (heading, tasks) in group_items(opts, &category_completed, |x| &x.orig) {
+            if let Some(heading) = heading {
+                writeln!(w, "\n{}", heading)?;
+            }
+            for x in tasks {
+                writeln!(w)?;
+
+                let task_color = if has_been_recurred(x) { Green } else { Blue };
+                let task = paint_task(opts, &x.orig, task_color.normal());
+                let task = format!(
+                    "{}{}{}",
+                    line_prefix(opts, x.orig_line, &x.after_lines),
+                    task,
+                    score_suffix(opts, x)
+                );
+                writeln!(w, "{}", wrap_line(opts.width, " → ", &task))?;
+                for description in delta_descriptions(opts, x) {
+                    writeln!(w, "{}", wrap_line(opts.width, "    → ", &description))?;
+                }
+            }
+        }
+    }
+
+    if !category_changed.is_empty() {
+        if !is_first_change {
+            writeln!(w)?;
+        }
+        is_first_change = false;
+        writeln!(w, "Changed tasks")?;
+        writeln!(w, "-------------")?;
+        for (heading, tasks) in group_items(opts, &category_changed, |x| &x.orig) {
+            if let Some(heading) = heading {
+                writeln!(w, "\n{}", heading)?;
+            }
+            for x in tasks {
+                writeln!(w)?;
+
+                let style = if has_been_postponed(x) {
+                    Yellow.normal()
+                } else {
+                    Style::new()
+                };
+                let task = paint_task(opts, &x.orig, style);
+                let task = format!(
+                    "{}{}{}",
+                    line_prefix(opts, x.orig_line, &x.after_lines),
+                    task,
+                    score_suffix(opts, x)
+                );
+                writeln!(w, "{}", wrap_line(opts.width, " → ", &task))?;
+                for description in delta_descriptions(opts, x) {
+                    writeln!(w, "{}", wrap_line(opts.width, "    → ", &description))?;
+                }
+            }
+        }
+    }
+
+    // Moves are not changes, but are displayed as such when asked for
+    if !category_moved.is_empty() {
+        if !is_first_change {
+            writeln!(w)?;
+        }
+        is_first_change = false;
+        writeln!(w, "Moved tasks")?;
+        writeln!(w, "-----------")?;
+        for x in &category_moved {
+            writeln!(w)?;
+            let task = format!(
+                "{}{}",
+                line_prefix(opts, x.orig_line, &x.after_lines),
+                paint_task(opts, &x.orig, Style::new())
+            );
+            writeln!(w, "{}", wrap_line(opts.width, " → ", &task))?;
+            writeln!(w, "    → {}", move_description(x))?;
+        }
+    }
+
+    // Nice display
+    if is_first_change {
+        writeln!(w, "No changes.")?;
+    }
+
+    // Identical tasks are not changes, so come after the “No changes.” if any
+    if let Some(category_unchanged) = category_unchanged.filter(|u| !u.is_empty()) {
+        writeln!(w)?;
+        writeln!(w, "Unchanged tasks")?;
+        writeln!(w, "---------------")?;
+        for (heading, tasks) in group_items(opts, &category_unchanged, |t| t) {
+            writeln!(w)?;
+            if let Some(heading) = heading {
+                writeln!(w, "{}", heading)?;
+            }
+            for t in tasks {
+                let task = paint_task(opts, t, Style::new().dimmed());
+                writeln!(w, "{}", wrap_line(opts.width, " → ", &task))?;
+            }
+        }
+    }
+
+    if let Some(stats) = stats {
+        writeln!(w)?;
+        writeln!(w, "Statistics")?;
+        writeln!(w, "----------")?;
+        writeln!(w)?;
+        write!(w, "{}", stats)?;
+    }
+
+    if !is_first_change && opts.summary_line {
+        writeln!(w, "\nSummary: {}", counts.detailed())?;
+    }
+
+    Ok(())
+}
+
+fn porcelain_date(d: &Option<TaskDate>) -> String {
+    match d {
+        Some(d) => d.format("%Y-%m-%d").to_string(),
+        None => "-".to_owned(),
+    }
+}
+
+fn porcelain_priority(p: &Option<char>) -> String {
+    match p {
+        Some(p) => p.to_string(),
+        None => "-".to_owned(),
+    }
+}
+
+fn porcelain_recurrence(r: &Option<Recurrence>) -> String {
+    match r {
+        Some(r) => r.to_string(),
+        None => "-".to_owned(),
+    }
+}
+
+fn porcelain_change(c: &Changes) -> Vec<String> {
+    use self::Changes::*;
+    match c {
+        Created(d) => vec![format!("created\t{}", porcelain_date(d))],
+        RecurredStrict => vec!["recurred-strict".to_owned()],
+        RecurredFrom(d) => vec![format!("recurred-from\t{}", porcelain_date(d))],
+        SkippedOccurrences(n) => vec![format!("skipped-occurrences\t{}", n)],
+
+        FinishedAt(d) => vec![format!("completed-at\t{}", porcelain_date(&Some(*d)))],
+        InferredFinishDate(d) => vec![format!("completed-inferred\t{}", porcelain_date(&Some(*d)))],
+        PostponedStrictBy(d, _, _) => vec![format!("postponed-strict-by\t{}", d.num_days())],
+        PostponedThresholdBy(d, _, _) => {
+            vec![format!("postponed-threshold-by\t{}", d.num_days())]
+        }
+
+        Finished(true) => vec!["completed".to_owned()],
+        Finished(false) => vec!["uncompleted".to_owned()],
+        Priority(a, b) => vec![format!(
+            "priority\t{}\t{}",
+            porcelain_priority(a),
+            porcelain_priority(b)
+        )],
+        FinishDate(a, b) => vec![format!(
+            "finish-date\t{}\t{}",
+            porcelain_date(a),
+            porcelain_date(b)
+        )],
+        CreateDate(a, b) => vec![format!(
+            "create-date\t{}\t{}",
+            porcelain_date(a),
+            porcelain_date(b)
+        )],
+        Subject(s) => vec![format!("subject\t{}\t{}", s.before, s.after)],
+        DueDate(a, b) => vec![format!(
+            "due-date\t{}\t{}",
+            porcelain_date(a),
+            porcelain_date(b)
+        )],
+        ThresholdDate(a, b) => vec![format!(
+            "threshold-date\t{}\t{}",
+            porcelain_date(a),
+            porcelain_date(b)
+        )],
+        Tags(a, b) => a
+            .iter()
+            .map(|(k, v)| format!("tag-removed\t{}:{}", k, v))
+            .chain(b.iter().map(|(k, v)| format!("tag-added\t{}:{}", k, v)))
+            .collect(),
+        Recurrence(a, b) => vec![format!(
+            "recurrence\t{}\t{}",
+            porcelain_recurrence(a),
+            porcelain_recurrence(b)
+        )],
+        // Sorted even if the changes were not computed but eg. deserialized, as removed words
+        // then need not be in order either
+        Projects(a, b) => sorted(a)
+            .iter()
+            .map(|p| format!("project-removed\t+{}", p))
+            .chain(sorted(b).iter().map(|p| format!("project-added\t+{}", p)))
+            .collect(),
+        Contexts(a, b) => sorted(a)
+            .iter()
+            .map(|c| format!("context-removed\t@{}", c))
+            .chain(sorted(b).iter().map(|c| format!("context-added\t@{}", c)))
+            .collect(),
+    }
+}
+
+// The porcelain format is meant to be parsed by scripts, and is thus considered stable.
+// Each task is displayed on a line of the form `<code>\t<task>`, where `<code>` is one of `N`
+// (new), `D` (deleted), `C` (completed), `R` (recurred), `S` (split) or `M` (changed). Each
+// change undergone by the task is then displayed on a continuation line of the form
+// `  <keyword>[\t<value>...]`, after a `similarity\t<percent>` line for tasks that were matched
+// with another one (see `ChangedTask::similarity`). Successive occurrences of a recurring task
+// each start with a `recurred-*` keyword, the copies of a copied task but the first a `copied`
+// line, and split tasks only have a `split-into\t<task>` line per part. New tasks that were
+// already completed are `C`, with a single `created\t<completion date>` line.
+pub fn display_changeset_porcelain(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+) -> String {
+    display_changeset_porcelain_with(new_tasks, changes, &DisplayOptions::default())
+}
+
+// Only `hidden_sections` is taken into account, the porcelain format being otherwise fixed
+pub fn display_changeset_porcelain_with(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> String {
+    let report = categorize_changeset_with(new_tasks, changes, opts);
+
+    let mut res = String::new();
+    for (t, _) in report.new_section() {
+        res += &format!("N\t{}\n", t);
+    }
+    for t in &report.deleted {
+        res += &format!("D\t{}\n", t);
+    }
+    let created_completed = report.created_completed();
+    let changed = report
+        .completed
+        .iter()
+        .chain(&created_completed)
+        .chain(&report.changed);
+    for x in changed {
+        if is_split(x) {
+            res += &format!("S\t{}\n", x.orig);
+            for t in &x.after {
+                res += &format!("  split-into\t{}\n", t);
+            }
+            continue;
+        }
+        let code = if has_been_recurred(x) {
+            'R'
+        } else if has_been_completed(x) {
+            'C'
+        } else {
+            'M'
+        };
+        res += &format!("{}\t{}\n", code, x.orig);
+        if let Some(similarity) = x.similarity {
+            res += &format!("  similarity\t{}\n", similarity);
+        }
+        for (i, chgs) in x.delta.iter().enumerate() {
+            if i > 0 && matches!(x.delta, TaskDelta::Copied(_)) {
+                res += "  copied\n";
+            }
+            for c in chgs {
+                for line in porcelain_change(c) {
+                    res += &format!("  {}\n", line);
+                }
+            }
+        }
+    }
+    res
+}
+
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// The porcelain keyword of each change, also the class of its `<span>` in the HTML output
+pub fn change_keyword(c: &Changes) -> &'static str {
+    use self::Changes::*;
+    match c {
+        Created(_) => "created",
+        RecurredStrict => "recurred-strict",
+        RecurredFrom(_) => "recurred-from",
+        SkippedOccurrences(_) => "skipped-occurrences",
+        FinishedAt(_) => "completed-at",
+        InferredFinishDate(_) => "completed-inferred",
+        PostponedStrictBy(..) => "postponed-strict-by",
+        PostponedThresholdBy(..) => "postponed-threshold-by",
+        Finished(true) => "completed",
+        Finished(false) => "uncompleted",
+        Priority(..) => "priority",
+        FinishDate(..) => "finish-date",
+        CreateDate(..) => "create-date",
+        Subject(..) => "subject",
+        DueDate(..) => "due-date",
+        ThresholdDate(..) => "threshold-date",
+        Tags(..) => "tags",
+        Recurrence(..) => "recurrence",
+        Projects(..) => "projects",
+        Contexts(..) => "contexts",
+    }
+}
+
+// Like `change_str`, with `<del>` and `<ins>` instead of colors
+fn change_html(opts: &DisplayOptions, c: &Changes, capitalize: bool) -> String {
+    let mut text = match *c {
+        Changes::Subject(ref s) if opts.word_diff != WordDiff::None => {
+            let mut res = "changed subject ‘".to_owned();
+            for op in s.ops.iter() {
+                match op {
+                    DiffOp::Equal(w) => res += &html_escape(w),
+                    DiffOp::Delete(w) => res += &format!("<del>{}</del>", html_escape(w)),
+                    DiffOp::Insert(w) => res += &format!("<ins>{}</ins>", html_escape(w)),
+                }
+            }
+            res + "’"
+        }
+        _ => html_escape(&c.describe_with(&|d| opts.format_date(d))),
+    };
+    if capitalize {
+        let mut chars = text.chars();
+        let first_char = chars.next().expect("Internal error E038").to_uppercase();
+        text = format!("{}{}", first_char, chars.as_str());
+    }
+    if let Some((overdue, note)) = due_note(opts, c) {
+        let class = if overdue { "overdue" } else { "due-in" };
+        text += &format!("<span class=\"{}\">{}</span>", class, html_escape(&note));
+    }
+    format!("<span class=\"{}\">{}</span>", change_keyword(c), text)
+}
+
+fn changes_html(opts: &DisplayOptions, chgs: &[Changes]) -> String {
+    use itertools::Position::*;
+    chgs.iter()
+        .with_position()
+        .map(|c| match c {
+            First(c) | Only(c) => change_html(opts, c, true),
+            Middle(c) => format!(", {}", change_html(opts, c, false)),
+            Last(c) => format!(" and {}", change_html(opts, c, false)),
+        })
+        .join("")
+}
+
+// The `<li>` of a task, with classes for its priority and due date so that they can be styled
+fn task_html(opts: &DisplayOptions, t: &Task, changes: &[&Vec<Changes>]) -> String {
+    let mut classes = vec!["task".to_owned()];
+    if let Some(p) = priority_char(&t.priority) {
+        classes.push(format!("priority-{}", p.to_ascii_lowercase()));
+    }
+    if let Some(due) = t.due_date {
+        classes.push("due".to_owned());
+        if opts.today.is_some_and(|today| due < today) {
+            classes.push("overdue".to_owned());
+        }
+    }
+    let mut res = format!(
+        "<li class=\"{}\">{}",
+        classes.join(" "),
+        html_escape(&task_str(opts, t))
+    );
+    let changes = changes.iter().filter(|c| !c.is_empty()).collect_vec();
+    if !changes.is_empty() {
+        res += "\n<ul class=\"changes\">\n";
+        for chgs in changes {
+            res += &format!("<li>{}</li>\n", changes_html(opts, chgs));
+        }
+        res += "</ul>\n";
+    }
+    res + "</li>\n"
+}
+
+type TaskWithChanges<'a> = (&'a Task, Vec<&'a Vec<Changes>>);
+
+fn with_changes(tasks: &[ChangedTask<Vec<Changes>>]) -> Vec<TaskWithChanges<'_>> {
+    tasks
+        .iter()
+        .map(|x| (&x.orig, x.delta.iter().collect()))
+        .collect()
+}
+
+// A standalone HTML fragment, meant to be styled by the page it is included in
+pub fn display_changeset_html_with(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    opts: &DisplayOptions,
+) -> String {
+    let report = categorize_changeset_with(new_tasks, changes, opts);
+    let is_empty = report.counts().is_empty();
+    let mut res = "<div class=\"todiff\">\n".to_owned();
+    if let Some((before, after)) = &opts.labels {
+        res += &format!(
+            "<p class=\"header\">Comparing ‘{}’ → ‘{}’</p>\n",
+            html_escape(before),
+            html_escape(after)
+        );
+    }
+
+    let mut section = |class: &str, title: &str, items: Vec<TaskWithChanges>| {
+        if items.is_empty() {
+            return;
+        }
+        res += &format!("<h2>{}</h2>\n", title);
+        for (heading, group) in group_items(opts, &items, |(t, _)| t) {
+            if let Some(heading) = heading {
+                res += &format!("<h3>{}</h3>\n", html_escape(&heading));
+            }
+            res += &format!("<ul class=\"{}\">\n", class);
+            for (t, chgs) in group {
+                res += &task_html(opts, t, chgs);
+            }
+            res += "</ul>\n";
+        }
+    };
+    section(
+        "new",
+        "New tasks",
+        report
+            .new_section()
+            .into_iter()
+            .map(|(t, _)| (t, vec![]))
+            .collect(),
+    );
+    section(
+        "deleted",
+        "Deleted tasks",
+        report.deleted.iter().map(|t| (t, vec![])).collect(),
+    );
+    let created_completed = report.created_completed();
+    let mut completed = with_changes(&report.completed);
+    completed.extend(with_changes(&created_completed));
+    section("completed", "Completed tasks", completed);
+    section("changed", "Changed tasks", with_changes(&report.changed));
+
+    if is_empty {
+        res += "<p>No changes.</p>\n";
+    }
+    res + "</div>\n"
+}
+
+// Why tasks were matched as they were, meant to be read by humans debugging the matching: one
+// paragraph per task of `from`, then one per new task of `to`
+pub fn display_explanation(explanation: &Explanation) -> String {
+    let mut res = String::new();
+    for m in explanation.matches.iter() {
+        res += &format!("- {}\n", m.orig);
+        match m.partner {
+            Some((ref t, similarity)) if m.archived => {
+                res += &format!("    matched with archived ‘{}’ ({})\n", t, similarity)
+            }
+            Some((ref t, similarity)) => {
+                res += &format!("    matched with ‘{}’ ({})\n", t, similarity)
+            }
+            None if m
+                .best_rejected
+                .as_ref()
+                .is_some_and(|(_, s)| s.is_admissible()) =>
+            {
+                res += "    not matched, its candidates being better matched with other tasks\n"
+            }
+            None => res += "    not matched: no admissible candidate\n",
+        }
+        if let Some((ref t, similarity)) = m.best_rejected {
+            res += &format!("    closest other: ‘{}’ ({})\n", t, similarity);
+        }
+    }
+    for (task, reattachment) in explanation.new_tasks.iter() {
+        res += &format!("+ {}\n", task);
+        res += &match reattachment {
+            Reattachment::Attached(t) => format!("    new occurrence of ‘{}’\n", t),
+            Reattachment::NoRecurringTask => "    new: no recurring task\n".to_owned(),
+            Reattachment::NotSimilar(t, similarity) => {
+                format!("    new: closest recurring task ‘{}’ ({})\n", t, similarity)
+            }
+            Reattachment::NotPlausible(t) => format!(
+                "    new: due date inconsistent with the recurrence of ‘{}’\n",
+                t
+            ),
+        };
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use subject_diff::SubjectDiff;
+
+    #[test]
+    fn test_date_format() {
+        let d = |s: &str| s.parse::<TaskDate>().unwrap();
+        assert_eq!(
+            "%d %b %Y".parse::<DateFormat>(),
+            Ok(DateFormat::Strftime("%d %b %Y".to_owned()))
+        );
+        assert_eq!("relative".parse::<DateFormat>(), Ok(DateFormat::Relative));
+        assert!("%Q".parse::<DateFormat>().is_err());
+        assert!("%".parse::<DateFormat>().is_err());
+
+        let today = Some(d("2018-06-15"));
+        let relative = |s| DateFormat::Relative.format(d(s), today);
+        assert_eq!(
+            DateFormat::default().format(d("2018-06-15"), today),
+            "2018-06-15"
+        );
+        assert_eq!(
+            DateFormat::Relative.format(d("2018-06-15"), None),
+            "2018-06-15"
+        );
+        assert_eq!(relative("2018-06-15"), "today");
+        assert_eq!(relative("2018-06-16"), "tomorrow");
+        assert_eq!(relative("2018-06-14"), "yesterday");
+        assert_eq!(relative("2018-06-29"), "in 2 weeks");
+        assert_eq!(relative("2018-03-15"), "3 months ago");
+    }
+
+    #[test]
+    fn test_task_str() {
+        let opts = DisplayOptions::default().date_format("%d/%m/%Y".parse().unwrap());
+        let task = |s: &str| s.parse::<Task>().unwrap();
+        assert_eq!(
+            task_str(
+                &opts,
+                &task("x 2018-06-02 2018-06-01 meet 2018-06-01 team due:2018-06-03")
+            ),
+            "x 02/06/2018 01/06/2018 meet 2018-06-01 team due:03/06/2018"
+        );
+        assert_eq!(
+            task_str(
+                &opts,
+                &task("(A) 2018-06-01 call mom t:2018-06-02 on:2018-06-03")
+            ),
+            "(A) 01/06/2018 call mom t:02/06/2018 on:2018-06-03"
+        );
+    }
+
+    #[test]
+    fn test_wrap_line() {
+        assert_eq!(
+            wrap_line(None, " → ", "do a thing that is quite long"),
+            " → do a thing that is quite long"
+        );
+        assert_eq!(
+            wrap_line(Some(14), " → ", "do a thing that is quite long"),
+            " → do a thing\n   that is\n   quite long"
+        );
+        // Words too long for a line are not split
+        assert_eq!(
+            wrap_line(Some(10), "    → ", "do something"),
+            "    → do\n      something"
+        );
+        // Display width rather than bytes
+        assert_eq!(
+            wrap_line(Some(12), " → ", "été ça où là"),
+            " → été ça où\n   là"
+        );
+        assert_eq!(wrap_line(Some(9), " → ", "日本 語"), " → 日本\n   語");
+        // Colors do not count, and do not spill over the indentation
+        let colored = format!("{} thing", Red.paint("do a long"));
+        assert_eq!(
+            wrap_line(Some(10), " → ", &colored),
+            format!(
+                " → {}\x1b[0m\n   {}\n   thing",
+                Red.paint("do a").to_string().trim_end_matches("\x1b[0m"),
+                Red.paint("long")
+            )
+        );
+    }
+
+    #[test]
+    fn test_paint_task() {
+        let line = "(A) 2024-01-01 call mom +family @phone due:2024-02-01";
+        let task = line.parse::<Task>().unwrap();
+        let plain = DisplayOptions::default();
+        let fancy = plain.clone().fancy_tasks(true);
+        // Nothing changes without colors
+        assert_eq!(paint_task(&plain, &task, Red.normal()), line);
+        assert_eq!(paint_task(&fancy, &task, Red.normal()), line);
+        let colored = plain.colorize(true);
+        assert_eq!(
+            paint_task(&colored, &task, Red.normal()),
+            Red.paint(line).to_string()
+        );
+        let fancy = fancy.colorize(true);
+        // Deleted tasks stay red, with the words in between restoring it
+        assert_eq!(
+            paint_task(&fancy, &task, Red.normal()),
+            "\x1b[31m(A) \x1b[2m2024-01-01\x1b[0m\x1b[31m call mom \x1b[1m+family\x1b[0m\x1b[31m \
+             \x1b[1m@phone\x1b[0m\x1b[31m \x1b[2mdue:2024-02-01\x1b[0m"
+        );
+        assert_eq!(
+            paint_task(&fancy, &task, Style::new()),
+            "(\x1b[31mA\x1b[0m) \x1b[2m2024-01-01\x1b[0m call mom \x1b[1m+family\x1b[0m \
+             \x1b[1m@phone\x1b[0m \x1b[2mdue:2024-02-01\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_hyperlinks() {
+        let line = "read https://example.com/a?b=c +web url:https://todiff.rs";
+        let task = line.parse::<Task>().unwrap();
+        let colored = DisplayOptions::default().colorize(true);
+        let linked = colored.clone().hyperlinks(true);
+        assert_eq!(
+            paint_task(&linked, &task, Style::new()),
+            "read \x1b]8;;https://example.com/a?b=c\x1b\\https://example.com/a?b=c\x1b]8;;\x1b\\ +web \
+             url:\x1b]8;;https://todiff.rs\x1b\\https://todiff.rs\x1b]8;;\x1b\\"
+        );
+        // Without hyperlinks or colors, nothing changes
+        assert_eq!(paint_task(&colored, &task, Style::new()), line);
+        let uncolored = DisplayOptions::default().hyperlinks(true);
+        assert_eq!(paint_task(&uncolored, &task, Style::new()), line);
+        // The link tag is configurable, and its value need not be a URL
+        let other = linked.clone().link_tag("doc");
+        assert_eq!(link_word(&other, "url:x".to_owned()), "url:x");
+        assert_eq!(
+            link_word(&other, "doc:x".to_owned()),
+            "doc:\x1b]8;;x\x1b\\x\x1b]8;;\x1b\\"
+        );
+        assert_eq!(link_word(&other, "doc:".to_owned()), "doc:");
+        assert_eq!(link_word(&other, "https://".to_owned()), "https://");
+        assert_eq!(
+            link_word(&other, "https://a\x07".to_owned()),
+            "https://a\x07"
+        );
+        // Links take no room, so that lines are wrapped as without them
+        let painted = paint_task(&linked, &task, Red.normal());
+        assert_eq!(display_width(&painted), line.len());
+        assert_eq!(
+            wrap_line(Some(40), " → ", &painted).lines().count(),
+            wrap_line(Some(40), " → ", line).lines().count()
+        );
+        // Only the color is restored on the next line
+        assert!(wrap_line(Some(40), " → ", &painted).contains("\x1b[0m\n   \x1b[31murl:\x1b]8;;"));
+    }
+
+    #[test]
+    fn test_tags_str() {
+        let tags = |t: &[(&str, &str)]| -> Vec<(String, String)> {
+            t.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            tags_str(
+                true,
+                &tags(&[("a", "x"), ("b", "2")]),
+                &tags(&[("a", "y"), ("c", "3")])
+            ),
+            vec![
+                ANSIString::from("removed tag "),
+                Red.paint("b:2"),
+                ANSIString::from(", "),
+                ANSIString::from("changed "),
+                ANSIString::from("a from "),
+                Red.paint("x"),
+                ANSIString::from(" to "),
+                Green.paint("y"),
+                ANSIString::from(" and "),
+                ANSIString::from("added tag "),
+                Green.paint("c:3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_numeric_tag_value() {
+        assert_eq!(numeric_tag_value("3"), Some((3, "")));
+        assert_eq!(numeric_tag_value("045m"), Some((45, "m")));
+        assert_eq!(numeric_tag_value("-2d"), Some((-2, "d")));
+        assert_eq!(numeric_tag_value("3.5h"), None);
+        assert_eq!(numeric_tag_value("h3"), None);
+        assert_eq!(numeric_tag_value("-"), None);
+        assert_eq!(numeric_tag_value(""), None);
+    }
+
+    #[test]
+    fn test_due_annotation() {
+        let date = |s: &str| s.parse::<TaskDate>().unwrap();
+        let opts = DisplayOptions::default().today(date("2018-06-15"));
+        let annotation = |opts: &DisplayOptions, c| due_annotation(opts, &c).map(|a| a.to_string());
+        assert_eq!(
+            annotation(&opts, Changes::DueDate(None, Some(date("2018-06-14")))),
+            Some(" (now overdue)".to_owned())
+        );
+        assert_eq!(
+            annotation(&opts, Changes::DueDate(None, Some(date("2018-06-15")))),
+            Some(" (due today)".to_owned())
+        );
+        assert_eq!(
+            annotation(
+                &opts,
+                Changes::PostponedStrictBy(
+                    chrono::Duration::days(2),
+                    date("2018-06-16"),
+                    date("2018-06-18")
+                )
+            ),
+            Some(" (due in 3 days)".to_owned())
+        );
+        assert_eq!(
+            annotation(&opts, Changes::DueDate(Some(date("2018-06-14")), None)),
+            None
+        );
+        assert_eq!(
+            annotation(
+                &DisplayOptions::default(),
+                Changes::DueDate(None, Some(date("2018-06-14")))
+            ),
+            None
+        );
+        assert_eq!(
+            annotation(
+                &opts.colorize(true),
+                Changes::DueDate(None, Some(date("2018-06-14")))
+            ),
+            Some(Red.paint(" (now overdue)").to_string())
+        );
+    }
+
+    #[test]
+    fn test_duration_str() {
+        use std::str::FromStr;
+        let test = |from: &str, to: &str, expected: &str| {
+            let from = TaskDate::from_str(from).unwrap();
+            let to = TaskDate::from_str(to).unwrap();
+            assert_eq!(duration_str(from, to), expected);
+        };
+        test("2018-06-01", "2018-06-02", "1 day");
+        test("2018-06-01", "2018-06-04", "3 days");
+        test("2018-06-01", "2018-06-08", "1 week");
+        test("2018-06-01", "2018-07-06", "5 weeks");
+        test("2018-06-01", "2018-07-01", "1 month");
+        test("2018-07-01", "2018-08-01", "1 month");
+        test("2018-01-31", "2018-02-28", "1 month");
+        test("2018-02-28", "2018-03-31", "1 month");
+        test("2018-02-28", "2018-03-28", "1 month");
+        test("2018-01-15", "2019-03-15", "14 months");
+        test("2018-01-15", "2020-01-15", "2 years");
+        test("2018-01-15", "2019-01-16", "about 1 year");
+        test("2018-01-15", "2018-12-16", "335 days");
+    }
+
+    #[test]
+    fn test_describe() {
+        use self::Changes::*;
+        use std::str::FromStr;
+        let d = |s: &str| TaskDate::from_str(s).unwrap();
+        let rec = |s: &str| todo_txt::task::Recurrence::from_str(s).unwrap();
+        let strs = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let tags = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let tests = vec![
+            (
+                Created(Some(d("2018-06-01"))),
+                "created already completed on 2018-06-01",
+            ),
+            (Created(None), "created already completed"),
+            (RecurredStrict, "recurred (strict)"),
+            (
+                RecurredFrom(Some(d("2018-06-01"))),
+                "recurred (from 2018-06-01)",
+            ),
+            (RecurredFrom(None), "recurred"),
+            (SkippedOccurrences(1), "skipped 1 occurrence"),
+            (SkippedOccurrences(3), "skipped 3 occurrences"),
+            (FinishedAt(d("2018-06-01")), "completed on 2018-06-01"),
+            (
+                InferredFinishDate(d("2018-06-17")),
+                "completed on 2018-06-17 (inferred)",
+            ),
+            (
+                PostponedStrictBy(chrono::Duration::days(7), d("2018-06-01"), d("2018-06-08")),
+                "postponed (strict) by 1 week",
+            ),
+            (
+                PostponedStrictBy(chrono::Duration::days(-2), d("2018-06-03"), d("2018-06-01")),
+                "brought forward (strict) by 2 days",
+            ),
+            (
+                PostponedThresholdBy(chrono::Duration::days(14), d("2018-06-01"), d("2018-06-15")),
+                "postponed (threshold) by 2 weeks",
+            ),
+            (
+                PostponedThresholdBy(chrono::Duration::days(-2), d("2018-06-10"), d("2018-06-08")),
+                "brought forward (threshold) by 2 days",
+            ),
+            (Finished(true), "completed"),
+            (Finished(false), "uncompleted"),
+            (Priority(None, None), "removed priority"),
+            (Priority(Some('A'), None), "removed priority"),
+            (Priority(None, Some('B')), "added priority (B)"),
+            (Priority(Some('A'), Some('A')), "kept priority (A)"),
+            (
+                Priority(Some('A'), Some('B')),
+                "lowered priority from (A) to (B)",
+            ),
+            (
+                Priority(Some('C'), Some('A')),
+                "raised priority from (C) to (A)",
+            ),
+            (FinishDate(None, None), "removed completion date"),
+            (
+                FinishDate(Some(d("2018-06-01")), None),
+                "removed completion date",
+            ),
+            (
+                FinishDate(None, Some(d("2018-06-01"))),
+                "added completion date 2018-06-01",
+            ),
+            (
+                FinishDate(Some(d("2018-06-01")), Some(d("2018-06-02"))),
+                "set completion date to 2018-06-02",
+            ),
+            (CreateDate(None, None), "removed creation date"),
+            (
+                CreateDate(Some(d("2018-06-01")), None),
+                "removed creation date",
+            ),
+            (
+                CreateDate(None, Some(d("2018-06-01"))),
+                "added creation date 2018-06-01",
+            ),
+            (
+                CreateDate(Some(d("2018-06-01")), Some(d("2018-06-02"))),
+                "set creation date to 2018-06-02",
+            ),
+            (
+                Subject(SubjectDiff::new(
+                    "do a thing".to_owned(),
+                    "do the thing".to_owned(),
+                    WordDiff::Word,
+                )),
+                "set subject to ‘do the thing’",
+            ),
+            (DueDate(None, None), "removed due date"),
+            (DueDate(Some(d("2018-06-01")), None), "removed due date"),
+            (
+                DueDate(None, Some(d("2018-06-01"))),
+                "added due date 2018-06-01",
+            ),
+            (
+                DueDate(Some(d("2018-06-01")), Some(d("2018-06-02"))),
+                "postponed to 2018-06-02",
+            ),
+            (
+                DueDate(Some(d("2018-06-02")), Some(d("2018-06-01"))),
+                "moved due date earlier to 2018-06-01",
+            ),
+            (ThresholdDate(None, None), "removed threshold date"),
+            (
+                ThresholdDate(Some(d("2018-06-01")), None),
+                "removed threshold date",
+            ),
+            (
+                ThresholdDate(None, Some(d("2018-06-01"))),
+                "added threshold date 2018-06-01",
+            ),
+            (
+                ThresholdDate(Some(d("2018-06-01")), Some(d("2018-06-02"))),
+                "set threshold date to 2018-06-02",
+            ),
+            (Tags(tags(&[("a", "1")]), tags(&[])), "removed tag a:1"),
+            (
+                Tags(tags(&[]), tags(&[("a", "1"), ("b", "2"), ("c", "3")])),
+                "added tags a:1, b:2 and c:3",
+            ),
+            (
+                Tags(tags(&[("a", "x"), ("b", "2")]), tags(&[("a", "y")])),
+                "removed tag b:2 and changed a from x to y",
+            ),
+            (
+                Tags(
+                    tags(&[("a", "x"), ("b", "y"), ("c", "3"), ("c", "4")]),
+                    tags(&[("a", "y"), ("b", "z"), ("c", "5"), ("d", "6")]),
+                ),
+                "removed tags c:3 and c:4, changed a from x to y and b from y to z and added tags c:5 and d:6",
+            ),
+            (
+                Tags(tags(&[("est", "3")]), tags(&[("est", "5")])),
+                "increased est by 2 (3 → 5)",
+            ),
+            (
+                Tags(tags(&[("spent", "45m")]), tags(&[("spent", "30m")])),
+                "decreased spent by 15m (45m → 30m)",
+            ),
+            (
+                Tags(tags(&[("est", "-2")]), tags(&[("est", "-5")])),
+                "decreased est by 3 (-2 → -5)",
+            ),
+            (
+                Tags(tags(&[("est", "03")]), tags(&[("est", "10")])),
+                "increased est by 7 (03 → 10)",
+            ),
+            (
+                Tags(tags(&[("est", "03")]), tags(&[("est", "3")])),
+                "changed est from 03 to 3",
+            ),
+            (
+                Tags(
+                    tags(&[("est", "-9223372036854775808")]),
+                    tags(&[("est", "9223372036854775807")]),
+                ),
+                "changed est from -9223372036854775808 to 9223372036854775807",
+            ),
+            (
+                Tags(tags(&[("spent", "1h")]), tags(&[("spent", "45m")])),
+                "changed spent from 1h to 45m",
+            ),
+            (
+                Tags(
+                    tags(&[("est", "3"), ("at", "home")]),
+                    tags(&[("est", "2"), ("at", "work")]),
+                ),
+                "changed at from home to work and decreased est by 1 (3 → 2)",
+            ),
+            (Recurrence(Some(rec("1w")), None), "removed recurrence"),
+            (
+                Recurrence(None, Some(rec("+1m"))),
+                "made recurring every 1m (strict)",
+            ),
+            (
+                Recurrence(Some(rec("1w")), Some(rec("2w"))),
+                "changed recurrence from 1w to 2w",
+            ),
+            (
+                Projects(strs(&["work"]), strs(&[])),
+                "removed from project +work",
+            ),
+            (Contexts(strs(&[]), strs(&["home"])), "added context @home"),
+        ];
+        for (change, expected) in tests {
+            assert_eq!(change.describe(), expected);
+            assert_eq!(change.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_porcelain_change() {
+        use self::Changes::*;
+        let strs = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            porcelain_change(&Projects(
+                strs(&["work", "home"]),
+                strs(&["town", "family"])
+            )),
+            vec![
+                "project-removed\t+home",
+                "project-removed\t+work",
+                "project-added\t+family",
+                "project-added\t+town",
+            ]
+        );
+        assert_eq!(
+            porcelain_change(&Contexts(strs(&["phone", "car"]), strs(&[]))),
+            vec!["context-removed\t@car", "context-removed\t@phone"]
+        );
+    }
+
+    #[test]
+    fn test_categorize_changeset() {
+        use std::str::FromStr;
+        let task = |s: &str| Task::from_str(s).unwrap();
+        let from = vec![task("do a thing"), task("call mom"), task("pay the rent")];
+        let to = vec![
+            task("x do a thing"),
+            task("(A) pay the rent"),
+            task("x 2018-06-03 2018-06-01 buy milk"),
+            task("water the plants"),
+        ];
+        let (new_tasks, changes) = compute_changeset(from, to, 0);
+        let report = categorize_changeset(new_tasks, changes);
+        // The new task already completed is kept as is, but displayed as completed
+        assert_eq!(
+            report.new,
+            vec![
+                task("water the plants"),
+                task("x 2018-06-03 2018-06-01 buy milk")
+            ]
+        );
+        assert_eq!(
+            report.new_section(),
+            vec![(&task("water the plants"), None)]
+        );
+        assert_eq!(report.deleted, vec![task("call mom")]);
+        let completed = ChangedTask {
+            orig: task("do a thing"),
+            delta: TaskDelta::Changed(vec![Changes::Finished(true)]),
+            after: vec![task("x do a thing")],
+            orig_line: None,
+            after_lines: vec![],
+            similarity: Some(100),
+        };
+        assert_eq!(report.completed, vec![completed.clone()]);
+        let date = "2018-06-03".parse::<TaskDate>().unwrap();
+        assert_eq!(
+            report.completed_section(),
+            vec![
+                completed,
+                ChangedTask {
+                    orig: task("x 2018-06-03 2018-06-01 buy milk"),
+                    delta: TaskDelta::Changed(vec![Changes::Created(Some(date))]),
+                    after: vec![task("x 2018-06-03 2018-06-01 buy milk")],
+                    orig_line: None,
+                    after_lines: vec![],
+                    similarity: None,
+                },
+            ]
+        );
+        assert_eq!(
+            report.changed,
+            vec![ChangedTask {
+                orig: task("pay the rent"),
+                delta: TaskDelta::Changed(vec![Changes::Priority(None, Some('A'))]),
+                after: vec![task("(A) pay the rent")],
+                orig_line: None,
+                after_lines: vec![],
+                similarity: Some(100),
+            }]
+        );
+        assert_eq!(
+            report.counts().to_string(),
+            "1 new, 1 deleted, 2 completed, 1 changed"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use std;
+use std::cmp::min;
+
+// Returns the levenshtein distance between `a` and `b` if it is at most `bound`, and None
+// otherwise. Like `strsim::levenshtein`, the distance is counted in characters.
+// Only the cells at most `bound` away from the diagonal are computed, and the computation stops
+// as soon as a whole row exceeds `bound`, so that very different strings are rejected quickly.
+pub fn bounded_levenshtein(a: &str, b: &str, bound: usize) -> Option<usize> {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // The distance is at least the difference between the lengths
+    if a.len().abs_diff(b.len()) > bound {
+        return None;
+    }
+    // Common prefixes and suffixes do not change the distance
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+    if a.is_empty() || b.is_empty() {
+        return Some(a.len() + b.len());
+    }
+
+    // Distances above `bound` are all stored as `bound + 1`
+    let over = bound + 1;
+    // `prev[j]` is the distance between the first i - 1 characters of `a` and j of `b`
+    let mut prev = (0..=b.len()).map(|j| min(j, over)).collect::<Vec<_>>();
+    let mut cur = vec![over; b.len() + 1];
+    for i in 1..=a.len() {
+        let lo = std::cmp::max(1, i.saturating_sub(bound));
+        let hi = min(b.len(), i + bound);
+        cur[lo - 1] = if lo == 1 { min(i, over) } else { over };
+        let mut row_min = cur[lo - 1];
+        for j in lo..=hi {
+            let substitution = prev[j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            let d = min(min(substitution, prev[j] + 1), min(cur[j - 1] + 1, over));
+            cur[j] = d;
+            row_min = min(row_min, d);
+        }
+        // The next row reads one cell further right, which must not be a stale value
+        if hi < b.len() {
+            cur[hi + 1] = over;
+        }
+        if row_min > bound {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    Some(prev[b.len()]).filter(|d| *d <= bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strsim::levenshtein;
+
+    #[test]
+    fn test_bounded_levenshtein() {
+        let words = [
+            "",
+            "a",
+            "ab",
+            "call mom",
+            "call mum",
+            "call mom tomorrow",
+            "phone mom",
+            "write the report",
+            "rewrite the reports",
+            "kitten",
+            "sitting",
+            "éléphant",
+            "elephant",
+            "🐘 elephant",
+            "aaaaaaaaaa",
+            "bbbbbbbbbb",
+        ];
+        for a in words.iter() {
+            for b in words.iter() {
+                let d = levenshtein(a, b);
+                for bound in d.saturating_sub(3)..d + 3 {
+                    assert_eq!(
+                        bounded_levenshtein(a, b, bound),
+                        Some(d).filter(|d| *d <= bound),
+                        "distance between ‘{}’ and ‘{}’ bounded by {}",
+                        a,
+                        b,
+                        bound
+                    );
+                }
+            }
+        }
+    }
+}
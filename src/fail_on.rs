@@ -0,0 +1,158 @@
+// The changes that make `todiff --fail-on` fail, eg. in a pre-commit hook rejecting deleted tasks
+// but not completed ones. They are looked for in the sections of `categorize_changeset`, so that
+// hidden sections and the tasks left out by filters do not count.
+
+use display_changes::{change_keyword, ChangesetReport, Section};
+use std;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChangeKind {
+    // Any task of the section
+    Section(Section),
+    // A change undergone by a completed or changed task, by its porcelain keyword, see
+    // `change_keyword`
+    Change(&'static str),
+}
+
+impl ChangeKind {
+    // The sections, then the porcelain keywords but `completed`, which names the section: it holds
+    // every task that was completed
+    pub const NAMES: &'static [&'static str] = &[
+        "new",
+        "deleted",
+        "completed",
+        "changed",
+        "created",
+        "recurred-strict",
+        "recurred-from",
+        "skipped-occurrences",
+        "completed-at",
+        "completed-inferred",
+        "postponed-strict-by",
+        "postponed-threshold-by",
+        "uncompleted",
+        "priority",
+        "finish-date",
+        "create-date",
+        "subject",
+        "due-date",
+        "threshold-date",
+        "tags",
+        "recurrence",
+        "projects",
+        "contexts",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ChangeKind::Section(Section::New) => "new",
+            ChangeKind::Section(Section::Deleted) => "deleted",
+            ChangeKind::Section(Section::Completed) => "completed",
+            ChangeKind::Section(Section::Changed) => "changed",
+            ChangeKind::Change(keyword) => keyword,
+        }
+    }
+
+    pub fn is_in(&self, report: &ChangesetReport) -> bool {
+        match *self {
+            ChangeKind::Section(Section::New) => !report.new_section().is_empty(),
+            ChangeKind::Section(Section::Deleted) => !report.deleted.is_empty(),
+            ChangeKind::Section(Section::Completed) => !report.completed_section().is_empty(),
+            ChangeKind::Section(Section::Changed) => !report.changed.is_empty(),
+            ChangeKind::Change(keyword) => report
+                .completed_section()
+                .iter()
+                .chain(&report.changed)
+                .flat_map(|x| x.delta.iter().flatten())
+                .any(|c| change_keyword(c) == keyword),
+        }
+    }
+}
+
+impl std::str::FromStr for ChangeKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ChangeKind, String> {
+        match ChangeKind::NAMES.iter().find(|&&name| name == s) {
+            Some(_) if Section::NAMES.contains(&s) => Ok(ChangeKind::Section(s.parse()?)),
+            Some(name) => Ok(ChangeKind::Change(name)),
+            None => Err(format!("unknown kind of change ‘{}’", s)),
+        }
+    }
+}
+
+// The kinds of `kinds` found in `report`, in the same order
+pub fn find_kinds(kinds: &[ChangeKind], report: &ChangesetReport) -> Vec<ChangeKind> {
+    kinds.iter().filter(|k| k.is_in(report)).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compute_changes::compute_changeset;
+    use display_changes::{categorize_changeset, categorize_changeset_with, DisplayOptions};
+    use std::str::FromStr;
+    use todo_txt::task::Extended as Task;
+
+    fn tasks(lines: &[&str]) -> Vec<Task> {
+        lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+    }
+
+    fn kinds(names: &[&str]) -> Vec<ChangeKind> {
+        names.iter().map(|n| n.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        for name in ChangeKind::NAMES {
+            assert_eq!(name.parse::<ChangeKind>().unwrap().name(), *name);
+        }
+        assert_eq!(
+            "deleted".parse::<ChangeKind>(),
+            Ok(ChangeKind::Section(Section::Deleted))
+        );
+        assert_eq!(
+            "subject".parse::<ChangeKind>(),
+            Ok(ChangeKind::Change("subject"))
+        );
+        assert!("subject-changed".parse::<ChangeKind>().is_err());
+    }
+
+    #[test]
+    fn test_find_kinds() {
+        let from = tasks(&[
+            "2018-05-30 call mom",
+            "water the plants due:2018-06-01",
+            "buy milk",
+            "fix the bike",
+        ]);
+        let to = tasks(&[
+            "x 2018-06-02 2018-05-30 call mom",
+            "water the plants due:2018-06-04",
+            "buy milk +home",
+            "x 2018-06-02 2018-06-01 book the holidays",
+        ]);
+        let (new_tasks, changes) = compute_changeset(from, to, 50);
+        let all = kinds(ChangeKind::NAMES);
+        let report = categorize_changeset(new_tasks.clone(), changes.clone());
+        assert_eq!(
+            find_kinds(&all, &report),
+            kinds(&[
+                "deleted",
+                "completed",
+                "changed",
+                "created",
+                "completed-at",
+                "postponed-strict-by",
+                "projects",
+            ])
+        );
+        // Hidden sections do not count, nor do their changes
+        let opts = DisplayOptions::default().hide(Section::Completed);
+        let report = categorize_changeset_with(new_tasks, changes, &opts);
+        assert_eq!(
+            find_kinds(&all, &report),
+            kinds(&["deleted", "changed", "postponed-strict-by", "projects"])
+        );
+    }
+}
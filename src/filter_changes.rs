@@ -0,0 +1,163 @@
+use compute_changes::*;
+use std::str::FromStr;
+use todo_txt::task::Extended as Task;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TaskPattern {
+    // `+project`, compared like todo_txt does, ie. case-insensitively
+    Project(String),
+    // `@context`, compared case-insensitively
+    Context(String),
+    // Anything else, searched as-is in the task line
+    Substring(String),
+}
+
+impl FromStr for TaskPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TaskPattern, String> {
+        if s.is_empty() {
+            return Err("empty task pattern".to_owned());
+        }
+        Ok(match (s.chars().next(), s.len() > 1) {
+            (Some('+'), true) => TaskPattern::Project(s[1..].to_lowercase()),
+            (Some('@'), true) => TaskPattern::Context(s[1..].to_lowercase()),
+            _ => TaskPattern::Substring(s.to_owned()),
+        })
+    }
+}
+
+impl TaskPattern {
+    pub fn matches(&self, task: &Task) -> bool {
+        match self {
+            TaskPattern::Project(p) => task.projects.contains(p),
+            TaskPattern::Context(c) => task.contexts.contains(c),
+            TaskPattern::Substring(s) => task.to_string().contains(s.as_str()),
+        }
+    }
+}
+
+// A task is selected if it matches any of the `include` patterns (or if there are none), and
+// none of the `exclude` patterns
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TaskFilter {
+    pub include: Vec<TaskPattern>,
+    pub exclude: Vec<TaskPattern>,
+}
+
+impl TaskFilter {
+    pub fn include(mut self, pattern: TaskPattern) -> TaskFilter {
+        self.include.push(pattern);
+        self
+    }
+
+    pub fn exclude(mut self, pattern: TaskPattern) -> TaskFilter {
+        self.exclude.push(pattern);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    // Decides for a group of tasks that are the same task at different points in time, so that
+    // eg. a task moved out of a project is still selected by this project
+    fn selects<'a, I: Clone + Iterator<Item = &'a Task>>(&self, tasks: I) -> bool {
+        let any = |p: &TaskPattern| tasks.clone().any(|t| p.matches(t));
+        (self.include.is_empty() || self.include.iter().any(&any)) && !self.exclude.iter().any(any)
+    }
+
+    pub fn selects_task(&self, task: &Task) -> bool {
+        self.selects(std::iter::once(task))
+    }
+}
+
+// Restricts the output of `match_tasks` to the selected tasks. This is done after matching, so
+// that a pair is kept as soon as either its before or one of its after states is selected.
+pub fn filter_matches(
+    new_tasks: Vec<Task>,
+    matches: Vec<ChangedTask<Task>>,
+    filter: &TaskFilter,
+) -> (Vec<Task>, Vec<ChangedTask<Task>>) {
+    if filter.is_empty() {
+        return (new_tasks, matches);
+    }
+    let new_tasks = new_tasks
+        .into_iter()
+        .filter(|t| filter.selects_task(t))
+        .collect();
+    let matches = matches
+        .into_iter()
+        .filter(|m| filter.selects(std::iter::once(&m.orig).chain(&m.after)))
+        .collect();
+    (new_tasks, matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(s: &str) -> Task {
+        Task::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_task_pattern() {
+        assert_eq!(
+            "+Work".parse::<TaskPattern>(),
+            Ok(TaskPattern::Project("work".to_owned()))
+        );
+        assert_eq!(
+            "@home".parse::<TaskPattern>(),
+            Ok(TaskPattern::Context("home".to_owned()))
+        );
+        assert_eq!(
+            "+".parse::<TaskPattern>(),
+            Ok(TaskPattern::Substring("+".to_owned()))
+        );
+        assert!("".parse::<TaskPattern>().is_err());
+
+        let t = task("(A) call mom +Family @phone");
+        assert!(TaskPattern::Project("family".to_owned()).matches(&t));
+        assert!(!TaskPattern::Project("fam".to_owned()).matches(&t));
+        assert!(TaskPattern::Context("phone".to_owned()).matches(&t));
+        assert!(TaskPattern::Substring("ll mo".to_owned()).matches(&t));
+        assert!(!TaskPattern::Substring("Mom".to_owned()).matches(&t));
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let filter = TaskFilter::default()
+            .include(TaskPattern::Project("work".to_owned()))
+            .exclude(TaskPattern::Context("home".to_owned()));
+        let new_tasks = vec![
+            task("new +work"),
+            task("new +play"),
+            task("new +work @home"),
+        ];
+        let matches = vec![
+            ChangedTask::new(
+                task("moved out +work"),
+                TaskDelta::Changed(task("moved out +play")),
+            ),
+            ChangedTask::new(
+                task("moved in +play"),
+                TaskDelta::Changed(task("moved in +work")),
+            ),
+            ChangedTask::new(task("gone +play"), TaskDelta::Deleted),
+            ChangedTask::new(
+                task("taken home +work"),
+                TaskDelta::Changed(task("taken home +work @home")),
+            ),
+        ];
+        let (new_tasks, matches) = filter_matches(new_tasks, matches, &filter);
+        assert_eq!(new_tasks, vec![task("new +work")]);
+        assert_eq!(
+            matches
+                .iter()
+                .map(|m| m.orig.to_string())
+                .collect::<Vec<_>>(),
+            vec!["moved out +work", "moved in +play"]
+        );
+    }
+}
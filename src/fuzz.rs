@@ -0,0 +1,236 @@
+// Turning arbitrary bytes into a pair of task lists and options, and running the whole pipeline on
+// them, for the cargo-fuzz target in `fuzz/`. The bytes are read as a sequence of choices, zeroes
+// being used once they are exhausted, so that any input is valid and small changes to an input
+// make small changes to the tasks. The inputs that once made the pipeline panic are kept in
+// `fuzz/regressions` and replayed by the tests.
+
+use chrono::Duration;
+use compute_changes::*;
+use display_changes::*;
+use serialize_changes::serialize_changeset_with;
+use std::str::FromStr;
+use todo_txt::task::Extended as Task;
+use todo_txt::Date as TaskDate;
+
+// Most characters are letters so that subjects are similar enough to be matched, the others are
+// those the todo.txt format gives a meaning to, and some of varying widths
+const SUBJECT_CHARS: &[char] = &[
+    'a', 'b', 'c', 'a', 'b', 'c', ' ', ' ', ' ', '+', '@', '#', ':', '-', '/', 'x', '(', 'A', ')',
+    '1', '2', 'é', '漢', '\u{301}', '\t',
+];
+const TAG_KEYS: &[&str] = &["rec", "due", "t", "id", "f", "h", "note", "k"];
+const RECURRENCE_CHARS: &[char] = &['+', '-', '0', '1', '7', '9', 'd', 'w', 'm', 'y', 'b', 'x'];
+
+struct Choices<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Choices<'a> {
+    fn byte(&mut self) -> u8 {
+        match self.bytes.split_first() {
+            Some((b, rest)) => {
+                self.bytes = rest;
+                *b
+            }
+            None => 0,
+        }
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.byte() as usize % n
+    }
+
+    fn flag(&mut self) -> bool {
+        self.byte() & 1 == 1
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+
+    // Mostly close to each other, so that due dates and recurrences line up, and sometimes at the
+    // ends of what todo.txt can represent
+    fn date(&mut self) -> TaskDate {
+        let base = TaskDate::from_ymd_opt(2018, 6, 1).expect("Internal error E052");
+        match self.below(16) {
+            0 => TaskDate::from_ymd_opt(1, 1, 1).expect("Internal error E078"),
+            1 => TaskDate::from_ymd_opt(9999, 12, 31).expect("Internal error E079"),
+            _ => base + Duration::days(i64::from(self.byte() as i8)),
+        }
+    }
+
+    // A recurrence that todo_txt accepts, though not necessarily a sensible one
+    fn recurrence(&mut self) -> String {
+        let strict = if self.flag() { "+" } else { "" };
+        let num = self.pick(&[1, 1, 2, 7, 0, -1, 5000, i64::from(u32::MAX), i64::MAX]);
+        format!("{}{}{}", strict, num, self.pick(&['d', 'w', 'm', 'y', 'b']))
+    }
+
+    fn text(&mut self, chars: &[char], max_len: usize) -> String {
+        (0..self.below(max_len + 1))
+            .map(|_| self.pick(chars))
+            .collect()
+    }
+
+    fn task(&mut self) -> Option<Task> {
+        let mut line = String::new();
+        if self.flag() {
+            line += "x ";
+            if self.flag() {
+                line += &format!("{} ", self.date());
+            }
+        }
+        if self.flag() {
+            line += &format!("{} ", self.date());
+        }
+        line += &self.text(SUBJECT_CHARS, 24);
+        for _ in 0..self.below(4) {
+            let key = self.pick(TAG_KEYS);
+            let value = match key {
+                "rec" if self.below(4) == 0 => self.text(RECURRENCE_CHARS, 12),
+                "rec" => self.recurrence(),
+                "due" | "t" if self.flag() => self.date().to_string(),
+                _ => self.text(SUBJECT_CHARS, 4),
+            };
+            line += &format!(" {}:{}", key, value);
+        }
+        let mut task = Task::from_str(&line).ok()?;
+        // Beyond what can be written in a todo.txt file, from the lowest priority upwards
+        if self.flag() {
+            task.priority = (self.byte() % 64).into();
+        }
+        Some(task)
+    }
+
+    fn tasks(&mut self) -> Vec<Task> {
+        (0..self.below(8)).filter_map(|_| self.task()).collect()
+    }
+
+    // A task resembling `task`, as tasks rarely match by chance
+    fn edit(&mut self, task: &Task) -> Task {
+        let mut res = task.clone();
+        if self.flag() {
+            res.finished = !res.finished;
+            res.finish_date = Some(self.date()).filter(|_| res.finished);
+        }
+        if self.flag() {
+            res.due_date = Some(self.date());
+        }
+        if self.flag() {
+            res.threshold_date = Some(self.date());
+        }
+        if self.flag() {
+            res.create_date = Some(self.date());
+        }
+        if self.flag() {
+            res.priority = (self.byte() % 64).into();
+        }
+        if self.flag() {
+            let c = self.pick(SUBJECT_CHARS);
+            res.subject.push(c);
+        }
+        res
+    }
+
+    // Edits of some of the tasks of `from`, mixed with other tasks
+    fn edited_tasks(&mut self, from: &[Task]) -> Vec<Task> {
+        let mut to = Vec::new();
+        for _ in 0..self.below(10) {
+            match self.below(from.len() + 2) {
+                i if i < from.len() => to.push(self.edit(&from[i])),
+                _ => to.extend(self.task()),
+            }
+        }
+        to
+    }
+
+    fn diff_options(&mut self) -> DiffOptions {
+        DiffOptions::default()
+            .allowed_divergence(self.below(101))
+            .detect_recurrences(self.below(4) != 0)
+            .exact_only(self.below(8) == 0)
+            .strict_subject(self.flag())
+            .similarity_metric(if self.flag() {
+                SimilarityMetric::Token
+            } else {
+                SimilarityMetric::Levenshtein
+            })
+            .loose_recurrence(self.flag())
+            .detect_splits(self.flag())
+            .detect_copies(self.flag())
+            .granular(self.flag())
+            .word_diff(self.pick(&[WordDiff::Char, WordDiff::Word, WordDiff::None]))
+            .parallel(false)
+    }
+
+    fn display_options(&mut self) -> DisplayOptions {
+        let mut opts = DisplayOptions::default()
+            .colorize(self.flag())
+            .word_diff(self.pick(&[WordDiff::Char, WordDiff::Word, WordDiff::None]))
+            .today(self.date())
+            .show_moves(self.flag())
+            .show_unchanged(self.flag())
+            .show_scores(self.flag())
+            .show_stats(self.flag())
+            .width(self.below(40));
+        if self.flag() {
+            opts = opts.date_format(DateFormat::Relative);
+        }
+        if self.flag() {
+            opts = opts.group_by(self.pick(&[GroupBy::Project, GroupBy::Context]));
+        }
+        opts
+    }
+}
+
+// The lists of tasks to diff and how, as read from `data`
+pub fn decode(data: &[u8]) -> (Vec<Task>, Vec<Task>, DiffOptions, DisplayOptions) {
+    let mut choices = Choices { bytes: data };
+    let diff_options = choices.diff_options();
+    let display_options = choices.display_options();
+    let from = choices.tasks();
+    let to = choices.edited_tasks(&from);
+    (from, to, diff_options, display_options)
+}
+
+// Diffs the tasks read from `data`, and displays the changeset in every format
+pub fn run_pipeline(data: &[u8]) {
+    let (from, to, diff_options, display_options) = decode(data);
+    let (new_tasks, changes) = compute_changeset_with(from, to, &diff_options);
+    display_changeset_with(new_tasks.clone(), changes.clone(), &display_options);
+    display_changeset_porcelain_with(new_tasks.clone(), changes.clone(), &display_options);
+    display_changeset_html_with(new_tasks.clone(), changes.clone(), &display_options);
+    serialize_changeset_with(new_tasks, changes, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::panic;
+
+    #[test]
+    fn test_fuzz_regressions() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/regressions");
+        let mut inputs = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        inputs.sort();
+        assert!(!inputs.is_empty());
+        for path in inputs {
+            let input = fs::read(&path).unwrap();
+            let result = panic::catch_unwind(|| run_pipeline(&input));
+            assert!(result.is_ok(), "{} fails", path.display());
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let (from, to, _, _) = decode(&[]);
+        assert!(from.is_empty() && to.is_empty());
+        // Any input is valid, however short
+        let (from, _, _, _) = decode(&[0; 20]);
+        assert!(from.is_empty());
+    }
+}
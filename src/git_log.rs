@@ -0,0 +1,250 @@
+// Diffing a todo.txt file across its git history, one changeset per commit that touched it. The
+// history is read by running `git log` and `git show` in the directory of the file.
+
+use batch::{BatchPair, PairDiff};
+use chrono::NaiveDate;
+use compute_changes::{changeset_has_changes, compute_changeset_with, DiffOptions};
+use io::{read_tasks_with, ReadError, ReadOptions};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use todo_txt::task::Extended as Task;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Commit {
+    pub hash: String,
+    pub date: NaiveDate,
+    // The first line of the commit message
+    pub subject: String,
+}
+
+impl Commit {
+    pub fn short_hash(&self) -> &str {
+        &self.hash[..self.hash.len().min(7)]
+    }
+
+    // Eg. `1a2b3c4 2018-06-01 Complete the errands`
+    pub fn label(&self) -> String {
+        format!("{} {} {}", self.short_hash(), self.date, self.subject)
+    }
+}
+
+#[derive(Debug)]
+pub enum GitError {
+    // git could not be run at all
+    Run(io::Error),
+    // git failed, eg. outside of a repository
+    Failed { command: String, stderr: String },
+    // A line of `git log` that is not a commit as requested
+    Output(String),
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GitError::Run(error) => write!(f, "Unable to run git: {}", error),
+            GitError::Failed { command, stderr } => {
+                write!(f, "‘{}’ failed: {}", command, stderr.trim_end())
+            }
+            GitError::Output(line) => write!(f, "unexpected output of git log: ‘{}’", line),
+        }
+    }
+}
+
+impl std::error::Error for GitError {}
+
+// Why a version of the file has no changeset
+#[derive(Debug)]
+pub enum SkipReason {
+    // Its tasks are the same as in the previous version, eg. only comments changed
+    Unchanged,
+    // It could not be read, eg. because the commit deleted the file
+    Missing(String),
+    Unparseable(ReadError),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::Unchanged => write!(f, "no task changed"),
+            SkipReason::Missing(error) => write!(f, "{}", error.trim_end()),
+            SkipReason::Unparseable(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LogEntry {
+    // The changes from the previous readable version of the file to the one of a commit, labelled
+    // with the commit
+    Diff(PairDiff),
+    Skipped { commit: Commit, reason: SkipReason },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct LogOptions {
+    // Only the commits of this day or later
+    pub since: Option<NaiveDate>,
+    // Only the most recent commits
+    pub max: Option<usize>,
+}
+
+impl LogOptions {
+    pub fn since(mut self, since: NaiveDate) -> LogOptions {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn max(mut self, max: usize) -> LogOptions {
+        self.max = Some(max);
+        self
+    }
+}
+
+// The directory to run git in, and the path of the file relative to it
+fn split_path(path: &str) -> (&Path, String) {
+    let path = Path::new(path);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (dir, name)
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>, GitError> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .map_err(GitError::Run)?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(GitError::Failed {
+            command: format!("git {}", args.join(" ")),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+fn parse_commit(line: &str) -> Option<Commit> {
+    let mut fields = line.splitn(3, '\x1f');
+    let hash = fields.next()?;
+    let date = fields.next()?.parse().ok()?;
+    let subject = fields.next()?;
+    Some(Commit {
+        hash: hash.to_owned(),
+        date,
+        subject: subject.to_owned(),
+    })
+}
+
+// The commits that touched the file at `path`, oldest first
+pub fn file_history(path: &str) -> Result<Vec<Commit>, GitError> {
+    let (dir, name) = split_path(path);
+    let output = git(
+        dir,
+        &[
+            "log",
+            "--format=%H%x1f%ad%x1f%s",
+            "--date=short",
+            "--",
+            &name,
+        ],
+    )?;
+    let mut commits = String::from_utf8_lossy(&output)
+        .lines()
+        .map(|line| parse_commit(line).ok_or_else(|| GitError::Output(line.to_owned())))
+        .collect::<Result<Vec<_>, _>>()?;
+    commits.reverse();
+    Ok(commits)
+}
+
+// The tasks of the file at `path` as of `commit`
+fn tasks_at(
+    path: &str,
+    commit: &Commit,
+    opts: &ReadOptions,
+) -> Result<(Vec<Task>, Vec<ReadError>), SkipReason> {
+    let (dir, name) = split_path(path);
+    let contents = git(dir, &["show", &format!("{}:./{}", commit.hash, name)])
+        .map_err(|e| SkipReason::Missing(e.to_string()))?;
+    let name = format!("{}:{}", commit.short_hash(), path);
+    read_tasks_with(&contents[..], &name, opts)
+        .map(|outcome| (outcome.tasks, outcome.warnings))
+        .map_err(SkipReason::Unparseable)
+}
+
+// The changes of the file at `path` in each of the commits selected by `log_opts`, oldest first.
+// Each is diffed with the previous readable version of the file, which may be older than the
+// selected commits, and versions that cannot be read or whose tasks did not change are skipped.
+pub fn log_changesets(
+    path: &str,
+    log_opts: &LogOptions,
+    read_opts: &ReadOptions,
+    diff_opts: &DiffOptions,
+) -> Result<Vec<LogEntry>, GitError> {
+    let history = file_history(path)?;
+    // The commits following the last one older than `since`, as dates need not be ordered
+    let start = match log_opts.since {
+        Some(since) => history
+            .iter()
+            .rposition(|c| c.date < since)
+            .map_or(0, |i| i + 1),
+        None => 0,
+    };
+    let start = log_opts
+        .max
+        .map_or(start, |max| start.max(history.len().saturating_sub(max)));
+
+    let mut entries = Vec::new();
+    let mut previous = history[..start].iter().rev().find_map(|c| {
+        tasks_at(path, c, read_opts)
+            .ok()
+            .map(|(tasks, _)| (c, tasks))
+    });
+    for commit in history[start..].iter() {
+        let (tasks, warnings) = match tasks_at(path, commit, read_opts) {
+            Ok(read) => read,
+            Err(reason) => {
+                let commit = commit.clone();
+                entries.push(LogEntry::Skipped { commit, reason });
+                continue;
+            }
+        };
+        let (before, from) = match previous.take() {
+            Some(previous) => previous,
+            // The first version of the file is not diffed with anything
+            None => {
+                previous = Some((commit, tasks));
+                continue;
+            }
+        };
+        let (new_tasks, changes) = compute_changeset_with(from, tasks.clone(), diff_opts);
+        if changeset_has_changes(&new_tasks, &changes) {
+            entries.push(LogEntry::Diff(PairDiff {
+                pair: BatchPair {
+                    label: Some(commit.label()),
+                    ..BatchPair::new(
+                        &format!("{}:{}", before.short_hash(), path),
+                        &format!("{}:{}", commit.short_hash(), path),
+                    )
+                },
+                changeset: Ok((new_tasks, changes)),
+                warnings,
+            }));
+        } else {
+            entries.push(LogEntry::Skipped {
+                commit: commit.clone(),
+                reason: SkipReason::Unchanged,
+            });
+        }
+        previous = Some((commit, tasks));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,559 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
+use todo_txt::task::Extended as Task;
+
+#[derive(Debug)]
+pub enum ReadError {
+    // The file could not be opened at all
+    Open {
+        path: String,
+        error: io::Error,
+    },
+    // Reading failed midway (line numbers are 1-based)
+    Read {
+        path: String,
+        line_number: usize,
+        error: io::Error,
+    },
+    // The line is not valid UTF-8 or not a valid task (line numbers are 1-based)
+    Parse {
+        path: String,
+        line_number: usize,
+        line: String,
+    },
+}
+
+impl ReadError {
+    pub fn path(&self) -> &str {
+        match self {
+            ReadError::Open { path, .. }
+            | ReadError::Read { path, .. }
+            | ReadError::Parse { path, .. } => path,
+        }
+    }
+
+    pub fn line_number(&self) -> Option<usize> {
+        match self {
+            ReadError::Open { .. } => None,
+            ReadError::Read { line_number, .. } | ReadError::Parse { line_number, .. } => {
+                Some(*line_number)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Open { path, error } => {
+                write!(f, "Unable to open file ‘{}’: {}", path, error)
+            }
+            ReadError::Read {
+                path,
+                line_number,
+                error,
+            } => write!(
+                f,
+                "{}:{}: unable to read line: {}",
+                path, line_number, error
+            ),
+            ReadError::Parse {
+                path,
+                line_number,
+                line,
+            } => write!(
+                f,
+                "{}:{}: unable to parse line:\n{}",
+                path, line_number, line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+fn parse_line(bytes: &[u8], path: &str, line_number: usize) -> Result<Task, ReadError> {
+    let parse_error = || ReadError::Parse {
+        path: path.to_owned(),
+        line_number,
+        line: String::from_utf8_lossy(bytes).into_owned(),
+    };
+    let line = std::str::from_utf8(bytes).map_err(|_| parse_error())?;
+    Task::from_str(line).map_err(|_| parse_error())
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions {
+    // Skip unparseable lines, reporting them as warnings, instead of failing
+    pub lenient: bool,
+    // Parse the lines starting with `#` as tasks, rather than skipping them as comments
+    pub strict: bool,
+}
+
+impl ReadOptions {
+    pub fn lenient(mut self, lenient: bool) -> ReadOptions {
+        self.lenient = lenient;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> ReadOptions {
+        self.strict = strict;
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReadOutcome {
+    pub tasks: Vec<Task>,
+    // The 1-based line number of each of `tasks`
+    pub line_numbers: Vec<usize>,
+    // The lines skipped in lenient mode, always empty otherwise
+    pub warnings: Vec<ReadError>,
+    // The comment lines and their line numbers, always empty in strict mode
+    pub comments: Vec<(usize, String)>,
+}
+
+// A line of a todo list, as told apart by `read_lines`
+enum Line {
+    Blank,
+    Comment(String),
+    Task(Vec<u8>),
+}
+
+// The lines of `reader` with their 1-based line numbers, without their line endings
+fn read_lines<'a, R: BufRead + 'a>(
+    reader: R,
+    path: &'a str,
+    opts: ReadOptions,
+) -> impl Iterator<Item = Result<(usize, Line), ReadError>> + 'a {
+    reader.split(b'\n').enumerate().map(move |(i, line)| {
+        let line_number = i + 1;
+        let mut line = line.map_err(|error| ReadError::Read {
+            path: path.to_owned(),
+            line_number,
+            error,
+        })?;
+        // Like BufRead::lines, accept CRLF line endings
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let line = if line.iter().all(u8::is_ascii_whitespace) {
+            Line::Blank
+        } else if !opts.strict && line.iter().find(|c| !c.is_ascii_whitespace()) == Some(&b'#') {
+            Line::Comment(String::from_utf8_lossy(&line).into_owned())
+        } else {
+            Line::Task(line)
+        };
+        Ok((line_number, line))
+    })
+}
+
+// `path` is only used for error reporting. Blank lines are always ignored, and so are comments
+// unless `opts.strict` is set.
+pub fn read_tasks_with<R: BufRead>(
+    reader: R,
+    path: &str,
+    opts: &ReadOptions,
+) -> Result<ReadOutcome, ReadError> {
+    let mut outcome = ReadOutcome::default();
+    for line in read_lines(reader, path, *opts) {
+        match line? {
+            (_, Line::Blank) => (),
+            (line_number, Line::Comment(comment)) => outcome.comments.push((line_number, comment)),
+            (line_number, Line::Task(line)) => outcome.push_line(&line, path, line_number, opts)?,
+        }
+    }
+    Ok(outcome)
+}
+
+impl ReadOutcome {
+    fn push_line(
+        &mut self,
+        line: &[u8],
+        path: &str,
+        line_number: usize,
+        opts: &ReadOptions,
+    ) -> Result<(), ReadError> {
+        match parse_line(line, path, line_number) {
+            Ok(task) => {
+                self.tasks.push(task);
+                self.line_numbers.push(line_number);
+            }
+            Err(e) if opts.lenient => self.warnings.push(e),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    // The tasks paired with their line numbers, as expected by `match_tasks_indexed`
+    pub fn indexed(self) -> Vec<(usize, Task)> {
+        self.line_numbers.into_iter().zip(self.tasks).collect()
+    }
+
+    // The line numbers of each task found several times, in file order. Such copies are matched
+    // like any other task, eg. one of them being reported as new if the task was duplicated.
+    pub fn duplicates(&self) -> Vec<Vec<usize>> {
+        duplicates(self.line_numbers.iter().cloned().zip(&self.tasks))
+    }
+}
+
+// The line numbers of each task found several times among `tasks`, which are in file order
+fn duplicates<'a, I: IntoIterator<Item = (usize, &'a Task)>>(tasks: I) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut index = HashMap::new();
+    for (line, t) in tasks {
+        let i = *index.entry(t.to_string()).or_insert(groups.len());
+        if i == groups.len() {
+            groups.push(Vec::new());
+        }
+        groups[i].push(line);
+    }
+    groups.retain(|lines| lines.len() > 1);
+    groups
+}
+
+// Two lists read by `read_residual_tasks_with`
+#[derive(Debug, Default)]
+pub struct ResidualOutcome {
+    // The tasks of each list that are not in `identical`
+    pub before: ReadOutcome,
+    pub after: ReadOutcome,
+    // The tasks found identically in both lists, with their line numbers in each, in file order
+    pub identical: Vec<(usize, usize, Task)>,
+}
+
+impl ResidualOutcome {
+    // Like `ReadOutcome::duplicates` for each list, identical tasks included
+    pub fn duplicates(&self) -> [Vec<Vec<usize>>; 2] {
+        let with_identical = |outcome: &'_ ReadOutcome, identical: Vec<(usize, &'_ Task)>| {
+            let mut tasks = outcome
+                .line_numbers
+                .iter()
+                .cloned()
+                .zip(&outcome.tasks)
+                .chain(identical)
+                .collect::<Vec<_>>();
+            tasks.sort_by_key(|(line, _)| *line);
+            duplicates(tasks)
+        };
+        [
+            with_identical(
+                &self.before,
+                self.identical.iter().map(|(l, _, t)| (*l, t)).collect(),
+            ),
+            with_identical(
+                &self.after,
+                self.identical.iter().map(|(_, l, t)| (*l, t)).collect(),
+            ),
+        ]
+    }
+}
+
+// Like `read_tasks_with` on both lists, but setting aside the lines found identically in both for
+// which `may_set_aside` holds, the k-th copy of such a line in `before` going with its k-th copy in
+// `after`. Only the lines of `before` are kept while `after` is read, and each line set aside is
+// only parsed once, so that very large lists that barely changed can be diffed in little memory.
+// The other lines of `after` are kept until both lists are read, so that lists that changed a lot
+// still take both lists' worth of lines.
+pub fn read_residual_tasks_with<R, S, F>(
+    before: R,
+    after: S,
+    paths: [&str; 2],
+    opts: &ReadOptions,
+    may_set_aside: F,
+) -> Result<ResidualOutcome, ReadError>
+where
+    R: BufRead,
+    S: BufRead,
+    F: Fn(&str) -> bool,
+{
+    let mut res = ResidualOutcome::default();
+    let mut before_lines = Vec::new();
+    for line in read_lines(before, paths[0], *opts) {
+        match line? {
+            (_, Line::Blank) => (),
+            (line_number, Line::Comment(comment)) => {
+                res.before.comments.push((line_number, comment))
+            }
+            (line_number, Line::Task(line)) => before_lines.push((line_number, line)),
+        }
+    }
+
+    // The line numbers in `after` and the task of the lines of `before` set aside
+    let mut set_aside = vec![None; before_lines.len()];
+    let mut after_lines = Vec::new();
+    {
+        // The positions in `before_lines` of the copies of each line, not set aside yet
+        let mut copies = HashMap::<&[u8], VecDeque<usize>>::new();
+        for (i, (_, line)) in before_lines.iter().enumerate() {
+            if std::str::from_utf8(line).is_ok_and(&may_set_aside) {
+                copies.entry(line).or_default().push_back(i);
+            }
+        }
+        for line in read_lines(after, paths[1], *opts) {
+            match line? {
+                (_, Line::Blank) => (),
+                (line_number, Line::Comment(comment)) => {
+                    res.after.comments.push((line_number, comment))
+                }
+                (line_number, Line::Task(line)) => {
+                    // Invalid lines are left to be reported with the others
+                    let copy = copies
+                        .get_mut(line.as_slice())
+                        .and_then(VecDeque::pop_front)
+                        .and_then(|i| {
+                            let (before_line_number, ref before_line) = before_lines[i];
+                            let task = parse_line(before_line, paths[0], before_line_number);
+                            task.ok().map(|t| (i, t))
+                        });
+                    match copy {
+                        Some((i, task)) => set_aside[i] = Some((line_number, task)),
+                        None => after_lines.push((line_number, line)),
+                    }
+                }
+            }
+        }
+    }
+
+    for ((line_number, line), set_aside) in before_lines.into_iter().zip(set_aside) {
+        match set_aside {
+            Some((after_line_number, task)) => {
+                res.identical.push((line_number, after_line_number, task))
+            }
+            None => res.before.push_line(&line, paths[0], line_number, opts)?,
+        }
+    }
+    for (line_number, line) in after_lines {
+        res.after.push_line(&line, paths[1], line_number, opts)?;
+    }
+    Ok(res)
+}
+
+pub fn read_tasks<R: BufRead>(reader: R, path: &str) -> Result<Vec<Task>, ReadError> {
+    read_tasks_with(reader, path, &ReadOptions::default()).map(|o| o.tasks)
+}
+
+// The reader of `path` and its name for error reporting, `-` standing for the standard input
+pub fn open_list(path: &str) -> Result<(Box<dyn BufRead>, &str), ReadError> {
+    if path == "-" {
+        Ok((Box::new(io::stdin().lock()), "<stdin>"))
+    } else {
+        let file = File::open(path).map_err(|error| ReadError::Open {
+            path: path.to_owned(),
+            error,
+        })?;
+        Ok((Box::new(BufReader::new(file)), path))
+    }
+}
+
+// `-` stands for the standard input
+pub fn read_tasks_from_with(path: &str, opts: &ReadOptions) -> Result<ReadOutcome, ReadError> {
+    let (reader, name) = open_list(path)?;
+    read_tasks_with(reader, name, opts)
+}
+
+// Like `read_residual_tasks_with`, for the files at `before` and `after`
+pub fn read_residual_tasks_from_with<F: Fn(&str) -> bool>(
+    before: &str,
+    after: &str,
+    opts: &ReadOptions,
+    may_set_aside: F,
+) -> Result<ResidualOutcome, ReadError> {
+    let (before, before_name) = open_list(before)?;
+    let (after, after_name) = open_list(after)?;
+    read_residual_tasks_with(
+        before,
+        after,
+        [before_name, after_name],
+        opts,
+        may_set_aside,
+    )
+}
+
+pub fn read_tasks_indexed<R: BufRead>(
+    reader: R,
+    path: &str,
+) -> Result<Vec<(usize, Task)>, ReadError> {
+    read_tasks_with(reader, path, &ReadOptions::default()).map(ReadOutcome::indexed)
+}
+
+pub fn read_tasks_from(path: &str) -> Result<Vec<Task>, ReadError> {
+    read_tasks_from_with(path, &ReadOptions::default()).map(|o| o.tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_tasks() {
+        let input: &[u8] = b"do a thing\r\nx do another thing\n";
+        let tasks = read_tasks(input, "todo.txt").unwrap();
+        assert_eq!(
+            tasks.iter().map(Task::to_string).collect::<Vec<_>>(),
+            vec!["do a thing", "x do another thing"]
+        );
+    }
+
+    #[test]
+    fn test_read_tasks_invalid_line() {
+        let input: &[u8] = b"do a thing\ndo another thing\ndo a \xff thing\nlast thing\n";
+        let err = read_tasks(input, "todo.txt").unwrap_err();
+        assert_eq!(err.path(), "todo.txt");
+        assert_eq!(err.line_number(), Some(3));
+        match err {
+            ReadError::Parse { ref line, .. } => assert_eq!(line, "do a \u{fffd} thing"),
+            _ => panic!("unexpected error {:?}", err),
+        }
+        assert!(err.to_string().starts_with("todo.txt:3: "));
+    }
+
+    #[test]
+    fn test_read_tasks_lenient() {
+        let input: &[u8] = b"do a thing\n\n  \n\xff\ndo another thing\n\xfe\n";
+        let outcome =
+            read_tasks_with(input, "todo.txt", &ReadOptions::default().lenient(true)).unwrap();
+        assert_eq!(
+            outcome
+                .tasks
+                .iter()
+                .map(Task::to_string)
+                .collect::<Vec<_>>(),
+            vec!["do a thing", "do another thing"]
+        );
+        assert_eq!(
+            outcome
+                .warnings
+                .iter()
+                .map(ReadError::line_number)
+                .collect::<Vec<_>>(),
+            vec![Some(4), Some(6)]
+        );
+        assert_eq!(outcome.line_numbers, vec![1, 5]);
+
+        let err = read_tasks(input, "todo.txt").unwrap_err();
+        assert_eq!(err.line_number(), Some(4));
+    }
+
+    #[test]
+    fn test_read_tasks_indexed() {
+        let input: &[u8] = b"do a thing\n\n  \nx do another thing\n";
+        let tasks = read_tasks_indexed(input, "todo.txt").unwrap();
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|(i, t)| (*i, t.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, "do a thing".to_owned()),
+                (4, "x do another thing".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_tasks_comments() {
+        let input: &[u8] = b"# work\ndo a thing\n  # home\ncall mom\n";
+        let outcome = read_tasks_with(input, "todo.txt", &ReadOptions::default()).unwrap();
+        assert_eq!(
+            outcome.comments,
+            vec![(1, "# work".to_owned()), (3, "  # home".to_owned())]
+        );
+        assert_eq!(
+            outcome
+                .indexed()
+                .into_iter()
+                .map(|(i, t)| (i, t.to_string()))
+                .collect::<Vec<_>>(),
+            vec![(2, "do a thing".to_owned()), (4, "call mom".to_owned())]
+        );
+
+        let outcome =
+            read_tasks_with(input, "todo.txt", &ReadOptions::default().strict(true)).unwrap();
+        assert_eq!(outcome.tasks.len(), 4);
+        assert_eq!(outcome.comments, vec![]);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let input: &[u8] = b"do a thing\ncall mom\n\ndo a thing\nx call mom\ndo a thing\n";
+        let outcome = read_tasks_with(input, "todo.txt", &ReadOptions::default()).unwrap();
+        assert_eq!(outcome.duplicates(), vec![vec![1, 4, 6]]);
+    }
+
+    #[test]
+    fn test_read_residual_tasks() {
+        let before: &[u8] = b"do a thing\ncall mom\n# home\ndo a thing\n\xff\nfix the bike\n";
+        let after: &[u8] = b"call dad\ndo a thing\n\xff\ncall mom\nfix the bike\n";
+        let opts = ReadOptions::default().lenient(true);
+        let residual = read_residual_tasks_with(before, after, ["before", "after"], &opts, |l| {
+            !l.contains("bike")
+        })
+        .unwrap();
+        let lines = |outcome: &ReadOutcome| {
+            outcome
+                .line_numbers
+                .iter()
+                .zip(&outcome.tasks)
+                .map(|(i, t)| (*i, t.to_string()))
+                .collect::<Vec<_>>()
+        };
+        // The first copy of a line goes with the first copy in the other list
+        assert_eq!(
+            residual
+                .identical
+                .iter()
+                .map(|(i, j, t)| (*i, *j, t.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, 2, "do a thing".to_owned()),
+                (2, 4, "call mom".to_owned())
+            ]
+        );
+        assert_eq!(
+            lines(&residual.before),
+            vec![(4, "do a thing".to_owned()), (6, "fix the bike".to_owned())]
+        );
+        assert_eq!(
+            lines(&residual.after),
+            vec![(1, "call dad".to_owned()), (5, "fix the bike".to_owned())]
+        );
+        assert_eq!(residual.before.comments, vec![(3, "# home".to_owned())]);
+        // Invalid lines are reported rather than set aside
+        assert_eq!(residual.before.warnings.len(), 1);
+        assert_eq!(residual.after.warnings[0].line_number(), Some(3));
+        assert_eq!(residual.duplicates(), [vec![vec![1, 4]], vec![]]);
+    }
+
+    #[test]
+    fn test_read_residual_tasks_not_set_aside() {
+        let before: &[u8] = b"call mom due:2018-06-10\nbuy milk\n";
+        let after: &[u8] = b"buy milk\ncall mom due:2018-06-10\n";
+        let residual = read_residual_tasks_with(
+            before,
+            after,
+            ["before", "after"],
+            &ReadOptions::default(),
+            |l| !l.contains("due:"),
+        )
+        .unwrap();
+        let line = "call mom due:2018-06-10";
+        assert_eq!(residual.identical.len(), 1);
+        assert_eq!(residual.identical[0].2.to_string(), "buy milk");
+        // Both copies are left to be matched
+        assert_eq!(residual.before.line_numbers, vec![1]);
+        assert_eq!(residual.before.tasks[0].to_string(), line);
+        assert_eq!(residual.after.line_numbers, vec![2]);
+        assert_eq!(residual.after.tasks[0].to_string(), line);
+    }
+
+    #[test]
+    fn test_read_tasks_from_missing_file() {
+        let err = read_tasks_from("/nonexistent/todo.txt").unwrap_err();
+        assert_eq!(err.path(), "/nonexistent/todo.txt");
+        assert_eq!(err.line_number(), None);
+    }
+}
@@ -4,21 +4,59 @@ extern crate chrono;
 extern crate clap;
 extern crate diff;
 extern crate itertools;
+#[cfg(unix)]
+extern crate libc;
+#[macro_use]
+extern crate serde_json;
 extern crate strsim;
 
 extern crate todo_txt;
+extern crate toml;
+extern crate unicode_width;
 
 #[cfg(test)]
 #[macro_use]
 extern crate pretty_assertions;
-#[cfg(feature = "integration_tests")]
+#[cfg(test)]
+extern crate proptest;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
 #[macro_use]
 extern crate serde_derive;
 
+pub mod anonymize;
+pub mod apply_changes;
+pub mod batch;
+pub mod check;
+pub mod cli;
+pub mod color_policy;
 pub mod compute_changes;
+pub mod config;
 pub mod display_changes;
+pub mod edit_distance;
+pub mod fail_on;
+pub mod filter_changes;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
+pub mod git_log;
+pub mod io;
 pub mod merge_changes;
+pub mod pin_matches;
+pub mod progress;
+#[cfg(feature = "serde")]
+mod serde_helpers;
+pub mod serialize_changes;
 pub mod stable_marriage;
+pub mod stats;
+pub mod subject_diff;
+#[cfg(any(test, feature = "bench"))]
+pub mod synthetic;
+pub mod template;
+#[cfg(test)]
+mod test_utils;
 
 #[cfg(all(test, not(feature = "integration_tests")))]
 #[test]
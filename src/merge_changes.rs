@@ -2,8 +2,10 @@ use self::MergeResult::*;
 use compute_changes::TaskDelta::*;
 use compute_changes::*;
 use itertools::Itertools;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use todo_txt::task::Extended as Task;
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum MergeResult<T> {
     Merged(T),
@@ -20,64 +22,426 @@ impl<T> MergeResult<T> {
             Merged(t) => Merged(f(t)),
             Conflict(t, t1, t2) => Conflict(
                 f(t),
-                t1.into_iter().map(|x| f(x)).collect(),
-                t2.into_iter().map(|x| f(x)).collect(),
+                t1.into_iter().map(&mut f).collect(),
+                t2.into_iter().map(f).collect(),
             ),
         }
     }
 }
 
+// What to do with the tasks both sides changed in incompatible ways
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MergeStrategy {
+    // Leave a conflict to be resolved by hand
+    Conflict,
+    // Keep the tasks of the left side
+    Ours,
+    // Keep the tasks of the right side
+    Theirs,
+    // Keep the tasks of both sides, only once if identical
+    Union,
+}
+
+impl std::str::FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MergeStrategy, String> {
+        match s {
+            "conflict" => Ok(MergeStrategy::Conflict),
+            "ours" => Ok(MergeStrategy::Ours),
+            "theirs" => Ok(MergeStrategy::Theirs),
+            "union" => Ok(MergeStrategy::Union),
+            _ => Err(format!("unknown merge strategy ‘{}’", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for MergeStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            MergeStrategy::Conflict => "conflict",
+            MergeStrategy::Ours => "ours",
+            MergeStrategy::Theirs => "theirs",
+            MergeStrategy::Union => "union",
+        })
+    }
+}
+
+// Decides which of `left` and `right` a part of the task should be taken from, failing if both
+// changed it differently
+fn pick_side<'a, T, F>(from: &Task, left: &'a Task, right: &'a Task, part: F) -> Option<&'a Task>
+where
+    T: PartialEq,
+    F: Fn(&Task) -> T,
+{
+    let (f, l, r) = (part(from), part(left), part(right));
+    if l == r || r == f {
+        Some(left)
+    } else if l == f {
+        Some(right)
+    } else {
+        None
+    }
+}
+
+// Merges two modified versions of a task, as long as they did not change the same fields in
+// different ways. The subject is merged as a whole, along with the projects, contexts and hashtags
+// it contains, and the completion along with its date. Tags are merged one by one.
+fn merge_task_fields(from: &Task, left: &Task, right: &Task) -> Option<Task> {
+    let mut res = from.clone();
+
+    let t = pick_side(from, left, right, |t| t.subject.clone())?;
+    res.subject = t.subject.clone();
+    res.projects = t.projects.clone();
+    res.contexts = t.contexts.clone();
+    res.hashtags = t.hashtags.clone();
+
+    let t = pick_side(from, left, right, |t| (t.finished, t.finish_date))?;
+    res.finished = t.finished;
+    res.finish_date = t.finish_date;
+
+    res.priority = pick_side(from, left, right, |t| t.priority.clone())?
+        .priority
+        .clone();
+    res.create_date = pick_side(from, left, right, |t| t.create_date)?.create_date;
+    res.threshold_date = pick_side(from, left, right, |t| t.threshold_date)?.threshold_date;
+    res.due_date = pick_side(from, left, right, |t| t.due_date)?.due_date;
+    res.recurrence = pick_side(from, left, right, |t| t.recurrence.clone())?
+        .recurrence
+        .clone();
+    res.note = pick_side(from, left, right, |t| t.note.clone())?
+        .note
+        .clone();
+    res.flagged = pick_side(from, left, right, |t| t.flagged)?.flagged;
+    res.hidden = pick_side(from, left, right, |t| t.hidden)?.hidden;
+
+    let keys = from
+        .tags
+        .keys()
+        .chain(left.tags.keys())
+        .chain(right.tags.keys())
+        .cloned()
+        .collect::<BTreeSet<String>>();
+    for k in keys {
+        match pick_side(from, left, right, |t| t.tags.get(&k).cloned())?
+            .tags
+            .get(&k)
+        {
+            Some(v) => res.tags.insert(k, v.clone()),
+            None => res.tags.remove(&k),
+        };
+    }
+
+    Some(res)
+}
+
+// The positions of tasks in a list, each position being given out only once
+struct Positions {
+    positions: HashMap<String, VecDeque<usize>>,
+}
+
+impl Positions {
+    fn new(tasks: &[Task]) -> Positions {
+        let mut positions = HashMap::new();
+        for (i, t) in tasks.iter().enumerate() {
+            positions
+                .entry(t.to_string())
+                .or_insert_with(VecDeque::new)
+                .push_back(i);
+        }
+        Positions { positions }
+    }
+
+    fn take(&mut self, task: &Task) -> Option<usize> {
+        self.positions
+            .get_mut(&task.to_string())
+            .and_then(VecDeque::pop_front)
+    }
+}
+
 pub fn merge_3way(
     from: Vec<Task>,
     left: Vec<Task>,
     right: Vec<Task>,
     allowed_divergence: usize,
 ) -> Vec<MergeResult<Task>> {
-    let (mut new_left, changes_left) = match_tasks(from.clone(), left, allowed_divergence);
-    let (mut new_right, changes_right) = match_tasks(from, right, allowed_divergence);
+    merge_3way_with(
+        from,
+        left,
+        right,
+        &DiffOptions::default().allowed_divergence(allowed_divergence),
+    )
+}
+
+pub fn merge_3way_with(
+    from: Vec<Task>,
+    left: Vec<Task>,
+    right: Vec<Task>,
+    opts: &DiffOptions,
+) -> Vec<MergeResult<Task>> {
+    merge_3way_anchored_with(from, left, right, opts)
+        .into_iter()
+        .map(|(_, m)| m)
+        .collect()
+}
+
+// Merge results, along with the index in `left` of the task each stands for, if any
+pub type AnchoredMerge = Vec<(Option<usize>, MergeResult<Task>)>;
+
+// Like `merge_3way_with`, but also returns the anchor of each result
+pub fn merge_3way_anchored_with(
+    from: Vec<Task>,
+    left: Vec<Task>,
+    right: Vec<Task>,
+    opts: &DiffOptions,
+) -> AnchoredMerge {
+    let mut positions = Positions::new(&left);
+    let (mut new_left, changes_left) = match_tasks(from.clone(), left, opts);
+    let (mut new_right, changes_right) = match_tasks(from, right, opts);
+
+    let mut merged_new =
+        remove_common_by_key(&mut new_left, &mut new_right, |t| t.subject.as_str())
+            .into_iter()
+            .map(Merged)
+            .collect_vec();
+    if opts.allowed_divergence == 0 || opts.exact_only {
+        merged_new.extend(new_left.into_iter().map(Merged));
+        merged_new.extend(new_right.into_iter().map(Merged));
+    } else {
+        // Similar tasks added on both sides are likely the same task, let the user decide
+        let (pairs, new_right) = match_similar_tasks(new_left, new_right, opts);
+        merged_new.extend(pairs.into_iter().map(|(l, r)| match r {
+            Some(r) => Conflict(Task::default(), vec![l], vec![r]),
+            None => Merged(l),
+        }));
+        merged_new.extend(new_right.into_iter().map(Merged));
+    }
+
+    // Tasks are sorted by their position in `left`. Those only in `right` are put after the
+    // previous task of `from`, or at the end if they are new.
+    let mut anchor = None;
+    let mut merged = Vec::new();
+    for (left_chgt, right_chgt) in changes_left.into_iter().zip(changes_right) {
+        let position = match left_chgt.delta {
+            Identical => positions.take(&left_chgt.orig),
+            ref delta => delta.iter().filter_map(|t| positions.take(t)).min(),
+        };
+        let key = match position {
+            Some(i) => {
+                anchor = Some(i);
+                2 * i + 1
+            }
+            None => anchor.map_or(0, |i| 2 * i + 2),
+        };
+        let results = match (left_chgt.delta, right_chgt.delta) {
+            (Identical, Identical) => vec![Merged(left_chgt.orig)],
+            (Identical, right_delta) => right_delta.into_iter().map(Merged).collect_vec(),
+            (left_delta, Identical) => left_delta.into_iter().map(Merged).collect_vec(),
+            (left_delta, right_delta) if left_delta == right_delta => {
+                left_delta.into_iter().map(Merged).collect_vec()
+            }
+            (Changed(left_task), Changed(right_task)) => {
+                match merge_task_fields(&left_chgt.orig, &left_task, &right_task) {
+                    Some(t) => vec![Merged(t)],
+                    None => vec![Conflict(left_chgt.orig, vec![left_task], vec![right_task])],
+                }
+            }
+            (left_delta, right_delta) => vec![Conflict(
+                left_chgt.orig,
+                left_delta.into_iter().collect_vec(),
+                right_delta.into_iter().collect_vec(),
+            )],
+        };
+        merged.extend(results.into_iter().map(|m| (key, position, m)));
+    }
+    for m in merged_new {
+        let position = match m {
+            Merged(ref t) => positions.take(t),
+            Conflict(_, ref left, _) => left.iter().filter_map(|t| positions.take(t)).min(),
+        };
+        merged.push((position.map_or(usize::MAX, |i| 2 * i + 1), position, m));
+    }
+
+    merged.sort_by_key(|(key, _, _)| *key);
+    merged
+        .into_iter()
+        .map(|(_, position, m)| (position, m))
+        .collect()
+}
+
+// How conflicts are written out by `merge_to_string_with`, defaulting to what git expects
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MarkerOptions {
+    // Number of characters of each conflict marker
+    pub marker_size: usize,
+    // Labels following the markers, usually the names of the merged files
+    pub label_ours: Option<String>,
+    pub label_base: Option<String>,
+    pub label_theirs: Option<String>,
+    // Also display the ancestor of the conflicting tasks, like git's diff3 conflict style
+    pub diff3: bool,
+}
+
+impl Default for MarkerOptions {
+    fn default() -> MarkerOptions {
+        MarkerOptions {
+            marker_size: 7,
+            label_ours: None,
+            label_base: None,
+            label_theirs: None,
+            diff3: false,
+        }
+    }
+}
+
+impl MarkerOptions {
+    pub fn marker_size(mut self, marker_size: usize) -> MarkerOptions {
+        self.marker_size = marker_size;
+        self
+    }
+
+    pub fn label_ours(mut self, label: &str) -> MarkerOptions {
+        self.label_ours = Some(label.to_owned());
+        self
+    }
+
+    pub fn label_base(mut self, label: &str) -> MarkerOptions {
+        self.label_base = Some(label.to_owned());
+        self
+    }
+
+    pub fn label_theirs(mut self, label: &str) -> MarkerOptions {
+        self.label_theirs = Some(label.to_owned());
+        self
+    }
+
+    pub fn diff3(mut self, diff3: bool) -> MarkerOptions {
+        self.diff3 = diff3;
+        self
+    }
+
+    fn marker(&self, c: char, label: &Option<String>) -> String {
+        let marker = std::iter::repeat_n(c, self.marker_size).collect::<String>();
+        match label {
+            Some(label) => format!("{} {}", marker, label),
+            None => marker,
+        }
+    }
+}
 
-    let mut merged_new = remove_common(&mut new_left, &mut new_right);
-    merged_new.extend(new_left);
-    merged_new.extend(new_right);
+// Resolves the conflicts of a merge according to `strategy`, returning the number of conflicts
+// that were resolved this way
+pub fn resolve_conflicts(
+    merge: Vec<MergeResult<Task>>,
+    strategy: MergeStrategy,
+) -> (Vec<MergeResult<Task>>, usize) {
+    let (merge, resolved) =
+        resolve_anchored_conflicts(merge.into_iter().map(|m| (None, m)).collect(), strategy);
+    (merge.into_iter().map(|(_, m)| m).collect(), resolved)
+}
 
-    changes_left
+// Like `resolve_conflicts`, for the results of `merge_3way_anchored_with`: tasks a conflict is
+// resolved to keep its anchor
+pub fn resolve_anchored_conflicts(
+    merge: AnchoredMerge,
+    strategy: MergeStrategy,
+) -> (AnchoredMerge, usize) {
+    let mut resolved = 0;
+    let merge = merge
         .into_iter()
-        .zip(changes_right.into_iter())
-        .flat_map(
-            |(left_chgt, right_chgt)| match (left_chgt.delta, right_chgt.delta) {
-                (Identical, Identical) => vec![Merged(left_chgt.orig)],
-                (Identical, right_delta) => right_delta.into_iter().map(Merged).collect_vec(),
-                (left_delta, Identical) => left_delta.into_iter().map(Merged).collect_vec(),
-                (left_delta, right_delta) => vec![Conflict(
-                    left_chgt.orig,
-                    left_delta.into_iter().collect_vec(),
-                    right_delta.into_iter().collect_vec(),
-                )],
-            },
-        )
-        .chain(merged_new.into_iter().map(Merged))
-        .collect::<Vec<MergeResult<Task>>>()
+        .flat_map(|(anchor, m)| {
+            let results: Vec<MergeResult<Task>> = match (m, strategy) {
+                (Conflict(orig, left, right), MergeStrategy::Conflict) => {
+                    vec![Conflict(orig, left, right)]
+                }
+                (Conflict(_, left, right), strategy) => {
+                    resolved += 1;
+                    let tasks = match strategy {
+                        MergeStrategy::Ours => left,
+                        MergeStrategy::Theirs => right,
+                        _ => {
+                            let mut tasks = left;
+                            for t in right {
+                                if !tasks.contains(&t) {
+                                    tasks.push(t);
+                                }
+                            }
+                            tasks
+                        }
+                    };
+                    tasks.into_iter().map(Merged).collect()
+                }
+                (merged, _) => vec![merged],
+            };
+            results.into_iter().map(move |m| (anchor, m))
+        })
+        .collect();
+    (merge, resolved)
 }
 
 pub fn merge_to_string(merge: Vec<MergeResult<Task>>) -> String {
-    merge
-        .into_iter()
-        .flat_map(|m| match m.map(|t| Task::to_string(&t)) {
-            Merged(t) => vec![t],
-            Conflict(t, left, right) => Some("<<<<<".to_owned())
+    merge_to_string_with(merge, &MarkerOptions::default())
+}
+
+// The lines a merge result is written as
+fn merge_result_lines(m: MergeResult<Task>, opts: &MarkerOptions) -> Vec<String> {
+    match m.map(|t| Task::to_string(&t)) {
+        Merged(t) => vec![t],
+        Conflict(t, left, right) => {
+            // Tasks added on both sides have an empty ancestor
+            let base = if opts.diff3 {
+                Some(opts.marker('|', &opts.label_base))
+                    .into_iter()
+                    .chain(Some(t).filter(|t| !t.is_empty()))
+                    .collect()
+            } else {
+                vec![]
+            };
+            Some(opts.marker('<', &opts.label_ours))
                 .into_iter()
                 .chain(left)
-                .chain(Some("|||||".to_owned()))
-                .chain(Some(t))
-                .chain(Some("=====".to_owned()))
+                .chain(base)
+                .chain(Some(opts.marker('=', &None)))
                 .chain(right)
-                .chain(Some(">>>>>".to_owned()))
-                .collect::<Vec<_>>(),
-        })
+                .chain(Some(opts.marker('>', &opts.label_theirs)))
+                .collect::<Vec<_>>()
+        }
+    }
+}
+
+pub fn merge_to_string_with(merge: Vec<MergeResult<Task>>, opts: &MarkerOptions) -> String {
+    merge
+        .into_iter()
+        .flat_map(|m| merge_result_lines(m, opts))
         .join("\n")
 }
 
-pub fn merge_successful(merge: &Vec<MergeResult<Task>>) -> bool {
+// Like `merge_to_string_with`, but with the comments of the current file (see
+// `ReadOutcome::comments`) put back before the task that followed them. `anchors` are, for each
+// result, the line of the current file holding the task it stands for, if any. Comments followed
+// by tasks that are not in the merge (eg. deleted by the other side) move down to the next task
+// that is.
+pub fn merge_to_string_with_comments(
+    merge: Vec<MergeResult<Task>>,
+    opts: &MarkerOptions,
+    anchors: &[Option<usize>],
+    comments: &[(usize, String)],
+) -> String {
+    let mut comments = comments.iter().peekable();
+    let mut lines = Vec::new();
+    for (m, anchor) in merge.into_iter().zip(anchors) {
+        if let Some(anchor) = *anchor {
+            while let Some((_, comment)) = comments.next_if(|(line, _)| *line < anchor) {
+                lines.push(comment.clone());
+            }
+        }
+        lines.extend(merge_result_lines(m, opts));
+    }
+    lines.extend(comments.map(|(_, comment)| comment.clone()));
+    lines.join("\n")
+}
+
+pub fn merge_successful(merge: &[MergeResult<Task>]) -> bool {
     merge.iter().all(|x| match x {
         Merged(_) => true,
         Conflict(_, _, _) => false,
@@ -93,3 +457,23 @@ pub fn extract_merge_result(merge: Vec<MergeResult<Task>>) -> Option<Vec<Task>>
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use test_utils::arb_tasks;
+
+    proptest! {
+        #[test]
+        fn prop_self_merge_is_identity(tasks in arb_tasks(), allowed_divergence in 0..101usize) {
+            let merge = merge_3way(
+                tasks.clone(),
+                tasks.clone(),
+                tasks.clone(),
+                allowed_divergence,
+            );
+            prop_assert_eq!(extract_merge_result(merge), Some(tasks));
+        }
+    }
+}
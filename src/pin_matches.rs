@@ -0,0 +1,288 @@
+// Manual corrections of the matching, for the pairs no similarity threshold gets right. They are
+// read from a file where each line is one of:
+//  - `BEFORE_SUBJECT ||| AFTER_SUBJECT`, pinning the tasks with these exact subjects together
+//  - `12 -> 7`, pinning the task on line 12 of the old list with the one on line 7 of the new one
+//  - `!SUBJECT`, forbidding the tasks with this exact subject from being matched with anything
+//    but an identical task, so that any change makes them deleted and new
+// Blank lines and lines starting with `#` are ignored.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use todo_txt::task::Extended as Task;
+
+// A task of one of the lists, by its subject or by its 1-based line number. A subject designates
+// the first task with this subject that is not already pinned, so that duplicated tasks can be
+// pinned by repeating the line.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TaskRef {
+    Subject(String),
+    Line(usize),
+}
+
+impl fmt::Display for TaskRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskRef::Subject(subject) => write!(f, "‘{}’", subject),
+            TaskRef::Line(line) => write!(f, "on line {}", line),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Pin {
+    // The line of the pin in its file, for the errors
+    pub line_number: usize,
+    pub before: TaskRef,
+    pub after: TaskRef,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct MatchPins {
+    // The file the pins were read from, for the errors
+    pub path: String,
+    pub pins: Vec<Pin>,
+    // The subjects of the tasks that are only matched with identical tasks
+    pub unmatchable: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum PinsError {
+    Open {
+        path: String,
+        error: io::Error,
+    },
+    Syntax {
+        path: String,
+        line_number: usize,
+        line: String,
+    },
+    // A side of a pin has no task (left to be pinned) in its list
+    Missing {
+        path: String,
+        line_number: usize,
+        side: &'static str,
+        task: TaskRef,
+    },
+}
+
+impl fmt::Display for PinsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PinsError::Open { path, error } => {
+                write!(f, "Unable to open pairs file ‘{}’: {}", path, error)
+            }
+            PinsError::Syntax {
+                path,
+                line_number,
+                line,
+            } => write!(
+                f,
+                "{}:{}: expected ‘BEFORE ||| AFTER’, ‘LINE -> LINE’ or ‘!SUBJECT’, found ‘{}’",
+                path, line_number, line
+            ),
+            PinsError::Missing {
+                path,
+                line_number,
+                side,
+                task,
+            } => write!(
+                f,
+                "{}:{}: no unpinned task {} in {}",
+                path, line_number, task, side
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PinsError {}
+
+fn parse_line_pin(line: &str) -> Option<(usize, usize)> {
+    let (before, after) = line.split_once("->")?;
+    let line_number = |s: &str| s.trim().parse().ok().filter(|l| *l > 0);
+    Some((line_number(before)?, line_number(after)?))
+}
+
+// `path` is only used in the errors
+pub fn parse_pins(contents: &str, path: &str) -> Result<MatchPins, PinsError> {
+    let mut pins = MatchPins {
+        path: path.to_owned(),
+        ..MatchPins::default()
+    };
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(subject) = trimmed.strip_prefix('!') {
+            pins.unmatchable.push(subject.trim().to_owned());
+        } else if let Some((before, after)) = trimmed.split_once("|||") {
+            pins.pins.push(Pin {
+                line_number: i + 1,
+                before: TaskRef::Subject(before.trim().to_owned()),
+                after: TaskRef::Subject(after.trim().to_owned()),
+            });
+        } else if let Some((before, after)) = parse_line_pin(trimmed) {
+            pins.pins.push(Pin {
+                line_number: i + 1,
+                before: TaskRef::Line(before),
+                after: TaskRef::Line(after),
+            });
+        } else {
+            return Err(PinsError::Syntax {
+                path: path.to_owned(),
+                line_number: i + 1,
+                line: line.to_owned(),
+            });
+        }
+    }
+    Ok(pins)
+}
+
+pub fn read_pins(path: &str) -> Result<MatchPins, PinsError> {
+    let contents = fs::read_to_string(path).map_err(|error| PinsError::Open {
+        path: path.to_owned(),
+        error,
+    })?;
+    parse_pins(&contents, path)
+}
+
+// The position in `tasks` of the task designated by `task_ref` and not `taken` yet
+fn find(tasks: &[(usize, Task)], taken: &[bool], task_ref: &TaskRef) -> Option<usize> {
+    tasks.iter().enumerate().position(|(i, (line, t))| {
+        !taken[i]
+            && match task_ref {
+                TaskRef::Subject(subject) => t.subject == *subject,
+                TaskRef::Line(l) => line == l,
+            }
+    })
+}
+
+impl MatchPins {
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty() && self.unmatchable.is_empty()
+    }
+
+    pub fn is_unmatchable(&self, task: &Task) -> bool {
+        self.unmatchable.contains(&task.subject)
+    }
+
+    // The positions in `from` and `to` of the pinned tasks, given with their line numbers, or
+    // why they could not be found
+    fn find_pinned(
+        &self,
+        from: &[(usize, Task)],
+        to: &[(usize, Task)],
+    ) -> Vec<Result<(usize, usize), PinsError>> {
+        let mut from_taken = vec![false; from.len()];
+        let mut to_taken = vec![false; to.len()];
+        let missing = |pin: &Pin, side, task: &TaskRef| PinsError::Missing {
+            path: self.path.clone(),
+            line_number: pin.line_number,
+            side,
+            task: task.clone(),
+        };
+        self.pins
+            .iter()
+            .map(|pin| {
+                let i = find(from, &from_taken, &pin.before)
+                    .ok_or_else(|| missing(pin, "<BEFORE>", &pin.before))?;
+                let j = find(to, &to_taken, &pin.after)
+                    .ok_or_else(|| missing(pin, "<AFTER>", &pin.after))?;
+                from_taken[i] = true;
+                to_taken[j] = true;
+                Ok((i, j))
+            })
+            .collect()
+    }
+
+    // Pins whose tasks are missing are ignored, so this should be checked with `check` first
+    pub fn resolve(&self, from: &[(usize, Task)], to: &[(usize, Task)]) -> Vec<(usize, usize)> {
+        self.find_pinned(from, to)
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect()
+    }
+
+    pub fn check(&self, from: &[(usize, Task)], to: &[(usize, Task)]) -> Result<(), PinsError> {
+        self.find_pinned(from, to)
+            .into_iter()
+            .try_for_each(|pair| pair.map(|_| ()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_parse_pins() {
+        let pins = parse_pins(
+            "# corrections\ncall mom ||| call dad\n\n 12 -> 7\n!water the plants\n",
+            "pairs.txt",
+        )
+        .unwrap();
+        assert_eq!(
+            pins,
+            MatchPins {
+                path: "pairs.txt".to_owned(),
+                pins: vec![
+                    Pin {
+                        line_number: 2,
+                        before: TaskRef::Subject("call mom".to_owned()),
+                        after: TaskRef::Subject("call dad".to_owned()),
+                    },
+                    Pin {
+                        line_number: 4,
+                        before: TaskRef::Line(12),
+                        after: TaskRef::Line(7),
+                    },
+                ],
+                unmatchable: vec!["water the plants".to_owned()],
+            }
+        );
+        assert_eq!(
+            parse_pins("call mom\n", "pairs.txt").unwrap_err().to_string(),
+            "pairs.txt:1: expected ‘BEFORE ||| AFTER’, ‘LINE -> LINE’ or ‘!SUBJECT’, found ‘call mom’"
+        );
+        assert!(parse_pins("0 -> 1\n", "pairs.txt").is_err());
+    }
+
+    #[test]
+    fn test_resolve_pins() {
+        let tasks = |lines: &[(usize, &str)]| -> Vec<(usize, Task)> {
+            lines
+                .iter()
+                .map(|(l, t)| (*l, Task::from_str(t).unwrap()))
+                .collect()
+        };
+        let from = tasks(&[
+            (1, "call mom"),
+            (2, "call mom"),
+            (4, "(A) pay rent due:2018-06-01"),
+        ]);
+        let to = tasks(&[(1, "pay rent"), (2, "call dad"), (3, "call mom")]);
+        let pins = |contents| parse_pins(contents, "pairs.txt").unwrap();
+        let resolve = |contents| pins(contents).resolve(&from, &to);
+        let check = |contents| pins(contents).check(&from, &to).unwrap_err().to_string();
+        assert_eq!(
+            resolve("call mom ||| call dad\ncall mom ||| call mom\npay rent ||| pay rent\n"),
+            vec![(0, 1), (1, 2), (2, 0)]
+        );
+        assert_eq!(resolve("4 -> 3\n"), vec![(2, 2)]);
+        assert_eq!(
+            resolve("call mom ||| call dad\ncall mom ||| call dad\n4 -> 1\n"),
+            vec![(0, 1), (2, 0)]
+        );
+        assert_eq!(
+            check("call mom ||| call dad\ncall mom ||| call dad\n"),
+            "pairs.txt:2: no unpinned task ‘call dad’ in <AFTER>"
+        );
+        assert_eq!(
+            check("3 -> 1\n"),
+            "pairs.txt:1: no unpinned task on line 3 in <BEFORE>"
+        );
+        assert!(pins("4 -> 1\n").check(&from, &to).is_ok());
+    }
+}
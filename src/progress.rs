@@ -0,0 +1,126 @@
+// Reporting how far along a long diff is, for `--progress`. The matching calls the callback of
+// `DiffOptions::progress` as it goes, which does nothing unless one was set, and readers can be
+// wrapped in a `ProgressReader` to report the parsing of a file.
+
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::sync::Arc;
+
+// The expensive phases of a diff
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Phase {
+    // Reading and parsing the lists, in bytes
+    Parsing,
+    // Ranking the candidates of each task upfront, in tasks, see
+    // `Matcher::precompute_preference_lists`
+    Preferences,
+    // Matching each task in turn, in tasks
+    Proposals,
+}
+
+type Callback = dyn Fn(Phase, usize, usize) + Send + Sync;
+
+// A callback told the phase, how much of it is done and its total. It may be called from several
+// threads at once, so that the amounts of a phase do not always increase.
+#[derive(Clone, Default)]
+pub struct Progress(Option<Arc<Callback>>);
+
+impl Progress {
+    pub fn new<F: Fn(Phase, usize, usize) + Send + Sync + 'static>(callback: F) -> Progress {
+        Progress(Some(Arc::new(callback)))
+    }
+
+    pub fn report(&self, phase: Phase, done: usize, total: usize) {
+        if let Some(ref callback) = self.0 {
+            callback(phase, done, total);
+        }
+    }
+}
+
+// Progress does not change the result of a diff, so all callbacks are alike
+impl PartialEq for Progress {
+    fn eq(&self, _other: &Progress) -> bool {
+        true
+    }
+}
+
+impl Eq for Progress {}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "Progress(..)"
+        } else {
+            "Progress(None)"
+        })
+    }
+}
+
+// Reports the bytes read from `inner` as `Phase::Parsing`, starting from `offset` out of `total`
+// so that several files can be reported as one
+pub struct ProgressReader<R> {
+    inner: R,
+    done: usize,
+    total: usize,
+    progress: Progress,
+}
+
+impl<R: BufRead> ProgressReader<R> {
+    pub fn new(inner: R, offset: usize, total: usize, progress: Progress) -> ProgressReader<R> {
+        ProgressReader {
+            inner,
+            done: offset,
+            total,
+            progress,
+        }
+    }
+
+    fn advance(&mut self, amount: usize) {
+        self.done += amount;
+        self.progress.report(Phase::Parsing, self.done, self.total);
+    }
+}
+
+impl<R: BufRead> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let amount = self.inner.read(buf)?;
+        self.advance(amount);
+        Ok(amount)
+    }
+}
+
+impl<R: BufRead> BufRead for ProgressReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount);
+        self.advance(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::read_tasks;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_progress_reader() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let progress = {
+            let reports = reports.clone();
+            Progress::new(move |phase, done, total| {
+                reports.lock().unwrap().push((phase, done, total))
+            })
+        };
+        let input: &[u8] = b"do a thing\ncall mom\n";
+        let reader = ProgressReader::new(input, 100, 120, progress);
+        assert_eq!(read_tasks(reader, "todo.txt").unwrap().len(), 2);
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.last(), Some(&(Phase::Parsing, 120, 120)));
+        assert!(reports.iter().all(|(_, done, _)| *done > 100));
+        assert_eq!(Progress::default(), Progress::new(|_, _, _| ()));
+    }
+}
@@ -0,0 +1,77 @@
+// Dates are (de)serialized as ISO 8601 strings by chrono itself, but durations need some help
+
+// Durations of whole days, as their number of days
+pub mod duration_days {
+    use chrono::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(d: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(d.num_days())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        i64::deserialize(deserializer).map(Duration::days)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use compute_changes::*;
+    use merge_changes::*;
+    use serde_json;
+    use std::str::FromStr;
+    use todo_txt::task::Extended as Task;
+
+    fn tasks(lines: &[&str]) -> Vec<Task> {
+        lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_changeset_round_trip() {
+        let from = tasks(&[
+            "(A) 2018-06-01 pay the rent due:2018-06-01 rec:+1m",
+            "call mom +family",
+            "buy milk",
+        ]);
+        let to = tasks(&[
+            "x 2018-06-02 2018-06-01 pay the rent due:2018-06-01 rec:+1m",
+            "(A) 2018-06-01 pay the rent due:2018-07-01 rec:+1m",
+            "call mom +family @phone t:2018-06-05",
+            "water the plants",
+        ]);
+        let changeset = compute_changeset(from, to, 25);
+        let json = serde_json::to_string(&changeset).unwrap();
+        assert_eq!(
+            serde_json::from_str::<(Vec<Task>, Vec<ChangedTask<Vec<Changes>>>)>(&json).unwrap(),
+            changeset
+        );
+
+        let postponed = Changes::PostponedStrictBy(
+            chrono::Duration::days(30),
+            todo_txt::Date::from_ymd(2018, 6, 1),
+            todo_txt::Date::from_ymd(2018, 7, 1),
+        );
+        let json = serde_json::to_value(&postponed).unwrap();
+        assert_eq!(
+            json,
+            json!({ "PostponedStrictBy": [30, "2018-06-01", "2018-07-01"] })
+        );
+        assert_eq!(serde_json::from_value::<Changes>(json).unwrap(), postponed);
+    }
+
+    #[test]
+    fn test_merge_round_trip() {
+        let merge = merge_3way(
+            tasks(&["do a thing", "call the plumber"]),
+            tasks(&["x do a thing", "call the plumbers"]),
+            tasks(&["do a thing due:2018-06-01", "call a plumber"]),
+            30,
+        );
+        assert!(!merge_successful(&merge));
+        let json = serde_json::to_string(&merge).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<MergeResult<Task>>>(&json).unwrap(),
+            merge
+        );
+    }
+}
@@ -0,0 +1,704 @@
+use batch::PairDiff;
+use check::{Finding, Problem};
+use compute_changes::*;
+use serde_json::{Map, Value};
+use stats::Stats;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use subject_diff::{DiffOp, SubjectDiff, WordDiff};
+use todo_txt::task::Extended as Task;
+use todo_txt::task::Recurrence;
+use todo_txt::Date as TaskDate;
+
+// The JSON structure produced here is meant to be consumed by scripts, and is thus considered
+// stable: fields may be added, but existing fields will not be renamed or removed.
+
+fn date_json(d: &Option<TaskDate>) -> Value {
+    match d {
+        Some(d) => Value::String(d.format("%Y-%m-%d").to_string()),
+        None => Value::Null,
+    }
+}
+
+fn priority_json(p: &Option<char>) -> Value {
+    match p {
+        Some(p) => Value::String(p.to_string()),
+        None => Value::Null,
+    }
+}
+
+fn diff_op_json(op: &DiffOp) -> Value {
+    match op {
+        DiffOp::Equal(text) => json!({ "op": "equal", "text": text }),
+        DiffOp::Delete(text) => json!({ "op": "delete", "text": text }),
+        DiffOp::Insert(text) => json!({ "op": "insert", "text": text }),
+    }
+}
+
+fn recurrence_json(r: &Option<Recurrence>) -> Value {
+    match r {
+        Some(r) => Value::String(r.to_string()),
+        None => Value::Null,
+    }
+}
+
+fn tags_json(tags: &[(String, String)]) -> Value {
+    Value::Object(
+        tags.iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect::<Map<String, Value>>(),
+    )
+}
+
+fn change_json(c: &Changes) -> Value {
+    use self::Changes::*;
+    match c {
+        Created(d) => json!({ "type": "Created", "date": date_json(d) }),
+        RecurredStrict => json!({ "type": "RecurredStrict" }),
+        RecurredFrom(d) => json!({ "type": "RecurredFrom", "date": date_json(d) }),
+        SkippedOccurrences(n) => json!({ "type": "SkippedOccurrences", "count": n }),
+
+        FinishedAt(d) => json!({ "type": "FinishedAt", "date": date_json(&Some(*d)) }),
+        InferredFinishDate(d) => {
+            json!({ "type": "InferredFinishDate", "date": date_json(&Some(*d)) })
+        }
+        PostponedStrictBy(d, a, b) => json!({
+            "type": "PostponedStrictBy",
+            "days": d.num_days(),
+            "before": date_json(&Some(*a)),
+            "after": date_json(&Some(*b)),
+        }),
+        PostponedThresholdBy(d, a, b) => json!({
+            "type": "PostponedThresholdBy",
+            "days": d.num_days(),
+            "before": date_json(&Some(*a)),
+            "after": date_json(&Some(*b)),
+        }),
+
+        Finished(b) => json!({ "type": "Finished", "finished": b }),
+        Priority(a, b) => json!({
+            "type": "Priority",
+            "before": priority_json(a),
+            "after": priority_json(b),
+        }),
+        FinishDate(a, b) => json!({
+            "type": "FinishDate",
+            "before": date_json(a),
+            "after": date_json(b),
+        }),
+        CreateDate(a, b) => json!({
+            "type": "CreateDate",
+            "before": date_json(a),
+            "after": date_json(b),
+        }),
+        Subject(s) => json!({
+            "type": "Subject",
+            "before": s.before,
+            "after": s.after,
+            "diff": s.ops.iter().map(diff_op_json).collect::<Vec<_>>(),
+        }),
+        DueDate(a, b) => json!({
+            "type": "DueDate",
+            "before": date_json(a),
+            "after": date_json(b),
+        }),
+        ThresholdDate(a, b) => json!({
+            "type": "ThresholdDate",
+            "before": date_json(a),
+            "after": date_json(b),
+        }),
+        Tags(a, b) => json!({
+            "type": "Tags",
+            "removed": tags_json(a),
+            "added": tags_json(b),
+        }),
+        Recurrence(a, b) => json!({
+            "type": "Recurrence",
+            "before": recurrence_json(a),
+            "after": recurrence_json(b),
+        }),
+        Projects(a, b) => json!({ "type": "Projects", "removed": a, "added": b }),
+        Contexts(a, b) => json!({ "type": "Contexts", "removed": a, "added": b }),
+    }
+}
+
+fn changes_json(chgs: &[Changes]) -> Value {
+    Value::Array(chgs.iter().map(change_json).collect())
+}
+
+fn delta_json(delta: &TaskDelta<Vec<Changes>>) -> Value {
+    use self::TaskDelta::*;
+    match delta {
+        Identical => json!({ "type": "Identical" }),
+        Deleted => json!({ "type": "Deleted" }),
+        Changed(chgs) => json!({ "type": "Changed", "changes": changes_json(chgs) }),
+        Recurred(occurrences) => json!({
+            "type": "Recurred",
+            "occurrences": occurrences.iter().map(|c| changes_json(c)).collect::<Vec<_>>(),
+        }),
+        Split(fragments) => json!({
+            "type": "Split",
+            "fragments": fragments.iter().map(|c| changes_json(c)).collect::<Vec<_>>(),
+        }),
+        Copied(copies) => json!({
+            "type": "Copied",
+            "copies": copies.iter().map(|c| changes_json(c)).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+pub fn changeset_json(new_tasks: &[Task], changes: &[ChangedTask<Vec<Changes>>]) -> Value {
+    json!({
+        "new": new_tasks.iter().map(Task::to_string).collect::<Vec<_>>(),
+        "changes": changes
+            .iter()
+            .map(|x| {
+                json!({
+                    "orig": x.orig.to_string(),
+                    "delta": delta_json(&x.delta),
+                    "similarity": x.similarity,
+                })
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+pub fn stats_json(stats: &Stats) -> Value {
+    json!({
+        "completed": stats.completed,
+        "average_days_to_complete": stats.average_days_to_complete,
+        "postponed_days": stats.postponed_days,
+        "postponed_more_than_once": stats.postponed_more_than_once,
+        "completed_by_project": stats.completed_by_project,
+    })
+}
+
+// `labels` are the names of the compared lists, see `DisplayOptions::labels`
+pub fn serialize_changeset_with(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    labels: Option<(&str, &str)>,
+) -> String {
+    serialize_changeset_with_stats(new_tasks, changes, labels, None)
+}
+
+// Like `serialize_changeset_with`, with the `stats` of the changeset, see `compute_stats`
+pub fn serialize_changeset_with_stats(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+    labels: Option<(&str, &str)>,
+    stats: Option<&Stats>,
+) -> String {
+    let mut json = changeset_json(&new_tasks, &changes);
+    if let Some((before, after)) = labels {
+        json["labels"] = json!({ "before": before, "after": after });
+    }
+    if let Some(stats) = stats {
+        json["stats"] = stats_json(stats);
+    }
+    serde_json::to_string_pretty(&json).expect("Internal error E013")
+}
+
+pub fn serialize_changeset(
+    new_tasks: Vec<Task>,
+    changes: Vec<ChangedTask<Vec<Changes>>>,
+) -> String {
+    serialize_changeset_with(new_tasks, changes, None)
+}
+
+// The changesets of `diff_pairs` as an array, each with its `label` and the `labels` of its
+// files. Pairs whose files could not be read have an `error` instead of `new` and `changes`.
+pub fn serialize_batch(diffs: &[PairDiff]) -> String {
+    let diffs = diffs
+        .iter()
+        .map(|diff| {
+            let mut json = match &diff.changeset {
+                Ok((new_tasks, changes)) => changeset_json(new_tasks, changes),
+                Err(e) => json!({ "error": e.to_string() }),
+            };
+            json["label"] = json!(diff.pair.label());
+            json["labels"] = json!({ "before": diff.pair.before, "after": diff.pair.after });
+            json
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string_pretty(&diffs).expect("Internal error E048")
+}
+
+// Reading back changesets, eg. to apply them elsewhere. Errors tell what was expected where.
+
+fn field<'a>(json: &'a Value, name: &str) -> Result<&'a Value, String> {
+    json.get(name)
+        .ok_or_else(|| format!("missing field ‘{}’", name))
+}
+
+fn string_from_json(json: &Value) -> Result<&str, String> {
+    json.as_str()
+        .ok_or_else(|| format!("expected a string, found {}", json))
+}
+
+fn array_from_json(json: &Value) -> Result<&Vec<Value>, String> {
+    json.as_array()
+        .ok_or_else(|| format!("expected an array, found {}", json))
+}
+
+fn date_from_json(json: &Value) -> Result<Option<TaskDate>, String> {
+    match json {
+        Value::Null => Ok(None),
+        _ => {
+            let s = string_from_json(json)?;
+            TaskDate::parse_from_str(s, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|e| format!("invalid date ‘{}’: {}", s, e))
+        }
+    }
+}
+
+fn diff_op_from_json(json: &Value) -> Result<DiffOp, String> {
+    let text = string_from_json(field(json, "text")?)?.to_owned();
+    match string_from_json(field(json, "op")?)? {
+        "equal" => Ok(DiffOp::Equal(text)),
+        "delete" => Ok(DiffOp::Delete(text)),
+        "insert" => Ok(DiffOp::Insert(text)),
+        op => Err(format!("unknown diff operation ‘{}’", op)),
+    }
+}
+
+// The diff was added later on, and is computed again when missing
+fn subject_from_json(json: &Value) -> Result<SubjectDiff, String> {
+    let before = string_from_json(field(json, "before")?)?.to_owned();
+    let after = string_from_json(field(json, "after")?)?.to_owned();
+    Ok(match json.get("diff") {
+        Some(ops) => SubjectDiff {
+            before,
+            after,
+            ops: array_from_json(ops)?
+                .iter()
+                .map(diff_op_from_json)
+                .collect::<Result<_, _>>()?,
+        },
+        None => SubjectDiff::new(before, after, WordDiff::Word),
+    })
+}
+
+fn some_date_from_json(json: &Value) -> Result<TaskDate, String> {
+    date_from_json(json)?.ok_or_else(|| "expected a date, found null".to_owned())
+}
+
+fn priority_from_json(json: &Value) -> Result<Option<char>, String> {
+    match json {
+        Value::Null => Ok(None),
+        _ => {
+            let s = string_from_json(json)?;
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c @ 'A'..='Z'), None) => Ok(Some(c)),
+                _ => Err(format!("invalid priority ‘{}’", s)),
+            }
+        }
+    }
+}
+
+fn recurrence_from_json(json: &Value) -> Result<Option<Recurrence>, String> {
+    match json {
+        Value::Null => Ok(None),
+        _ => Recurrence::from_str(string_from_json(json)?)
+            .map(Some)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+// Changesets serialized before similarities were added have none
+fn similarity_from_json(json: Option<&Value>) -> Result<Option<u8>, String> {
+    match json {
+        None | Some(Value::Null) => Ok(None),
+        Some(json) => match json.as_u64() {
+            Some(s) if s <= 100 => Ok(Some(s as u8)),
+            _ => Err(format!("expected a percentage, found {}", json)),
+        },
+    }
+}
+
+fn tags_from_json(json: &Value) -> Result<Vec<(String, String)>, String> {
+    json.as_object()
+        .ok_or_else(|| format!("expected an object, found {}", json))?
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), string_from_json(v)?.to_owned())))
+        .collect()
+}
+
+fn strings_from_json(json: &Value) -> Result<Vec<String>, String> {
+    array_from_json(json)?
+        .iter()
+        .map(|v| string_from_json(v).map(str::to_owned))
+        .collect()
+}
+
+fn task_from_json(json: &Value) -> Result<Task, String> {
+    let s = string_from_json(json)?;
+    Task::from_str(s).map_err(|e| format!("invalid task ‘{}’: {}", s, e))
+}
+
+fn change_from_json(json: &Value) -> Result<Changes, String> {
+    use self::Changes::*;
+    let before = || field(json, "before");
+    let after = || field(json, "after");
+    Ok(match string_from_json(field(json, "type")?)? {
+        "Created" => Created(date_from_json(field(json, "date")?)?),
+        "RecurredStrict" => RecurredStrict,
+        "RecurredFrom" => RecurredFrom(date_from_json(field(json, "date")?)?),
+        "SkippedOccurrences" => {
+            let count = field(json, "count")?;
+            SkippedOccurrences(
+                count
+                    .as_u64()
+                    .and_then(|n| u32::try_from(n).ok())
+                    .ok_or_else(|| format!("expected a count, found {}", count))?,
+            )
+        }
+
+        "FinishedAt" => FinishedAt(some_date_from_json(field(json, "date")?)?),
+        "InferredFinishDate" => InferredFinishDate(some_date_from_json(field(json, "date")?)?),
+        "PostponedStrictBy" => PostponedStrictBy(
+            chrono::Duration::days(
+                field(json, "days")?
+                    .as_i64()
+                    .ok_or_else(|| "expected a number of days".to_owned())?,
+            ),
+            some_date_from_json(before()?)?,
+            some_date_from_json(after()?)?,
+        ),
+        "PostponedThresholdBy" => PostponedThresholdBy(
+            chrono::Duration::days(
+                field(json, "days")?
+                    .as_i64()
+                    .ok_or_else(|| "expected a number of days".to_owned())?,
+            ),
+            some_date_from_json(before()?)?,
+            some_date_from_json(after()?)?,
+        ),
+
+        "Finished" => Finished(
+            field(json, "finished")?
+                .as_bool()
+                .ok_or_else(|| "expected a boolean".to_owned())?,
+        ),
+        "Priority" => Priority(
+            priority_from_json(before()?)?,
+            priority_from_json(after()?)?,
+        ),
+        "FinishDate" => FinishDate(date_from_json(before()?)?, date_from_json(after()?)?),
+        "CreateDate" => CreateDate(date_from_json(before()?)?, date_from_json(after()?)?),
+        "Subject" => Subject(subject_from_json(json)?),
+        "DueDate" => DueDate(date_from_json(before()?)?, date_from_json(after()?)?),
+        "ThresholdDate" => ThresholdDate(date_from_json(before()?)?, date_from_json(after()?)?),
+        "Tags" => Tags(
+            tags_from_json(field(json, "removed")?)?,
+            tags_from_json(field(json, "added")?)?,
+        ),
+        "Recurrence" => Recurrence(
+            recurrence_from_json(before()?)?,
+            recurrence_from_json(after()?)?,
+        ),
+        "Projects" => Projects(
+            strings_from_json(field(json, "removed")?)?,
+            strings_from_json(field(json, "added")?)?,
+        ),
+        "Contexts" => Contexts(
+            strings_from_json(field(json, "removed")?)?,
+            strings_from_json(field(json, "added")?)?,
+        ),
+        t => return Err(format!("unknown change type ‘{}’", t)),
+    })
+}
+
+fn changes_from_json(json: &Value) -> Result<Vec<Changes>, String> {
+    array_from_json(json)?
+        .iter()
+        .map(change_from_json)
+        .collect()
+}
+
+fn delta_from_json(json: &Value) -> Result<TaskDelta<Vec<Changes>>, String> {
+    use self::TaskDelta::*;
+    let changes_list = |name| {
+        let list = array_from_json(field(json, name)?)?;
+        if list.is_empty() {
+            return Err(format!("expected at least one element in ‘{}’", name));
+        }
+        list.iter()
+            .map(changes_from_json)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    Ok(match string_from_json(field(json, "type")?)? {
+        "Identical" => Identical,
+        "Deleted" => Deleted,
+        "Changed" => Changed(changes_from_json(field(json, "changes")?)?),
+        "Recurred" => Recurred(changes_list("occurrences")?),
+        "Split" => Split(changes_list("fragments")?),
+        "Copied" => Copied(changes_list("copies")?),
+        t => return Err(format!("unknown delta type ‘{}’", t)),
+    })
+}
+
+fn changed_task_from_json(json: &Value) -> Result<ChangedTask<Vec<Changes>>, String> {
+    let orig = task_from_json(field(json, "orig")?)?;
+    let delta = delta_from_json(field(json, "delta")?)?;
+    Ok(ChangedTask {
+        after: apply_delta(&orig, &delta).map_err(|e| e.to_string())?,
+        orig,
+        delta,
+        orig_line: None,
+        after_lines: Vec::new(),
+        similarity: similarity_from_json(json.get("similarity"))?,
+    })
+}
+
+// Reads back a changeset serialized by `serialize_changeset`. The tasks each change led to are
+// recomputed with `apply_delta`, and line numbers are lost.
+pub fn deserialize_changeset(json: &str) -> Result<Changeset, String> {
+    let json = serde_json::from_str::<Value>(json).map_err(|e| e.to_string())?;
+    let new_tasks = array_from_json(field(&json, "new")?)?
+        .iter()
+        .map(task_from_json)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("new: {}", e))?;
+    let changes = array_from_json(field(&json, "changes")?)?
+        .iter()
+        .enumerate()
+        .map(|(i, c)| changed_task_from_json(c).map_err(|e| format!("changes[{}]: {}", i, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((new_tasks, changes))
+}
+
+fn problem_json(p: &Problem) -> Value {
+    use check::Problem::*;
+    match p {
+        Unparseable => json!({ "type": "Unparseable" }),
+        MissingFinishDate => json!({ "type": "MissingFinishDate" }),
+        FinishedBeforeCreated(finish, create) => json!({
+            "type": "FinishedBeforeCreated",
+            "finish_date": date_json(&Some(*finish)),
+            "create_date": date_json(&Some(*create)),
+        }),
+        InvalidRecurrence(v) => json!({ "type": "InvalidRecurrence", "value": v }),
+        InvalidDueDate(v) => json!({ "type": "InvalidDueDate", "value": v }),
+        Duplicate(line) => json!({ "type": "Duplicate", "of_line": line }),
+    }
+}
+
+// The findings of `check_tasks` on the file at `path`, each with a human-readable `message`
+pub fn serialize_findings(path: &str, findings: &[Finding]) -> String {
+    let findings = findings
+        .iter()
+        .map(|f| {
+            let mut json = problem_json(&f.problem);
+            json["line_number"] = json!(f.line_number);
+            json["message"] = json!(f.problem.to_string());
+            json
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string_pretty(&json!({ "path": path, "findings": findings }))
+        .expect("Internal error E044")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_change_json() {
+        let date = TaskDate::from_str("2018-06-15").unwrap();
+        assert_eq!(
+            change_json(&Changes::PostponedStrictBy(
+                Duration::days(3),
+                date,
+                date + Duration::days(3)
+            )),
+            json!({
+                "type": "PostponedStrictBy",
+                "days": 3,
+                "before": "2018-06-15",
+                "after": "2018-06-18",
+            })
+        );
+        assert_eq!(
+            change_json(&Changes::DueDate(None, Some(date))),
+            json!({ "type": "DueDate", "before": null, "after": "2018-06-15" })
+        );
+        assert_eq!(
+            change_json(&Changes::Priority(Some('C'), None)),
+            json!({ "type": "Priority", "before": "C", "after": null })
+        );
+        assert_eq!(
+            change_json(&Changes::Tags(
+                vec![("a".to_owned(), "1".to_owned())],
+                vec![("b".to_owned(), "2".to_owned())]
+            )),
+            json!({ "type": "Tags", "removed": { "a": "1" }, "added": { "b": "2" } })
+        );
+        let subject = Changes::Subject(SubjectDiff::new(
+            "call mom".to_owned(),
+            "call dad".to_owned(),
+            WordDiff::Word,
+        ));
+        let json = change_json(&subject);
+        assert_eq!(
+            json,
+            json!({
+                "type": "Subject",
+                "before": "call mom",
+                "after": "call dad",
+                "diff": [
+                    { "op": "equal", "text": "call " },
+                    { "op": "delete", "text": "mom" },
+                    { "op": "insert", "text": "dad" },
+                ],
+            })
+        );
+        assert_eq!(change_from_json(&json), Ok(subject.clone()));
+        // Reports written before subject diffs were serialized still load
+        let legacy = json!({ "type": "Subject", "before": "call mom", "after": "call dad" });
+        assert_eq!(change_from_json(&legacy), Ok(subject));
+    }
+
+    #[test]
+    fn test_changeset_json() {
+        let from = vec![
+            Task::from_str("do a thing").unwrap(),
+            Task::from_str("do another thing").unwrap(),
+        ];
+        let to = vec![
+            Task::from_str("x do a thing").unwrap(),
+            Task::from_str("something new").unwrap(),
+        ];
+        let (new_tasks, changes) = compute_changeset(from, to, 0);
+        assert_eq!(
+            changeset_json(&new_tasks, &changes),
+            json!({
+                "new": ["something new"],
+                "changes": [
+                    {
+                        "orig": "do a thing",
+                        "delta": {
+                            "type": "Changed",
+                            "changes": [{ "type": "Finished", "finished": true }],
+                        },
+                        "similarity": 100,
+                    },
+                    {
+                        "orig": "do another thing",
+                        "delta": { "type": "Deleted" },
+                        "similarity": null,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_serialize_changeset_labels() {
+        let from = vec![Task::from_str("do a thing").unwrap()];
+        let json = |labels| -> Value {
+            let (new_tasks, changes) = compute_changeset(from.clone(), from.clone(), 0);
+            serde_json::from_str(&serialize_changeset_with(new_tasks, changes, labels)).unwrap()
+        };
+        assert_eq!(
+            json(Some(("old.txt", "todo.txt")))["labels"],
+            json!({ "before": "old.txt", "after": "todo.txt" })
+        );
+        assert_eq!(json(None).get("labels"), None);
+    }
+
+    #[test]
+    fn test_deserialize_changeset() {
+        let tasks = |lines: &[&str]| -> Vec<Task> {
+            lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+        };
+        let from = tasks(&[
+            "(A) 2018-06-01 pay the rent due:2018-06-01 t:2018-05-25 rec:+1m",
+            "call mom +family who:me",
+            "buy milk",
+            "water the plants",
+        ]);
+        let to = tasks(&[
+            "x 2018-06-02 2018-06-01 pay the rent due:2018-06-01 t:2018-05-25 rec:+1m",
+            "(A) 2018-06-02 pay the rent due:2018-07-01 t:2018-06-25 rec:+1m",
+            "(B) call dad @phone who:you",
+            "water the plants",
+            "something new",
+        ]);
+        let changeset = compute_changeset(from, to, 50);
+        let json = serialize_changeset(changeset.0.clone(), changeset.1.clone());
+        assert_eq!(deserialize_changeset(&json), Ok(changeset));
+
+        let error = |json| deserialize_changeset(json).unwrap_err();
+        assert_eq!(error(r#"{ "new": [] }"#), "missing field ‘changes’");
+        assert_eq!(
+            error(r#"{ "new": [], "changes": [{ "orig": "a", "delta": { "type": "Moved" } }] }"#),
+            "changes[0]: unknown delta type ‘Moved’"
+        );
+        assert_eq!(
+            error(
+                r#"{ "new": [], "changes": [{ "orig": "a", "delta": {
+                    "type": "Changed",
+                    "changes": [{ "type": "DueDate", "before": null, "after": "soon" }]
+                } }] }"#
+            ),
+            "changes[0]: invalid date ‘soon’: input contains invalid characters"
+        );
+        assert_eq!(
+            error(
+                r#"{ "new": [], "changes": [
+                    { "orig": "a", "delta": { "type": "Deleted" }, "similarity": 101 }
+                ] }"#
+            ),
+            "changes[0]: expected a percentage, found 101"
+        );
+        assert_eq!(
+            error(
+                r#"{ "new": [], "changes": [{ "orig": "a", "delta": {
+                    "type": "Changed",
+                    "changes": [{ "type": "SkippedOccurrences", "count": 4294967296 }]
+                } }] }"#
+            ),
+            "changes[0]: expected a count, found 4294967296"
+        );
+    }
+
+    #[test]
+    fn test_serialize_findings() {
+        let findings = vec![
+            Finding {
+                line_number: 2,
+                problem: Problem::InvalidRecurrence("weekly".to_owned()),
+            },
+            Finding {
+                line_number: 3,
+                problem: Problem::Duplicate(1),
+            },
+        ];
+        let json: Value = serde_json::from_str(&serialize_findings("todo.txt", &findings)).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "path": "todo.txt",
+                "findings": [
+                    {
+                        "type": "InvalidRecurrence",
+                        "value": "weekly",
+                        "line_number": 2,
+                        "message": "invalid recurrence ‘rec:weekly’, ignored",
+                    },
+                    {
+                        "type": "Duplicate",
+                        "of_line": 1,
+                        "line_number": 3,
+                        "message": "duplicate of the task on line 1",
+                    },
+                ],
+            })
+        );
+    }
+}
@@ -1,135 +1,284 @@
 use itertools::Itertools;
+use progress::Phase;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 use std;
 use std::cmp::Ordering;
-
+use std::collections::HashMap;
+#[cfg(feature = "rayon")]
+use std::sync::atomic::{self, AtomicUsize};
+
+// Items and matchers must be shareable across threads when preference lists are computed in
+// parallel, and this bound is a no-op otherwise
+#[cfg(feature = "rayon")]
+pub trait MaybeSync: Sync {}
+#[cfg(feature = "rayon")]
+impl<T: Sync + ?Sized> MaybeSync for T {}
+#[cfg(not(feature = "rayon"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "rayon"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+/// How the individuals of one list rank the individuals of the other list, for `stable_marriage`.
+///
+/// Implementors only say which pairs may be matched at all and how good each of them is: the
+/// preference lists are built from the scores by `stable_marriage` itself.
+///
+/// # Example
+///
+/// Matching words with the words of the other list sharing their first letter, the longest being
+/// preferred:
+///
+/// ```
+/// use todiff::stable_marriage::{stable_marriage, Matcher};
+///
+/// struct FirstLetter;
+///
+/// impl Matcher for FirstLetter {
+///     type Item = &'static str;
+///     type Target = &'static str;
+///
+///     fn is_admissible(&self, x: &Self::Item, y: &Self::Target) -> bool {
+///         x.chars().next() == y.chars().next()
+///     }
+///
+///     fn score(&self, _x: &Self::Item, y: &Self::Target) -> Option<u32> {
+///         Some(y.len() as u32)
+///     }
+///
+///     fn is_perfect_match(&self, x: &Self::Item, y: &Self::Target) -> bool {
+///         x == y
+///     }
+/// }
+///
+/// let (matches, unmatched) = stable_marriage(
+///     vec!["apple", "banana", "cherry"],
+///     vec!["avocado", "blueberry", "bean"],
+///     &FirstLetter,
+///     &FirstLetter,
+/// );
+/// assert_eq!(
+///     matches,
+///     vec![
+///         ("avocado", Some("apple")),
+///         ("blueberry", Some("banana")),
+///         ("bean", None),
+///     ]
+/// );
+/// assert_eq!(unmatched, vec!["cherry"]);
+/// ```
 pub trait Matcher {
     type Item;
     type Target;
 
-    fn is_admissible(&self, x: &Self::Item, y: &Self::Target) -> bool;
+    /// Whether `x` may be matched with `y` at all. Defaults to `y` having a score for `x`.
+    fn is_admissible(&self, x: &Self::Item, y: &Self::Target) -> bool {
+        self.score(x, y).is_some()
+    }
 
-    fn cmp_3way(&self, from: &Self::Item, left: &Self::Target, right: &Self::Target) -> Ordering;
+    /// How much `x` wants to be matched with `y`, higher being better. Targets without a score are
+    /// ranked after all the others.
+    fn score(&self, x: &Self::Item, y: &Self::Target) -> Option<u32>;
 
-    fn compute_preference_list<Q>(
-        &self,
-        item: &Self::Item,
-        targets: &Vec<Woman<Self>>,
-        other_matcher: &Q,
-    ) -> Vec<usize>
-    where
-        Q: Matcher<Item = Self::Target, Target = Self::Item>,
-    {
-        let mut admissibles = targets
-            .iter()
-            .enumerate()
-            .filter(|(_, x)| x.prefers_to_current(other_matcher, item))
-            .map(|(i, x)| (i, &x.data))
-            .filter(|(_, x)| self.is_admissible(item, x))
-            .collect::<Vec<_>>();
-
-        admissibles.sort_unstable_by(|(i, left), (j, right)| {
-            self.cmp_3way(item, left, right).then(i.cmp(&j)).reverse()
-        });
+    /// Whether `x` and `y` are made for each other, so that `x` is matched with `y` without
+    /// considering any other target, provided `y` is not matched yet.
+    ///
+    /// If a good number of individuals have a perfect match, quadratic behaviour is strongly
+    /// reduced.
+    fn is_perfect_match(&self, _x: &Self::Item, _y: &Self::Target) -> bool {
+        false
+    }
 
-        admissibles.into_iter().map(|(i, _)| i).collect::<Vec<_>>()
+    /// Whether `from` prefers `left` (`Less`) or `right` (`Greater`), derived from the scores.
+    /// Overriding it allows for finer comparisons than the scores.
+    fn cmp_3way(&self, from: &Self::Item, left: &Self::Target, right: &Self::Target) -> Ordering {
+        self.score(from, right).cmp(&self.score(from, left))
     }
 
-    // Captures a notion of a couple being made for each other.
-    // If a good number of matchings have a perfect match, quadratic behaviour is strongly reduced.
-    fn is_perfect_match(&self, _x: &Self::Item, _y: &Self::Target) -> bool {
+    /// Whether preference lists may be computed upfront on all cores, which requires the `rayon`
+    /// feature. This does not change the resulting matching.
+    fn precompute_preference_lists(&self) -> bool {
         false
     }
 
-    // Looks through currently unengaged women for a potential perfect match.
-    fn find_perfect_match<'a>(
-        &self,
-        item: &Self::Item,
-        targets: &'a mut Vec<Woman<Self>>,
-    ) -> Option<&'a mut Woman<Self>> {
-        targets
-            .iter_mut()
-            .filter(|x| x.current_match.is_none())
-            .find(|x| self.is_perfect_match(item, &x.data))
+    /// Whether `cmp_3way` is a total preorder, so that an individual can rank all its admissible
+    /// targets once and for all, rather than comparing them again on each proposal. This does not
+    /// change the resulting matching.
+    fn supports_ranking(&self) -> bool {
+        false
     }
+
+    /// Called as `stable_marriage` goes through the men, with the number of men done so far in
+    /// `Phase::Preferences` or `Phase::Proposals` and the number of men. Does nothing by default.
+    fn progress(&self, _phase: Phase, _done: usize, _total: usize) {}
 }
 
-struct Man<P: Matcher + ?Sized> {
-    data: P::Item,
+struct Man {
+    // Index in the list of men
+    idx: usize,
     // Most preferred last
     prefs: Vec<usize>,
 }
 
-impl<P: Matcher + ?Sized> Man<P> {}
-
-pub struct Woman<P: Matcher + ?Sized> {
+struct Woman<P: Matcher + ?Sized> {
     data: P::Target,
-    current_match: Option<Man<P>>,
+    current_match: Option<Man>,
     current_is_perfect: bool,
+    // The rank of each admissible man, lower being preferred, once she received a proposal
+    ranks: Option<HashMap<usize, usize>>,
 }
 
 impl<P: Matcher + ?Sized> Woman<P> {
-    fn prefers_to_current<Q>(&self, matcher: &Q, item: &P::Item) -> bool
+    fn prefers_to_current<Q>(&self, matcher: &Q, men: &[P::Item], man: usize) -> bool
     where
         Q: Matcher<Item = P::Target, Target = P::Item>,
     {
-        if self.current_is_perfect || !matcher.is_admissible(&self.data, item) {
+        if self.current_is_perfect {
+            return false;
+        }
+        if let Some(ref ranks) = self.ranks {
+            return match (ranks.get(&man), &self.current_match) {
+                (None, _) => false,
+                (Some(_), None) => true,
+                (Some(rank), Some(cnt_man)) => ranks.get(&cnt_man.idx).is_none_or(|r| r > rank),
+            };
+        }
+        if !matcher.is_admissible(&self.data, &men[man]) {
             return false;
         }
         if let Some(ref cnt_man) = self.current_match {
-            matcher.cmp_3way(&self.data, &cnt_man.data, item) == Ordering::Greater
+            matcher.cmp_3way(&self.data, &men[cnt_man.idx], &men[man]) == Ordering::Greater
         } else {
             true
         }
     }
 
-    fn replace_match(&mut self, man: Man<P>) -> Option<Man<P>> {
+    fn compute_ranks<Q>(&mut self, matcher: &Q, men: &[P::Item])
+    where
+        Q: Matcher<Item = P::Target, Target = P::Item>,
+    {
+        let mut admissibles = men
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| matcher.is_admissible(&self.data, x))
+            .collect::<Vec<_>>();
+        admissibles.sort_by(|(_, left), (_, right)| matcher.cmp_3way(&self.data, left, right));
+        let mut ranks = HashMap::with_capacity(admissibles.len());
+        let mut rank = 0;
+        for (n, (i, x)) in admissibles.iter().enumerate() {
+            // Equally preferred men share the same rank
+            if n > 0 && matcher.cmp_3way(&self.data, admissibles[n - 1].1, x) != Ordering::Equal {
+                rank = n;
+            }
+            ranks.insert(*i, rank);
+        }
+        self.ranks = Some(ranks);
+    }
+
+    fn replace_match(&mut self, man: Man) -> Option<Man> {
         let mut old_match = Some(man);
         std::mem::swap(&mut self.current_match, &mut old_match);
         old_match
     }
 }
 
-// Computes a stable matching between two lists of individuals.
-// See https://en.wikipedia.org/wiki/Stable_marriage_problem
-// This implements an extended version of the Gale-Shapley algorithm that allows for some
-// individuals to not rank every individual from the other list, in which case those two
-// individuals will never be matched together. In particular, the lists need not be the same size.
-// This algorithm favors men.
-// Returns matchings from the women's perspective, and unmatched men.
-// The order of women is preserved from the input list.
-pub fn stable_marriage<M, W, P: Matcher<Item = M, Target = W>, Q: Matcher<Item = W, Target = M>>(
+// The targets `man` wants to propose to, most preferred last. `man` is his index in `men`.
+fn compute_preference_list<P, Q>(
+    matcher: &P,
+    men: &[P::Item],
+    man: usize,
+    targets: &[Woman<P>],
+    other_matcher: &Q,
+) -> Vec<usize>
+where
+    P: Matcher,
+    Q: Matcher<Item = P::Target, Target = P::Item>,
+{
+    let item = &men[man];
+    let mut admissibles = targets
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.prefers_to_current(other_matcher, men, man))
+        .map(|(i, x)| (i, &x.data))
+        .filter(|(_, x)| matcher.is_admissible(item, x))
+        .collect::<Vec<_>>();
+
+    admissibles.sort_unstable_by(|(i, left), (j, right)| {
+        matcher.cmp_3way(item, left, right).then(i.cmp(j)).reverse()
+    });
+
+    admissibles.into_iter().map(|(i, _)| i).collect::<Vec<_>>()
+}
+
+// Looks through currently unengaged women for a potential perfect match.
+fn find_perfect_match<'a, P: Matcher>(
+    matcher: &P,
+    item: &P::Item,
+    targets: &'a mut [Woman<P>],
+) -> Option<&'a mut Woman<P>> {
+    targets
+        .iter_mut()
+        .filter(|x| x.current_match.is_none())
+        .find(|x| matcher.is_perfect_match(item, &x.data))
+}
+
+/// Computes a stable matching between two lists of individuals.
+/// See <https://en.wikipedia.org/wiki/Stable_marriage_problem>
+///
+/// This implements an extended version of the Gale-Shapley algorithm that allows for some
+/// individuals to not rank every individual from the other list, in which case those two
+/// individuals will never be matched together. In particular, the lists need not be the same size.
+/// This algorithm favors men.
+///
+/// Returns matchings from the women's perspective, and unmatched men.
+/// The order of women is preserved from the input list.
+pub fn stable_marriage<M, W, P, Q>(
     men: Vec<M>,
     women: Vec<W>,
     men_matcher: &P,
     women_matcher: &Q,
-) -> (Vec<(W, Option<M>)>, Vec<M>) {
+) -> (Vec<(W, Option<M>)>, Vec<M>)
+where
+    M: MaybeSync,
+    W: MaybeSync,
+    P: Matcher<Item = M, Target = W> + MaybeSync,
+    Q: Matcher<Item = W, Target = M> + MaybeSync,
+{
     let mut women = women
         .into_iter()
         .map(|item| Woman {
             data: item,
             current_match: None,
             current_is_perfect: false,
+            ranks: None,
         })
         .collect::<Vec<Woman<P>>>();
 
+    let mut precomputed_prefs =
+        precompute_preference_lists(&men, &women, men_matcher, women_matcher);
+
     let mut no_longer_engageables = Vec::new();
-    'outer_loop: for item in men {
-        let mut man = Man {
-            data: item,
-            prefs: vec![],
-        };
+    'outer_loop: for idx in 0..men.len() {
+        men_matcher.progress(Phase::Proposals, idx, men.len());
+        let mut man = Man { idx, prefs: vec![] };
 
-        if let Some(woman) = men_matcher.find_perfect_match(&man.data, &mut women) {
+        if let Some(woman) = find_perfect_match(men_matcher, &men[idx], &mut women) {
             woman.current_is_perfect = true;
             woman.replace_match(man);
             continue;
         }
-        man.prefs = men_matcher.compute_preference_list(&man.data, &women, women_matcher);
+        man.prefs = match precomputed_prefs.get_mut(idx).and_then(Option::take) {
+            Some(prefs) => prefs,
+            None => compute_preference_list(men_matcher, &men, idx, &women, women_matcher),
+        };
 
         // Loop while the man we hold is still engageable
         while let Some(i) = man.prefs.pop() {
             let woman = &mut women[i];
-            if woman.prefers_to_current(women_matcher, &man.data) {
+            if woman.ranks.is_none() && women_matcher.supports_ranking() {
+                woman.compute_ranks(women_matcher, &men);
+            }
+            if woman.prefers_to_current(women_matcher, &men, man.idx) {
                 if let Some(rejected_man) = woman.replace_match(man) {
                     man = rejected_man;
                 } else {
@@ -141,56 +290,205 @@ pub fn stable_marriage<M, W, P: Matcher<Item = M, Target = W>, Q: Matcher<Item =
         // `man` has no remaining women he wants to propose to
         no_longer_engageables.push(man);
     }
+    men_matcher.progress(Phase::Proposals, men.len(), men.len());
 
+    let mut men = men.into_iter().map(Some).collect_vec();
+    let mut take = |man: Man| men[man.idx].take().expect("Internal error E033");
     (
         women
             .into_iter()
-            .map(|x| (x.data, x.current_match.map(|man| man.data)))
-            .collect_vec(),
-        no_longer_engageables
-            .into_iter()
-            .map(|man| man.data)
+            .map(|x| (x.data, x.current_match.map(&mut take)))
             .collect_vec(),
+        no_longer_engageables.into_iter().map(take).collect_vec(),
     )
 }
 
+/// Why a matching is not stable, as found by `verify_stable`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Instability {
+    /// The man is matched to several women.
+    SeveralMatches { man: usize },
+    /// The matched man and woman do not both find each other admissible.
+    Inadmissible { man: usize, woman: usize },
+    /// The man and woman are mutually admissible, and each would rather be with the other than
+    /// with their current match (if any).
+    BlockingPair { man: usize, woman: usize },
+}
+
+impl std::fmt::Display for Instability {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Instability::SeveralMatches { man } => {
+                write!(f, "man {} is matched to several women", man)
+            }
+            Instability::Inadmissible { man, woman } => {
+                write!(
+                    f,
+                    "man {} and woman {} are not mutually admissible",
+                    man, woman
+                )
+            }
+            Instability::BlockingPair { man, woman } => write!(
+                f,
+                "man {} and woman {} prefer each other to their matches",
+                man, woman
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Instability {}
+
+/// Checks that a matching, as returned by `stable_marriage`, is stable, ie. that no man and woman
+/// would both rather be together.
+///
+/// `matches` gives for each woman the index of her match in `men`, and must thus be as long as
+/// `women`.
+pub fn verify_stable<M, W, P, Q>(
+    matches: &[Option<usize>],
+    men: &[M],
+    women: &[W],
+    men_matcher: &P,
+    women_matcher: &Q,
+) -> Result<(), Instability>
+where
+    P: Matcher<Item = M, Target = W>,
+    Q: Matcher<Item = W, Target = M>,
+{
+    assert_eq!(matches.len(), women.len(), "one match is needed per woman");
+    let mut men_matches = vec![None; men.len()];
+    for (woman, man) in matches.iter().enumerate() {
+        if let Some(man) = *man {
+            if men_matches[man].is_some() {
+                return Err(Instability::SeveralMatches { man });
+            }
+            if !men_matcher.is_admissible(&men[man], &women[woman])
+                || !women_matcher.is_admissible(&women[woman], &men[man])
+            {
+                return Err(Instability::Inadmissible { man, woman });
+            }
+            men_matches[man] = Some(woman);
+        }
+    }
+    for (man, man_match) in men_matches.iter().enumerate() {
+        for (woman, woman_match) in matches.iter().enumerate() {
+            if *man_match == Some(woman)
+                || !men_matcher.is_admissible(&men[man], &women[woman])
+                || !women_matcher.is_admissible(&women[woman], &men[man])
+            {
+                continue;
+            }
+            let man_prefers = man_match.is_none_or(|cur| {
+                men_matcher.cmp_3way(&men[man], &women[woman], &women[cur]) == Ordering::Less
+            });
+            let woman_prefers = woman_match.is_none_or(|cur| {
+                women_matcher.cmp_3way(&women[woman], &men[man], &men[cur]) == Ordering::Less
+            });
+            if man_prefers && woman_prefers {
+                return Err(Instability::BlockingPair { man, woman });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Women only ever trade up, so a woman who does not want a man when no one is engaged yet never
+// will. Hence, the lists computed upfront only differ from the ones computed by the proposal loop
+// by women that reject the man anyway, and the resulting matching is the same.
+// Men who may have a perfect match are left to the proposal loop, as they likely need no list.
+#[cfg(feature = "rayon")]
+fn precompute_preference_lists<M, W, P, Q>(
+    men: &[M],
+    women: &[Woman<P>],
+    men_matcher: &P,
+    women_matcher: &Q,
+) -> Vec<Option<Vec<usize>>>
+where
+    M: MaybeSync,
+    W: MaybeSync,
+    P: Matcher<Item = M, Target = W> + MaybeSync,
+    Q: Matcher<Item = W, Target = M> + MaybeSync,
+{
+    if !men_matcher.precompute_preference_lists() {
+        return Vec::new();
+    }
+    let done = AtomicUsize::new(0);
+    (0..men.len())
+        .into_par_iter()
+        .map(|man| {
+            let prefs = if women
+                .iter()
+                .any(|w| men_matcher.is_perfect_match(&men[man], &w.data))
+            {
+                None
+            } else {
+                Some(compute_preference_list(
+                    men_matcher,
+                    men,
+                    man,
+                    women,
+                    women_matcher,
+                ))
+            };
+            let done = done.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+            men_matcher.progress(Phase::Preferences, done, men.len());
+            prefs
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn precompute_preference_lists<M, W, P, Q>(
+    _men: &[M],
+    _women: &[Woman<P>],
+    _men_matcher: &P,
+    _women_matcher: &Q,
+) -> Vec<Option<Vec<usize>>>
+where
+    P: Matcher<Item = M, Target = W>,
+    Q: Matcher<Item = W, Target = M>,
+{
+    Vec::new()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use itertools::Itertools;
+    use proptest::prelude::*;
+    use synthetic::Generator;
 
     struct IndexMatcher(Vec<Vec<usize>>);
 
-    impl Matcher for IndexMatcher {
+    // Like IndexMatcher, but letting women rank their admissible men upfront
+    struct RankingIndexMatcher(IndexMatcher);
+
+    impl Matcher for RankingIndexMatcher {
         type Item = usize;
         type Target = usize;
 
         fn is_admissible(&self, x: &Self::Item, y: &Self::Target) -> bool {
-            self.0[*x].iter().position(|j| j == y).is_some()
+            self.0.is_admissible(x, y)
         }
 
-        fn cmp_3way(
-            &self,
-            from: &Self::Item,
-            left: &Self::Target,
-            right: &Self::Target,
-        ) -> Ordering {
-            let pos_left = self.0[*from].iter().position(|j| j == left);
-            let pos_right = self.0[*from].iter().position(|j| j == right);
-            pos_left.cmp(&pos_right)
+        fn score(&self, x: &Self::Item, y: &Self::Target) -> Option<u32> {
+            self.0.score(x, y)
         }
 
-        fn compute_preference_list<Q>(
-            &self,
-            item: &Self::Item,
-            _targets: &Vec<Woman<Self>>,
-            _other_matcher: &Q,
-        ) -> Vec<usize>
-        where
-            Q: Matcher<Item = Self::Target, Target = Self::Item>,
-        {
-            // Cheat, because we know which targets will be used
-            self.0[*item].iter().cloned().rev().collect_vec()
+        fn supports_ranking(&self) -> bool {
+            true
+        }
+    }
+
+    impl Matcher for IndexMatcher {
+        type Item = usize;
+        type Target = usize;
+
+        // The earlier in the list, the higher the score
+        fn score(&self, x: &Self::Item, y: &Self::Target) -> Option<u32> {
+            let prefs = &self.0[*x];
+            let pos = prefs.iter().position(|j| j == y)?;
+            Some((prefs.len() - pos) as u32)
         }
     }
 
@@ -281,4 +579,160 @@ mod tests {
 
         test_case(men, women, expected_men, expected_women);
     }
+
+    // Random preference lists of men and women, where everyone finds only some of the other list
+    // admissible, and women mostly rank the men who find them admissible
+    fn random_preference_lists(len: usize, prefs: usize) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+        let mut generator = Generator::new(42);
+        let men = (0..len)
+            .map(|_| {
+                (0..prefs)
+                    .map(|_| generator.below(len))
+                    .unique()
+                    .collect_vec()
+            })
+            .collect_vec();
+        let mut women = vec![vec![]; len];
+        for (i, man) in men.iter().enumerate() {
+            for &j in man {
+                if generator.below(10) > 0 {
+                    women[j].push((generator.below(len), i));
+                }
+            }
+        }
+        let women = women
+            .into_iter()
+            .map(|mut woman| {
+                woman.extend((0..3).map(|_| (generator.below(len), generator.below(len))));
+                woman.sort();
+                woman.into_iter().map(|(_, i)| i).unique().collect_vec()
+            })
+            .collect_vec();
+        (men, women)
+    }
+
+    #[test]
+    fn test_stable_marriage_ranking() {
+        let len = 2000;
+        let (men, women) = random_preference_lists(len, 30);
+        let men_matcher = IndexMatcher(men);
+        let unranked = stable_marriage(
+            (0..len).collect_vec(),
+            (0..len).collect_vec(),
+            &men_matcher,
+            &IndexMatcher(women.clone()),
+        );
+        let ranked = stable_marriage(
+            (0..len).collect_vec(),
+            (0..len).collect_vec(),
+            &men_matcher,
+            &RankingIndexMatcher(IndexMatcher(women)),
+        );
+        assert!(ranked.0.iter().filter(|(_, m)| m.is_some()).count() > len / 2);
+        assert_eq!(unranked, ranked);
+    }
+
+    // Like IndexMatcher, but with men and women who are each other's first choice being perfect
+    // matches
+    struct PerfectIndexMatcher(IndexMatcher, IndexMatcher);
+
+    impl Matcher for PerfectIndexMatcher {
+        type Item = usize;
+        type Target = usize;
+
+        fn is_admissible(&self, x: &Self::Item, y: &Self::Target) -> bool {
+            self.0.is_admissible(x, y)
+        }
+
+        fn score(&self, x: &Self::Item, y: &Self::Target) -> Option<u32> {
+            self.0.score(x, y)
+        }
+
+        fn is_perfect_match(&self, x: &Self::Item, y: &Self::Target) -> bool {
+            (self.0).0[*x].first() == Some(y) && (self.1).0[*y].first() == Some(x)
+        }
+    }
+
+    #[test]
+    fn test_verify_stable() {
+        let men = IndexMatcher(vec![vec![0, 1], vec![0]]);
+        let women = IndexMatcher(vec![vec![1, 0], vec![0]]);
+        let check =
+            |matches: &[Option<usize>]| verify_stable(matches, &[0, 1], &[0, 1], &men, &women);
+        assert_eq!(check(&[Some(1), Some(0)]), Ok(()));
+        assert_eq!(
+            check(&[Some(0), None]),
+            Err(Instability::BlockingPair { man: 1, woman: 0 })
+        );
+        assert_eq!(
+            check(&[None, Some(1)]),
+            Err(Instability::Inadmissible { man: 1, woman: 1 })
+        );
+        assert_eq!(
+            check(&[Some(0), Some(0)]),
+            Err(Instability::SeveralMatches { man: 0 })
+        );
+    }
+
+    // Preference lists of `len` individuals, each finding admissible a random part of the `other`
+    // individuals of the other list
+    fn preference_lists(len: usize, other: usize) -> impl Strategy<Value = Vec<Vec<usize>>> {
+        proptest::collection::vec(
+            (Just((0..other).collect_vec()).prop_shuffle(), 0..=other)
+                .prop_map(|(prefs, n)| prefs[..n].to_vec()),
+            len,
+        )
+    }
+
+    fn men_and_women() -> impl Strategy<Value = (Vec<Vec<usize>>, Vec<Vec<usize>>)> {
+        (0..8usize, 0..8usize).prop_flat_map(|(men, women)| {
+            (preference_lists(men, women), preference_lists(women, men))
+        })
+    }
+
+    fn check_stable<P, Q>(men: usize, women: usize, men_matcher: &P, women_matcher: &Q)
+    where
+        P: Matcher<Item = usize, Target = usize> + MaybeSync,
+        Q: Matcher<Item = usize, Target = usize> + MaybeSync,
+    {
+        let (men, women) = ((0..men).collect_vec(), (0..women).collect_vec());
+        let (matches, _) = stable_marriage(men.clone(), women.clone(), men_matcher, women_matcher);
+        let matches = matches.into_iter().map(|(_, m)| m).collect_vec();
+        assert_eq!(
+            verify_stable(&matches, &men, &women, men_matcher, women_matcher),
+            Ok(())
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_stable_marriage_is_stable((men, women) in men_and_women()) {
+            check_stable(
+                men.len(),
+                women.len(),
+                &IndexMatcher(men),
+                &IndexMatcher(women),
+            );
+        }
+
+        #[test]
+        fn prop_stable_marriage_ranking_is_stable((men, women) in men_and_women()) {
+            check_stable(
+                men.len(),
+                women.len(),
+                &IndexMatcher(men),
+                &RankingIndexMatcher(IndexMatcher(women)),
+            );
+        }
+
+        #[test]
+        fn prop_stable_marriage_perfect_is_stable((men, women) in men_and_women()) {
+            check_stable(
+                men.len(),
+                women.len(),
+                &PerfectIndexMatcher(IndexMatcher(men.clone()), IndexMatcher(women.clone())),
+                &IndexMatcher(women),
+            );
+        }
+    }
 }
@@ -0,0 +1,207 @@
+// Aggregates over a changeset, eg. how long the completed tasks took, rather than the tasks
+// themselves. They are computed from the sections of `categorize_changeset`, so hidden sections do
+// not count.
+
+use compute_changes::{ChangedTask, Changes};
+use display_changes::ChangesetReport;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Stats {
+    // The tasks of the completed section, including those created and completed in between
+    pub completed: usize,
+    // The average number of days between the creation and the completion of the completed tasks,
+    // among those having both dates
+    pub average_days_to_complete: Option<f64>,
+    // The days by which due dates were pushed back, tasks brought forward not counting
+    pub postponed_days: i64,
+    // The tasks postponed several times, which only happens to the occurrences of recurring tasks
+    pub postponed_more_than_once: usize,
+    // The number of completed tasks of each project, tasks without project being left out
+    pub completed_by_project: BTreeMap<String, usize>,
+}
+
+// By how many days `change` postpones its task, if it does
+fn postponement_days(change: &Changes) -> Option<i64> {
+    let days = match *change {
+        Changes::PostponedStrictBy(d, _, _) => d.num_days(),
+        Changes::DueDate(Some(before), Some(after)) => (after - before).num_days(),
+        _ => return None,
+    };
+    Some(days).filter(|d| *d > 0)
+}
+
+fn postponements(x: &ChangedTask<Vec<Changes>>) -> Vec<i64> {
+    x.delta
+        .iter()
+        .flatten()
+        .filter_map(postponement_days)
+        .collect()
+}
+
+pub fn compute_stats(report: &ChangesetReport) -> Stats {
+    let created_completed = report.created_completed();
+    let completed = report
+        .completed
+        .iter()
+        .chain(&created_completed)
+        .collect::<Vec<_>>();
+    let mut stats = Stats {
+        completed: completed.len(),
+        ..Stats::default()
+    };
+    let mut days_to_complete = Vec::new();
+    for x in completed.iter() {
+        days_to_complete.extend(
+            x.after
+                .iter()
+                .filter(|t| t.finished)
+                .filter_map(|t| Some((t.finish_date? - t.create_date?).num_days())),
+        );
+        for project in x.orig.projects.iter() {
+            *stats
+                .completed_by_project
+                .entry(project.clone())
+                .or_insert(0) += 1;
+        }
+    }
+    if !days_to_complete.is_empty() {
+        let total = days_to_complete.iter().sum::<i64>();
+        stats.average_days_to_complete = Some(total as f64 / days_to_complete.len() as f64);
+    }
+    for x in completed.into_iter().chain(&report.changed) {
+        let postponements = postponements(x);
+        stats.postponed_days += postponements.iter().sum::<i64>();
+        if postponements.len() > 1 {
+            stats.postponed_more_than_once += 1;
+        }
+    }
+    stats
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Completed: {} task{}",
+            self.completed,
+            if self.completed == 1 { "" } else { "s" }
+        )?;
+        if let Some(days) = self.average_days_to_complete {
+            write!(f, ", in {:.1} days on average", days)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Postponed: {} days in total, {} task{} more than once",
+            self.postponed_days,
+            self.postponed_more_than_once,
+            if self.postponed_more_than_once == 1 {
+                ""
+            } else {
+                "s"
+            }
+        )?;
+        if !self.completed_by_project.is_empty() {
+            let projects = self
+                .completed_by_project
+                .iter()
+                .map(|(p, n)| format!("+{} {}", p, n))
+                .collect::<Vec<_>>();
+            writeln!(f, "Completed by project: {}", projects.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use compute_changes::{compute_changeset, TaskDelta};
+    use display_changes::categorize_changeset;
+    use std::str::FromStr;
+    use todo_txt::task::Extended as Task;
+    use todo_txt::Date as TaskDate;
+
+    fn tasks(lines: &[&str]) -> Vec<Task> {
+        lines.iter().map(|l| Task::from_str(l).unwrap()).collect()
+    }
+
+    fn date(s: &str) -> TaskDate {
+        TaskDate::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        let from = tasks(&[
+            "2018-05-20 call mom +family",
+            "2018-05-01 pay rent due:2018-06-01 +money",
+            "2018-06-01 water plants due:2018-06-02 rec:1w +home",
+            "buy milk",
+        ]);
+        let to = tasks(&[
+            "x 2018-06-04 2018-05-20 call mom +family",
+            "2018-05-01 pay rent due:2018-06-08 +money",
+            "x 2018-06-03 2018-06-01 water plants due:2018-06-02 rec:1w +home",
+            "2018-06-03 water plants due:2018-06-10 rec:1w +home",
+            "x 2018-06-05 2018-06-01 buy bread +home",
+        ]);
+        let (new_tasks, mut changes) = compute_changeset(from, to, 25);
+        // A strictly recurring task whose two occurrences were postponed
+        changes.push(ChangedTask {
+            orig: tasks(&["2018-06-01 run due:2018-06-02 rec:+1w"]).remove(0),
+            delta: TaskDelta::Recurred(vec![
+                vec![
+                    Changes::FinishedAt(date("2018-06-03")),
+                    Changes::PostponedStrictBy(
+                        Duration::days(3),
+                        date("2018-06-02"),
+                        date("2018-06-05"),
+                    ),
+                ],
+                vec![
+                    Changes::RecurredStrict,
+                    Changes::PostponedStrictBy(
+                        Duration::days(2),
+                        date("2018-06-09"),
+                        date("2018-06-11"),
+                    ),
+                ],
+            ]),
+            after: tasks(&[
+                "x 2018-06-03 2018-06-01 run due:2018-06-05 rec:+1w",
+                "2018-06-03 run due:2018-06-11 rec:+1w",
+            ]),
+            orig_line: None,
+            after_lines: Vec::new(),
+            similarity: Some(100),
+        });
+        let stats = compute_stats(&categorize_changeset(new_tasks, changes));
+        assert_eq!(
+            stats,
+            Stats {
+                completed: 4,
+                // 15 days for call mom, 2 for water plants and run, 4 for buy bread
+                average_days_to_complete: Some(5.75),
+                postponed_days: 12,
+                postponed_more_than_once: 1,
+                completed_by_project: vec![("family".to_owned(), 1), ("home".to_owned(), 2)]
+                    .into_iter()
+                    .collect(),
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "Completed: 4 tasks, in 5.8 days on average\n\
+             Postponed: 12 days in total, 1 task more than once\n\
+             Completed by project: +family 1, +home 2\n"
+        );
+        assert_eq!(
+            compute_stats(&categorize_changeset(Vec::new(), Vec::new())).to_string(),
+            "Completed: 0 tasks\nPostponed: 0 days in total, 0 tasks more than once\n"
+        );
+    }
+}
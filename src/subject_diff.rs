@@ -0,0 +1,243 @@
+// The differences between the subjects of a changed task, computed once along with the change so
+// that each output format highlights the same regions without diffing again. Subjects are diffed
+// word by word, falling back to characters within a slightly modified word, or character by
+// character.
+
+use diff;
+use std;
+use std::fmt;
+use strsim::levenshtein;
+
+// Granularity of subject diffs
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WordDiff {
+    Char,
+    Word,
+    // The whole subject is replaced, ie. nothing is highlighted
+    None,
+}
+
+impl std::str::FromStr for WordDiff {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<WordDiff, String> {
+        match s {
+            "char" => Ok(WordDiff::Char),
+            "word" => Ok(WordDiff::Word),
+            "none" => Ok(WordDiff::None),
+            _ => Err(format!("invalid word diff granularity ‘{}’", s)),
+        }
+    }
+}
+
+// A span of the subjects, the concatenation of the `Equal` and `Delete` spans being the subject
+// before and that of the `Equal` and `Insert` spans the subject after
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DiffOp {
+    Equal(String),
+    Delete(String),
+    Insert(String),
+}
+
+fn char_diff(s: &str, t: &str) -> Vec<DiffOp> {
+    diff::chars(s, t)
+        .into_iter()
+        .map(|d| match d {
+            diff::Result::Both(c, _) => DiffOp::Equal(c.to_string()),
+            diff::Result::Left(c) => DiffOp::Delete(c.to_string()),
+            diff::Result::Right(c) => DiffOp::Insert(c.to_string()),
+        })
+        .collect()
+}
+
+// Splits a string into alternating runs of whitespace and non-whitespace characters
+fn tokenize(s: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut prev_is_space = None;
+    for (i, c) in s.char_indices() {
+        let is_space = c.is_whitespace();
+        if prev_is_space.is_some_and(|p| p != is_space) {
+            res.push(&s[start..i]);
+            start = i;
+        }
+        prev_is_space = Some(is_space);
+    }
+    if start < s.len() {
+        res.push(&s[start..]);
+    }
+    res
+}
+
+// Whether two words are close enough for a character diff between them to be readable
+fn are_similar_words(a: &str, b: &str) -> bool {
+    2 * levenshtein(a, b) <= std::cmp::max(a.chars().count(), b.chars().count())
+}
+
+// Diffs the words of two strings, falling back to a character diff when a single word was
+// slightly modified
+fn word_diff(s: &str, t: &str) -> Vec<DiffOp> {
+    fn flush(res: &mut Vec<DiffOp>, lefts: &mut Vec<&str>, rights: &mut Vec<&str>) {
+        if lefts.len() == 1 && rights.len() == 1 && are_similar_words(lefts[0], rights[0]) {
+            res.extend(char_diff(lefts[0], rights[0]));
+        } else {
+            res.extend(lefts.iter().map(|l| DiffOp::Delete(l.to_string())));
+            res.extend(rights.iter().map(|r| DiffOp::Insert(r.to_string())));
+        }
+        lefts.clear();
+        rights.clear();
+    }
+
+    let (s_tokens, t_tokens) = (tokenize(s), tokenize(t));
+    let mut res = Vec::new();
+    let mut lefts = Vec::new();
+    let mut rights = Vec::new();
+    for d in diff::slice(&s_tokens, &t_tokens) {
+        match d {
+            diff::Result::Both(w, _) => {
+                flush(&mut res, &mut lefts, &mut rights);
+                res.push(DiffOp::Equal(w.to_string()));
+            }
+            diff::Result::Left(w) => lefts.push(w),
+            diff::Result::Right(w) => rights.push(w),
+        }
+    }
+    flush(&mut res, &mut lefts, &mut rights);
+    res
+}
+
+// Merges adjacent spans of the same kind, to keep the highlighting short
+fn merge_ops(ops: Vec<DiffOp>) -> Vec<DiffOp> {
+    use self::DiffOp::*;
+    let mut res: Vec<DiffOp> = Vec::new();
+    for op in ops {
+        match (res.last_mut(), op) {
+            (Some(Equal(a)), Equal(b))
+            | (Some(Delete(a)), Delete(b))
+            | (Some(Insert(a)), Insert(b)) => a.push_str(&b),
+            (_, op) => res.push(op),
+        }
+    }
+    res
+}
+
+pub fn diff_subjects(before: &str, after: &str, granularity: WordDiff) -> Vec<DiffOp> {
+    merge_ops(match granularity {
+        WordDiff::Char => char_diff(before, after),
+        WordDiff::Word => word_diff(before, after),
+        WordDiff::None => vec![
+            DiffOp::Delete(before.to_owned()),
+            DiffOp::Insert(after.to_owned()),
+        ],
+    })
+    .into_iter()
+    .filter(|op| op != &DiffOp::Delete(String::new()) && op != &DiffOp::Insert(String::new()))
+    .collect()
+}
+
+// A subject change. It is identified by the subjects alone, the operations being derived from
+// them: it compares and is debug-formatted as the `before, after` pair.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct SubjectDiff {
+    pub before: String,
+    pub after: String,
+    pub ops: Vec<DiffOp>,
+}
+
+impl SubjectDiff {
+    pub fn new(before: String, after: String, granularity: WordDiff) -> SubjectDiff {
+        let ops = diff_subjects(&before, &after, granularity);
+        SubjectDiff { before, after, ops }
+    }
+}
+
+impl PartialEq for SubjectDiff {
+    fn eq(&self, other: &SubjectDiff) -> bool {
+        self.before == other.before && self.after == other.after
+    }
+}
+
+impl Eq for SubjectDiff {}
+
+impl fmt::Debug for SubjectDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}, {:?}", self.before, self.after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiffOp::*;
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("call  the plumber "),
+            vec!["call", "  ", "the", " ", "plumber", " "]
+        );
+        assert_eq!(tokenize(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_word_diff() {
+        let diff = |s, t| diff_subjects(s, t, WordDiff::Word);
+        assert_eq!(
+            diff("call the plumber", "email the electrician"),
+            vec![
+                Delete("call".to_owned()),
+                Insert("email".to_owned()),
+                Equal(" the ".to_owned()),
+                Delete("plumber".to_owned()),
+                Insert("electrician".to_owned()),
+            ]
+        );
+        assert_eq!(
+            diff("call the plumber", "call the plumbers now"),
+            vec![
+                Equal("call the ".to_owned()),
+                Delete("plumber".to_owned()),
+                Insert("plumbers now".to_owned()),
+            ]
+        );
+        // A single slightly modified word falls back to a character diff
+        assert_eq!(
+            diff("call the plumber", "call the plumbers"),
+            vec![Equal("call the plumber".to_owned()), Insert("s".to_owned())]
+        );
+        assert_eq!(
+            diff("do a thing", "do an thing"),
+            vec![
+                Equal("do a".to_owned()),
+                Insert("n".to_owned()),
+                Equal(" thing".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subject_diff() {
+        let diff = SubjectDiff::new("call mom".to_owned(), "call dad".to_owned(), WordDiff::Char);
+        assert_eq!(
+            diff.ops,
+            vec![
+                Equal("call ".to_owned()),
+                Delete("mom".to_owned()),
+                Insert("dad".to_owned()),
+            ]
+        );
+        assert_eq!(format!("{:?}", diff), "\"call mom\", \"call dad\"");
+        // The operations depend on the granularity, but not what the change is
+        let whole = SubjectDiff::new("call mom".to_owned(), "call dad".to_owned(), WordDiff::None);
+        assert_eq!(
+            whole.ops,
+            vec![Delete("call mom".to_owned()), Insert("call dad".to_owned())]
+        );
+        assert_eq!(whole, diff);
+        // Each op list spells out both subjects
+        let ops = diff_subjects("", "do a thing", WordDiff::Word);
+        assert_eq!(ops, vec![Insert("do a thing".to_owned())]);
+    }
+}
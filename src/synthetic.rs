@@ -0,0 +1,241 @@
+// Generating realistic pairs of todo lists of any size, for the benchmarks of `benches/` and the
+// property tests. The tasks only depend on the seed, and not on the platform, so that benchmarks
+// always measure the same work.
+
+use chrono::Duration;
+use compute_changes::add_recurrence;
+use std::str::FromStr;
+use todo_txt::task::Extended as Task;
+use todo_txt::task::Recurrence;
+use todo_txt::Date as TaskDate;
+
+const WORDS: &[&str] = &[
+    "call", "buy", "fix", "write", "review", "plan", "clean", "book", "the", "a", "mom", "report",
+    "printer", "holidays", "bread", "kitchen", "garage", "taxes", "slides", "doctor",
+];
+const PROJECTS: &[&str] = &["+work", "+home", "+family", "+todiff"];
+const CONTEXTS: &[&str] = &["@phone", "@computer", "@errands"];
+// Keys that todo_txt gives no meaning to
+const TAG_KEYS: &[&str] = &["ticket", "area", "who", "estimate", "link"];
+
+// Random tasks and edits of tasks, from a seed
+pub struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        Generator { state: seed }
+    }
+
+    // A number in `0..n`, drawn with a linear congruential generator
+    pub fn below(&mut self, n: usize) -> usize {
+        self.state = self
+            .state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (self.state >> 33) as usize % n
+    }
+
+    // Whether an event of probability `percent` happened
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    pub fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+
+    // A Fisher-Yates shuffle
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+
+    // A date in the few months around June 2018
+    pub fn date(&mut self) -> TaskDate {
+        let base = TaskDate::from_ymd_opt(2018, 6, 1).expect("Internal error E056");
+        base + Duration::days(self.below(120) as i64 - 60)
+    }
+
+    // Three to seven words, sometimes with a project or a context
+    pub fn subject(&mut self) -> String {
+        let mut words = (0..3 + self.below(5))
+            .map(|_| self.pick(WORDS))
+            .collect::<Vec<_>>();
+        if self.chance(40) {
+            words.push(self.pick(PROJECTS));
+        }
+        if self.chance(20) {
+            words.push(self.pick(CONTEXTS));
+        }
+        words.join(" ")
+    }
+
+    pub fn tag(&mut self) -> (String, String) {
+        let value = format!("{}{}", self.pick(WORDS), self.below(100));
+        (self.pick(TAG_KEYS).to_owned(), value)
+    }
+
+    // A pending task, with a creation date and sometimes a priority and a due date
+    pub fn task(&mut self) -> Task {
+        let mut line = String::new();
+        if self.chance(30) {
+            line += &format!("({}) ", self.pick(&['A', 'B', 'C']));
+        }
+        line += &format!("{} {}", self.date(), self.subject());
+        if self.chance(30) {
+            line += &format!(" due:{}", self.date());
+        }
+        parse(&line)
+    }
+
+    // A task with one word of its subject replaced, or a word added
+    pub fn edit_subject(&mut self, task: &Task) -> Task {
+        let mut words = task.subject.split(' ').collect::<Vec<_>>();
+        let i = self.below(words.len() + 1);
+        if i == words.len() {
+            words.push(self.pick(WORDS));
+        } else {
+            words[i] = self.pick(WORDS);
+        }
+        let mut res = task.clone();
+        res.subject = words.join(" ");
+        // Projects and contexts are read from the subject
+        parse(&res.to_string())
+    }
+
+    // A task with one tag added, one removed, or the value of one changed
+    pub fn edit_tags(&mut self, task: &Task) -> Task {
+        let mut res = task.clone();
+        let keys = res.tags.keys().cloned().collect::<Vec<_>>();
+        let (key, value) = self.tag();
+        match self.below(3) {
+            0 if !keys.is_empty() => {
+                res.tags.remove(&keys[self.below(keys.len())]);
+            }
+            1 if !keys.is_empty() => {
+                res.tags.insert(keys[self.below(keys.len())].clone(), value);
+            }
+            _ => {
+                res.tags.insert(key, value);
+            }
+        }
+        res
+    }
+}
+
+fn parse(line: &str) -> Task {
+    Task::from_str(line).expect("Internal error E057")
+}
+
+// `n` tasks, and the same tasks with the subjects of `percent` of them edited
+pub fn edited_subjects(n: usize, percent: usize, seed: u64) -> (Vec<Task>, Vec<Task>) {
+    let mut generator = Generator::new(seed);
+    let from = (0..n).map(|_| generator.task()).collect::<Vec<_>>();
+    let to = from
+        .iter()
+        .map(|t| {
+            if generator.chance(percent) {
+                generator.edit_subject(t)
+            } else {
+                t.clone()
+            }
+        })
+        .collect();
+    (from, to)
+}
+
+// `n` tasks with a few tags each, most of which had their tags edited
+pub fn tag_churn(n: usize, seed: u64) -> (Vec<Task>, Vec<Task>) {
+    let mut generator = Generator::new(seed);
+    let from = (0..n)
+        .map(|_| {
+            let mut task = generator.task();
+            for _ in 0..1 + generator.below(4) {
+                let (key, value) = generator.tag();
+                task.tags.insert(key, value);
+            }
+            task
+        })
+        .collect::<Vec<_>>();
+    let to = from
+        .iter()
+        .map(|t| {
+            let mut task = t.clone();
+            for _ in 0..generator.below(4) {
+                task = generator.edit_tags(&task);
+            }
+            task
+        })
+        .collect();
+    (from, to)
+}
+
+// `n` tasks, most of them recurring, half of which were completed and had their next occurrence
+// created, sometimes several times in a row
+pub fn recurring(n: usize, seed: u64) -> (Vec<Task>, Vec<Task>) {
+    let mut generator = Generator::new(seed);
+    let from = (0..n)
+        .map(|_| {
+            let mut task = generator.task();
+            if generator.chance(80) {
+                let rec = generator.pick(&["1d", "1w", "+1w", "2w", "+1m"]);
+                task.recurrence = Some(Recurrence::from_str(rec).expect("Internal error E058"));
+                task.due_date = Some(task.due_date.unwrap_or_else(|| generator.date()));
+            }
+            task
+        })
+        .collect::<Vec<_>>();
+    let mut to = Vec::new();
+    for task in from.iter() {
+        let mut current = task.clone();
+        if current.recurrence.is_some() && generator.chance(50) {
+            for _ in 0..1 + generator.below(3) {
+                let finish = current.due_date.expect("Internal error E059");
+                let mut done = current.clone();
+                done.finished = true;
+                done.finish_date = Some(finish);
+                to.push(done);
+                let rec = current.recurrence.as_ref().expect("Internal error E080");
+                current.due_date = add_recurrence(rec, finish);
+                current.create_date = Some(finish);
+            }
+        }
+        to.push(current);
+    }
+    (from, to)
+}
+
+// `n` tasks with a due date, and the same tasks postponed by a few days, the most common diff
+pub fn postponed(n: usize, seed: u64) -> (Vec<Task>, Vec<Task>) {
+    let mut generator = Generator::new(seed);
+    let from = (0..n)
+        .map(|_| {
+            let mut task = generator.task();
+            task.due_date = Some(task.due_date.unwrap_or_else(|| generator.date()));
+            task
+        })
+        .collect::<Vec<_>>();
+    let to = from
+        .iter()
+        .map(|t| {
+            let mut task = t.clone();
+            let delay = Duration::days(1 + generator.below(14) as i64);
+            task.due_date = task.due_date.map(|due| due + delay);
+            task
+        })
+        .collect();
+    (from, to)
+}
+
+// `n` tasks whose subjects only differ by a number, every task being similar enough to be matched
+// with any other, and the same tasks in reverse order with a word changed: the worst case for
+// matching
+pub fn all_pairs_admissible(n: usize) -> (Vec<Task>, Vec<Task>) {
+    let task = |plant: &str, i: usize| parse(&format!("water the {} number {} +home", plant, i));
+    let from = (0..n).map(|i| task("plant", i)).collect();
+    let to = (0..n).rev().map(|i| task("plants", i)).collect();
+    (from, to)
+}
@@ -0,0 +1,771 @@
+// Displaying changesets in a format given by a template, for `todiff --template FILE`. The syntax
+// is a small subset of mustache's:
+//
+// - `{{name}}` is replaced by the value of a variable, and `{{name | csv}}` or `{{name | html}}`
+//   escapes it for a CSV field or for HTML
+// - `{{#each list}}…{{/each}}` repeats its contents for each item of a list
+// - `{{#if name}}…{{else}}…{{/if}}` and `{{#unless name}}…{{/unless}}` test whether a variable is
+//   neither empty nor `0`, or a list has items, `{{else}}` being optional
+// - `{{! comment }}` is left out
+//
+// A line holding nothing but one of the tags other than variables is left out altogether, so that
+// blocks can be laid out on their own lines.
+//
+// Everywhere, `counts` is the summary of the changeset (eg. “1 new, 0 deleted, 2 completed, 0
+// changed”), `counts.new`, `counts.deleted`, `counts.completed`, `counts.changed`,
+// `counts.recurred` and `counts.postponed` the number of tasks of each kind, and `labels.before`
+// and `labels.after` the names of the compared lists, and the lists are the sections `new`,
+// `deleted`, `completed` and `changed`. For each of their tasks, `section` is the name of the
+// section, `orig` the task before, `after` the task after (its first occurrence if it recurred),
+// `task` whichever of them the default output displays, and `similarity` the similarity of `orig`
+// and `after` in percents when computed. The fields `subject`, `priority`, `create_date`,
+// `finish_date`, `due_date`, `threshold_date`, `recurrence`, `projects`, `contexts`, `finished`
+// (`true` or empty), `line` and `tags.KEY` of these three tasks are also available, eg.
+// `{{orig.due_date}}`, along with the list `changes`. For each change, `change` is its
+// description (also `change.description`) and `change.kind` its porcelain keyword, eg.
+// `postponed-strict-by`. Within all lists, `@index` is the 1-based position of the item, and
+// `@first` and `@last` whether it is the first or last one.
+
+use compute_changes::*;
+use display_changes::*;
+use std::fmt;
+use std::fs;
+use std::io;
+use todo_txt::task::Extended as Task;
+
+const TASK_FIELDS: &[&str] = &[
+    "subject",
+    "priority",
+    "create_date",
+    "finish_date",
+    "due_date",
+    "threshold_date",
+    "recurrence",
+    "projects",
+    "contexts",
+    "finished",
+    "line",
+];
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Open {
+        path: String,
+        error: io::Error,
+    },
+    Syntax {
+        path: String,
+        line_number: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::Open { path, error } => {
+                write!(f, "Unable to open template ‘{}’: {}", path, error)
+            }
+            TemplateError::Syntax {
+                path,
+                line_number,
+                message,
+            } => write!(f, "{}:{}: {}", path, line_number, message),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Filter {
+    Csv,
+    Html,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Node {
+    Text(String),
+    Variable(String, Option<Filter>),
+    Each(String, Vec<Node>),
+    // (name, whether the condition is negated, then, else)
+    If(String, bool, Vec<Node>, Vec<Node>),
+}
+
+// What the items of a list are, and thus which variables they define
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Scope {
+    Root,
+    Task,
+    Change,
+}
+
+impl Scope {
+    fn has_variable(self, name: &str) -> bool {
+        let is_task_field = |field: &str| {
+            TASK_FIELDS.contains(&field)
+                || field.strip_prefix("tags.").is_some_and(|k| !k.is_empty())
+        };
+        match self {
+            Scope::Root => [
+                "counts",
+                "counts.new",
+                "counts.deleted",
+                "counts.completed",
+                "counts.changed",
+                "counts.recurred",
+                "counts.postponed",
+                "labels.before",
+                "labels.after",
+            ]
+            .contains(&name),
+            Scope::Task => {
+                ["section", "similarity", "task", "orig", "after"].contains(&name)
+                    || ["task.", "orig.", "after."]
+                        .iter()
+                        .any(|p| name.strip_prefix(p).is_some_and(is_task_field))
+            }
+            Scope::Change => ["change", "change.description", "change.kind"].contains(&name),
+        }
+    }
+
+    // The scope of the items of the list `name`, if it is one
+    fn list(self, name: &str) -> Option<Scope> {
+        match self {
+            Scope::Root if Section::NAMES.contains(&name) => Some(Scope::Task),
+            Scope::Task if name == "changes" => Some(Scope::Change),
+            _ => None,
+        }
+    }
+}
+
+// A chunk of the template: text, or the contents of a `{{…}}` tag and its line number
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Token {
+    Text(String),
+    Tag(String, usize),
+}
+
+fn is_standalone_tag(tag: &str) -> bool {
+    ["#", "/", "!"].iter().any(|p| tag.starts_with(p)) || tag == "else"
+}
+
+fn tokenize(contents: &str, path: &str) -> Result<Vec<Token>, TemplateError> {
+    let mut tokens = Vec::new();
+    for (i, line) in contents.split_inclusive('\n').enumerate() {
+        let mut line_tokens = Vec::new();
+        let mut rest = line;
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| TemplateError::Syntax {
+                    path: path.to_owned(),
+                    line_number: i + 1,
+                    message: "unclosed ‘{{’".to_owned(),
+                })?;
+            if start > 0 {
+                line_tokens.push(Token::Text(rest[..start].to_owned()));
+            }
+            let tag = rest[start + 2..start + end].trim().to_owned();
+            line_tokens.push(Token::Tag(tag, i + 1));
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            line_tokens.push(Token::Text(rest.to_owned()));
+        }
+        let tags = line_tokens
+            .iter()
+            .filter_map(|t| match t {
+                Token::Tag(tag, _) => Some(tag),
+                Token::Text(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let blank = line_tokens.iter().all(|t| match t {
+            Token::Text(text) => text.trim().is_empty(),
+            Token::Tag(..) => true,
+        });
+        if blank && tags.len() == 1 && is_standalone_tag(tags[0]) {
+            line_tokens.retain(|t| matches!(t, Token::Tag(..)));
+        }
+        tokens.extend(line_tokens);
+    }
+    Ok(tokens)
+}
+
+// How a sequence of nodes ended
+enum End {
+    Eof,
+    Else(usize),
+    // (the name of the block, eg. `each`, its line number)
+    Close(String, usize),
+}
+
+struct Parser<'a> {
+    tokens: std::vec::IntoIter<Token>,
+    path: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, line_number: usize, message: String) -> TemplateError {
+        TemplateError::Syntax {
+            path: self.path.to_owned(),
+            line_number,
+            message,
+        }
+    }
+
+    fn check_variable(
+        &self,
+        scopes: &[Scope],
+        name: &str,
+        line: usize,
+    ) -> Result<(), TemplateError> {
+        let in_loop = scopes.len() > 1;
+        let is_loop_variable = ["@index", "@first", "@last"].contains(&name);
+        if (in_loop && is_loop_variable) || scopes.iter().any(|s| s.has_variable(name)) {
+            Ok(())
+        } else {
+            Err(self.error(line, format!("unknown variable ‘{}’", name)))
+        }
+    }
+
+    // Parses the nodes up to the end of the template or of the enclosing block
+    fn parse_nodes(&mut self, scopes: &mut Vec<Scope>) -> Result<(Vec<Node>, End), TemplateError> {
+        let mut nodes = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            let (tag, line) = match token {
+                Token::Text(text) => {
+                    nodes.push(Node::Text(text));
+                    continue;
+                }
+                Token::Tag(tag, line) => (tag, line),
+            };
+            if tag.starts_with('!') {
+                continue;
+            }
+            if tag == "else" {
+                return Ok((nodes, End::Else(line)));
+            }
+            if let Some(block) = tag.strip_prefix('/') {
+                return Ok((nodes, End::Close(block.trim().to_owned(), line)));
+            }
+            if let Some(block) = tag.strip_prefix('#') {
+                let (kind, name) = match block.split_once(char::is_whitespace) {
+                    Some((kind, name)) => (kind, name.trim().to_owned()),
+                    None => (block, String::new()),
+                };
+                nodes.push(self.parse_block(scopes, kind, name, line)?);
+                continue;
+            }
+            let (name, filter) = match tag.split_once('|') {
+                Some((name, filter)) => {
+                    let filter = match filter.trim() {
+                        "csv" => Filter::Csv,
+                        "html" => Filter::Html,
+                        f => return Err(self.error(line, format!("unknown filter ‘{}’", f))),
+                    };
+                    (name.trim().to_owned(), Some(filter))
+                }
+                None => (tag, None),
+            };
+            self.check_variable(scopes, &name, line)?;
+            nodes.push(Node::Variable(name, filter));
+        }
+        Ok((nodes, End::Eof))
+    }
+
+    fn parse_block(
+        &mut self,
+        scopes: &mut Vec<Scope>,
+        kind: &str,
+        name: String,
+        line: usize,
+    ) -> Result<Node, TemplateError> {
+        let tag = format!("{{{{#{} {}}}}}", kind, name);
+        let close = |parser: &Parser, end: End| match end {
+            End::Close(ref block, _) if block == kind => Ok(()),
+            End::Eof => Err(parser.error(line, format!("unclosed ‘{}’", tag))),
+            End::Else(l) => Err(parser.error(l, "unexpected ‘{{else}}’".to_owned())),
+            End::Close(block, l) => Err(parser.error(
+                l,
+                format!("expected ‘{{{{/{}}}}}’, found ‘{{{{/{}}}}}’", kind, block),
+            )),
+        };
+        match kind {
+            "each" => {
+                let item_scope = scopes
+                    .iter()
+                    .rev()
+                    .find_map(|s| s.list(&name))
+                    .ok_or_else(|| self.error(line, format!("unknown list ‘{}’", name)))?;
+                scopes.push(item_scope);
+                let (body, end) = self.parse_nodes(scopes)?;
+                scopes.pop();
+                close(self, end)?;
+                Ok(Node::Each(name, body))
+            }
+            "if" | "unless" => {
+                if scopes.iter().all(|s| s.list(&name).is_none()) {
+                    self.check_variable(scopes, &name, line)?;
+                }
+                let (then, end) = self.parse_nodes(scopes)?;
+                let (otherwise, end) = match end {
+                    End::Else(_) => self.parse_nodes(scopes)?,
+                    end => (Vec::new(), end),
+                };
+                close(self, end)?;
+                Ok(Node::If(name, kind == "unless", then, otherwise))
+            }
+            _ => Err(self.error(line, format!("unknown block ‘#{}’", kind))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+// `path` is only used in the errors
+pub fn parse_template(contents: &str, path: &str) -> Result<Template, TemplateError> {
+    let mut parser = Parser {
+        tokens: tokenize(contents, path)?.into_iter(),
+        path,
+    };
+    let (nodes, end) = parser.parse_nodes(&mut vec![Scope::Root])?;
+    match end {
+        End::Eof => Ok(Template { nodes }),
+        End::Else(line) => Err(parser.error(line, "unexpected ‘{{else}}’".to_owned())),
+        End::Close(block, line) => {
+            Err(parser.error(line, format!("unexpected ‘{{{{/{}}}}}’", block)))
+        }
+    }
+}
+
+pub fn read_template(path: &str) -> Result<Template, TemplateError> {
+    let contents = fs::read_to_string(path).map_err(|error| TemplateError::Open {
+        path: path.to_owned(),
+        error,
+    })?;
+    parse_template(&contents, path)
+}
+
+// A task of a section, with what the variables of its scope are computed from
+struct Item {
+    section: &'static str,
+    orig: Option<(Task, Option<usize>)>,
+    after: Option<(Task, Option<usize>)>,
+    similarity: Option<u8>,
+    changes: Vec<Changes>,
+}
+
+impl Item {
+    fn changed(section: &'static str, x: &ChangedTask<Vec<Changes>>) -> Item {
+        Item {
+            section,
+            orig: Some((x.orig.clone(), x.orig_line)),
+            after: x
+                .after
+                .first()
+                .map(|t| (t.clone(), x.after_lines.first().cloned())),
+            similarity: x.similarity,
+            changes: x.delta.iter().flatten().cloned().collect(),
+        }
+    }
+
+    // The task displayed by `display_changeset`
+    fn task(&self) -> Option<&(Task, Option<usize>)> {
+        self.orig.as_ref().or(self.after.as_ref())
+    }
+}
+
+fn items(report: &ChangesetReport, section: &str) -> Vec<Item> {
+    match section {
+        "new" => report
+            .new_section()
+            .into_iter()
+            .map(|(t, line)| Item {
+                section: "new",
+                orig: None,
+                after: Some((t.clone(), line)),
+                similarity: None,
+                changes: Vec::new(),
+            })
+            .collect(),
+        "deleted" => report
+            .deleted
+            .iter()
+            .zip(
+                report
+                    .deleted_lines
+                    .iter()
+                    .cloned()
+                    .chain(std::iter::repeat(None)),
+            )
+            .map(|(t, line)| Item {
+                section: "deleted",
+                orig: Some((t.clone(), line)),
+                after: None,
+                similarity: None,
+                changes: Vec::new(),
+            })
+            .collect(),
+        "completed" => report
+            .completed_section()
+            .iter()
+            .map(|x| Item::changed("completed", x))
+            .collect(),
+        "changed" => report
+            .changed
+            .iter()
+            .map(|x| Item::changed("changed", x))
+            .collect(),
+        _ => panic!("Internal error E072"),
+    }
+}
+
+fn task_field(opts: &DisplayOptions, task: Option<&(Task, Option<usize>)>, field: &str) -> String {
+    let (t, line) = match task {
+        Some((t, line)) => (t, line),
+        None => return String::new(),
+    };
+    let date = |d: &Option<todo_txt::Date>| d.map(|d| opts.format_date(d)).unwrap_or_default();
+    match field {
+        "" => task_str(opts, t),
+        "subject" => t.subject.clone(),
+        "priority" => priority_char(&t.priority)
+            .map(String::from)
+            .unwrap_or_default(),
+        "create_date" => date(&t.create_date),
+        "finish_date" => date(&t.finish_date),
+        "due_date" => date(&t.due_date),
+        "threshold_date" => date(&t.threshold_date),
+        "recurrence" => t
+            .recurrence
+            .as_ref()
+            .map(|r| r.to_string())
+            .unwrap_or_default(),
+        "projects" => t.projects.join(" "),
+        "contexts" => t.contexts.join(" "),
+        "finished" => if t.finished { "true" } else { "" }.to_owned(),
+        "line" => line.map(|l| l.to_string()).unwrap_or_default(),
+        _ => match field.strip_prefix("tags.") {
+            Some(key) => t.tags.get(key).cloned().unwrap_or_default(),
+            None => panic!("Internal error E073"),
+        },
+    }
+}
+
+// An item of a list being rendered
+enum Frame<'a> {
+    Root,
+    Task(&'a Item),
+    Change(&'a Changes),
+}
+
+struct Renderer<'a> {
+    report: &'a ChangesetReport,
+    opts: &'a DisplayOptions,
+    sections: &'a [(&'static str, Vec<Item>)],
+    // The items being rendered, each with its position in its list and the length of the list
+    frames: Vec<(Frame<'a>, usize, usize)>,
+}
+
+impl<'a> Renderer<'a> {
+    fn frame_variable(&self, frame: &Frame, name: &str) -> Option<String> {
+        let opts = self.opts;
+        match frame {
+            Frame::Root => {
+                let counts = self.report.counts();
+                let label = |i: usize| {
+                    let labels = opts.labels.as_ref();
+                    labels.map(|l| if i == 0 { &l.0 } else { &l.1 }).cloned()
+                };
+                Some(match name {
+                    "counts" => counts.to_string(),
+                    "counts.new" => counts.new.to_string(),
+                    "counts.deleted" => counts.deleted.to_string(),
+                    "counts.completed" => counts.completed.to_string(),
+                    "counts.changed" => counts.changed.to_string(),
+                    "counts.recurred" => counts.recurred.to_string(),
+                    "counts.postponed" => counts.postponed.to_string(),
+                    "labels.before" => label(0).unwrap_or_default(),
+                    "labels.after" => label(1).unwrap_or_default(),
+                    _ => return None,
+                })
+            }
+            Frame::Task(item) => {
+                let (task, field) = match name.split_once('.') {
+                    Some((task, field)) => (task, field),
+                    None => (name, ""),
+                };
+                match (task, field) {
+                    ("section", "") => Some(item.section.to_owned()),
+                    ("similarity", "") => {
+                        Some(item.similarity.map(|s| s.to_string()).unwrap_or_default())
+                    }
+                    ("task", field) => Some(task_field(opts, item.task(), field)),
+                    ("orig", field) => Some(task_field(opts, item.orig.as_ref(), field)),
+                    ("after", field) => Some(task_field(opts, item.after.as_ref(), field)),
+                    _ => None,
+                }
+            }
+            Frame::Change(change) => match name {
+                "change" | "change.description" => {
+                    Some(change.describe_with(&|d| opts.format_date(d)))
+                }
+                "change.kind" => Some(change_keyword(change).to_owned()),
+                _ => None,
+            },
+        }
+    }
+
+    fn variable(&self, name: &str) -> String {
+        let (_, index, len) = self.frames.last().expect("Internal error E070");
+        let flag = |b: bool| if b { "true" } else { "" }.to_owned();
+        match name {
+            "@index" => return (index + 1).to_string(),
+            "@first" => return flag(*index == 0),
+            "@last" => return flag(index + 1 == *len),
+            _ => (),
+        }
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|(frame, _, _)| self.frame_variable(frame, name))
+            .expect("Internal error E071")
+    }
+
+    // The number of items of the list `name`, if it is one
+    fn list_len(&self, name: &str) -> Option<usize> {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|(frame, _, _)| match frame {
+                Frame::Root => self
+                    .sections
+                    .iter()
+                    .find(|(section, _)| *section == name)
+                    .map(|(_, items)| items.len()),
+                Frame::Task(item) if name == "changes" => Some(item.changes.len()),
+                _ => None,
+            })
+    }
+
+    fn render(&mut self, nodes: &[Node], res: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => *res += text,
+                Node::Variable(name, filter) => {
+                    let value = self.variable(name);
+                    *res += &match filter {
+                        None => value,
+                        Some(Filter::Csv) => csv_field(&value),
+                        Some(Filter::Html) => html_escape(&value),
+                    };
+                }
+                Node::If(name, negated, then, otherwise) => {
+                    let holds = match self.list_len(name) {
+                        Some(len) => len > 0,
+                        None => !["", "0"].contains(&self.variable(name).as_str()),
+                    };
+                    self.render(if holds != *negated { then } else { otherwise }, res);
+                }
+                Node::Each(name, body) => {
+                    let len = self.list_len(name).expect("Internal error E069");
+                    for i in 0..len {
+                        let frame = self.list_item(name, i);
+                        self.frames.push((frame, i, len));
+                        self.render(body, res);
+                        self.frames.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    fn list_item(&self, name: &str, i: usize) -> Frame<'a> {
+        for (frame, _, _) in self.frames.iter().rev() {
+            match *frame {
+                Frame::Root => {
+                    if let Some((_, items)) = self.sections.iter().find(|(s, _)| *s == name) {
+                        return Frame::Task(&items[i]);
+                    }
+                }
+                Frame::Task(item) if name == "changes" => return Frame::Change(&item.changes[i]),
+                _ => (),
+            }
+        }
+        panic!("Internal error E074")
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+pub fn render_template(
+    template: &Template,
+    report: &ChangesetReport,
+    opts: &DisplayOptions,
+) -> String {
+    let sections = Section::NAMES
+        .iter()
+        .map(|&section| (section, items(report, section)))
+        .collect::<Vec<_>>();
+    let mut renderer = Renderer {
+        report,
+        opts,
+        sections: &sections,
+        frames: vec![(Frame::Root, 0, 1)],
+    };
+    let mut res = String::new();
+    renderer.render(&template.nodes, &mut res);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn report() -> ChangesetReport {
+        let tasks = |lines: &[&str]| -> Vec<(usize, Task)> {
+            lines
+                .iter()
+                .enumerate()
+                .map(|(i, l)| (i + 1, Task::from_str(l).unwrap()))
+                .collect()
+        };
+        let from = tasks(&[
+            "call mom",
+            "pay the rent due:2018-06-01 who:me",
+            "fix the bike, then ride it",
+            "water the plants",
+        ]);
+        let to = tasks(&[
+            "x 2018-06-02 2018-06-01 call mom",
+            "pay the rent due:2018-06-05 who:me",
+            "(A) fix the bike, then ride it +home",
+            "buy \"milk\"",
+        ]);
+        let opts = DiffOptions::default();
+        let (new_tasks, changes) = compute_changeset_indexed_with(from, to, Vec::new(), &opts);
+        categorize_changeset_indexed_with(new_tasks, changes, &DisplayOptions::default())
+    }
+
+    fn render(template: &str) -> String {
+        let template = parse_template(template, "test.tmpl").unwrap();
+        render_template(&template, &report(), &DisplayOptions::default())
+    }
+
+    fn example(name: &str) -> String {
+        let path = format!("{}/templates/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let template = read_template(&path).unwrap();
+        render_template(&template, &report(), &DisplayOptions::default())
+    }
+
+    #[test]
+    fn test_example_templates() {
+        assert_eq!(
+            example("csv.tmpl"),
+            "section,line,task,change\n\
+             new,4,\"buy \"\"milk\"\"\",\n\
+             deleted,4,water the plants,\n\
+             completed,1,call mom,completed on 2018-06-02\n\
+             completed,1,call mom,added creation date 2018-06-01\n\
+             changed,2,pay the rent due:2018-06-01 who:me,postponed (strict) by 4 days\n\
+             changed,3,\"fix the bike, then ride it\",added priority (A)\n\
+             changed,3,\"fix the bike, then ride it\",added to project +home\n"
+        );
+        assert_eq!(
+            example("brief.tmpl"),
+            "+ buy \"milk\"\n\
+             - water the plants\n\
+             x call mom (2018-06-02)\n\
+             ~ pay the rent: postponed (strict) by 4 days\n\
+             ~ fix the bike, then ride it: added priority (A), added to project +home\n\
+             1 new, 1 deleted, 1 completed, 2 changed\n"
+        );
+    }
+
+    #[test]
+    fn test_render_template() {
+        // Blocks alone on their line take no line
+        assert_eq!(
+            render("{{#each changed}}\n  {{@index}}. {{orig.due_date}}\n{{/each}}\n"),
+            "  1. 2018-06-01\n  2. \n"
+        );
+        // Inner lists can refer to the variables of the outer ones
+        assert_eq!(
+            render("{{#each changed}}{{#each changes}}{{@index}}:{{change.kind}}@{{orig.line}} {{/each}}{{/each}}"),
+            "1:postponed-strict-by@2 1:priority@3 2:projects@3 "
+        );
+        assert_eq!(
+            render("{{#if deleted}}{{counts.deleted}} deleted{{else}}none{{/if}}"),
+            "1 deleted"
+        );
+        assert_eq!(
+            render("{{#each new}}{{#unless orig}}{{after.subject | html}}{{/unless}}{{/each}}"),
+            "buy &quot;milk&quot;"
+        );
+        assert_eq!(
+            render("{{#if counts.recurred}}recurred{{else}}{{labels.before}}.{{/if}}"),
+            "."
+        );
+        assert_eq!(
+            render("{{#each changed}}{{#if @first}}{{task.tags.who}}{{/if}}{{/each}}"),
+            "me"
+        );
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let error = |template: &str| {
+            parse_template(template, "test.tmpl")
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("{{counts}}\n{{#each new}}\n{{tsk}}\n{{/each}}\n"),
+            "test.tmpl:3: unknown variable ‘tsk’"
+        );
+        assert_eq!(
+            error("a\n{{#each changed}}\n{{#each changes}}\n{{/each}}\n"),
+            "test.tmpl:2: unclosed ‘{{#each changed}}’"
+        );
+        assert_eq!(
+            error("{{#if new}}\n{{/each}}"),
+            "test.tmpl:2: expected ‘{{/if}}’, found ‘{{/each}}’"
+        );
+        assert_eq!(error("\n\n{{/if}}"), "test.tmpl:3: unexpected ‘{{/if}}’");
+        assert_eq!(error("{{else}}"), "test.tmpl:1: unexpected ‘{{else}}’");
+        assert_eq!(error("a {{counts"), "test.tmpl:1: unclosed ‘{{’");
+        assert_eq!(
+            error("{{#each changes}}{{/each}}"),
+            "test.tmpl:1: unknown list ‘changes’"
+        );
+        assert_eq!(
+            error("{{@index}}"),
+            "test.tmpl:1: unknown variable ‘@index’"
+        );
+        assert_eq!(
+            error("{{counts | json}}"),
+            "test.tmpl:1: unknown filter ‘json’"
+        );
+        assert_eq!(error("{{#with new}}"), "test.tmpl:1: unknown block ‘#with’");
+        // Variables of a scope are not available outside of it
+        assert_eq!(
+            error("{{#each new}}{{#each changes}}{{/each}}{{/each}}{{change}}"),
+            "test.tmpl:1: unknown variable ‘change’"
+        );
+        assert!(read_template("/nonexistent.tmpl")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unable to open template ‘/nonexistent.tmpl’"));
+    }
+}
@@ -0,0 +1,75 @@
+// Generators of tasks for the property tests of the other modules
+
+use proptest::prelude::*;
+use std::str::FromStr;
+use synthetic;
+use todo_txt::task::Extended as Task;
+
+// A random task, written as a line and parsed like the tasks of a file. Its subject may be empty.
+pub fn arb_task() -> impl Strategy<Value = Task> {
+    let date = || {
+        proptest::option::of(
+            (1..4u32, 1..4u32).prop_map(|(m, d)| format!("2018-{:02}-{:02} ", m, d)),
+        )
+    };
+    let words = proptest::sample::select(vec![
+        "call", "mom", "Mom", "+family", "+Work", "+work", "@phone", "@home", "#urgent",
+    ]);
+    let tags = proptest::sample::select(vec![
+        "due:2018-01-01",
+        "due:2018-01-08",
+        "t:2018-01-01",
+        "t:2018-01-03",
+        "id:1",
+        "id:2",
+        "who:me",
+        "rec:1w",
+        "rec:+1m",
+    ]);
+    (
+        any::<bool>(),
+        proptest::option::of(proptest::sample::select(vec!['A', 'B'])),
+        date(),
+        date(),
+        proptest::collection::vec(words, 0..6),
+        proptest::collection::vec(tags, 0..4),
+    )
+        .prop_map(|(finished, priority, finish, create, words, tags)| {
+            let line = format!(
+                "{}{}{}{}{} {}",
+                if finished { "x " } else { "" },
+                priority.map_or(String::new(), |p| format!("({}) ", p)),
+                finish.unwrap_or_default(),
+                create.unwrap_or_default(),
+                words.join(" "),
+                tags.join(" ")
+            );
+            Task::from_str(&line).unwrap()
+        })
+}
+
+// A random todo list, drawn from a few tasks so that it often has duplicated tasks
+pub fn arb_tasks() -> impl Strategy<Value = Vec<Task>> {
+    (
+        proptest::collection::vec(arb_task(), 1..6),
+        proptest::collection::vec(any::<proptest::sample::Index>(), 0..10),
+    )
+        .prop_map(|(pool, picks)| {
+            picks
+                .iter()
+                .map(|i| pool[i.index(pool.len())].clone())
+                .collect()
+        })
+}
+
+// A pair of todo lists from one of the generators of the benchmarks, larger and more realistic
+// than those of `arb_tasks`
+pub fn arb_workload() -> impl Strategy<Value = (Vec<Task>, Vec<Task>)> {
+    (0..5usize, 0..40usize, any::<u64>()).prop_map(|(kind, n, seed)| match kind {
+        0 => synthetic::edited_subjects(n, 20, seed),
+        1 => synthetic::tag_churn(n, seed),
+        2 => synthetic::recurring(n, seed),
+        3 => synthetic::postponed(n, seed),
+        _ => synthetic::all_pairs_admissible(n),
+    })
+}
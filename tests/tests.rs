@@ -2,6 +2,7 @@
 extern crate pretty_assertions;
 extern crate itertools;
 extern crate serde;
+extern crate serde_json;
 extern crate serde_yaml;
 extern crate todiff;
 extern crate todo_txt;
@@ -16,6 +17,8 @@ use std::io::BufReader;
 use std::str::FromStr;
 use todiff::compute_changes::*;
 use todiff::display_changes::*;
+use todiff::filter_changes::*;
+use todiff::io::*;
 use todiff::merge_changes::*;
 use todo_txt::task::Extended as Task;
 
@@ -26,7 +29,7 @@ fn tasks_from_strings(strings: Vec<String>) -> Vec<Task> {
         .collect()
 }
 
-fn tasks_to_strings(tasks: &Vec<Task>) -> Vec<String> {
+fn tasks_to_strings(tasks: &[Task]) -> Vec<String> {
     tasks.iter().map(Task::to_string).collect()
 }
 
@@ -46,20 +49,117 @@ trait Test: DeserializeOwned {
 #[derive(Deserialize, Debug)]
 struct ChangesetTest {
     allowed_divergence: Option<usize>,
+    detect_recurrences: Option<bool>,
+    ignore: Option<Vec<String>>,
+    exact: Option<bool>,
+    id_tag: Option<String>,
+    strict_subject: Option<bool>,
+    similarity_metric: Option<String>,
+    loose_recurrence: Option<bool>,
+    detect_splits: Option<bool>,
+    detect_copies: Option<bool>,
+    granular: Option<bool>,
+    filter: Option<Vec<String>>,
+    filter_not: Option<Vec<String>>,
     #[serde(deserialize_with = "deserialize_tasks")]
     from: Vec<Task>,
     #[serde(deserialize_with = "deserialize_tasks")]
     to: Vec<Task>,
+    #[serde(default, deserialize_with = "deserialize_tasks")]
+    archived: Vec<Task>,
     new: Vec<String>,
     changes: Vec<TaskDelta<Vec<String>>>,
+    // The tasks each task of `from` became, unchecked when absent
+    after: Option<Vec<Vec<String>>>,
 }
 
 impl Test for ChangesetTest {
     fn run(self: ChangesetTest) {
         // Test that compute_changeset returns what is expected
-        let allowed_divergence = self.allowed_divergence.unwrap_or(0);
-        let (computed_new, computed_changes) =
-            compute_changeset(self.from.clone(), self.to.clone(), allowed_divergence);
+        let filter = TaskFilter {
+            include: self
+                .filter
+                .iter()
+                .flatten()
+                .map(|p| p.parse().unwrap())
+                .collect(),
+            exclude: self
+                .filter_not
+                .iter()
+                .flatten()
+                .map(|p| p.parse().unwrap())
+                .collect(),
+        };
+        let opts = self.ignore.iter().flatten().fold(
+            DiffOptions::default()
+                .allowed_divergence(self.allowed_divergence.unwrap_or(0))
+                .detect_recurrences(self.detect_recurrences.unwrap_or(true))
+                .exact_only(self.exact.unwrap_or(false))
+                .id_tag(self.id_tag.as_ref().map_or("id", String::as_str))
+                .strict_subject(self.strict_subject.unwrap_or(false))
+                .loose_recurrence(self.loose_recurrence.unwrap_or(false))
+                .detect_splits(self.detect_splits.unwrap_or(false))
+                .detect_copies(self.detect_copies.unwrap_or(false))
+                .granular(self.granular.unwrap_or(false))
+                .similarity_metric(
+                    self.similarity_metric
+                        .as_ref()
+                        .map_or(SimilarityMetric::Levenshtein, |m| m.parse().unwrap()),
+                )
+                .filter(filter),
+            |opts, f| opts.ignore(f.parse().unwrap()),
+        );
+        let (computed_new, computed_changes) = compute_changeset_archived_with(
+            self.from.clone(),
+            self.to.clone(),
+            self.archived.clone(),
+            &opts,
+        );
+
+        // Test that setting identical lines aside changes nothing
+        let (before, after) = (
+            tasks_to_strings(&self.from).join("\n"),
+            tasks_to_strings(&self.to).join("\n"),
+        );
+        let in_memory = compute_changeset_indexed_with(
+            read_tasks_indexed(before.as_bytes(), "before").unwrap(),
+            read_tasks_indexed(after.as_bytes(), "after").unwrap(),
+            self.archived.clone(),
+            &opts,
+        );
+        let streamed = if self.archived.is_empty() {
+            compute_changeset_from_readers(before.as_bytes(), after.as_bytes(), &opts).unwrap()
+        } else {
+            let residual = read_residual_tasks_with(
+                before.as_bytes(),
+                after.as_bytes(),
+                ["before", "after"],
+                &ReadOptions::default(),
+                |line| may_set_aside(line, &opts),
+            )
+            .unwrap();
+            compute_changeset_residual_with(
+                residual.before.indexed(),
+                residual.after.indexed(),
+                residual.identical,
+                self.archived,
+                &opts,
+                false,
+            )
+            .0
+        };
+        assert_eq!(in_memory, streamed, "Mismatching streamed changeset");
+
+        if let Some(after) = self.after {
+            assert_eq!(
+                after,
+                computed_changes
+                    .iter()
+                    .map(|tc| tasks_to_strings(&tc.after))
+                    .collect::<Vec<_>>(),
+                "Mismatching resulting tasks"
+            );
+        }
 
         let computed_new_as_str = tasks_to_strings(&computed_new);
         let computed_changes_as_strs = computed_changes
@@ -86,6 +186,24 @@ struct DisplayTest {
     from: Vec<Task>,
     #[serde(deserialize_with = "deserialize_tasks")]
     to: Vec<Task>,
+    porcelain: Option<bool>,
+    html: Option<bool>,
+    quiet: Option<bool>,
+    group_by: Option<String>,
+    hide: Option<Vec<String>>,
+    summary_line: Option<bool>,
+    today: Option<String>,
+    date_format: Option<String>,
+    labels: Option<(String, String)>,
+    width: Option<usize>,
+    // The names of both lists, the tasks being numbered from 1 on each side
+    line_numbers: Option<(String, String)>,
+    show_moves: Option<bool>,
+    show_scores: Option<bool>,
+    show_stats: Option<bool>,
+    show_unchanged: Option<bool>,
+    detect_splits: Option<bool>,
+    detect_copies: Option<bool>,
     changes: String,
 }
 
@@ -93,9 +211,70 @@ impl Test for DisplayTest {
     fn run(self: DisplayTest) {
         // Test that the output of the command is as expected
         let allowed_divergence = self.allowed_divergence.unwrap_or(0);
-        let (new_tasks, changes) =
-            compute_changeset(self.from.clone(), self.to.clone(), allowed_divergence);
-        let output = display_changeset(new_tasks, changes, false);
+        let numbered = |tasks: Vec<Task>| {
+            tasks
+                .into_iter()
+                .enumerate()
+                .map(|(i, t)| (i + 1, t))
+                .collect()
+        };
+        let (new_tasks, changes) = compute_changeset_indexed_with(
+            numbered(self.from),
+            numbered(self.to),
+            Vec::new(),
+            &DiffOptions::default()
+                .allowed_divergence(allowed_divergence)
+                .detect_splits(self.detect_splits.unwrap_or(false))
+                .detect_copies(self.detect_copies.unwrap_or(false)),
+        );
+        let opts = self.hide.iter().flatten().fold(
+            DisplayOptions::default()
+                .summary_line(self.summary_line.unwrap_or(false))
+                .show_moves(self.show_moves.unwrap_or(false))
+                .show_scores(self.show_scores.unwrap_or(false))
+                .show_stats(self.show_stats.unwrap_or(false))
+                .show_unchanged(self.show_unchanged.unwrap_or(false)),
+            |opts, s| opts.hide(s.parse().unwrap()),
+        );
+        let opts = match self.today {
+            Some(d) => opts.today(d.parse().unwrap()),
+            None => opts,
+        };
+        let opts = match self.date_format {
+            Some(f) => opts.date_format(f.parse().unwrap()),
+            None => opts,
+        };
+        let opts = match self.labels {
+            Some((before, after)) => opts.labels(&before, &after),
+            None => opts,
+        };
+        let opts = match self.width {
+            Some(width) => opts.width(width),
+            None => opts,
+        };
+        let opts = match self.line_numbers {
+            Some((before, after)) => opts.line_numbers(&before, &after),
+            None => opts,
+        };
+        // Only the default output displays line numbers
+        let indexed_new_tasks = new_tasks.clone();
+        let new_tasks = new_tasks.into_iter().map(|(_, t)| t).collect();
+        let output = if self.quiet.unwrap_or(false) {
+            display_changeset_summary_with(new_tasks, changes, &opts)
+        } else if self.porcelain.unwrap_or(false) {
+            display_changeset_porcelain_with(new_tasks, changes, &opts)
+        } else if self.html.unwrap_or(false) {
+            display_changeset_html_with(new_tasks, changes, &opts)
+        } else {
+            let opts = match self.group_by {
+                Some(g) => opts.group_by(g.parse().unwrap()),
+                None => opts,
+            };
+            let mut output = Vec::new();
+            display_changeset_indexed_to_with(&mut output, indexed_new_tasks, changes, &opts)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
 
         // Split into lines to make diff easier to read
         assert_eq!(
@@ -105,6 +284,21 @@ impl Test for DisplayTest {
     }
 }
 
+// The changes of a changeset, regardless of the tasks they apply to: after a merge, the changes
+// made by one side then apply to tasks that may also have been changed by the other side
+fn deltas(
+    (new_tasks, changes): (Vec<Task>, Vec<ChangedTask<Vec<Changes>>>),
+) -> (Vec<String>, Vec<TaskDelta<Vec<Changes>>>) {
+    (
+        tasks_to_strings(&new_tasks),
+        changes
+            .into_iter()
+            .map(|c| c.delta)
+            .filter(|d| !d.is_identical())
+            .collect(),
+    )
+}
+
 #[derive(Deserialize, Debug)]
 struct MergeTest {
     allowed_divergence: Option<usize>,
@@ -114,6 +308,13 @@ struct MergeTest {
     left: Vec<Task>,
     #[serde(deserialize_with = "deserialize_tasks")]
     right: Vec<Task>,
+    // Both sides made some of the same changes, which thus appear in only one diff after merge
+    same_changes: Option<bool>,
+    diff3: Option<bool>,
+    marker_size: Option<usize>,
+    // The labels of our side, of the ancestor and of their side
+    labels: Option<(String, String, String)>,
+    strategy: Option<String>,
     result: String,
 }
 
@@ -127,20 +328,38 @@ impl Test for MergeTest {
             self.right.clone(),
             allowed_divergence,
         );
+        let computed_changes = match self.strategy {
+            Some(ref strategy) => resolve_conflicts(computed_changes, strategy.parse().unwrap()).0,
+            None => computed_changes,
+        };
+        let marker_options = MarkerOptions::default()
+            .diff3(self.diff3.unwrap_or(false))
+            .marker_size(self.marker_size.unwrap_or(7));
+        let marker_options = match self.labels {
+            Some((ref ours, ref base, ref theirs)) => marker_options
+                .label_ours(ours)
+                .label_base(base)
+                .label_theirs(theirs),
+            None => marker_options,
+        };
         assert_eq!(
             self.result.trim(),
-            merge_to_string(computed_changes.clone()),
+            merge_to_string_with(computed_changes.clone(), &marker_options),
             "Mismatching merge result"
         );
 
+        // Resolving conflicts drops some changes from the result
+        if self.same_changes.unwrap_or(false) || self.strategy.is_some() {
+            return;
+        }
         if let Some(merge_result) = extract_merge_result(computed_changes) {
             let diff_from_left =
                 compute_changeset(self.from.clone(), self.left.clone(), allowed_divergence);
             let diff_right_result =
                 compute_changeset(self.right.clone(), merge_result.clone(), allowed_divergence);
             assert_eq!(
-                display_changeset(diff_from_left.0, diff_from_left.1, false),
-                display_changeset(diff_right_result.0, diff_right_result.1, false),
+                deltas(diff_from_left),
+                deltas(diff_right_result),
                 "Mismatching diffs after merge"
             );
 
@@ -149,8 +368,8 @@ impl Test for MergeTest {
             let diff_left_result =
                 compute_changeset(self.left.clone(), merge_result.clone(), allowed_divergence);
             assert_eq!(
-                display_changeset(diff_from_right.0, diff_from_right.1, false),
-                display_changeset(diff_left_result.0, diff_left_result.1, false),
+                deltas(diff_from_right),
+                deltas(diff_left_result),
                 "Mismatching diffs after merge"
             );
         }
@@ -158,7 +377,7 @@ impl Test for MergeTest {
 }
 
 fn run_tests_from_yaml<T: Test>(suite: &str, path: &str) {
-    let file = File::open(path).expect(&format!("Unable to open file ‘{}’", path));
+    let file = File::open(path).unwrap_or_else(|_| panic!("Unable to open file ‘{}’", path));
     let test_map: BTreeMap<String, T> =
         serde_yaml::from_reader(BufReader::new(&file)).unwrap_or_else(|e| panic!("{}", e));
     for (name, test) in test_map {
@@ -173,3 +392,1510 @@ fn test_yamls() {
     run_tests_from_yaml::<DisplayTest>("display", "tests/display_tests.yaml");
     run_tests_from_yaml::<MergeTest>("merge", "tests/merge_tests.yaml");
 }
+
+fn write_temp_file(name: &str, tasks: &[&str]) -> String {
+    let path = std::env::temp_dir().join(format!("todiff-test-{}-{}", std::process::id(), name));
+    std::fs::write(&path, tasks.join("\n")).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+fn run_with_stdin(bin: &str, args: &[&str], stdin: &str) -> std::process::Output {
+    run_with_config(bin, args, stdin, None)
+}
+
+// Runs with `config` as configuration file, and otherwise none (whatever the user has)
+fn run_with_config(
+    bin: &str,
+    args: &[&str],
+    stdin: &str,
+    config: Option<&str>,
+) -> std::process::Output {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    let mut command = Command::new(bin);
+    match config {
+        Some(config) => command.env("TODIFF_CONFIG", config),
+        None => command.env_remove("TODIFF_CONFIG").env(
+            "XDG_CONFIG_HOME",
+            std::env::temp_dir().join("todiff-test-no-config"),
+        ),
+    };
+    let mut child = command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The process may exit without reading its input, hence the ignored result
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_stdin() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let after = write_temp_file("stdin-after.txt", &["x do a thing"]);
+
+    let output = run_with_stdin(todiff, &["--color", "never", "-", &after], "do a thing\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "Comparing ‘<stdin>’ → ‘{}’\n\n\
+             Completed tasks\n---------------\n\n → do a thing\n    → Completed\n\n",
+            after
+        )
+    );
+
+    let output = run_with_stdin(todiff, &["-", "-"], "do a thing\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("standard input"));
+}
+
+#[test]
+fn test_exit_code() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("exit-code-before.txt", &["do a thing", "do another thing"]);
+    let identical = write_temp_file(
+        "exit-code-identical.txt",
+        &["do another thing", "do a thing"],
+    );
+    let different = write_temp_file("exit-code-different.txt", &["x do a thing"]);
+
+    let output = run_with_stdin(todiff, &["--no-header", &before, &identical], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "No changes.\n\n");
+
+    let output = run_with_stdin(todiff, &[&before, &different], "");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run_with_stdin(todiff, &[&before, "/nonexistent/todo.txt"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("/nonexistent/todo.txt"));
+
+    let output = run_with_stdin(todiff, &[&before], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    // Like diff --brief
+    let output = run_with_stdin(todiff, &["--brief", &before, &identical], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    let output = run_with_stdin(todiff, &["--brief", &before, &different], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("Files {} and {} differ\n", before, different)
+    );
+}
+
+#[test]
+fn test_merge_exit_code() {
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let ancestor = write_temp_file("merge-exit-code-ancestor.txt", &["do a thing"]);
+    let current = write_temp_file("merge-exit-code-current.txt", &["x do a thing"]);
+    let clean = write_temp_file(
+        "merge-exit-code-clean.txt",
+        &["do a thing", "do another thing"],
+    );
+    let conflicting = write_temp_file("merge-exit-code-conflicting.txt", &[]);
+
+    let output = run_with_stdin(todiff_merge, &[&ancestor, &current, &clean], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "x do a thing\ndo another thing\n"
+    );
+
+    let output = run_with_stdin(todiff_merge, &[&ancestor, &current, &conflicting], "");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run_with_stdin(
+        todiff_merge,
+        &["--overwrite", &ancestor, &current, "/nonexistent/todo.txt"],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("/nonexistent/todo.txt"));
+    assert_eq!(std::fs::read_to_string(&current).unwrap(), "x do a thing");
+
+    let output = run_with_stdin(todiff_merge, &[&ancestor, &current], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run_with_stdin(todiff_merge, &["-", "-", &clean], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_merge_stdin() {
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let ancestor = write_temp_file("merge-stdin-ancestor.txt", &["aaaa"]);
+    let other = write_temp_file("merge-stdin-other.txt", &["aaaa", "cccc"]);
+
+    let output = run_with_stdin(todiff_merge, &[&ancestor, "-", &other], "aaaa\nbbbb\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "aaaa\nbbbb\ncccc\n"
+    );
+
+    let output = run_with_stdin(todiff_merge, &["-", "-", &other], "aaaa\n");
+    assert!(!output.status.success());
+
+    let output = run_with_stdin(
+        todiff_merge,
+        &["--overwrite", &ancestor, "-", &other],
+        "aaaa\n",
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_merge_comments() {
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let ancestor = write_temp_file(
+        "merge-comments-ancestor.txt",
+        &["call mom", "pay the rent", "water the plants"],
+    );
+    let current = write_temp_file(
+        "merge-comments-current.txt",
+        &[
+            "# family",
+            "call mom",
+            "",
+            "# home",
+            "pay the rent",
+            "water the plants",
+            "# end of the list",
+        ],
+    );
+    let other = write_temp_file(
+        "merge-comments-other.txt",
+        &["x call mom", "water the plants", "buy milk"],
+    );
+
+    let output = run_with_stdin(
+        todiff_merge,
+        &["--overwrite", &ancestor, &current, &other],
+        "",
+    );
+    assert!(output.status.success());
+    // The comment before the task deleted by <OTHER> moves down to the next task
+    assert_eq!(
+        std::fs::read_to_string(&current).unwrap(),
+        "# family\nx call mom\n# home\nwater the plants\nbuy milk\n# end of the list"
+    );
+
+    // Comments of <ANCESTOR> and <OTHER> (here the commented result above) are dropped
+    let output = run_with_stdin(todiff_merge, &[&ancestor, &ancestor, &current], "");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "x call mom\nwater the plants\nbuy milk\n"
+    );
+
+    // Unless they are parsed as tasks, which are then new in <OTHER>
+    let output = run_with_stdin(
+        todiff_merge,
+        &["--strict-parse", &ancestor, &ancestor, &current],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "x call mom\nwater the plants\n# family\n# home\nbuy milk\n# end of the list\n"
+    );
+}
+
+#[test]
+fn test_comments() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("comments-before.txt", &["# work", "do a thing"]);
+    let after = write_temp_file(
+        "comments-after.txt",
+        &["# work stuff", "x do a thing", "", "# home"],
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &["--no-header", "--show-line-numbers", &before, &after],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "Completed tasks\n\
+             ---------------\n\
+             \n \
+             → [{}:2 → {}:2] do a thing\n    \
+             → Completed\n\n",
+            before, after
+        )
+    );
+
+    let output = run_with_stdin(todiff, &["--no-header", &before, &before], "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "No changes.\n\n");
+
+    let output = run_with_stdin(todiff, &["--strict-parse", "--quiet", &before, &after], "");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "2 new, 1 deleted, 1 completed, 0 changed\n"
+    );
+}
+
+#[test]
+fn test_config() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let config = write_temp_file(
+        "config.toml",
+        &[
+            "similarity = 100",
+            "word-diff = \"char\"",
+            "id-tag = \"key\"",
+            "strategy = \"ours\"",
+            "colour = \"always\"",
+        ],
+    );
+    let before = write_temp_file("config-before.txt", &["call mom key:1"]);
+    let after = write_temp_file("config-after.txt", &["email mom key:1"]);
+
+    // Tasks are matched by their `key:` tag, then the subject change is highlighted by character
+    let output = run_with_config(
+        todiff,
+        &["--color", "always", "--quiet", &before, &after],
+        "",
+        Some(&config),
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0 new, 0 deleted, 0 completed, 1 changed\n"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "todiff: warning: {}: unknown option ‘colour’ (ignored)\n",
+            config
+        )
+    );
+    let colored = |args: &[&str]| {
+        let args = [
+            &["--color", "always", "--no-header"],
+            args,
+            &[&before, &after],
+        ]
+        .concat();
+        String::from_utf8(run_with_config(todiff, &args, "", Some(&config)).stdout).unwrap()
+    };
+    assert_ne!(colored(&[]), colored(&["--word-diff", "word"]));
+    assert_eq!(colored(&[]), colored(&["--word-diff", "char"]));
+
+    // Command-line options take precedence
+    let output = run_with_config(
+        todiff,
+        &["--id-tag", "id", "--quiet", &before, &after],
+        "",
+        Some(&config),
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1 new, 1 deleted, 0 completed, 0 changed\n"
+    );
+    let output = run_with_config(
+        todiff,
+        &[
+            "--id-tag",
+            "id",
+            "--similarity",
+            "50",
+            "--quiet",
+            &before,
+            &after,
+        ],
+        "",
+        Some(&config),
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0 new, 0 deleted, 0 completed, 1 changed\n"
+    );
+
+    // todiff-merge reads it too
+    let ancestor = write_temp_file("config-ancestor.txt", &["call the plumber"]);
+    let current = write_temp_file("config-current.txt", &["call the plumbers"]);
+    let other = write_temp_file("config-other.txt", &["call a plumber"]);
+    let output = run_with_config(
+        todiff_merge,
+        &["--similarity", "75", &ancestor, &current, &other],
+        "",
+        Some(&config),
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "call the plumbers\n"
+    );
+    let output = run_with_config(
+        todiff_merge,
+        &[
+            "--similarity",
+            "75",
+            "--strategy",
+            "theirs",
+            &ancestor,
+            &current,
+            &other,
+        ],
+        "",
+        Some(&config),
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "call a plumber\n"
+    );
+
+    // Flags set in the configuration can be turned off again, the last one given winning
+    let exact = write_temp_file("config-exact.toml", &["exact = true"]);
+    let quiet = |args: &[&str]| {
+        let args = [args, &["--quiet", &ancestor, &current]].concat();
+        String::from_utf8(run_with_config(todiff, &args, "", Some(&exact)).stdout).unwrap()
+    };
+    assert_eq!(quiet(&[]), "1 new, 1 deleted, 0 completed, 0 changed\n");
+    assert_eq!(
+        quiet(&["--no-exact"]),
+        "0 new, 0 deleted, 0 completed, 1 changed\n"
+    );
+    assert_eq!(
+        quiet(&["--no-exact", "--exact"]),
+        "1 new, 1 deleted, 0 completed, 0 changed\n"
+    );
+
+    // An invalid configuration is an error, like an invalid option
+    let invalid = write_temp_file("config-invalid.toml", &["similarity = 101"]);
+    let output = run_with_config(todiff, &[&before, &after], "", Some(&invalid));
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "todiff: {}: invalid value for ‘similarity’: must be between 0 and 100\n",
+            invalid
+        )
+    );
+}
+
+#[test]
+fn test_apply() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("apply-before.txt", &["call mom", "buy milk", "pay rent"]);
+    let after = write_temp_file(
+        "apply-after.txt",
+        &["x call mom", "pay rent due:2020-01-01", "water the plants"],
+    );
+    let output = run_with_stdin(todiff, &["--format", "json", &before, &after], "");
+    let changeset = write_temp_file(
+        "apply-changeset.json",
+        &[&String::from_utf8(output.stdout).unwrap()],
+    );
+
+    // Tasks are matched by similarity, and comments are kept in place
+    let target = write_temp_file(
+        "apply-target.txt",
+        &[
+            "# errands",
+            "buy milk",
+            "call mum",
+            "",
+            "pay rent",
+            "read a book",
+        ],
+    );
+    let applied = "# errands\nx call mum\npay rent due:2020-01-01\nread a book\nwater the plants\n";
+    let output = run_with_stdin(todiff, &["apply", &changeset, &target], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), applied);
+
+    let output = run_with_stdin(todiff, &["apply", "--in-place", &changeset, &target], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(std::fs::read_to_string(&target).unwrap() + "\n", applied);
+
+    // Changes that cannot be applied are reported
+    let output = run_with_stdin(todiff, &["apply", &changeset, "-"], "pay rent\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "pay rent due:2020-01-01\nwater the plants\n"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "todiff: no task matches ‘call mom’, its changes were not applied\n\
+         todiff: no task matches ‘buy milk’, its changes were not applied\n"
+    );
+
+    let output = run_with_stdin(todiff, &["apply", &target, &target], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_batch() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let monday = write_temp_file("batch-1.txt", &["call mom", "pay rent"]);
+    let tuesday = write_temp_file("batch-2.txt", &["call mom!", "pay rent", "buy milk"]);
+    let wednesday = write_temp_file("batch-3.txt", &["x 2018-06-03 call mom!", "buy milk"]);
+    let manifest = write_temp_file(
+        "batch-manifest.txt",
+        &[
+            "# week 23",
+            &format!("{} {}", monday, tuesday),
+            &format!("{} {} Tuesday", tuesday, wednesday),
+        ],
+    );
+
+    let output = run_with_stdin(todiff, &["batch", "--quiet", &manifest], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{} → {}: 1 new, 0 deleted, 0 completed, 1 changed\n\
+             Tuesday: 0 new, 1 deleted, 1 completed, 0 changed\n\
+             Total over 2 pairs: 1 new, 1 deleted, 1 completed, 1 changed\n",
+            monday, tuesday
+        )
+    );
+
+    // The same pairs, from the files in order
+    let pattern = monday.replace("batch-1.txt", "batch-?.txt");
+    let output = run_with_stdin(
+        todiff,
+        &["batch", "--format", "json", "--glob", &pattern],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert_eq!(json[1]["labels"]["after"], wednesday);
+    assert_eq!(json[1]["new"], serde_json::json!([]));
+
+    // Pairs that cannot be read do not prevent diffing the others, unless --fail-fast
+    let missing = monday.replace("batch-1.txt", "batch-missing.txt");
+    let manifest = write_temp_file(
+        "batch-missing-manifest.txt",
+        &[
+            &format!("{} {}", missing, monday),
+            &format!("{} {}", monday, tuesday),
+        ],
+    );
+    let output = run_with_stdin(todiff, &["batch", "-q", &manifest], "");
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout
+        .ends_with("Total over 2 pairs (1 failed): 1 new, 0 deleted, 0 completed, 1 changed\n"));
+    let output = run_with_stdin(todiff, &["batch", "-q", "--fail-fast", &manifest], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Total over 1 pair (1 failed): 0 new, 0 deleted, 0 completed, 0 changed\n"
+    );
+}
+
+// Runs git in `dir`, with fixed identities and dates so that the history is reproducible
+fn git_in(dir: &std::path::Path, args: &[&str], date: &str) {
+    let status = std::process::Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .env("GIT_AUTHOR_NAME", "todiff")
+        .env("GIT_AUTHOR_EMAIL", "todiff@example.com")
+        .env("GIT_COMMITTER_NAME", "todiff")
+        .env("GIT_COMMITTER_EMAIL", "todiff@example.com")
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn test_log() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let repo = std::env::temp_dir().join(format!("todiff-test-{}-log", std::process::id()));
+    let _ = std::fs::remove_dir_all(&repo);
+    std::fs::create_dir(&repo).unwrap();
+    git_in(&repo, &["init", "-q"], "2018-06-01T10:00:00");
+    let versions: &[(&[u8], &str, &str)] = &[
+        (b"call mom\npay rent\n", "Start", "2018-06-01"),
+        (b"call mom!\npay rent\nbuy milk\n", "Add milk", "2018-06-02"),
+        (
+            b"# errands\ncall mom!\npay rent\nbuy milk\n",
+            "Comment",
+            "2018-06-03",
+        ),
+        (b"call mom!\nbuy \xff milk\n", "Break", "2018-06-04"),
+        (b"x 2018-06-05 call mom!\nbuy milk\n", "Done", "2018-06-05"),
+    ];
+    for (contents, subject, date) in versions {
+        std::fs::write(repo.join("todo.txt"), contents).unwrap();
+        let date = format!("{}T10:00:00", date);
+        git_in(&repo, &["add", "todo.txt"], &date);
+        git_in(&repo, &["commit", "-q", "-m", subject], &date);
+    }
+    let path = repo.join("todo.txt");
+    let path = path.to_str().unwrap();
+    // The hashes are not known, so they are removed from the lines of the commits
+    let without_hashes = |output: Vec<u8>| -> String {
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().map(|l| match l.find(" 2018-") {
+            Some(i) => format!(
+                "{}{}",
+                &l[..l[..i].rfind(' ').map_or(0, |j| j + 1)],
+                &l[i + 1..]
+            ),
+            None => l.to_owned(),
+        });
+        lines.map(|l| l + "\n").collect()
+    };
+
+    let output = run_with_stdin(todiff, &["log", "-q", path], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        without_hashes(output.stdout),
+        "2018-06-02 Add milk: 1 new, 0 deleted, 0 completed, 1 changed\n\
+         2018-06-05 Done: 0 new, 1 deleted, 1 completed, 0 changed\n\
+         Total over 2 commits: 1 new, 1 deleted, 1 completed, 1 changed\n"
+    );
+    let stderr = without_hashes(output.stderr);
+    assert!(stderr.starts_with(
+        "todiff: skipping 2018-06-03 Comment: no task changed\n\
+         todiff: skipping 2018-06-04 Break: "
+    ));
+
+    // The version before the selected commits is the base of the first one
+    let output = run_with_stdin(todiff, &["log", "-q", "--since", "2018-06-04", path], "");
+    assert_eq!(
+        without_hashes(output.stdout),
+        "2018-06-05 Done: 0 new, 1 deleted, 1 completed, 0 changed\n\
+         Total over 1 commit: 0 new, 1 deleted, 1 completed, 0 changed\n"
+    );
+    let output = run_with_stdin(todiff, &["log", "--format", "json", "--max", "3", path], "");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 1);
+
+    let output = run_with_stdin(todiff, &["log", "/"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_check() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let clean = write_temp_file(
+        "check-clean.txt",
+        &["call mom", "x 2020-01-02 2020-01-01 pay rent"],
+    );
+    let dirty = write_temp_file(
+        "check-dirty.txt",
+        &["call mom rec:weekly", "x pay rent", "call mom rec:weekly"],
+    );
+
+    let output = run_with_stdin(todiff, &["check", &clean], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+
+    let output = run_with_stdin(todiff, &["check", &dirty], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{0}:1: invalid recurrence ‘rec:weekly’, ignored\n\
+             {0}:2: completed task without a completion date\n\
+             {0}:3: invalid recurrence ‘rec:weekly’, ignored\n\
+             {0}:3: duplicate of the task on line 1\n",
+            dirty
+        )
+    );
+
+    let output = run_with_stdin(todiff, &["check", "--format", "json", "-"], "x pay rent\n");
+    assert_eq!(output.status.code(), Some(1));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["path"], "<stdin>");
+    assert_eq!(json["findings"][0]["type"], "MissingFinishDate");
+    assert_eq!(json["findings"][0]["line_number"], 1);
+
+    let output = run_with_stdin(todiff, &["check", "/nonexistent/todo.txt"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_subcommands() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let ancestor = write_temp_file("subcommands-ancestor.txt", &["call mom", "pay the rent"]);
+    let current = write_temp_file(
+        "subcommands-current.txt",
+        &["x call mom", "pay the rent due:2020-01-01"],
+    );
+    let other = write_temp_file("subcommands-other.txt", &["call mom", "pay the rents"]);
+
+    // `diff` is the default subcommand
+    let stdout = |args: &[&str]| {
+        let output = run_with_stdin(todiff, args, "");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let summary = "0 new, 0 deleted, 1 completed, 1 changed\n";
+    assert_eq!(stdout(&["--quiet", &ancestor, &current]), summary);
+    assert_eq!(stdout(&["diff", "--quiet", &ancestor, &current]), summary);
+    assert_eq!(
+        stdout(&["--color", "never", "diff", "--quiet", &ancestor, &current]),
+        summary
+    );
+
+    // Shared options can be given before or after the subcommand
+    let summary = "1 new, 1 deleted, 0 completed, 0 changed\n";
+    assert_eq!(
+        stdout(&["--similarity", "100", "--quiet", &ancestor, &other]),
+        summary
+    );
+    assert_eq!(
+        stdout(&["--similarity", "100", "diff", "--quiet", &ancestor, &other]),
+        summary
+    );
+    assert_eq!(
+        stdout(&["diff", "--quiet", "--similarity", "100", &ancestor, &other]),
+        summary
+    );
+
+    // `todiff-merge` is the same as `todiff merge`
+    let merged = "x call mom\npay the rents due:2020-01-01\n";
+    assert_eq!(stdout(&["merge", &ancestor, &current, &other]), merged);
+    let output = run_with_stdin(todiff_merge, &[&ancestor, &current, &other], "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), merged);
+    let output = run_with_stdin(todiff_merge, &["--exact", &ancestor, &current, &other], "");
+    let exact = String::from_utf8(output.stdout).unwrap();
+    assert_ne!(exact, merged);
+    assert_eq!(
+        stdout(&["--exact", "merge", &ancestor, &current, &other]),
+        exact
+    );
+    assert_eq!(
+        stdout(&["merge", "--exact", &ancestor, &current, &other]),
+        exact
+    );
+}
+
+#[test]
+fn test_merge_markers() {
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let ancestor = write_temp_file("merge-markers-ancestor.txt", &["call the plumber"]);
+    let current = write_temp_file("merge-markers-current.txt", &["call the plumbers"]);
+    let other = write_temp_file("merge-markers-other.txt", &["call a plumber"]);
+
+    // The markers are labelled with the paths by default
+    let output = run_with_stdin(todiff_merge, &[&ancestor, &current, &other], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "<<<<<<< {}\ncall the plumbers\n=======\ncall a plumber\n>>>>>>> {}\n",
+            current, other
+        )
+    );
+
+    let output = run_with_stdin(
+        todiff_merge,
+        &[
+            "--diff3",
+            "--marker-size",
+            "3",
+            "--label-ours",
+            "HEAD",
+            "--label-base",
+            "base",
+            "--label-theirs",
+            "feature",
+            &ancestor,
+            &current,
+            &other,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("<<< HEAD\n"));
+}
+
+#[test]
+fn test_merge_strategy() {
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let ancestor = write_temp_file("merge-strategy-ancestor.txt", &["call the plumber"]);
+    let current = write_temp_file("merge-strategy-current.txt", &["call the plumbers"]);
+    let other = write_temp_file("merge-strategy-other.txt", &["call a plumber"]);
+
+    let output = run_with_stdin(
+        todiff_merge,
+        &["--strategy", "theirs", &ancestor, &current, &other],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "call a plumber\n"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "todiff-merge: 1 conflict resolved with the theirs strategy\n"
+    );
+
+    let output = run_with_stdin(
+        todiff_merge,
+        &["--strategy", "union", &ancestor, &ancestor, &other],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+}
+
+#[test]
+fn test_explain() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("explain-before.txt", &["call mom", "pay rent"]);
+    let after = write_temp_file("explain-after.txt", &["call mom please", "call mom"]);
+
+    let output = run_with_stdin(todiff, &["--explain", "--porcelain", &before, &after], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "N\tcall mom please\nD\tpay rent\n"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "- call mom\n\
+         \x20   matched with ‘call mom’ (identical)\n\
+         \x20   closest other: ‘call mom please’ (54% similar, not enough to match)\n\
+         - pay rent\n\
+         \x20   not matched: no admissible candidate\n\
+         \x20   closest other: ‘call mom’ (13% similar, not enough to match)\n\
+         + call mom please\n\
+         \x20   new: no recurring task\n"
+    );
+
+    // Tasks matched with archived ones are explained too
+    let done = write_temp_file("explain-done.txt", &["x 2018-06-02 pay rent"]);
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--explain",
+            "--quiet",
+            "--done-after",
+            &done,
+            &before,
+            &after,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains(
+        "- pay rent\n\
+         \x20   matched with archived ‘x 2018-06-02 pay rent’ (100% similar)\n"
+    ));
+}
+
+#[test]
+fn test_show_scores() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("show-scores-before.txt", &["call mom", "foo"]);
+    let after = write_temp_file("show-scores-after.txt", &["call mom please", "x foo"]);
+    let args = ["--similarity", "50", "--no-header", &before, &after];
+
+    let output = run_with_stdin(todiff, &[&["--show-scores"], &args[..]].concat(), "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains(" → call mom (match confidence 54%)\n"));
+
+    let output = run_with_stdin(todiff, &[&["--format", "json"], &args[..]].concat(), "");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["changes"][0]["orig"], "call mom");
+    assert_eq!(json["changes"][0]["similarity"], 54);
+
+    let output = run_with_stdin(
+        todiff,
+        &["--porcelain", "--show-scores", &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_stats() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file(
+        "stats-before.txt",
+        &["2018-05-20 call mom +family", "pay rent due:2018-06-01"],
+    );
+    let after = write_temp_file(
+        "stats-after.txt",
+        &[
+            "x 2018-06-04 2018-05-20 call mom +family",
+            "pay rent due:2018-06-08",
+        ],
+    );
+
+    let output = run_with_stdin(todiff, &["--stats", "--no-header", &before, &after], "");
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("\nStatistics\n----------\n\nCompleted: 1 task, in 15.0 days on average\n"));
+
+    let output = run_with_stdin(
+        todiff,
+        &["--stats", "--format", "json", &before, &after],
+        "",
+    );
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json["stats"],
+        serde_json::json!({
+            "completed": 1,
+            "average_days_to_complete": 15.0,
+            "postponed_days": 7,
+            "postponed_more_than_once": 0,
+            "completed_by_project": { "family": 1 },
+        })
+    );
+
+    let output = run_with_stdin(todiff, &["--stats", "--porcelain", &before, &after], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_pairs() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("pairs-before.txt", &["call mom", "# comment", "call dad"]);
+    let after = write_temp_file("pairs-after.txt", &["call dad!", "call mom!"]);
+    let pairs = write_temp_file(
+        "pairs-pairs.txt",
+        &["# swapped", "3 -> 2", "call mom ||| call dad!"],
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &["--porcelain", "--pairs", &pairs, &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "M\tcall mom\n  similarity\t56\n  subject\tcall mom\tcall dad!\n\
+         M\tcall dad\n  similarity\t56\n  subject\tcall dad\tcall mom!\n"
+    );
+
+    let pairs = write_temp_file("pairs-unmatchable.txt", &["!call mom"]);
+    let output = run_with_stdin(
+        todiff,
+        &["--porcelain", "--pairs", &pairs, &before, &after],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "N\tcall mom!\nD\tcall mom\nM\tcall dad\n  similarity\t89\n  subject\tcall dad\tcall dad!\n"
+    );
+
+    let pairs = write_temp_file("pairs-missing.txt", &["call mom ||| call mom"]);
+    let output = run_with_stdin(todiff, &["--pairs", &pairs, &before, &after], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "todiff: {}:1: no unpinned task ‘call mom’ in <AFTER>\n",
+            pairs
+        )
+    );
+}
+
+#[test]
+fn test_invalid_line() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let valid = write_temp_file("invalid-line-valid.txt", &["do a thing"]);
+    let invalid = std::env::temp_dir().join(format!(
+        "todiff-test-{}-invalid-line-invalid.txt",
+        std::process::id()
+    ));
+    std::fs::write(&invalid, b"do a thing\ndo another thing\n\xff\xfe\n").unwrap();
+    let invalid = invalid.to_str().unwrap();
+
+    let output = run_with_stdin(todiff, &[&valid, invalid], "");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("{}:3:", invalid)), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+
+    let output = run_with_stdin(todiff_merge, &[&valid, &valid, invalid], "");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("{}:3:", invalid)), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn test_lenient() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let todiff_merge = env!("CARGO_BIN_EXE_todiff-merge");
+    let before = write_temp_file(
+        "lenient-before.txt",
+        &["do a thing", "", "do another thing"],
+    );
+    let after = std::env::temp_dir().join(format!(
+        "todiff-test-{}-lenient-after.txt",
+        std::process::id()
+    ));
+    std::fs::write(&after, b"x do a thing\n\xff\n\ndo another thing\n").unwrap();
+    let after = after.to_str().unwrap();
+
+    let output = run_with_stdin(todiff, &["--porcelain", "--lenient", &before, after], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "C\tdo a thing\n  similarity\t100\n  completed\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("warning: {}:2:", after)),
+        "{}",
+        stderr
+    );
+
+    let output = run_with_stdin(todiff, &["--porcelain", &before, after], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run_with_stdin(todiff_merge, &["--lenient", &before, &before, after], "");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "x do a thing\ndo another thing\n"
+    );
+
+    let output = run_with_stdin(
+        todiff_merge,
+        &["--lenient", "--overwrite", &before, after, &before],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_ignore() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("ignore-before.txt", &["(A) do a thing due:2018-01-01"]);
+    let after = write_temp_file("ignore-after.txt", &["(B) do a thing due:2018-01-02"]);
+
+    let output = run_with_stdin(
+        todiff,
+        &["--ignore", "priority,due-date", &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = run_with_stdin(
+        todiff,
+        &["--porcelain", "--ignore", "priority", &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "M\t(A) do a thing due:2018-01-01\n  similarity\t100\n  postponed-strict-by\t1\n"
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &["--ignore", "priority,colour", &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("colour"));
+}
+
+#[test]
+fn test_header() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("header-before.txt", &["do a thing"]);
+    let after = write_temp_file("header-after.txt", &["do a thing"]);
+
+    // The lists are labelled with their paths by default
+    let output = run_with_stdin(todiff, &[&before, &after], "");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("Comparing ‘{}’ → ‘{}’\n\nNo changes.\n\n", before, after)
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &["--before-label", "yesterday", &before, &after],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("Comparing ‘yesterday’ → ‘{}’\n\nNo changes.\n\n", after)
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--no-header",
+            "--before-label",
+            "yesterday",
+            &before,
+            &after,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_show_line_numbers() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file(
+        "line-numbers-before.txt",
+        &["call bob about the move", "", "water the plants"],
+    );
+    let after = write_temp_file("line-numbers-after.txt", &["", "x call bob about the move"]);
+
+    // Empty lines count, and the lists are named by their paths
+    let output = run_with_stdin(
+        todiff,
+        &["--no-header", "--show-line-numbers", &before, &after],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "Deleted tasks\n\
+             -------------\n\
+             \n \
+             → [{b}:3] water the plants\n\
+             \n\
+             Completed tasks\n\
+             ---------------\n\
+             \n \
+             → [{b}:1 → {a}:2] call bob about the move\n    \
+             → Completed\n\n",
+            b = before,
+            a = after
+        )
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &["--porcelain", "--show-line-numbers", &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_show_moves() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("moves-before.txt", &["a thing", "b thing", "c thing"]);
+    let after = write_temp_file("moves-after.txt", &["a thing", "c thing", "b thing"]);
+
+    // Reordering alone is not a change
+    let output = run_with_stdin(
+        todiff,
+        &["--no-header", "--show-moves", &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Moved tasks\n\
+         -----------\n\
+         \n \
+         → b thing\n    \
+         → Moved down 1 line\n\n"
+    );
+}
+
+#[test]
+fn test_duplicates() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("duplicates-before.txt", &["do a thing", "call mom"]);
+    let after = write_temp_file(
+        "duplicates-after.txt",
+        &[
+            "x do a thing",
+            "call mom",
+            "",
+            "x do a thing",
+            "x do a thing",
+        ],
+    );
+
+    let output = run_with_stdin(todiff, &["--no-header", &before, &after], "");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "todiff: warning: {}:1: task duplicated on lines 4, 5\n",
+            after
+        )
+    );
+    // One copy is the task that was completed, the others are new
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Completed tasks\n\
+         ---------------\n\
+         \n \
+         → do a thing\n    \
+         → Completed\n\
+         \n \
+         → x do a thing\n    \
+         → Created already completed\n\
+         \n \
+         → x do a thing\n    \
+         → Created already completed\n\n"
+    );
+}
+
+#[test]
+fn test_width() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file(
+        "width-before.txt",
+        &["take over the world, then the rest of the universe"],
+    );
+    let after = write_temp_file(
+        "width-after.txt",
+        &["x take over the world, then the rest of the universe"],
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &["--no-header", "--width", "40", &before, &after],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Completed tasks\n---------------\n\n \
+         → take over the world, then the rest of\n   \
+         the universe\n    → Completed\n\n"
+    );
+}
+
+#[test]
+fn test_exact() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("exact-before.txt", &["do a thing", "do another thing"]);
+    let after = write_temp_file("exact-after.txt", &["do a thing", "do another thing!"]);
+
+    let output = run_with_stdin(todiff, &["--porcelain", &before, &after], "");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "M\tdo another thing\n  similarity\t95\n  subject\tdo another thing\tdo another thing!\n"
+    );
+
+    let output = run_with_stdin(todiff, &["--porcelain", "--exact", &before, &after], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "N\tdo another thing!\nD\tdo another thing\n"
+    );
+}
+
+#[test]
+fn test_only_hide() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file("only-before.txt", &["do a thing", "do another thing"]);
+    let after = write_temp_file("only-after.txt", &["x do a thing", "something new"]);
+
+    let output = run_with_stdin(
+        todiff,
+        &["--quiet", "--only", "completed", &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0 new, 0 deleted, 1 completed, 0 changed\n"
+    );
+
+    // Only hidden changes
+    let output = run_with_stdin(todiff, &["--only", "changed", &before, &after], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+
+    let output = run_with_stdin(todiff, &["--only", "postponed", &before, &after], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run_with_stdin(
+        todiff,
+        &["--only", "new", "--hide", "deleted", &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_fail_on() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file(
+        "fail-on-before.txt",
+        &["call mom", "do a thing", "fix the bike +home"],
+    );
+    let completed = write_temp_file(
+        "fail-on-completed.txt",
+        &[
+            "x call mom",
+            "do a thing",
+            "fix the bike +home",
+            "something new",
+        ],
+    );
+    let deleted = write_temp_file("fail-on-deleted.txt", &["call mom", "do a thing"]);
+    let hook = |after: &str| {
+        run_with_stdin(
+            todiff,
+            &[
+                "--brief",
+                "--ignore-new",
+                "--fail-on",
+                "deleted",
+                &before,
+                after,
+            ],
+            "",
+        )
+    };
+
+    // Completing tasks is allowed, and new tasks are not even reported
+    let output = hook(&completed);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+    let output = run_with_stdin(
+        todiff,
+        &["--porcelain", "--ignore-new", &before, &completed],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "C\tcall mom\n  similarity\t100\n  completed\n"
+    );
+
+    // Deleting one is not
+    let output = hook(&deleted);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("Files {} and {} differ\n", before, deleted)
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "todiff: found changes of the kinds given to --fail-on: deleted\n"
+    );
+
+    // Only displayed changes count
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--quiet",
+            "--fail-on",
+            "deleted",
+            "--filter",
+            "+home",
+            &before,
+            &deleted,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(3));
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--quiet",
+            "--fail-on",
+            "deleted",
+            "--only",
+            "new",
+            &before,
+            &deleted,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--quiet",
+            "--fail-on",
+            "new",
+            "--ignore-new",
+            &before,
+            &completed,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run_with_stdin(todiff, &["--fail-on", "removed", &before, &deleted], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_filter() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file(
+        "filter-before.txt",
+        &["write the report +work", "call grandma", "fix the printer"],
+    );
+    let after = write_temp_file(
+        "filter-after.txt",
+        &[
+            "write the report",
+            "call grandma @phone",
+            "x fix the printer",
+        ],
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--porcelain",
+            "--filter",
+            "+work",
+            "--filter",
+            "@phone",
+            &before,
+            &after,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "M\twrite the report +work\n  similarity\t100\n  project-removed\t+work\n\
+         M\tcall grandma\n  similarity\t100\n  context-added\t@phone\n"
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--porcelain",
+            "--filter-not",
+            "grandma",
+            "--filter-not",
+            "+work",
+            &before,
+            &after,
+        ],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "C\tfix the printer\n  similarity\t100\n  completed\n"
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--filter",
+            "printer",
+            "--filter-not",
+            "printer",
+            &before,
+            &after,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_done_after() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file(
+        "done-before.txt",
+        &["2018-06-01 call the plumber", "2018-06-01 buy some bread"],
+    );
+    let after = write_temp_file("done-after.txt", &[]);
+    let done_before = write_temp_file(
+        "done-done-before.txt",
+        &["x 2018-05-30 2018-05-01 buy some bread"],
+    );
+    let done_after = write_temp_file(
+        "done-done-after.txt",
+        &[
+            "x 2018-05-30 2018-05-01 buy some bread",
+            "x 2018-06-02 2018-06-01 call the plumber",
+        ],
+    );
+
+    let output = run_with_stdin(todiff, &["--porcelain", &before, &after], "");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "D\t2018-06-01 call the plumber\nD\t2018-06-01 buy some bread\n"
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &[
+            "--porcelain",
+            "--done-before",
+            &done_before,
+            "--done-after",
+            &done_after,
+            &before,
+            &after,
+        ],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "D\t2018-06-01 buy some bread\n\
+         C\t2018-06-01 call the plumber\n  similarity\t100\n  completed-at\t2018-06-02\n"
+    );
+
+    let output = run_with_stdin(
+        todiff,
+        &["--done-before", &done_before, &before, &after],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_anonymize() {
+    let todiff = env!("CARGO_BIN_EXE_todiff");
+    let before = write_temp_file(
+        "anonymize-before.txt",
+        &[
+            "(B) 2018-06-01 call mom +family due:2018-06-02",
+            "buy milk and eggs @shop",
+        ],
+    );
+    let after = write_temp_file(
+        "anonymize-after.txt",
+        &[
+            "x 2018-06-03 2018-06-01 call mom +family due:2018-06-02",
+            "buy milk and bread @shop",
+            "read a book",
+        ],
+    );
+
+    let output = run_with_stdin(todiff, &["--quiet", &before, &after], "");
+    let anonymized = run_with_stdin(todiff, &["--quiet", "--anonymize", &before, &after], "");
+    assert_eq!(anonymized.stdout, output.stdout);
+
+    let output = run_with_stdin(todiff, &["--porcelain", "--anonymize", &before, &after], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for word in [
+        "call", "mom", "family", "milk", "eggs", "bread", "shop", "book",
+    ] {
+        assert!(!stdout.contains(word), "{} in {}", word, stdout);
+    }
+    assert!(stdout.contains("(B) 2018-06-01 "), "{}", stdout);
+    assert!(stdout.contains(" due:2018-06-02"), "{}", stdout);
+}